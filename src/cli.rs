@@ -1,36 +1,65 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
-use clap::Parser;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use clap::{Parser, Subcommand};
 use log::LevelFilter;
+use serde::Deserialize;
 
+use crate::media::preview::GraphicsProtocol;
 use crate::network::client::ConnectionType;
+use crate::storage::config_file;
+use crate::storage::history_cache::RetentionRule;
+use crate::storage::secrets::Secret;
+use crate::tui::keybindings::Keybindings;
+use crate::tui::screens::chat::hooks::HookConfig;
+use crate::tui::screens::chat::translation::TranslationConfig;
+use crate::tui::theme::{DEFAULT_THEME, Theme};
 
 pub const DEFAULT_ADDRESS: &str = "0.0.0.0";
 pub const DEFAULT_PORT: u16 = 4348;
+pub const DEFAULT_MAX_UPLOAD_SIZE_MB: usize = 8;
+const DEFAULT_INITIAL_HISTORY: u32 = 50;
+const DEFAULT_HISTORY_PAGE_SIZE: u32 = 25;
+const DEFAULT_NOTES_KEEP_DAYS: u32 = 7;
+const DEFAULT_MAX_MESSAGE_LINES: usize = 12;
+const DEFAULT_USERNAME: &str = "penger";
+const DEFAULT_PASSWORD: &str = "epicpass4";
+const CONFIG_FILE: &str = "config.toml";
 
 /// Simple CLI to simulate login
+///
+/// Options that are not given fall back to `~/.config/chatger/config.toml`, and then to the built-in defaults.
 #[derive(Parser, Debug)]
 #[command(name = "chatger", version = "1.0", author = "blockdoth", about = "A chatger TUI client")]
 pub struct CliArgs {
-    /// Server address of chatger server to connect to
-    #[arg(long, default_value = DEFAULT_ADDRESS)]
-    pub address: String,
+    /// Server address of chatger server to connect to [default: 0.0.0.0]
+    #[arg(long)]
+    pub address: Option<String>,
 
-    /// Server port of chatger server to connect to
-    #[arg(long, default_value_t = DEFAULT_PORT)]
-    pub port: u16,
+    /// Server port of chatger server to connect to [default: 4348]
+    #[arg(long)]
+    pub port: Option<u16>,
 
-    /// Username
-    #[arg(long, default_value = "penger")]
-    pub username: String,
+    /// Username [default: penger]
+    #[arg(long)]
+    pub username: Option<String>,
 
-    /// Password
-    #[arg(long, default_value = "epicpass4")]
-    pub password: String,
+    /// Password [default: epicpass4]
+    #[arg(long)]
+    pub password: Option<String>,
 
-    /// Log level (error, warn, info, debug, trace)
-    #[arg(long, default_value_t = LevelFilter::Info)]
-    pub loglevel: LevelFilter,
+    /// Log level (error, warn, info, debug, trace) [default: info]
+    #[arg(long)]
+    pub loglevel: Option<LevelFilter>,
+
+    /// Log passwords and media data as they are instead of masking them, only for deep debugging
+    #[arg(long, default_value_t = false)]
+    pub unsafe_logs: bool,
 
     /// Automatically login
     #[arg(long, default_value_t = false)]
@@ -39,14 +68,541 @@ pub struct CliArgs {
     /// Enable TLS encryption
     #[arg(long, default_value_t = false)]
     pub enable_tls: bool,
+
+    /// Largest attachment to upload, in MiB [default: 8]
+    #[arg(long)]
+    pub max_upload_size: Option<usize>,
+
+    /// Directory downloaded media is saved to [default: the downloads directory]
+    #[arg(long)]
+    pub download_dir: Option<PathBuf>,
+
+    /// Config file to use instead of `~/.config/chatger/config.toml`
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Color theme, dark, light, mono or the name of a file in `~/.config/chatger/themes/` [default: dark]
+    #[arg(long)]
+    pub theme: Option<String>,
+
+    /// Ignore the config file and do not restore or save any state, to find out whether a problem comes from the config
+    #[arg(long, default_value_t = false)]
+    pub safe_mode: bool,
+
+    /// Record recent UI states so they can be stepped through with F9, for debugging UI glitches
+    #[arg(long, default_value_t = false)]
+    pub time_travel: bool,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum CliCommand {
+    /// Connect without the TUI, send packets typed on stdin and print the decoded responses
+    Repl,
+    /// Write the config file and channel groups to a single file, without passwords and the lock PIN
+    Export { file: PathBuf },
+    /// Replace the config file and channel groups with an exported profile, keeping the current passwords
+    Import { file: PathBuf },
+    /// Check the terminal, config file, keyring, storage and configured servers, and explain what to fix
+    Doctor,
+    /// Log in, send a message to a channel and exit once the server confirmed it, the message is read from stdin when not given
+    Send { channel: String, message: Vec<String> },
+    /// Log in, print the last messages of a channel and exit
+    History {
+        channel: String,
+        /// Number of messages to print
+        #[arg(long, default_value_t = 20)]
+        count: usize,
+        /// Print the messages as a JSON array instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+}
+
+/// Contents of `config.toml`, every field is optional
+///
+/// ```toml
+/// address = "chat.example.com"
+/// port = 4348
+/// username = "penger"
+/// enable_tls = true
+/// remember_login = false
+/// loglevel = "debug"
+/// theme = "dark"
+/// graphics = "sixel" # kitty, iterm2, sixel or halfblocks
+/// lock_pin = "1234"
+/// check_version = true
+/// initial_history = 50
+/// history_page_size = 25
+/// private_typing = ["hr", "secrets"]
+/// desktop_notifications = true
+/// correct_clock_skew = true
+/// bell = true
+/// title_unread = true
+/// highlight_words = ["deploy", "penguin"]
+/// author_avatars = true
+/// notes_keep_days = 7
+/// audit_log = true
+/// vim_mode = true
+/// max_message_lines = 12
+/// startup_commands = ["/join general", "/status dnd", "/logs on"]
+/// reconnect = "fresh" # resume or fresh
+///
+/// [[hooks]]
+/// event = "mention" # message, mention or disconnect
+/// command = "notify-send \"$CHATGER_AUTHOR\" \"$CHATGER_TEXT\""
+///
+/// [[retention]]
+/// channel = "random"
+/// keep_days = 7
+///
+/// [[accounts]]
+/// username = "penger"
+/// password = "epicpass4"
+/// address = "chat.example.com:4348"
+///
+/// [[workspaces]]
+/// name = "work"
+/// username = "penger"
+/// address = "chat.work.example.com:4348"
+/// pinned_channels = ["deploys", "incidents"]
+/// layout = { padding = 0, gutter = true }
+///
+/// [layout]
+/// padding = 0
+/// header_spacing = 1
+/// indent = 4
+/// gutter = true
+/// compact = true
+///
+/// [timestamps]
+/// clock = "12h"
+/// seconds = false
+/// timezone = "local"
+/// relative = true
+///
+/// [translation]
+/// command = "trans -brief :{language}"
+///
+/// [keybindings]
+/// quit = "ctrl+q"
+/// ```
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    address: Option<String>,
+    port: Option<u16>,
+    username: Option<String>,
+    password: Option<Secret>,
+    auto_login: Option<bool>,
+    enable_tls: Option<bool>,
+    remember_login: Option<bool>,
+    loglevel: Option<String>,
+    max_upload_size: Option<usize>,
+    download_dir: Option<PathBuf>,
+    graphics: Option<GraphicsProtocol>,
+    theme: Option<String>,
+    lock_pin: Option<Secret>,
+    check_version: Option<bool>,
+    initial_history: Option<u32>,
+    history_page_size: Option<u32>,
+    #[serde(default)]
+    private_typing: Vec<String>,
+    desktop_notifications: Option<bool>,
+    correct_clock_skew: Option<bool>,
+    bell: Option<bool>,
+    title_unread: Option<bool>,
+    #[serde(default)]
+    highlight_words: Vec<String>,
+    author_avatars: Option<bool>,
+    notes_keep_days: Option<u32>,
+    audit_log: Option<bool>,
+    vim_mode: Option<bool>,
+    max_message_lines: Option<usize>,
+    #[serde(default)]
+    startup_commands: Vec<String>,
+    reconnect: Option<ReconnectMode>,
+    #[serde(default)]
+    hooks: Vec<HookConfig>,
+    #[serde(default)]
+    retention: Vec<RetentionRule>,
+    #[serde(default)]
+    accounts: Vec<SavedAccount>,
+    #[serde(default)]
+    workspaces: Vec<Workspace>,
+    #[serde(default)]
+    layout: HistoryLayout,
+    #[serde(default)]
+    timestamps: TimestampFormat,
+    translation: Option<TranslationConfig>,
+    #[serde(default)]
+    keybindings: BTreeMap<String, String>,
+}
+
+/// `[layout]` table of the config file, tunes how densely the chat history is laid out
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryLayout {
+    /// Columns between the border and the messages
+    pub padding: u16,
+    /// Blank lines above every message
+    pub header_spacing: u16,
+    /// Columns the message body and attachments are indented by
+    pub indent: u16,
+    /// Reserves a column in front of the messages for the sending and failed glyphs, instead of spelling the status out
+    pub gutter: bool,
+    /// Draws messages of the same author sent shortly after each other under a single header
+    pub compact: bool,
+}
+
+impl Default for HistoryLayout {
+    fn default() -> Self {
+        HistoryLayout {
+            padding: 1,
+            header_spacing: 0,
+            indent: 2,
+            gutter: false,
+            compact: false,
+        }
+    }
+}
+
+/// What the client does after the connection to the server was lost
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconnectMode {
+    /// Continues the session with the token of the last login, the server then only sends what was missed.
+    /// Falls back to logging in again when the server does not support it or has forgotten the session
+    Resume,
+    /// Logs in again and reloads the channels like after a fresh login
+    Fresh,
+}
+
+/// `[timestamps]` table of the config file, how the times of messages are written
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimestampFormat {
+    pub clock: Clock,
+    /// Shows the seconds in the chat history, the reply bar and the expanded message popup
+    pub seconds: bool,
+    pub timezone: Timezone,
+    /// Shows how long ago messages were sent in the chat history instead of when, toggled with `Alt + T`
+    pub relative: bool,
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        TimestampFormat {
+            clock: Clock::H24,
+            seconds: true,
+            timezone: Timezone::Utc,
+            relative: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Clock {
+    #[serde(rename = "24h")]
+    H24,
+    #[serde(rename = "12h")]
+    H12,
 }
 
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Timezone {
+    /// The timezone of this computer
+    Local,
+    Utc,
+}
+
+impl TimestampFormat {
+    /// Time of day of a message as shown in the chat history
+    pub fn time(&self, timestamp: DateTime<Utc>) -> String {
+        self.format(timestamp, "", true)
+    }
+
+    /// The `date` chrono format followed by the time of day, `seconds` leaves the seconds out even when the config shows them
+    pub fn format(&self, timestamp: DateTime<Utc>, date: &str, seconds: bool) -> String {
+        let time = match (self.clock, seconds && self.seconds) {
+            (Clock::H24, true) => "%H:%M:%S",
+            (Clock::H24, false) => "%H:%M",
+            (Clock::H12, true) => "%I:%M:%S %p",
+            (Clock::H12, false) => "%I:%M %p",
+        };
+        let pattern = format!("{date}{time}");
+        match self.timezone {
+            Timezone::Local => timestamp.with_timezone(&Local).format(&pattern).to_string(),
+            Timezone::Utc => timestamp.format(&pattern).to_string(),
+        }
+    }
+
+    /// How long ago a message was sent, like `5m ago` or `yesterday 14:03`, messages from before yesterday get their date
+    pub fn relative(&self, timestamp: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        let elapsed = now.signed_duration_since(timestamp);
+        match self.date(now).signed_duration_since(self.date(timestamp)).num_days() {
+            // Clock skew can put messages slightly in the future
+            _ if elapsed.num_minutes() < 1 => "just now".to_owned(),
+            _ if elapsed.num_hours() < 1 => format!("{}m ago", elapsed.num_minutes()),
+            0 => format!("{}h ago", elapsed.num_hours()),
+            1 => format!("yesterday {}", self.format(timestamp, "", false)),
+            _ => self.format(timestamp, "%d-%m ", false),
+        }
+    }
+
+    /// Calendar day of a timestamp in the configured timezone
+    fn date(&self, timestamp: DateTime<Utc>) -> NaiveDate {
+        match self.timezone {
+            Timezone::Local => timestamp.with_timezone(&Local).date_naive(),
+            Timezone::Utc => timestamp.date_naive(),
+        }
+    }
+}
+
+impl HistoryLayout {
+    /// Glyphs are assumed to be a single column wide, with a column to separate them from the message
+    pub fn gutter_width(&self) -> u16 {
+        if self.gutter { 2 } else { 0 }
+    }
+}
+
+/// `[[accounts]]` entry of the config file, offered by the account switcher
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SavedAccount {
+    pub username: String,
+    /// Asked for on the login screen when left out
+    pub password: Option<Secret>,
+    /// `host:port`, as typed on the login screen
+    pub address: String,
+    /// Falls back to `enable_tls` of the config file
+    pub enable_tls: Option<bool>,
+}
+
+impl SavedAccount {
+    pub fn is_same(&self, other: &SavedAccount) -> bool {
+        self.username == other.username && self.address == other.address
+    }
+}
+
+/// `[[workspaces]]` entry of the config file, an account with the channels and layout that go with it
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    pub name: String,
+    pub username: String,
+    /// Asked for on the login screen when left out
+    pub password: Option<Secret>,
+    /// `host:port`, as typed on the login screen
+    pub address: String,
+    /// Falls back to `enable_tls` of the config file
+    pub enable_tls: Option<bool>,
+    /// Names of the channels pinned to the top, replacing the pinned channels of the server when given
+    #[serde(default)]
+    pub pinned_channels: Vec<String>,
+    pub show_logs: Option<bool>,
+    /// Hides every pane but the chat history and the message input, like `Alt + Z`
+    pub zen: Option<bool>,
+    /// Replaces the `[layout]` table while the workspace is open
+    pub layout: Option<HistoryLayout>,
+}
+
+impl Workspace {
+    pub fn account(&self) -> SavedAccount {
+        SavedAccount {
+            username: self.username.clone(),
+            password: self.password.clone(),
+            address: self.address.clone(),
+            enable_tls: self.enable_tls,
+        }
+    }
+}
+
+/// Fails when a config file would be rejected at startup
+pub fn check_config(config: &toml::Table) -> Result<()> {
+    let file = ConfigFile::deserialize(config.clone())?;
+    Keybindings::parse(&file.keybindings)?;
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
 pub struct AppConfig {
+    /// Config file that was loaded, or would have been when it does not exist
+    pub config_path: PathBuf,
+    /// Started with `--safe-mode`, the config file is ignored and no state is restored or saved
+    pub safe_mode: bool,
+    /// There was no config file or it was empty, new users get the tutorial hints
+    pub first_run: bool,
+    /// Started with `--time-travel`, every handled event records a copy of the state
+    pub time_travel: bool,
     pub address: String,
     pub port: u16,
     pub username: String,
-    pub password: String,
+    pub password: Secret,
     pub auto_login: bool,
+    /// Remembers successful logins and fills the login form with the last one, when no server or username is given
+    pub remember_login: bool,
+    /// Neither the CLI nor the config file named a server or username, the built-in defaults are used
+    pub default_login: bool,
     pub loglevel: LevelFilter,
+    /// Started with `--unsafe-logs`, passwords and media data are logged without being masked
+    pub unsafe_logs: bool,
     pub enable_tls: bool,
+    /// Largest attachment to upload, in bytes
+    pub max_upload_size: usize,
+    pub download_dir: PathBuf,
+    /// How images are drawn, detected from the terminal when unset
+    pub graphics: Option<GraphicsProtocol>,
+    /// Colors and text styles the UI is drawn with, a server can override it with `/theme`
+    pub theme: Theme,
+    /// Unlocks the lock screen instead of the account password
+    pub lock_pin: Option<Secret>,
+    /// Asks the server which client versions it supports after logging in
+    pub check_version: bool,
+    /// Messages requested per channel after logging in
+    pub initial_history: i8,
+    /// Messages requested per page when more history is loaded later
+    pub history_page_size: i8,
+    /// Names of the channels no typing indicator is sent in, others are still shown typing there
+    pub private_typing: Vec<String>,
+    /// Raises desktop notifications for mentions and for messages arriving while the terminal is not focused
+    pub desktop_notifications: bool,
+    /// Shows server timestamps on the local clock, for when the local clock is off
+    pub correct_clock_skew: bool,
+    /// Rings the terminal bell when a message arrives
+    pub bell: bool,
+    /// Shows the number of messages that arrived while the terminal was not focused in its title
+    pub title_unread: bool,
+    /// Words that get messages listed in the digest, next to mentions
+    pub highlight_words: Vec<String>,
+    /// Shows a tiny version of the profile picture of authors next to their messages
+    pub author_avatars: bool,
+    /// Days after which notes to self are deleted, 0 keeps them forever
+    pub notes_keep_days: u32,
+    /// Records every sent message and whether the server confirmed it in a file of its own
+    pub audit_log: bool,
+    /// Moves through the chat with vim keys, typing starts after `i`
+    pub vim_mode: bool,
+    /// Lines of a message shown in the history before it is cut off, 0 shows every message in full
+    pub max_message_lines: usize,
+    /// Slash commands run after logging in, once the channels are loaded
+    pub startup_commands: Vec<String>,
+    pub reconnect: ReconnectMode,
+    /// Commands run when messages arrive or the connection is lost, none run in safe mode as the config file is ignored
+    pub hooks: Vec<HookConfig>,
+    /// Limits on the cached history, per server and channel
+    pub retention: Vec<RetentionRule>,
+    /// Accounts listed by the account switcher
+    pub accounts: Vec<SavedAccount>,
+    /// Arrangements switched between with `/workspace`
+    pub workspaces: Vec<Workspace>,
+    pub layout: HistoryLayout,
+    pub timestamps: TimestampFormat,
+    /// Translates selected messages on demand, disabled when unset
+    pub translation: Option<TranslationConfig>,
+    /// Keys of the config file that trigger an action, on top of the default keys
+    pub keybindings: Keybindings,
+}
+
+impl AppConfig {
+    /// Merges the CLI arguments with the config file, the CLI takes precedence.
+    /// A missing config file is not an error, a malformed one is, since the logger is not running yet to report it.
+    pub fn load(args: CliArgs) -> Result<Self> {
+        let path = match args.config {
+            Some(path) => path,
+            None => config_file(CONFIG_FILE)?,
+        };
+        let (file, first_run): (ConfigFile, bool) = match fs::read_to_string(&path) {
+            _ if args.safe_mode => (ConfigFile::default(), false),
+            Ok(contents) => (
+                toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?,
+                contents.trim().is_empty(),
+            ),
+            Err(e) if e.kind() == ErrorKind::NotFound => (ConfigFile::default(), true),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        let loglevel = match (args.loglevel, file.loglevel) {
+            (Some(loglevel), _) => loglevel,
+            (None, Some(loglevel)) => loglevel
+                .parse()
+                .with_context(|| format!("Invalid loglevel \"{loglevel}\" in {}", path.display()))?,
+            (None, None) => LevelFilter::Info,
+        };
+
+        let keybindings = Keybindings::parse(&file.keybindings).with_context(|| format!("Invalid keybindings in {}", path.display()))?;
+        let default_login = args.address.is_none() && file.address.is_none() && args.username.is_none() && file.username.is_none();
+
+        Ok(AppConfig {
+            config_path: path,
+            safe_mode: args.safe_mode,
+            first_run,
+            time_travel: args.time_travel,
+            address: args.address.or(file.address).unwrap_or_else(|| DEFAULT_ADDRESS.to_owned()),
+            port: args.port.or(file.port).unwrap_or(DEFAULT_PORT),
+            username: args.username.or(file.username).unwrap_or_else(|| DEFAULT_USERNAME.to_owned()),
+            password: args
+                .password
+                .map(Secret::new)
+                .or(file.password)
+                .unwrap_or_else(|| Secret::new(DEFAULT_PASSWORD.to_owned())),
+            auto_login: args.auto_login || file.auto_login.unwrap_or(false),
+            remember_login: file.remember_login.unwrap_or(true),
+            default_login,
+            loglevel,
+            unsafe_logs: args.unsafe_logs,
+            enable_tls: args.enable_tls || file.enable_tls.unwrap_or(false),
+            max_upload_size: args.max_upload_size.or(file.max_upload_size).unwrap_or(DEFAULT_MAX_UPLOAD_SIZE_MB) * 1024 * 1024,
+            download_dir: args
+                .download_dir
+                .or(file.download_dir)
+                .or_else(dirs::download_dir)
+                .unwrap_or_else(|| PathBuf::from(".")),
+            graphics: file.graphics,
+            theme: Theme::load(args.theme.or(file.theme).as_deref().unwrap_or(DEFAULT_THEME))?,
+            lock_pin: file.lock_pin,
+            check_version: file.check_version.unwrap_or(false),
+            initial_history: history_length(file.initial_history.unwrap_or(DEFAULT_INITIAL_HISTORY)),
+            history_page_size: history_length(file.history_page_size.unwrap_or(DEFAULT_HISTORY_PAGE_SIZE)),
+            private_typing: file.private_typing,
+            desktop_notifications: file.desktop_notifications.unwrap_or(true),
+            correct_clock_skew: file.correct_clock_skew.unwrap_or(false),
+            bell: file.bell.unwrap_or(false),
+            title_unread: file.title_unread.unwrap_or(true),
+            highlight_words: file.highlight_words,
+            author_avatars: file.author_avatars.unwrap_or(false),
+            notes_keep_days: file.notes_keep_days.unwrap_or(DEFAULT_NOTES_KEEP_DAYS),
+            audit_log: file.audit_log.unwrap_or(false),
+            vim_mode: file.vim_mode.unwrap_or(false),
+            max_message_lines: file.max_message_lines.unwrap_or(DEFAULT_MAX_MESSAGE_LINES),
+            startup_commands: file.startup_commands,
+            reconnect: file.reconnect.unwrap_or(ReconnectMode::Resume),
+            hooks: file.hooks,
+            retention: file.retention,
+            accounts: file.accounts,
+            workspaces: file.workspaces,
+            layout: file.layout,
+            timestamps: file.timestamps,
+            translation: file.translation,
+            keybindings,
+        })
+    }
+}
+
+impl AppConfig {
+    /// Password of the `[[accounts]]` entry for this user on this server, when it has one
+    pub fn account_password(&self, address: &str, username: &str) -> Option<Secret> {
+        self.accounts
+            .iter()
+            .find(|account| account.address == address && account.username == username)
+            .and_then(|account| account.password.clone())
+    }
+}
+
+/// History requests carry the number of messages as a signed byte, so at most 127 messages fit in a page
+fn history_length(length: u32) -> i8 {
+    length.clamp(1, i8::MAX as u32) as i8
 }
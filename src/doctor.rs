@@ -0,0 +1,270 @@
+use std::env;
+use std::fs;
+use std::io::{IsTerminal, stdout};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use ratatui::crossterm::terminal;
+use tokio::net::lookup_host;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+use crate::cli::{AppConfig, CliArgs, DEFAULT_PORT};
+use crate::media::preview::GraphicsProtocol;
+use crate::network::client::{ConnectionType, ServerAddrInfo};
+use crate::network::probe::{ProbeOutcome, probe_connection};
+use crate::storage::secrets::keyring_available;
+use crate::storage::state_file;
+use crate::tui::events::TuiEvent;
+
+/// Smallest terminal the chat screen is laid out for without panes getting cramped
+const MIN_COLUMNS: u16 = 80;
+const MIN_ROWS: u16 = 24;
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+    /// Not needed, or not reached because an earlier check failed
+    Skip,
+}
+
+/// Prints the outcome of one check, with what to do about it when it did not pass
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn section(&self, name: &str) {
+        println!("\n{name}");
+    }
+
+    fn check(&mut self, status: Status, detail: impl AsRef<str>, hint: Option<&str>) {
+        let label = match status {
+            Status::Ok => "ok  ",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+            Status::Skip => "skip",
+        };
+        println!("  {label}  {}", detail.as_ref());
+        if let Some(hint) = hint.filter(|_| matches!(status, Status::Warn | Status::Fail)) {
+            println!("        → {hint}");
+        }
+        if status == Status::Fail {
+            self.failures += 1;
+        }
+    }
+}
+
+/// Checks whether the client can run here and reach its servers, for `chatger doctor`.
+/// Runs before the config is loaded the usual way, so a broken config file is reported instead of ending the program
+pub async fn run(args: CliArgs) -> Result<()> {
+    let mut report = Report { failures: 0 };
+    println!("chatger {} doctor", env!("CARGO_PKG_VERSION"));
+
+    check_terminal(&mut report);
+
+    report.section("Config");
+    let config = match AppConfig::load(args) {
+        Ok(config) => {
+            let detail = if config.safe_mode {
+                "ignored in safe mode, the defaults are used".to_owned()
+            } else if config.first_run {
+                format!("{} does not exist or is empty, the defaults are used", config.config_path.display())
+            } else {
+                format!("{} parsed", config.config_path.display())
+            };
+            report.check(Status::Ok, detail, None);
+            Some(config)
+        }
+        Err(e) => {
+            report.check(
+                Status::Fail,
+                format!("{e:#}"),
+                Some("Fix the config file, or start with --safe-mode to ignore it for now"),
+            );
+            None
+        }
+    };
+
+    report.section("Keyring");
+    match keyring_available() {
+        Ok(()) => report.check(Status::Ok, "the OS keyring can be reached", None),
+        Err(e) if cfg!(feature = "keyring") => report.check(
+            Status::Fail,
+            format!("the OS keyring can not be reached: {e}"),
+            Some("Start or unlock the keyring daemon, like gnome-keyring or KWallet, login codes are not remembered without it"),
+        ),
+        Err(_) => report.check(
+            Status::Warn,
+            "this build has no keyring support",
+            Some("Rebuild with `--features keyring` to have login codes filled in"),
+        ),
+    }
+
+    report.section("Storage");
+    match state_file("") {
+        Ok(dir) => check_writable(&mut report, "state and cache", &dir),
+        Err(e) => report.check(Status::Fail, e.to_string(), Some("Set HOME or XDG_STATE_HOME")),
+    }
+    if let Some(config) = &config {
+        check_writable(&mut report, "downloads", &config.download_dir);
+    }
+
+    if let Some(config) = &config {
+        check_servers(&mut report, config).await;
+    }
+
+    println!();
+    match report.failures {
+        0 => {
+            println!("No problems found");
+            Ok(())
+        }
+        1 => Err(anyhow!("1 check failed, see above")),
+        failures => Err(anyhow!("{failures} checks failed, see above")),
+    }
+}
+
+fn check_terminal(report: &mut Report) {
+    report.section("Terminal");
+    if stdout().is_terminal() {
+        report.check(Status::Ok, "output goes to a terminal", None);
+    } else {
+        report.check(
+            Status::Warn,
+            "output does not go to a terminal",
+            Some("Run chatger in a terminal, not through a pipe or redirect"),
+        );
+    }
+
+    match env::var("TERM") {
+        Ok(term) if term != "dumb" => report.check(Status::Ok, format!("TERM is {term}"), None),
+        _ => report.check(
+            Status::Fail,
+            "TERM is not set or dumb, the screen can not be drawn",
+            Some("Set TERM to what your terminal emulates, usually xterm-256color"),
+        ),
+    }
+
+    match env::var("COLORTERM") {
+        Ok(colorterm) if matches!(colorterm.as_str(), "truecolor" | "24bit") => report.check(Status::Ok, "24 bit colors", None),
+        _ => report.check(
+            Status::Warn,
+            "the terminal does not advertise 24 bit colors, theme colors may be approximated",
+            Some("Set COLORTERM=truecolor if your terminal supports it"),
+        ),
+    }
+
+    match terminal::size() {
+        Ok((columns, rows)) if columns >= MIN_COLUMNS && rows >= MIN_ROWS => report.check(Status::Ok, format!("{columns}x{rows} cells"), None),
+        Ok((columns, rows)) => report.check(
+            Status::Warn,
+            format!("{columns}x{rows} cells is smaller than {MIN_COLUMNS}x{MIN_ROWS}"),
+            Some("Enlarge the window, or hide panes with Alt + 1, 2 and 3 once running"),
+        ),
+        Err(e) => report.check(Status::Warn, format!("the size of the terminal is unknown: {e}"), None),
+    }
+
+    let graphics = GraphicsProtocol::detect();
+    let hint = "Set `graphics` in the config file when your terminal supports kitty, iterm2 or sixel images";
+    match graphics {
+        GraphicsProtocol::HalfBlocks => report.check(Status::Warn, "images are drawn with half blocks", Some(hint)),
+        protocol => report.check(Status::Ok, format!("images are drawn with {protocol:?}"), None),
+    }
+}
+
+/// Creates the directory when needed and writes and removes a file in it
+fn check_writable(report: &mut Report, name: &str, dir: &Path) {
+    let probe = dir.join(".chatger-doctor");
+    let result = fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&probe, b""))
+        .and_then(|_| fs::remove_file(&probe));
+    match result {
+        Ok(()) => report.check(Status::Ok, format!("{name} directory {} is writable", dir.display()), None),
+        Err(e) => report.check(
+            Status::Fail,
+            format!("{name} directory {} is not writable: {e}", dir.display()),
+            Some("Fix the owner or permissions of the directory"),
+        ),
+    }
+}
+
+/// Resolves every configured server and runs the connection test on it, which checks the certificate against the trust store when TLS is on
+async fn check_servers(report: &mut Report, config: &AppConfig) {
+    let mut servers = vec![("config".to_owned(), config.address.clone(), config.port, config.enable_tls)];
+    let accounts = config
+        .accounts
+        .iter()
+        .map(|account| (format!("account {}", account.username), &account.address, account.enable_tls));
+    let workspaces = config
+        .workspaces
+        .iter()
+        .map(|workspace| (format!("workspace {}", workspace.name), &workspace.address, workspace.enable_tls));
+    for (label, address, enable_tls) in accounts.chain(workspaces) {
+        let (host, port) = match address.split_once(':') {
+            Some((host, port)) => (host, port.parse().unwrap_or(DEFAULT_PORT)),
+            None => (address.as_str(), DEFAULT_PORT),
+        };
+        if !servers
+            .iter()
+            .any(|(_, known_host, known_port, _)| known_host == host && *known_port == port)
+        {
+            servers.push((label, host.to_owned(), port, enable_tls.unwrap_or(config.enable_tls)));
+        }
+    }
+
+    report.section("TLS");
+    report.check(
+        Status::Ok,
+        format!("{} trusted root certificates are built in", webpki_roots::TLS_SERVER_ROOTS.len()),
+        None,
+    );
+
+    for (label, host, port, enable_tls) in servers {
+        report.section(&format!("Server {host}:{port} ({label})"));
+        let addr = match timeout(LOOKUP_TIMEOUT, lookup_host((host.as_str(), port))).await {
+            Ok(Ok(mut addrs)) => addrs.next().ok_or_else(|| anyhow!("no addresses found")),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(anyhow!("no answer within {} seconds", LOOKUP_TIMEOUT.as_secs())),
+        };
+        let addr = match addr {
+            Ok(addr) => addr,
+            Err(e) => {
+                report.check(
+                    Status::Fail,
+                    format!("{host} does not resolve: {e}"),
+                    Some("Check the address for typos and your DNS settings"),
+                );
+                continue;
+            }
+        };
+        report.check(Status::Ok, format!("{host} resolves to {}", addr.ip()), None);
+
+        let domain = host.parse::<IpAddr>().is_err().then(|| host.clone());
+        let server = ServerAddrInfo {
+            ip: addr.ip(),
+            port: addr.port(),
+            domain,
+            connection_type: if enable_tls { ConnectionType::TLS } else { ConnectionType::Raw },
+        };
+        let (event_send, mut event_recv) = mpsc::channel(8);
+        tokio::spawn(probe_connection(server, event_send));
+        while let Some(event) = event_recv.recv().await {
+            let TuiEvent::ConnectionProbeUpdate(stage, outcome) = event else {
+                continue;
+            };
+            let hint = "Check the port and that the server is running, `enable_tls` has to match the server";
+            match outcome {
+                ProbeOutcome::Running => {}
+                ProbeOutcome::Passed(detail) => report.check(Status::Ok, format!("{}: {detail}", stage.label()), None),
+                ProbeOutcome::Skipped(reason) => report.check(Status::Skip, format!("{}: skipped, {reason}", stage.label()), None),
+                ProbeOutcome::Failed(reason) => report.check(Status::Fail, format!("{}: {reason}", stage.label()), Some(hint)),
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, stdin};
+use tokio::sync::mpsc::{self, Receiver};
+use tokio::time::timeout;
+
+use crate::cli::AppConfig;
+use crate::network::client::{Client, ServerAddrInfo};
+use crate::network::protocol::server::{HealthKind, HistoryMessage, ReturnStatus, ServerPayload};
+use crate::repl::resolve;
+use crate::storage::secrets::{totp_code, totp_secret};
+use crate::tui::events::{ChannelId, MediaId, MessageId, TuiEvent, UserId};
+
+/// How long the server gets to answer a request before the command gives up
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Most messages one history request can ask for, the length is a signed byte
+const HISTORY_PAGE_SIZE: usize = i8::MAX as usize;
+
+/// A logged in connection for subcommands that do one thing and exit, without the TUI
+struct Session {
+    client: Client,
+    payloads: Receiver<ServerPayload>,
+    events: Receiver<TuiEvent>,
+    server: ServerAddrInfo,
+}
+
+impl Session {
+    /// Connects to the configured server and logs in with the configured account.
+    /// Login codes are filled in from the keyring, there is no one to type them
+    async fn login(config: &AppConfig) -> Result<Session> {
+        let server = resolve(config).await?;
+        let (event_send, events) = mpsc::channel(10);
+        let (payload_send, payloads) = mpsc::channel(10);
+        let mut client = Client::new(event_send).with_raw_payloads(payload_send);
+        client.connect(&server).await?;
+        let mut session = Session {
+            client,
+            payloads,
+            events,
+            server,
+        };
+
+        session.client.login(config.username.clone(), config.password.clone()).await?;
+        let mut code_sent = false;
+        loop {
+            let packet = session
+                .expect("the login", |payload| match payload {
+                    ServerPayload::Login(packet) => Some(packet),
+                    _ => None,
+                })
+                .await?;
+            match packet.status {
+                ReturnStatus::Success => return Ok(session),
+                _ if packet.code_required && !code_sent => {
+                    let secret = totp_secret(&session.server.to_string(), &config.username)
+                        .ok_or_else(|| anyhow!("The server asks for a login code, log in with the TUI once to remember its secret in the keyring"))?;
+                    session.client.send_login_code(totp_code(&secret)?).await?;
+                    code_sent = true;
+                }
+                _ => {
+                    let reason = packet.error_message.unwrap_or_else(|| "no reason given".to_owned());
+                    return Err(anyhow!("Failed to log in as {}: {reason}", config.username));
+                }
+            }
+        }
+    }
+
+    /// Waits for the first payload `matches` accepts, answering health checks and skipping everything else that arrives meanwhile
+    async fn expect<T>(&mut self, what: &str, mut matches: impl FnMut(ServerPayload) -> Option<T>) -> Result<T> {
+        loop {
+            let payload = tokio::select! {
+                payload = timeout(RESPONSE_TIMEOUT, self.payloads.recv()) => match payload {
+                    Ok(Some(payload)) => payload,
+                    Ok(None) => return Err(anyhow!("The connection to {} was closed while waiting for {what}", self.server)),
+                    Err(_) => return Err(anyhow!("{} did not answer {what} within {} seconds", self.server, RESPONSE_TIMEOUT.as_secs())),
+                },
+                Some(TuiEvent::Disconnected) = self.events.recv() => {
+                    return Err(anyhow!("Disconnected from {} while waiting for {what}", self.server));
+                }
+            };
+            match payload {
+                ServerPayload::Health(packet) if packet.kind == HealthKind::Ping => self.client.send_healthcheck().await?,
+                ServerPayload::Goodbye(packet) => return Err(anyhow!("{} closed the connection: {}", self.server, packet.message)),
+                payload => {
+                    if let Some(value) = matches(payload) {
+                        return Ok(value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Looks a channel up by name, with or without the leading `#`
+    async fn find_channel(&mut self, name: &str) -> Result<ChannelId> {
+        let name = name.trim_start_matches('#');
+        self.client.request_channel_ids().await?;
+        let channel_ids = self
+            .expect("the channel list", |payload| match payload {
+                ServerPayload::ChannelsList(packet) => Some(match packet.status {
+                    ReturnStatus::Success => Ok(packet.channel_ids),
+                    _ => Err(anyhow!("Failed to list the channels: {}", packet.error_message.unwrap_or_default())),
+                }),
+                _ => None,
+            })
+            .await??;
+        self.client.request_channels(channel_ids).await?;
+        let channels = self
+            .expect("the channels", |payload| match payload {
+                ServerPayload::Channels(packet) => Some(match packet.status {
+                    ReturnStatus::Success => Ok(packet.channels),
+                    _ => Err(anyhow!("Failed to get the channels: {}", packet.error_message.unwrap_or_default())),
+                }),
+                _ => None,
+            })
+            .await??;
+
+        match channels.iter().find(|channel| channel.name == name) {
+            Some(channel) => Ok(channel.channel_id),
+            None => {
+                let names: Vec<String> = channels.iter().map(|channel| format!("#{}", channel.name)).collect();
+                Err(anyhow!("There is no channel #{name}, the channels are {}", names.join(", ")))
+            }
+        }
+    }
+}
+
+/// Sends one message to a channel and waits until the server confirmed it, for `chatger send`.
+/// The message is read from stdin when it is not given as arguments
+pub async fn send(config: AppConfig, channel: &str, words: Vec<String>) -> Result<()> {
+    let text = if words.is_empty() {
+        let mut text = String::new();
+        stdin().read_to_string(&mut text).await?;
+        text.trim_end().to_owned()
+    } else {
+        words.join(" ")
+    };
+    if text.trim().is_empty() {
+        return Err(anyhow!("The message is empty"));
+    }
+
+    let mut session = Session::login(&config).await?;
+    let channel_id = session.find_channel(channel).await?;
+    session.client.send_chat_message(channel_id, 0, text, vec![]).await?;
+    let message_id = session
+        .expect("the message", |payload| match payload {
+            ServerPayload::SendMessageAck(packet) => Some(match packet.status {
+                ReturnStatus::Success => Ok(packet.message_id),
+                _ => Err(anyhow!(
+                    "The server did not accept the message: {}",
+                    packet.error_message.unwrap_or_default()
+                )),
+            }),
+            _ => None,
+        })
+        .await??;
+    println!("Sent message {message_id} to #{}", channel.trim_start_matches('#'));
+    session.client.disconnect()
+}
+
+/// A message as printed by `chatger history --json`
+#[derive(Serialize)]
+struct PrintedMessage {
+    message_id: MessageId,
+    /// Unix seconds
+    timestamp: u64,
+    author_id: UserId,
+    author: String,
+    reply_id: MessageId,
+    text: String,
+    media_ids: Vec<MediaId>,
+}
+
+/// Prints the last messages of a channel, oldest first, for `chatger history`.
+/// Pages of older history are requested until enough messages are in or the channel has no more
+pub async fn history(config: AppConfig, channel: &str, count: usize, json: bool) -> Result<()> {
+    let mut session = Session::login(&config).await?;
+    let channel_id = session.find_channel(channel).await?;
+
+    let mut messages: BTreeMap<MessageId, HistoryMessage> = BTreeMap::new();
+    while messages.len() < count {
+        let page_size = (count - messages.len()).min(HISTORY_PAGE_SIZE) as i8;
+        match messages.keys().next() {
+            Some(&oldest) => session.client.request_history_by_message(channel_id, oldest, page_size).await?,
+            None => session.client.request_history_by_timestamp(channel_id, Utc::now(), page_size).await?,
+        }
+        let page = session
+            .expect("the history", |payload| match payload {
+                ServerPayload::History(packet) if packet.status == ReturnStatus::Failed => {
+                    Some(Err(anyhow!("Failed to get the history: {}", packet.error_message.unwrap_or_default())))
+                }
+                // Messages arriving live are notifications, not the answer
+                ServerPayload::History(packet) if packet.status == ReturnStatus::Success => Some(Ok(packet.messages)),
+                _ => None,
+            })
+            .await??;
+        let loaded = messages.len();
+        let page_len = page.len();
+        messages.extend(
+            page.into_iter()
+                .filter(|message| message.channel_id == channel_id)
+                .map(|message| (message.message_id, message)),
+        );
+        if page_len < page_size as usize || messages.len() == loaded {
+            break;
+        }
+    }
+
+    let mut user_ids: Vec<UserId> = messages.values().map(|message| message.user_id).collect();
+    user_ids.sort_unstable();
+    user_ids.dedup();
+    let mut names = HashMap::new();
+    for user_ids in user_ids.chunks(u8::MAX as usize) {
+        session.client.request_users(user_ids.to_vec()).await?;
+        let users = session
+            .expect("the users", |payload| match payload {
+                ServerPayload::Users(packet) => Some(packet.users),
+                _ => None,
+            })
+            .await?;
+        names.extend(users.into_iter().map(|user| (user.user_id, user.username)));
+    }
+    session.client.disconnect()?;
+
+    let printed: Vec<PrintedMessage> = messages
+        .into_values()
+        .map(|message| PrintedMessage {
+            message_id: message.message_id,
+            timestamp: message.sent_timestamp,
+            author_id: message.user_id,
+            author: names.get(&message.user_id).cloned().unwrap_or_else(|| "Unknown".to_owned()),
+            reply_id: message.reply_id,
+            text: message.message_text,
+            media_ids: message.media_ids,
+        })
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&printed)?);
+        return Ok(());
+    }
+    for message in printed {
+        let timestamp = DateTime::<Utc>::from_timestamp(message.timestamp as i64, 0).unwrap_or_default();
+        let attachments = match message.media_ids.len() {
+            0 => String::new(),
+            1 => " [1 attachment]".to_owned(),
+            count => format!(" [{count} attachments]"),
+        };
+        println!(
+            "[{}] {}: {}{attachments}",
+            config.timestamps.format(timestamp, "%Y-%m-%d ", true),
+            message.author,
+            message.text
+        );
+    }
+    Ok(())
+}
@@ -1,25 +1,37 @@
 mod cli;
+mod doctor;
+mod headless;
+mod media;
 mod network;
+mod repl;
+mod storage;
 mod tui;
 use anyhow::Result;
 use clap::Parser;
 
-use crate::cli::{AppConfig, CliArgs};
+use crate::cli::{AppConfig, CliArgs, CliCommand};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = CliArgs::parse();
+    let mut args = CliArgs::parse();
+    let command = args.command.take();
+    // Loads the config itself, to report a broken one instead of failing on it
+    if command == Some(CliCommand::Doctor) {
+        return doctor::run(args).await;
+    }
 
-    // TODO merge cli args and app config
-    let config = AppConfig {
-        address: args.address,
-        port: args.port,
-        username: args.username,
-        password: args.password,
-        loglevel: args.loglevel,
-        auto_login: args.auto_login,
-        enable_tls: args.enable_tls,
-    };
+    let config = AppConfig::load(args)?;
+    if config.safe_mode {
+        storage::disable_persistence();
+    }
 
-    tui::run(config).await
+    match command {
+        Some(CliCommand::Repl) => repl::run(config).await,
+        Some(CliCommand::Export { file }) => storage::profile::export(&config.config_path, &file),
+        Some(CliCommand::Import { file }) => storage::profile::import(&config.config_path, &file),
+        Some(CliCommand::Send { channel, message }) => headless::send(config, &channel, message).await,
+        Some(CliCommand::History { channel, count, json }) => headless::history(config, &channel, count, json).await,
+        Some(CliCommand::Doctor) => unreachable!("doctor runs before the config is loaded"),
+        None => tui::run(config).await,
+    }
 }
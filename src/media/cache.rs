@@ -0,0 +1,62 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use log::debug;
+
+use crate::tui::chat::MediaMessage;
+use crate::tui::events::MediaId;
+
+/// Bytes of fetched media kept in memory, the least recently used media is dropped once there is more
+pub const MEDIA_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Fetched media, bounded by the size of its data.
+///
+/// Previews and profile pictures are kept apart, so dropped media is remembered as fetched and is not requested
+/// again just for being on screen. It is requested again when its data is needed, like for a download.
+#[derive(Debug, Clone, Default)]
+pub struct MediaCache {
+    media: HashMap<MediaId, MediaMessage>,
+    /// Least recently used first
+    order: VecDeque<MediaId>,
+    size: usize,
+    dropped: HashSet<MediaId>,
+}
+
+impl MediaCache {
+    pub fn get(&mut self, media_id: MediaId) -> Option<&MediaMessage> {
+        if self.media.contains_key(&media_id) {
+            self.touch(media_id);
+        }
+        self.media.get(&media_id)
+    }
+
+    /// Whether the media was fetched before, even when it has been dropped since
+    pub fn is_fetched(&self, media_id: MediaId) -> bool {
+        self.media.contains_key(&media_id) || self.dropped.contains(&media_id)
+    }
+
+    pub fn insert(&mut self, media_id: MediaId, media: MediaMessage) {
+        self.size += media.media_data.len();
+        if let Some(previous) = self.media.insert(media_id, media) {
+            self.size -= previous.media_data.len();
+        }
+        self.dropped.remove(&media_id);
+        self.touch(media_id);
+
+        // The newest media is kept even when it is larger than the cache on its own
+        while self.size > MEDIA_CACHE_SIZE && self.order.len() > 1 {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(media) = self.media.remove(&oldest) {
+                self.size -= media.media_data.len();
+                debug!("Dropped media {oldest} from the cache");
+            }
+            self.dropped.insert(oldest);
+        }
+    }
+
+    fn touch(&mut self, media_id: MediaId) {
+        self.order.retain(|other| *other != media_id);
+        self.order.push_back(media_id);
+    }
+}
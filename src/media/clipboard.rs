@@ -0,0 +1,58 @@
+use std::io::Cursor;
+
+use anyhow::{Result, anyhow};
+use chrono::Local;
+use image::{ImageFormat, RgbaImage};
+
+use crate::network::protocol::MediaType;
+use crate::tui::chat::PendingAttachment;
+
+/// Image found on the clipboard, encoded as PNG since that is the only image format the protocol accepts
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipboardImage {
+    pub attachment: PendingAttachment,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Reads the image on the clipboard, `None` when the clipboard holds something else
+pub fn paste_image() -> Result<Option<ClipboardImage>> {
+    let Some((width, height, rgba)) = backend::read_image()? else {
+        return Ok(None);
+    };
+    let image = RgbaImage::from_raw(width, height, rgba).ok_or_else(|| anyhow!("The clipboard image is malformed"))?;
+    let mut encoded = Cursor::new(vec![]);
+    image.write_to(&mut encoded, ImageFormat::Png)?;
+    Ok(Some(ClipboardImage {
+        attachment: PendingAttachment {
+            filename: format!("clipboard-{}.png", Local::now().format("%Y%m%d-%H%M%S")),
+            data: encoded.into_inner(),
+            media_type: Some(MediaType::Image),
+        },
+        width,
+        height,
+    }))
+}
+
+#[cfg(feature = "clipboard")]
+mod backend {
+    use anyhow::Result;
+    use arboard::{Clipboard, Error};
+
+    pub fn read_image() -> Result<Option<(u32, u32, Vec<u8>)>> {
+        match Clipboard::new()?.get_image() {
+            Ok(image) => Ok(Some((image.width as u32, image.height as u32, image.bytes.into_owned()))),
+            Err(Error::ContentNotAvailable) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(not(feature = "clipboard"))]
+mod backend {
+    use anyhow::{Result, anyhow};
+
+    pub fn read_image() -> Result<Option<(u32, u32, Vec<u8>)>> {
+        Err(anyhow!("This build can not read the clipboard, it needs the clipboard feature"))
+    }
+}
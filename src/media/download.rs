@@ -0,0 +1,58 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::info;
+
+const FALLBACK_FILENAME: &str = "media";
+const MAX_FILENAME_LENGTH: usize = 200;
+
+/// Writes downloaded media into `dir` without overwriting existing files, returns the path it was saved to
+pub fn save_media(dir: &Path, filename: &str, data: &[u8]) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("Unable to create {}", dir.display()))?;
+    let filename = sanitize_filename(filename);
+    let (stem, extension) = match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (filename.as_str(), None),
+    };
+
+    let mut attempt = 0;
+    loop {
+        let candidate = match (attempt, extension) {
+            (0, _) => filename.clone(),
+            (_, Some(extension)) => format!("{stem} ({attempt}).{extension}"),
+            (_, None) => format!("{stem} ({attempt})"),
+        };
+        let path = dir.join(candidate);
+        // create_new fails instead of truncating when another file already has this name
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(data).with_context(|| format!("Unable to write {}", path.display()))?;
+                info!("Saved media to {}", path.display());
+                return Ok(path);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e).with_context(|| format!("Unable to create {}", path.display())),
+        }
+    }
+}
+
+/// Filenames come from other users, so anything that could escape the download directory or is invalid on some platform is replaced
+pub fn sanitize_filename(filename: &str) -> String {
+    let sanitized: String = filename
+        .chars()
+        .map(|chr| match chr {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            chr if chr.is_control() => '_',
+            chr => chr,
+        })
+        .take(MAX_FILENAME_LENGTH)
+        .collect();
+    let sanitized = sanitized.trim().trim_start_matches('.').trim_end_matches(['.', ' ']);
+    if sanitized.is_empty() {
+        FALLBACK_FILENAME.to_owned()
+    } else {
+        sanitized.to_owned()
+    }
+}
@@ -0,0 +1,144 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use log::{debug, warn};
+
+use crate::tui::events::{ChannelId, MediaId, MessageId, UserId};
+
+/// Requests that may be waiting on the server at the same time
+pub const MAX_CONCURRENT_FETCHES: usize = 4;
+/// Rough number of messages that fit in the chat history, the viewport height is only known while rendering
+pub const VISIBLE_MESSAGES: usize = 20;
+/// Messages this far outside the viewport are fetched ahead of scrolling, anything further away is cancelled
+pub const PREFETCH_DISTANCE: usize = 30;
+const MAX_FETCH_RETRIES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FetchPriority {
+    Prefetch,
+    Visible,
+}
+
+impl FetchPriority {
+    /// Priority of media attached to a message `distance` messages outside the viewport, `None` when it should not be fetched
+    pub fn for_distance(distance: usize) -> Option<Self> {
+        match distance {
+            0 => Some(FetchPriority::Visible),
+            distance if distance <= PREFETCH_DISTANCE => Some(FetchPriority::Prefetch),
+            _ => None,
+        }
+    }
+}
+
+/// What the wanted media was last worked out from, scanning the history again is only needed when this changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchView {
+    pub channel_id: ChannelId,
+    pub history_length: usize,
+    pub newest_message_id: Option<MessageId>,
+    pub scroll_offset: usize,
+    pub profile: Option<UserId>,
+    pub downloads: usize,
+    pub author_avatars: bool,
+}
+
+/// Schedules media downloads so the attachments on screen are fetched first without flooding the connection.
+///
+/// The server answers media requests in order and media packets do not carry their id,
+/// so requests in flight are matched to responses first in, first out and can not be cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct MediaFetcher {
+    /// Waiting requests, most important first
+    queue: Vec<(MediaId, FetchPriority, usize)>,
+    in_flight: VecDeque<MediaId>,
+    retries: HashMap<MediaId, u32>,
+    /// Media the server refused or that kept arriving corrupted, these are not requested again
+    failed: HashSet<MediaId>,
+    view: Option<FetchView>,
+}
+
+impl MediaFetcher {
+    /// Whether the view moved since the queue was last scheduled, remembers the new one
+    pub fn view_changed(&mut self, view: FetchView) -> bool {
+        if self.view.as_ref() == Some(&view) {
+            return false;
+        }
+        self.view = Some(view);
+        true
+    }
+
+    /// Schedules the queue again on the next tick, for changes the view does not show like a new profile picture
+    pub fn invalidate(&mut self) {
+        self.view = None;
+    }
+
+    /// Replaces the queue with the media that is currently wanted, given as `(media_id, distance)` from the viewport.
+    /// Queued media that is no longer wanted, because it scrolled far off-screen, is dropped.
+    pub fn schedule(&mut self, wanted: impl IntoIterator<Item = (MediaId, usize)>) {
+        let mut seen = HashSet::new();
+        let mut queue: Vec<_> = wanted
+            .into_iter()
+            .filter(|(media_id, _)| !self.failed.contains(media_id) && !self.in_flight.contains(media_id) && seen.insert(*media_id))
+            .filter_map(|(media_id, distance)| FetchPriority::for_distance(distance).map(|priority| (media_id, priority, distance)))
+            .collect();
+        queue.sort_by_key(|(_, priority, distance)| (Reverse(*priority), *distance));
+
+        let cancelled = self
+            .queue
+            .iter()
+            .filter(|(media_id, ..)| !queue.iter().any(|(queued_id, ..)| queued_id == media_id))
+            .count();
+        if cancelled > 0 {
+            debug!("Cancelled {cancelled} media fetches that scrolled out of view");
+        }
+        self.queue = queue;
+    }
+
+    /// Takes the most important queued media while there is room for more requests, the caller has to send these
+    pub fn next_requests(&mut self) -> Vec<MediaId> {
+        let available = MAX_CONCURRENT_FETCHES.saturating_sub(self.in_flight.len()).min(self.queue.len());
+        let requests: Vec<MediaId> = self.queue.drain(..available).map(|(media_id, ..)| media_id).collect();
+        self.in_flight.extend(&requests);
+        requests
+    }
+
+    /// Matches a response to the oldest request in flight
+    pub fn complete(&mut self) -> Option<MediaId> {
+        self.in_flight.pop_front()
+    }
+
+    /// Queues corrupted media again with the highest priority, returns false once it ran out of retries
+    pub fn retry(&mut self, media_id: MediaId) -> bool {
+        let retries = self.retries.entry(media_id).or_default();
+        if *retries >= MAX_FETCH_RETRIES {
+            self.retries.remove(&media_id);
+            self.fail(media_id);
+            return false;
+        }
+        *retries += 1;
+        warn!("Requesting media {media_id} again ({retries}/{MAX_FETCH_RETRIES})");
+        self.queue.insert(0, (media_id, FetchPriority::Visible, 0));
+        true
+    }
+
+    pub fn succeed(&mut self, media_id: MediaId) {
+        self.retries.remove(&media_id);
+    }
+
+    /// Gives media that failed before another chance, used when the user explicitly asks for it
+    pub fn forget_failure(&mut self, media_id: MediaId) {
+        self.failed.remove(&media_id);
+        self.retries.remove(&media_id);
+    }
+
+    pub fn fail(&mut self, media_id: MediaId) {
+        self.failed.insert(media_id);
+    }
+
+    /// Requests in flight are lost when the connection drops
+    pub fn reset(&mut self) {
+        self.queue.clear();
+        self.in_flight.clear();
+        self.view = None;
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::Cursor;
+
+use anyhow::{Result, anyhow};
+use image::ImageFormat;
+use image::imageops::FilterType;
+use log::debug;
+
+use crate::network::protocol::MediaType;
+pub mod cache;
+pub mod clipboard;
+pub mod download;
+pub mod fetch;
+pub mod preview;
+pub mod voice;
+
+/// Largest media file the protocol can carry, the packet length is a 4 byte field
+pub const MAX_MEDIA_SIZE: usize = u32::MAX as usize - 1024;
+const MAX_COMPRESS_ATTEMPTS: usize = 5;
+
+/// Result of inspecting a file before it is attached to a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedMedia {
+    pub media_type: MediaType,
+    pub mime_type: String,
+    /// Set when the server is likely to reject the file as the detected type
+    pub warning: Option<String>,
+}
+
+/// Infers the `MediaType` of a file from its magic bytes, falling back to its extension.
+///
+/// The protocol only accepts a single format per media type (PNG images, MP3 audio, MP4 video and UTF-8 text),
+/// other formats are sent as `Raw` so the server does not reject them.
+pub fn detect_media_type(filename: &str, data: &[u8]) -> DetectedMedia {
+    let mime_type = infer::get(data)
+        .map(|kind| kind.mime_type().to_owned())
+        .or_else(|| mime_guess::from_path(filename).first_raw().map(str::to_owned))
+        .unwrap_or_else(|| "application/octet-stream".to_owned());
+    debug!("Detected {mime_type} for {filename}");
+
+    let (media_type, warning) = match mime_type.as_str() {
+        "image/png" => (MediaType::Image, None),
+        "audio/mpeg" => (MediaType::Audio, None),
+        "video/mp4" => (MediaType::Video, None),
+        mime if mime.starts_with("text/") || is_text(data) => {
+            if is_text(data) {
+                (MediaType::Text, None)
+            } else {
+                (
+                    MediaType::Raw,
+                    Some(format!("{filename} is not valid UTF-8 text, sending it as a raw file")),
+                )
+            }
+        }
+        mime if mime.starts_with("image/") => (
+            MediaType::Raw,
+            Some(format!("Only PNG images are supported, sending {filename} as a raw file")),
+        ),
+        mime if mime.starts_with("audio/") => (
+            MediaType::Raw,
+            Some(format!("Only MP3 audio is supported, sending {filename} as a raw file")),
+        ),
+        mime if mime.starts_with("video/") => (
+            MediaType::Raw,
+            Some(format!("Only MP4 video is supported, sending {filename} as a raw file")),
+        ),
+        _ => (MediaType::Raw, None),
+    };
+
+    DetectedMedia {
+        media_type,
+        mime_type,
+        warning,
+    }
+}
+
+// TEXT media must be valid utf-8 without NULL characters
+fn is_text(data: &[u8]) -> bool {
+    !data.is_empty() && !data.contains(&0) && std::str::from_utf8(data).is_ok()
+}
+
+/// Whether the file is an image that `compress_image` can shrink
+pub fn is_compressible_image(data: &[u8]) -> bool {
+    image::guess_format(data).is_ok()
+}
+
+/// Downscales an image until it is encoded as a PNG of at most `limit` bytes, PNG being the only image format the protocol accepts
+pub fn compress_image(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let mut image = image::load_from_memory(data)?;
+    for _ in 0..MAX_COMPRESS_ATTEMPTS {
+        let mut encoded = Cursor::new(vec![]);
+        image.write_to(&mut encoded, ImageFormat::Png)?;
+        let encoded = encoded.into_inner();
+        debug!(
+            "Compressed image to {}x{} ({})",
+            image.width(),
+            image.height(),
+            format_size(encoded.len())
+        );
+        if encoded.len() <= limit {
+            return Ok(encoded);
+        }
+
+        // The encoded size scales roughly with the pixel count
+        let scale = ((limit as f64 / encoded.len() as f64).sqrt() * 0.9).min(0.9);
+        let width = ((image.width() as f64 * scale) as u32).max(1);
+        let height = ((image.height() as f64 * scale) as u32).max(1);
+        image = image.resize(width, height, FilterType::Triangle);
+    }
+    Err(anyhow!("Unable to compress the image below {}", format_size(limit)))
+}
+
+pub fn format_size(bytes: usize) -> String {
+    const KIB: f64 = 1024.0;
+    const MIB: f64 = 1024.0 * 1024.0;
+    match bytes as f64 {
+        size if size >= MIB => format!("{:.1} MiB", size / MIB),
+        size if size >= KIB => format!("{:.1} KiB", size / KIB),
+        _ => format!("{bytes} B"),
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::io::{BufWriter, Write, stdout};
+
+use anyhow::Result;
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbaImage};
+use log::debug;
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::cursor::MoveTo;
+use ratatui::crossterm::{queue, terminal};
+use ratatui::layout::Rect;
+use ratatui::style::Color;
+use ratatui::widgets::Widget;
+use serde::Deserialize;
+
+use crate::tui::events::{MediaId, ProfilePicId};
+
+/// Largest preview in the chat history, in cells
+pub const PREVIEW_MAX_ROWS: u16 = 8;
+const PREVIEW_MAX_COLUMNS: u16 = 32;
+/// Largest profile picture in the profile popup, in cells
+pub const AVATAR_MAX_ROWS: u16 = 6;
+const AVATAR_MAX_COLUMNS: u16 = 12;
+/// Profile pictures are transmitted to kitty with ids in the upper half so they never replace an attachment preview
+const KITTY_AVATAR_ID_BIT: u32 = 1 << 31;
+/// Used when the terminal does not report its size in pixels
+const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+#[cfg(feature = "graphics")]
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// How images are drawn in the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphicsProtocol {
+    Kitty,
+    Iterm2,
+    Sixel,
+    /// Two pixels per cell using `▀` with different foreground and background colors, works in any true color terminal
+    HalfBlocks,
+}
+
+impl GraphicsProtocol {
+    /// Uses the protocol from the config when this build supports it, detects it otherwise
+    pub fn from_config(configured: Option<Self>) -> Self {
+        match configured {
+            Some(protocol) if cfg!(feature = "graphics") => protocol,
+            _ => Self::detect(),
+        }
+    }
+
+    /// Guesses the protocol from the environment, terminals can not be queried reliably once the TUI is running
+    pub fn detect() -> Self {
+        if !cfg!(feature = "graphics") {
+            return GraphicsProtocol::HalfBlocks;
+        }
+        let term = env::var("TERM").unwrap_or_default();
+        let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+        let protocol = if env::var_os("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" || term == "xterm-ghostty" {
+            GraphicsProtocol::Kitty
+        } else if matches!(term_program.as_str(), "iTerm.app" | "WezTerm") || env::var("LC_TERMINAL").is_ok_and(|terminal| terminal == "iTerm2") {
+            GraphicsProtocol::Iterm2
+        } else if term.contains("sixel") || term.starts_with("foot") || term == "mlterm" {
+            GraphicsProtocol::Sixel
+        } else {
+            GraphicsProtocol::HalfBlocks
+        };
+        debug!("Using {protocol:?} to draw images");
+        protocol
+    }
+}
+
+/// Thumbnail of an image attachment, prepared once when the media arrives
+#[derive(Debug, Clone)]
+pub struct ImagePreview {
+    pub columns: u16,
+    pub rows: u16,
+    /// One pixel per half cell
+    half_blocks: RgbaImage,
+    /// Escape sequence that transmits the image, for the graphics protocols
+    encoded: Option<String>,
+    kitty_id: u32,
+}
+
+impl ImagePreview {
+    pub fn new(media_id: MediaId, data: &[u8], protocol: GraphicsProtocol) -> Result<Self> {
+        let image = image::load_from_memory(data)?;
+        let kitty_id = u32::try_from(media_id).unwrap_or(u32::MAX).max(1);
+        let preview = Self::fit(&image, (PREVIEW_MAX_COLUMNS, PREVIEW_MAX_ROWS), kitty_id, protocol)?;
+        debug!("Prepared a {}x{} preview of media {media_id}", preview.columns, preview.rows);
+        Ok(preview)
+    }
+
+    /// Scales the image into at most `max_columns` by `max_rows` cells, without upscaling small images
+    fn fit(image: &DynamicImage, (max_columns, max_rows): (u16, u16), kitty_id: u32, protocol: GraphicsProtocol) -> Result<Self> {
+        let (cell_width, cell_height) = cell_size();
+
+        let width_in_cells = image.width() as f32 / cell_width as f32;
+        let height_in_cells = image.height() as f32 / cell_height as f32;
+        let scale = (max_columns as f32 / width_in_cells).min(max_rows as f32 / height_in_cells).min(1.0);
+        let columns = ((width_in_cells * scale).round() as u16).clamp(1, max_columns);
+        let rows = ((height_in_cells * scale).round() as u16).clamp(1, max_rows);
+
+        let half_blocks = image.resize_exact(columns as u32, rows as u32 * 2, FilterType::Triangle).to_rgba8();
+        let encoded = match protocol {
+            GraphicsProtocol::HalfBlocks => None,
+            protocol => {
+                let thumbnail = image.resize_exact(columns as u32 * cell_width, rows as u32 * cell_height, FilterType::Triangle);
+                Some(encode(protocol, &thumbnail, kitty_id, columns, rows)?)
+            }
+        };
+
+        Ok(ImagePreview {
+            columns,
+            rows,
+            half_blocks,
+            encoded,
+            kitty_id,
+        })
+    }
+}
+
+/// Profile picture of a user, shown in the profile popup and as a two cell glyph next to message authors
+#[derive(Debug, Clone)]
+pub struct Avatar {
+    pub preview: ImagePreview,
+    /// Foreground and background of the two `▀` cells of the glyph
+    pub glyph: [(Color, Color); 2],
+}
+
+impl Avatar {
+    pub fn new(pfp_id: ProfilePicId, data: &[u8], protocol: GraphicsProtocol) -> Result<Self> {
+        let image = image::load_from_memory(data)?;
+        let kitty_id = KITTY_AVATAR_ID_BIT | (pfp_id as u32 & !KITTY_AVATAR_ID_BIT).max(1);
+        let preview = ImagePreview::fit(&image, (AVATAR_MAX_COLUMNS, AVATAR_MAX_ROWS), kitty_id, protocol)?;
+
+        let mosaic = image.resize_exact(2, 2, FilterType::Triangle).to_rgba8();
+        let glyph = [0, 1].map(|x| (half_block_color(mosaic.get_pixel(x, 0)), half_block_color(mosaic.get_pixel(x, 1))));
+        debug!("Prepared a {}x{} avatar from profile picture {pfp_id}", preview.columns, preview.rows);
+
+        Ok(Avatar { preview, glyph })
+    }
+}
+
+fn half_block_color(pixel: &image::Rgba<u8>) -> Color {
+    match pixel.0 {
+        [_, _, _, alpha] if alpha < 128 => Color::Reset,
+        [r, g, b, _] => Color::Rgb(r, g, b),
+    }
+}
+
+/// Renders a preview with unicode half blocks
+pub struct HalfBlockImage<'a>(pub &'a ImagePreview);
+
+impl Widget for HalfBlockImage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let preview = self.0;
+        for y in 0..preview.rows.min(area.height) {
+            for x in 0..preview.columns.min(area.width) {
+                let top = preview.half_blocks.get_pixel(x as u32, y as u32 * 2);
+                let bottom = preview.half_blocks.get_pixel(x as u32, y as u32 * 2 + 1);
+                buf[(area.x + x, area.y + y)]
+                    .set_char('▀')
+                    .set_fg(half_block_color(top))
+                    .set_bg(half_block_color(bottom));
+            }
+        }
+    }
+}
+
+/// Where a preview was laid out in the last frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImagePlacement {
+    pub media_id: MediaId,
+    pub area: Rect,
+    /// A profile picture, `media_id` is then its profile picture id
+    pub avatar: bool,
+}
+
+/// Draws previews through a graphics protocol on top of the frame ratatui drew.
+///
+/// The terminal keeps images around until they are overwritten or deleted, so they are only redrawn when their placements change.
+#[derive(Debug, Clone)]
+pub struct GraphicsRenderer {
+    pub protocol: GraphicsProtocol,
+    /// Placements laid out by the frame that is being drawn
+    pub placements: Vec<ImagePlacement>,
+    shown: Vec<ImagePlacement>,
+    screen: Rect,
+    kitty_transmitted: HashSet<u32>,
+}
+
+impl GraphicsRenderer {
+    pub fn new(protocol: GraphicsProtocol) -> Self {
+        GraphicsRenderer {
+            protocol,
+            placements: vec![],
+            shown: vec![],
+            screen: Rect::default(),
+            kitty_transmitted: HashSet::new(),
+        }
+    }
+
+    /// Whether previews are drawn into the ratatui buffer instead of by this renderer
+    pub fn uses_half_blocks(&self) -> bool {
+        self.protocol == GraphicsProtocol::HalfBlocks
+    }
+
+    pub fn draw(&mut self, previews: &HashMap<MediaId, ImagePreview>, avatars: &HashMap<ProfilePicId, Avatar>, buffer: &Buffer) -> Result<()> {
+        if self.uses_half_blocks() || (self.placements == self.shown && buffer.area == self.screen) {
+            return Ok(());
+        }
+        let mut out = BufWriter::new(stdout().lock());
+
+        if self.protocol == GraphicsProtocol::Kitty {
+            // Deletes the placements but keeps the transmitted images
+            write!(out, "\x1b_Ga=d,d=a,q=2\x1b\\")?;
+        } else {
+            // Sixel and iTerm2 images replace the cells they cover, restore what ratatui believes is there
+            let cells: Vec<_> = self
+                .shown
+                .iter()
+                .flat_map(|placement| placement.area.intersection(buffer.area).positions())
+                .map(|position| (position.x, position.y, &buffer[position]))
+                .collect();
+            CrosstermBackend::new(&mut out).draw(cells.into_iter())?;
+        }
+
+        for placement in &self.placements {
+            let preview = if placement.avatar {
+                avatars.get(&placement.media_id).map(|avatar| &avatar.preview)
+            } else {
+                previews.get(&placement.media_id)
+            };
+            let Some(preview) = preview else {
+                continue;
+            };
+            let Some(encoded) = &preview.encoded else {
+                continue;
+            };
+            queue!(out, MoveTo(placement.area.x, placement.area.y))?;
+            if self.protocol == GraphicsProtocol::Kitty {
+                if self.kitty_transmitted.insert(preview.kitty_id) {
+                    write!(out, "{encoded}")?;
+                }
+                write!(
+                    out,
+                    "\x1b_Ga=p,i={},p=1,c={},r={},C=1,q=2\x1b\\",
+                    preview.kitty_id, placement.area.width, placement.area.height
+                )?;
+            } else {
+                write!(out, "{encoded}")?;
+            }
+        }
+        out.flush()?;
+
+        self.shown = self.placements.clone();
+        self.screen = buffer.area;
+        Ok(())
+    }
+}
+
+fn cell_size() -> (u32, u32) {
+    match terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => {
+            ((size.width / size.columns).max(1) as u32, (size.height / size.rows).max(1) as u32)
+        }
+        _ => DEFAULT_CELL_SIZE,
+    }
+}
+
+#[cfg(feature = "graphics")]
+fn encode(protocol: GraphicsProtocol, image: &DynamicImage, kitty_id: u32, columns: u16, rows: u16) -> Result<String> {
+    use std::io::Cursor;
+
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use image::ImageFormat;
+
+    let png = || -> Result<Vec<u8>> {
+        let mut png = Cursor::new(vec![]);
+        image.write_to(&mut png, ImageFormat::Png)?;
+        Ok(png.into_inner())
+    };
+
+    match protocol {
+        GraphicsProtocol::Kitty => {
+            let data = STANDARD.encode(png()?);
+            let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+            let mut encoded = String::new();
+            for (idx, chunk) in chunks.iter().enumerate() {
+                let more = (idx + 1 < chunks.len()) as u8;
+                let chunk = std::str::from_utf8(chunk)?;
+                if idx == 0 {
+                    encoded.push_str(&format!("\x1b_Ga=t,f=100,i={kitty_id},q=2,m={more};{chunk}\x1b\\"));
+                } else {
+                    encoded.push_str(&format!("\x1b_Gm={more};{chunk}\x1b\\"));
+                }
+            }
+            Ok(encoded)
+        }
+        GraphicsProtocol::Iterm2 => {
+            let png = png()?;
+            Ok(format!(
+                "\x1b]1337;File=inline=1;size={};width={columns};height={rows};preserveAspectRatio=1:{}\x07",
+                png.len(),
+                STANDARD.encode(&png)
+            ))
+        }
+        GraphicsProtocol::Sixel => {
+            let rgba = image.to_rgba8();
+            let (width, height) = rgba.dimensions();
+            let sixel = icy_sixel::SixelImage::try_from_rgba(rgba.into_raw(), width as usize, height as usize)?;
+            Ok(sixel.encode()?)
+        }
+        GraphicsProtocol::HalfBlocks => Ok(String::new()),
+    }
+}
+
+#[cfg(not(feature = "graphics"))]
+fn encode(_protocol: GraphicsProtocol, _image: &DynamicImage, _kitty_id: u32, _columns: u16, _rows: u16) -> Result<String> {
+    Ok(String::new())
+}
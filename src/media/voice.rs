@@ -0,0 +1,196 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use log::debug;
+use tokio::time::Instant;
+
+const WAVEFORM_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Voice note that is being recorded or waiting to be sent
+#[derive(Debug, Clone)]
+pub enum VoiceNote {
+    Recording(VoiceRecorder),
+    Preview(RecordedVoiceNote),
+}
+
+/// Records mono samples from the default input device on a background thread until `finish` is called
+#[derive(Debug, Clone)]
+pub struct VoiceRecorder {
+    samples: Arc<Mutex<Vec<f32>>>,
+    stop: Arc<AtomicBool>,
+    sample_rate: Arc<AtomicU32>,
+    started: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedVoiceNote {
+    pub wav: Vec<u8>,
+    pub duration: Duration,
+    pub waveform: String,
+}
+
+impl VoiceRecorder {
+    pub fn start() -> Result<Self> {
+        let recorder = VoiceRecorder {
+            samples: Arc::new(Mutex::new(vec![])),
+            stop: Arc::new(AtomicBool::new(false)),
+            sample_rate: Arc::new(AtomicU32::new(0)),
+            started: Instant::now(),
+        };
+        capture::spawn(recorder.samples.clone(), recorder.stop.clone(), recorder.sample_rate.clone())?;
+        Ok(recorder)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Waveform of the last `seconds` of the recording, used as a live level indicator
+    pub fn recent_waveform(&self, seconds: f32, width: usize) -> String {
+        let samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        let recent = (self.sample_rate.load(Ordering::Relaxed) as f32 * seconds) as usize;
+        waveform(&samples[samples.len().saturating_sub(recent)..], width)
+    }
+
+    pub fn cancel(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops recording and encodes the recorded samples
+    pub fn finish(self, waveform_width: usize) -> Result<RecordedVoiceNote> {
+        self.stop.store(true, Ordering::Relaxed);
+        let samples = std::mem::take(&mut *self.samples.lock().unwrap_or_else(|e| e.into_inner()));
+        let sample_rate = self.sample_rate.load(Ordering::Relaxed);
+        if samples.is_empty() || sample_rate == 0 {
+            return Err(anyhow!("Nothing was recorded"));
+        }
+        debug!("Recorded {} samples at {sample_rate} Hz", samples.len());
+
+        Ok(RecordedVoiceNote {
+            wav: capture::encode_wav(&samples, sample_rate)?,
+            duration: Duration::from_secs_f32(samples.len() as f32 / sample_rate as f32),
+            waveform: waveform(&samples, waveform_width),
+        })
+    }
+}
+
+/// Renders the peak of each slice of the samples as a block character
+pub fn waveform(samples: &[f32], width: usize) -> String {
+    if samples.is_empty() || width == 0 {
+        return String::new();
+    }
+    samples
+        .chunks(samples.len().div_ceil(width))
+        .map(|chunk| {
+            let peak = chunk.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs())).min(1.0);
+            WAVEFORM_LEVELS[(peak * (WAVEFORM_LEVELS.len() - 1) as f32).round() as usize]
+        })
+        .collect()
+}
+
+#[cfg(feature = "voice")]
+mod capture {
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use anyhow::{Result, anyhow};
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+    use cpal::{FromSample, SampleFormat, SizedSample};
+    use log::{error, info};
+
+    /// Audio streams can not be moved between threads on every platform, so the stream lives on its own thread
+    pub fn spawn(samples: Arc<Mutex<Vec<f32>>>, stop: Arc<AtomicBool>, sample_rate: Arc<AtomicU32>) -> Result<()> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or_else(|| anyhow!("No audio input device available"))?;
+        let supported_config = device.default_input_config()?;
+        sample_rate.store(supported_config.sample_rate().0, Ordering::Relaxed);
+        info!("Recording from {}", device.name().unwrap_or_else(|_| "unknown device".to_owned()));
+
+        thread::spawn(move || {
+            let config = supported_config.config();
+            let stream = match supported_config.sample_format() {
+                SampleFormat::F32 => build_stream::<f32>(&device, &config, samples),
+                SampleFormat::I16 => build_stream::<i16>(&device, &config, samples),
+                SampleFormat::U16 => build_stream::<u16>(&device, &config, samples),
+                SampleFormat::I32 => build_stream::<i32>(&device, &config, samples),
+                other => Err(anyhow!("Unsupported sample format {other:?}")),
+            };
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!("Failed to start recording: {e}");
+                    return;
+                }
+            };
+            if let Err(e) = stream.play() {
+                error!("Failed to start recording: {e}");
+                return;
+            }
+            while !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(20));
+            }
+            drop(stream);
+        });
+        Ok(())
+    }
+
+    fn build_stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, samples: Arc<Mutex<Vec<f32>>>) -> Result<cpal::Stream>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let channels = config.channels.max(1) as usize;
+        let stream = device.build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let mut samples = samples.lock().unwrap_or_else(|e| e.into_inner());
+                // Downmix to mono
+                samples.extend(
+                    data.chunks(channels)
+                        .map(|frame| frame.iter().map(|sample| f32::from_sample(*sample)).sum::<f32>() / channels as f32),
+                );
+            },
+            |e| error!("Error while recording: {e}"),
+            None,
+        )?;
+        Ok(stream)
+    }
+
+    pub fn encode_wav(samples: &[f32], sample_rate: u32) -> Result<Vec<u8>> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut wav = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut wav, spec)?;
+        for sample in samples {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        writer.finalize()?;
+        Ok(wav.into_inner())
+    }
+}
+
+#[cfg(not(feature = "voice"))]
+mod capture {
+    use std::sync::atomic::{AtomicBool, AtomicU32};
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::{Result, anyhow};
+
+    pub fn spawn(_samples: Arc<Mutex<Vec<f32>>>, _stop: Arc<AtomicBool>, _sample_rate: Arc<AtomicU32>) -> Result<()> {
+        Err(anyhow!("Voice notes are not supported by this build, rebuild with `--features voice`"))
+    }
+
+    pub fn encode_wav(_samples: &[f32], _sample_rate: u32) -> Result<Vec<u8>> {
+        Err(anyhow!("Voice notes are not supported by this build, rebuild with `--features voice`"))
+    }
+}
@@ -1,5 +1,6 @@
+use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::net::{IpAddr, SocketAddr, SocketAddrV4};
+use std::net::{IpAddr, SocketAddrV4};
 use std::ops::DerefMut;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -8,26 +9,27 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use anyhow::{Result, anyhow};
 use chrono::{DateTime, Utc};
 use log::{debug, error, info};
-use rustls::pki_types::ServerName;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
-use tokio::net::TcpStream;
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::{Mutex, MutexGuard};
 use tokio::task::JoinHandle;
-use tokio_rustls::TlsConnector;
 
 use crate::network::handle_message;
-use crate::network::protocol::UserStatus;
 use crate::network::protocol::client::{
-    Anchor, ClientPacketType, ClientPayload, GetChannelsPacket, GetHistoryPacket, GetUsersPacket, LoginPacket, SendMessagePacket, Serialize,
-    StatusPacket, TypingPacket,
+    Anchor, ClientPacketType, ClientPayload, EditMessagePacket, GetChannelsPacket, GetHistoryPacket, GetMediaPacket, GetUsersPacket, LoginCodePacket,
+    LoginPacket, MarkReadPacket, OpenDirectChannelPacket, ResumePacket, SendMediaPacket, SendMessagePacket, Serialize, StatusPacket,
+    TokenLoginPacket, TypingPacket, UserConfigField, UserConfigSetPacket,
 };
 use crate::network::protocol::header::{Header, PacketType};
 use crate::network::protocol::server::{Deserialize, HealthCheckPacket, HealthKind, ServerPayload};
-use crate::tui::events::TuiEvent;
+use crate::network::protocol::{MediaType, UserStatus};
+use crate::network::transport::{ReadHalf, WriteHalf};
+use crate::storage::secrets::Secret;
+use crate::tui::events::{MediaId, MessageId, TuiEvent};
 
 pub const MAX_MESSAGE_LENGTH: usize = 16 * 1024; // TODO figure out actual max size
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ServerConnectionStatus {
@@ -37,6 +39,17 @@ pub enum ServerConnectionStatus {
     Reconnecting,
 }
 
+/// How a reconnect gets back into the account
+#[derive(Debug, Clone)]
+pub enum Reauthentication {
+    /// Continues the previous session, the server only sends what was missed
+    Resume(ResumePacket),
+    /// Logs in with the session token, without sending the password again
+    Token(Secret),
+    Password(String, Secret),
+}
+
+/// How the connection to a server is made, see `ConnectionType::transport`
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
 pub enum ConnectionType {
     Raw,
@@ -69,31 +82,102 @@ impl Hash for ServerAddrInfo {
         self.connection_type.hash(state);
     }
 }
+
+// Used as the key under which per server data is stored locally
+impl fmt::Display for ServerAddrInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.domain {
+            Some(domain) => write!(f, "{domain}:{}", self.port),
+            None => write!(f, "{}:{}", self.ip, self.port),
+        }
+    }
+}
+
 pub struct Client {
-    write_stream: Option<Box<dyn AsyncWrite + Send + Unpin>>,
+    write_stream: Option<WriteHalf>,
     event_send: Sender<TuiEvent>,
+    /// Receives the decoded packets instead of the TUI, used by the REPL
+    payload_send: Option<Sender<ServerPayload>>,
     recv_handle: Option<JoinHandle<()>>,
     pub time_since_last_transmit: InteractedTimeStamp,
     pub time_since_last_reconnect: InteractedTimeStamp,
     pub connection_status: ServerConnectionStatus,
+    upload: Option<PendingUpload>,
+    /// Packets sent while an upload is running, written once the media packet is complete as packets can not be interleaved
+    queued: Vec<u8>,
+}
+
+/// Media packet that is written to the server a chunk at a time, so its progress can be shown and it can be cancelled
+struct PendingUpload {
+    packet: Vec<u8>,
+    written: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadProgress {
+    pub written: usize,
+    pub total: usize,
+}
+
+impl UploadProgress {
+    pub fn is_done(&self) -> bool {
+        self.written >= self.total
+    }
 }
 
 impl Client {
+    /// Lets background work, like running the translation command, report back to the TUI
+    pub fn event_sender(&self) -> Sender<TuiEvent> {
+        self.event_send.clone()
+    }
+
     pub fn new(event_send: Sender<TuiEvent>) -> Self {
         Client {
             write_stream: None,
             event_send,
+            payload_send: None,
             recv_handle: None,
             time_since_last_transmit: InteractedTimeStamp::new(),
             time_since_last_reconnect: InteractedTimeStamp::new(),
             connection_status: ServerConnectionStatus::Disconnected,
+            upload: None,
+            queued: Vec::new(),
         }
     }
 
-    pub fn get_stream(&mut self) -> Result<&mut (dyn AsyncWrite + Send + Unpin)> {
+    /// Hands every packet the server sends to `payload_send` as is, instead of turning it into events for the TUI
+    pub fn with_raw_payloads(mut self, payload_send: Sender<ServerPayload>) -> Self {
+        self.payload_send = Some(payload_send);
+        self
+    }
+
+    /// Where the next packet is written. While an upload is running packets are queued behind it,
+    /// they go out between two upload chunks once the media packet is complete
+    pub async fn get_stream(&mut self) -> Result<&mut (dyn AsyncWrite + Send + Unpin)> {
+        if self.write_stream.is_none() {
+            return Err(anyhow!("Not connected to server"));
+        }
+        if self.upload.is_some() {
+            return Ok(&mut self.queued);
+        }
+        self.flush_queued().await?;
         Ok(self.write_stream.as_mut().ok_or_else(|| anyhow!("Not connected to server"))?)
     }
 
+    /// Writes the packets that were sent while an upload was running
+    async fn flush_queued(&mut self) -> Result<()> {
+        if self.queued.is_empty() {
+            return Ok(());
+        }
+        let stream = self.write_stream.as_mut().ok_or_else(|| anyhow!("Not connected to server"))?;
+        let queued = std::mem::take(&mut self.queued);
+        debug!("Sending {} bytes queued behind an upload", queued.len());
+        stream.write_all(&queued).await?;
+        stream.flush().await?;
+        self.time_since_last_transmit.update();
+        Ok(())
+    }
+
     pub async fn connect(&mut self, server_connection: &ServerAddrInfo) -> Result<()> {
         if self.write_stream.is_some() {
             if let Some(domain) = &server_connection.domain {
@@ -106,47 +190,17 @@ impl Client {
                 return Err(anyhow!("Already connected to {}:{}", server_connection.port, server_connection.ip));
             }
         }
-        let target_addr = SocketAddr::new(server_connection.ip, server_connection.port);
-        let connection_tcp = TcpStream::connect(target_addr).await?;
-        let src_addr = connection_tcp.local_addr().unwrap();
-
-        match server_connection.connection_type {
-            ConnectionType::Raw => {
-                let (read_stream, write_stream) = connection_tcp.into_split();
-
-                info!("Connected to {target_addr} from {src_addr}");
-                self.write_stream = Some(Box::new(write_stream));
-                self.recv_handle = Some(self.receiving_task(Box::new(read_stream)).await);
-                self.connection_status = ServerConnectionStatus::Connected;
-            }
-            ConnectionType::TLS => {
-                if let Some(domain) = server_connection.domain.clone() {
-                    // Source: https://docs.rs/rustls/latest/rustls/
-                    let root_store = rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-
-                    let config = rustls::ClientConfig::builder().with_root_certificates(root_store).with_no_client_auth();
-
-                    let connector = TlsConnector::from(Arc::new(config));
-                    let domain_name = ServerName::try_from(domain)?;
-
-                    let connection_tls = connector.connect(domain_name, connection_tcp).await?;
-                    let (read_stream, write_stream) = tokio::io::split(connection_tls);
-
-                    self.write_stream = Some(Box::new(write_stream));
-                    self.recv_handle = Some(self.receiving_task(Box::new(read_stream)).await);
-                    self.connection_status = ServerConnectionStatus::Connected;
-                    info!("Connected to {target_addr} from {src_addr} over TLS");
-                } else {
-                    return Err(anyhow!("TLS requires a domain"));
-                }
-            }
-        };
-
+        let (read_stream, write_stream) = server_connection.connection_type.transport().connect(server_connection).await?;
+        self.write_stream = Some(write_stream);
+        self.recv_handle = Some(self.receiving_task(read_stream).await);
+        self.connection_status = ServerConnectionStatus::Connected;
         Ok(())
     }
 
     pub fn disconnect(&mut self) -> Result<()> {
         self.write_stream = None;
+        self.upload = None;
+        self.queued.clear();
         if let Some(recv_handle) = &self.recv_handle {
             recv_handle.abort();
         }
@@ -155,18 +209,31 @@ impl Client {
         Ok(())
     }
 
-    pub async fn reconnect(&mut self, server_address: &ServerAddrInfo, username: String, password: String) -> Result<()> {
+    /// Connects again and gets back into the account the way `reauthentication` says
+    pub async fn reconnect(&mut self, server_address: &ServerAddrInfo, reauthentication: Reauthentication) -> Result<()> {
         self.disconnect()?;
         self.connection_status = ServerConnectionStatus::Reconnecting;
         self.connect(server_address).await?;
-        self.login(username, password).await?;
+        match reauthentication {
+            Reauthentication::Resume(packet) => self.send_resume(packet).await?,
+            Reauthentication::Token(session_token) => self.login_with_token(session_token).await?,
+            Reauthentication::Password(username, password) => self.login(username, password).await?,
+        }
         self.time_since_last_reconnect.update();
         Ok(())
     }
 
+    /// Sends any packet, for when there is no dedicated method for it
+    pub async fn send_packet(&mut self, packet_type: ClientPacketType, payload: ClientPayload) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(write_stream, interacted_ts, packet_type, payload).await
+    }
+
     pub async fn send_healthcheck(&mut self) -> Result<()> {
         let interacted_ts = self.time_since_last_transmit.clone();
-        let mut write_stream = self.get_stream()?;
+        let mut write_stream = self.get_stream().await?;
 
         Self::send_message(
             write_stream,
@@ -177,9 +244,9 @@ impl Client {
         .await
     }
 
-    pub async fn login(&mut self, username: String, password: String) -> Result<()> {
+    pub async fn login(&mut self, username: String, password: Secret) -> Result<()> {
         let interacted_ts = self.time_since_last_transmit.clone();
-        let mut write_stream = self.get_stream()?;
+        let mut write_stream = self.get_stream().await?;
 
         Self::send_message(
             &mut write_stream.deref_mut(),
@@ -190,9 +257,42 @@ impl Client {
         .await
     }
 
+    pub async fn login_with_token(&mut self, session_token: Secret) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(
+            write_stream,
+            interacted_ts,
+            ClientPacketType::TokenLogin,
+            ClientPayload::TokenLogin(TokenLoginPacket { session_token }),
+        )
+        .await
+    }
+
+    pub async fn send_resume(&mut self, packet: ResumePacket) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(write_stream, interacted_ts, ClientPacketType::Resume, ClientPayload::Resume(packet)).await
+    }
+
+    pub async fn send_login_code(&mut self, code: String) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(
+            write_stream,
+            interacted_ts,
+            ClientPacketType::LoginCode,
+            ClientPayload::LoginCode(LoginCodePacket { code }),
+        )
+        .await
+    }
+
     pub async fn request_channels(&mut self, channel_ids: Vec<u64>) -> Result<()> {
         let interacted_ts = self.time_since_last_transmit.clone();
-        let mut write_stream = self.get_stream()?;
+        let mut write_stream = self.get_stream().await?;
 
         Self::send_message(
             write_stream,
@@ -205,21 +305,21 @@ impl Client {
 
     pub async fn request_channel_ids(&mut self) -> Result<()> {
         let interacted_ts = self.time_since_last_transmit.clone();
-        let mut write_stream = self.get_stream()?;
+        let mut write_stream = self.get_stream().await?;
 
         Self::send_message(write_stream, interacted_ts, ClientPacketType::ChannelsList, ClientPayload::ChannelsList).await
     }
 
     pub async fn request_user_statuses(&mut self) -> Result<()> {
         let interacted_ts = self.time_since_last_transmit.clone();
-        let mut write_stream = self.get_stream()?;
+        let mut write_stream = self.get_stream().await?;
 
         Self::send_message(write_stream, interacted_ts, ClientPacketType::UserStatuses, ClientPayload::UserStatuses).await
     }
 
     pub async fn request_users(&mut self, user_ids: Vec<u64>) -> Result<()> {
         let interacted_ts = self.time_since_last_transmit.clone();
-        let mut write_stream = self.get_stream()?;
+        let mut write_stream = self.get_stream().await?;
 
         Self::send_message(
             write_stream,
@@ -232,7 +332,7 @@ impl Client {
 
     pub async fn request_history_by_timestamp(&mut self, channel_id: u64, timestamp: DateTime<Utc>, num_messages_back: i8) -> Result<()> {
         let interacted_ts = self.time_since_last_transmit.clone();
-        let mut write_stream = self.get_stream()?;
+        let mut write_stream = self.get_stream().await?;
 
         Self::send_message(
             write_stream,
@@ -247,9 +347,40 @@ impl Client {
         .await
     }
 
+    /// Negative lengths request the messages after the anchor instead of before it
+    pub async fn request_history_by_message(&mut self, channel_id: u64, message_id: MessageId, num_messages_back: i8) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(
+            write_stream,
+            interacted_ts,
+            ClientPacketType::History,
+            ClientPayload::History(GetHistoryPacket {
+                channel_id,
+                anchor: Anchor::MessageId(message_id),
+                num_messages_back,
+            }),
+        )
+        .await
+    }
+
+    pub async fn request_media(&mut self, media_id: MediaId) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(
+            write_stream,
+            interacted_ts,
+            ClientPacketType::Media,
+            ClientPayload::Media(GetMediaPacket { media_id }),
+        )
+        .await
+    }
+
     pub async fn send_chat_message(&mut self, channel_id: u64, reply_id: u64, message_text: String, media_ids: Vec<u64>) -> Result<()> {
         let interacted_ts = self.time_since_last_transmit.clone();
-        let mut write_stream = self.get_stream()?;
+        let mut write_stream = self.get_stream().await?;
 
         Self::send_message(
             write_stream,
@@ -267,7 +398,7 @@ impl Client {
 
     pub async fn send_typing(&mut self, channel_id: u64, is_typing: bool) -> Result<()> {
         let interacted_ts = self.time_since_last_transmit.clone();
-        let mut write_stream = self.get_stream()?;
+        let mut write_stream = self.get_stream().await?;
 
         Self::send_message(
             write_stream,
@@ -280,7 +411,7 @@ impl Client {
 
     pub async fn send_user_status(&mut self, status: UserStatus) -> Result<()> {
         let interacted_ts = self.time_since_last_transmit.clone();
-        let mut write_stream = self.get_stream()?;
+        let mut write_stream = self.get_stream().await?;
 
         Self::send_message(
             write_stream,
@@ -291,9 +422,162 @@ impl Client {
         .await
     }
 
-    async fn receiving_task(&mut self, mut read_stream: Box<dyn AsyncRead + Send + Unpin>) -> JoinHandle<()> {
+    pub async fn request_read_states(&mut self) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(write_stream, interacted_ts, ClientPacketType::ReadStates, ClientPayload::ReadStates).await
+    }
+
+    pub async fn request_direct_channels(&mut self) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(
+            write_stream,
+            interacted_ts,
+            ClientPacketType::DirectChannels,
+            ClientPayload::DirectChannels,
+        )
+        .await
+    }
+
+    pub async fn set_user_config(&mut self, fields: Vec<UserConfigField>) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(
+            write_stream,
+            interacted_ts,
+            ClientPacketType::UserConfigSet,
+            ClientPayload::UserConfigSet(UserConfigSetPacket { fields }),
+        )
+        .await
+    }
+
+    pub async fn open_direct_channel(&mut self, user_id: u64) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(
+            write_stream,
+            interacted_ts,
+            ClientPacketType::OpenDirectChannel,
+            ClientPayload::OpenDirectChannel(OpenDirectChannelPacket { user_id }),
+        )
+        .await
+    }
+
+    pub async fn send_mark_read(&mut self, channel_id: u64, message_id: u64) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(
+            write_stream,
+            interacted_ts,
+            ClientPacketType::MarkRead,
+            ClientPayload::MarkRead(MarkReadPacket { channel_id, message_id }),
+        )
+        .await
+    }
+
+    pub async fn request_server_info(&mut self) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(write_stream, interacted_ts, ClientPacketType::ServerInfo, ClientPayload::ServerInfo).await
+    }
+
+    pub async fn request_capabilities(&mut self) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(write_stream, interacted_ts, ClientPacketType::Capabilities, ClientPayload::Capabilities).await
+    }
+
+    pub async fn send_edit_message(&mut self, message_id: u64, message_text: String) -> Result<()> {
+        let interacted_ts = self.time_since_last_transmit.clone();
+        let mut write_stream = self.get_stream().await?;
+
+        Self::send_message(
+            write_stream,
+            interacted_ts,
+            ClientPacketType::EditMessage,
+            ClientPayload::EditMessage(EditMessagePacket { message_id, message_text }),
+        )
+        .await
+    }
+
+    /// Queues a media packet, which is written to the server by repeatedly calling `pump_upload`
+    pub fn start_media_upload(&mut self, filename: String, media_type: MediaType, media_data: Vec<u8>) -> Result<UploadProgress> {
+        if self.upload.is_some() {
+            return Err(anyhow!("Another upload is still in progress"));
+        }
+        if self.write_stream.is_none() {
+            return Err(anyhow!("Not connected to server"));
+        }
+        let packet = Self::encode_packet(
+            ClientPacketType::SendMedia,
+            ClientPayload::SendMedia(SendMediaPacket {
+                filename,
+                media_type,
+                media_data,
+            }),
+        );
+        let progress = UploadProgress {
+            written: 0,
+            total: packet.len(),
+        };
+        self.upload = Some(PendingUpload { packet, written: 0 });
+        Ok(progress)
+    }
+
+    /// Writes the next chunk of the running upload, returns the progress after writing it
+    pub async fn pump_upload(&mut self) -> Result<Option<UploadProgress>> {
+        let (Some(upload), Some(stream)) = (self.upload.as_mut(), self.write_stream.as_mut()) else {
+            return Ok(None);
+        };
+        let chunk_end = (upload.written + UPLOAD_CHUNK_SIZE).min(upload.packet.len());
+        if let Err(e) = stream.write_all(&upload.packet[upload.written..chunk_end]).await {
+            self.upload = None;
+            return Err(e.into());
+        }
+        stream.flush().await?;
+        self.time_since_last_transmit.update();
+        upload.written = chunk_end;
+
+        let progress = UploadProgress {
+            written: upload.written,
+            total: upload.packet.len(),
+        };
+        if progress.is_done() {
+            debug!("Finished uploading {} bytes", progress.total);
+            self.upload = None;
+            self.flush_queued().await?;
+        }
+        Ok(Some(progress))
+    }
+
+    /// Aborts the running upload. A partially written packet can not be recovered from,
+    /// so in that case the connection is dropped and left to the reconnect logic.
+    pub async fn cancel_upload(&mut self) -> Result<()> {
+        match self.upload.take() {
+            Some(upload) if upload.written > 0 => {
+                info!(
+                    "Cancelled upload after {}/{} bytes, dropping connection",
+                    upload.written,
+                    upload.packet.len()
+                );
+                self.disconnect()
+            }
+            _ => self.flush_queued().await,
+        }
+    }
+
+    async fn receiving_task(&mut self, mut read_stream: ReadHalf) -> JoinHandle<()> {
         info!("Started receiving task");
         let event_send = self.event_send.clone();
+        let payload_send = self.payload_send.clone();
         let interacted_timestamp = self.time_since_last_transmit.clone();
 
         tokio::spawn(async move {
@@ -303,7 +587,11 @@ impl Client {
                 match Self::read_message(&mut read_stream, interacted_timestamp.clone(), &mut header_buffer, &mut payload_buffer).await {
                     Ok((payload, _bytes_read)) => {
                         // TODO something with bytes read
-                        if let Err(e) = handle_message(payload, event_send.clone()).await {
+                        if let Some(payload_send) = &payload_send {
+                            if payload_send.send(payload).await.is_err() {
+                                break;
+                            }
+                        } else if let Err(e) = handle_message(payload, event_send.clone()).await {
                             error!("Error while handling message: {e:?}");
                         }
                     }
@@ -330,21 +618,26 @@ impl Client {
     ) -> Result<()> {
         debug!("Sending packet type: {packet_type:?}");
 
+        let packet = Self::encode_packet(packet_type, payload);
+
+        stream.write_all(&packet).await?;
+
+        stream.flush().await?;
+        transmission_timestamp.update();
+        Ok(())
+    }
+
+    fn encode_packet(packet_type: ClientPacketType, payload: ClientPayload) -> Vec<u8> {
         let payload_serialized = payload.serialize();
         let header = Header::new(packet_type.into(), payload_serialized.len() as u32);
         // debug!("Header {header:?}");
         let mut packet = header.serialize();
 
         debug!("Send header bytes: {packet:?}");
-        debug!("Send payload bytes: {payload_serialized:?}");
+        debug!("Send payload bytes: {} bytes", payload_serialized.len());
 
         packet.extend(payload_serialized);
-
-        stream.write_all(&packet).await?;
-
-        stream.flush().await?;
-        transmission_timestamp.update();
-        Ok(())
+        packet
     }
 
     pub async fn read_message(
@@ -374,7 +667,7 @@ impl Client {
             PacketType::Client(packet_type) => return Err(anyhow!("Received packet type {packet_type:?}, which is a client packet")),
         };
 
-        let payload = ServerPayload::deserialize_packet(payload_buffer, packet_type)?;
+        let payload = ServerPayload::deserialize_packet(&payload_buffer[..payload_size as usize], packet_type)?;
         debug!("Deserialized payload {payload:?}");
         transmission_timestamp.update();
         Ok(payload)
@@ -3,10 +3,13 @@ use log::{error, info};
 use tokio::sync::mpsc::Sender;
 
 use crate::network::protocol::server::{HealthKind, ReturnStatus, ServerPayload};
+use crate::storage::secrets::Secret;
 use crate::tui::chat::MediaMessage;
 use crate::tui::events::TuiEvent;
 pub mod client;
+pub mod probe;
 pub mod protocol;
+pub mod transport;
 
 pub async fn handle_message(payload: ServerPayload, event_send: Sender<TuiEvent>) -> Result<()> {
     use ServerPayload::*;
@@ -21,10 +24,20 @@ pub async fn handle_message(payload: ServerPayload, event_send: Sender<TuiEvent>
             }
             HealthKind::Pong => Err(anyhow!("Received Pong packet, which are meant for the server only")),
         },
+        Login(packet) if packet.code_required => {
+            info!("The server asks for a login code");
+            event_send
+                .send(TuiEvent::LoginCodeRequired(packet.error_message.filter(|message| !message.is_empty())))
+                .await?;
+            Ok(())
+        }
         Login(packet) => match packet.status {
             Success => {
                 info!("Succefully logged in");
                 event_send.send(TuiEvent::LoginSuccess(0)).await?; // TODO user id handling
+                if let Some(token) = packet.session_token {
+                    event_send.send(TuiEvent::SessionToken(token)).await?;
+                }
                 Ok(())
             }
             Failed => {
@@ -94,10 +107,14 @@ pub async fn handle_message(payload: ServerPayload, event_send: Sender<TuiEvent>
             Notification => Err(anyhow!("Malformed packet, notification bit should not be set")),
         },
         History(packet) => match packet.status {
-            Success | Notification => {
+            Success => {
                 event_send.send(TuiEvent::HistoryUpdate(packet.messages)).await?;
                 Ok(())
             }
+            Notification => {
+                event_send.send(TuiEvent::HistoryNotification(packet.messages)).await?;
+                Ok(())
+            }
             Failed => {
                 if let Some(message) = packet.error_message {
                     Err(anyhow!("Failed to retrieve history: {message}"))
@@ -112,11 +129,11 @@ pub async fn handle_message(payload: ServerPayload, event_send: Sender<TuiEvent>
                 Ok(())
             }
             Failed => {
-                if let Some(message) = packet.error_message {
-                    Err(anyhow!("Failed to send message: {message}"))
-                } else {
-                    Err(anyhow!("Failed to send message-but"))
-                }
+                info!("The server refused a message: {:?}", packet.error_message);
+                event_send
+                    .send(TuiEvent::MessageSendFailed(packet.error_message.filter(|message| !message.is_empty())))
+                    .await?;
+                Ok(())
             }
             Notification => Err(anyhow!("Malformed packet, notification bit should not be set")),
         },
@@ -141,11 +158,13 @@ pub async fn handle_message(payload: ServerPayload, event_send: Sender<TuiEvent>
                         filename: packet.filename,
                         media_type: packet.media_type,
                         media_data: packet.media_data,
+                        checksum: packet.checksum,
                     }))
                     .await?;
                 Ok(())
             }
             Failed => {
+                event_send.send(TuiEvent::MediaFailed).await?;
                 if let Some(message) = packet.error_message {
                     Err(anyhow!("Failed to retrieve media: {message}"))
                 } else {
@@ -164,5 +183,117 @@ pub async fn handle_message(payload: ServerPayload, event_send: Sender<TuiEvent>
             event_send.send(TuiEvent::UserStatusUpdate(packet.user_id, packet.status)).await?;
             Ok(())
         }
+        ChannelEvent(packet) => {
+            event_send.send(TuiEvent::ChannelEvent(packet)).await?;
+            Ok(())
+        }
+        ReadStates(packet) => match packet.status {
+            Success | Notification => {
+                event_send.send(TuiEvent::ReadStates(packet.read_states)).await?;
+                Ok(())
+            }
+            Failed => {
+                if let Some(message) = packet.error_message {
+                    Err(anyhow!("Failed to retrieve read states: {message}"))
+                } else {
+                    Err(anyhow!("Failed to retrieve read states"))
+                }
+            }
+        },
+        ServerInfo(packet) => match packet.status {
+            Success => {
+                info!("Server runs version {}", packet.server_version);
+                event_send
+                    .send(TuiEvent::ServerInfo(packet.server_version, packet.min_client_version))
+                    .await?;
+                Ok(())
+            }
+            Failed => {
+                if let Some(message) = packet.error_message {
+                    Err(anyhow!("Failed to retrieve server info: {message}"))
+                } else {
+                    Err(anyhow!("Failed to retrieve server info"))
+                }
+            }
+            Notification => Err(anyhow!("Malformed packet, notification bit should not be set")),
+        },
+        DirectChannels(packet) => match packet.status {
+            Success | Notification => {
+                event_send.send(TuiEvent::DirectChannels(packet.direct_channels)).await?;
+                Ok(())
+            }
+            Failed => {
+                if let Some(message) = packet.error_message {
+                    event_send.send(TuiEvent::DirectChannelFailed(message.clone())).await?;
+                    Err(anyhow!("Failed to open a direct channel: {message}"))
+                } else {
+                    Err(anyhow!("Failed to retrieve direct channels"))
+                }
+            }
+        },
+        Capabilities(packet) => match packet.status {
+            Success => {
+                info!("Server supports {:?}", packet.capabilities.names());
+                event_send.send(TuiEvent::Capabilities(packet.capabilities)).await?;
+                Ok(())
+            }
+            // The assumed capabilities stay in place
+            Failed => {
+                if let Some(message) = packet.error_message {
+                    Err(anyhow!("Failed to retrieve capabilities: {message}"))
+                } else {
+                    Err(anyhow!("Failed to retrieve capabilities"))
+                }
+            }
+            Notification => Err(anyhow!("Malformed packet, notification bit should not be set")),
+        },
+        Goodbye(packet) => {
+            info!("The server is closing the connection: {:?} {}", packet.reason, packet.message);
+            event_send.send(TuiEvent::ServerGoodbye(packet.reason, packet.message)).await?;
+            Ok(())
+        }
+        Resume(packet) => match packet.status {
+            Success => {
+                info!("Resumed the previous session");
+                event_send.send(TuiEvent::SessionResumed).await?;
+                Ok(())
+            }
+            Failed => {
+                info!("The server rejected resuming the session: {:?}", packet.error_message);
+                event_send
+                    .send(TuiEvent::ResumeRejected(packet.error_message.filter(|message| !message.is_empty())))
+                    .await?;
+                Ok(())
+            }
+            Notification => Err(anyhow!("Malformed packet, notification bit should not be set")),
+        },
+        UserConfigAck(packet) => match packet.status {
+            Success => {
+                event_send.send(TuiEvent::UserConfigAck(Ok(()))).await?;
+                Ok(())
+            }
+            Failed => {
+                let message = packet.error_message.unwrap_or_else(|| "Unknown error".to_owned());
+                event_send.send(TuiEvent::UserConfigAck(Err(message.clone()))).await?;
+                Err(anyhow!("Failed to update the profile: {message}"))
+            }
+            Notification => Err(anyhow!("Malformed packet, notification bit should not be set")),
+        },
+        MessageEdited(packet) => match packet.status {
+            Success | Notification => {
+                event_send
+                    .send(TuiEvent::MessageEdited(packet.channel_id, packet.message_id, packet.message_text))
+                    .await?;
+                Ok(())
+            }
+            Failed => {
+                event_send.send(TuiEvent::MessageEditFailed(packet.message_id)).await?;
+                if let Some(message) = packet.error_message {
+                    Err(anyhow!("Failed to edit message: {message}"))
+                } else {
+                    Err(anyhow!("Failed to edit message"))
+                }
+            }
+        },
     }
 }
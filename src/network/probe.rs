@@ -0,0 +1,165 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use rustls::pki_types::ServerName;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::Sender;
+use tokio::time::{Instant, timeout};
+
+use crate::network::client::{Client, ConnectionType, InteractedTimeStamp, MAX_MESSAGE_LENGTH, ServerAddrInfo};
+use crate::network::protocol::client::{ClientPacketType, ClientPayload};
+use crate::network::protocol::server::ServerPayload;
+use crate::network::transport::tls_connector;
+use crate::tui::events::TuiEvent;
+
+/// How long each stage may take before it counts as failed
+const STAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Steps of a connection test, each only runs when the one before it passed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeStage {
+    Lookup,
+    Tcp,
+    Tls,
+    Handshake,
+}
+
+impl ProbeStage {
+    pub const ALL: [ProbeStage; 4] = [ProbeStage::Lookup, ProbeStage::Tcp, ProbeStage::Tls, ProbeStage::Handshake];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ProbeStage::Lookup => "Address lookup",
+            ProbeStage::Tcp => "TCP connection",
+            ProbeStage::Tls => "TLS handshake",
+            ProbeStage::Handshake => "Protocol handshake",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProbeOutcome {
+    Running,
+    /// Passed, with details like the time it took
+    Passed(String),
+    Failed(String),
+    /// Not needed for this server, or not reached because an earlier stage failed
+    Skipped(String),
+}
+
+/// Tests whether `server` can be logged in to, on a connection of its own so the session of the client is left alone.
+/// Connects, does the TLS handshake when it is enabled and asks for the server info, without sending credentials.
+/// Every stage is reported with a `ConnectionProbeUpdate` event as it starts and ends.
+pub async fn probe_connection(server: ServerAddrInfo, event_send: Sender<TuiEvent>) {
+    let report = async |stage, outcome| {
+        let _ = event_send.send(TuiEvent::ConnectionProbeUpdate(stage, outcome)).await;
+    };
+    let skip_rest = async |stages: &[ProbeStage]| {
+        for stage in stages {
+            let _ = event_send
+                .send(TuiEvent::ConnectionProbeUpdate(
+                    *stage,
+                    ProbeOutcome::Skipped("an earlier stage failed".to_owned()),
+                ))
+                .await;
+        }
+    };
+
+    let target_addr = SocketAddr::new(server.ip, server.port);
+    report(ProbeStage::Tcp, ProbeOutcome::Running).await;
+    let started = Instant::now();
+    let tcp = match run_stage(TcpStream::connect(target_addr)).await {
+        Ok(tcp) => {
+            report(
+                ProbeStage::Tcp,
+                ProbeOutcome::Passed(format!("{target_addr} in {} ms", started.elapsed().as_millis())),
+            )
+            .await;
+            tcp
+        }
+        Err(e) => {
+            report(ProbeStage::Tcp, ProbeOutcome::Failed(e.to_string())).await;
+            return skip_rest(&[ProbeStage::Tls, ProbeStage::Handshake]).await;
+        }
+    };
+
+    let handshake = match (&server.connection_type, &server.domain) {
+        (ConnectionType::Raw, _) => {
+            report(ProbeStage::Tls, ProbeOutcome::Skipped("TLS is not enabled".to_owned())).await;
+            report(ProbeStage::Handshake, ProbeOutcome::Running).await;
+            handshake(tcp).await
+        }
+        (ConnectionType::TLS, None) => {
+            report(ProbeStage::Tls, ProbeOutcome::Failed("TLS requires a domain".to_owned())).await;
+            return skip_rest(&[ProbeStage::Handshake]).await;
+        }
+        (ConnectionType::TLS, Some(domain)) => {
+            report(ProbeStage::Tls, ProbeOutcome::Running).await;
+            let started = Instant::now();
+            let tls = match ServerName::try_from(domain.clone()) {
+                Ok(domain_name) => run_stage(tls_connector().connect(domain_name, tcp)).await,
+                Err(e) => Err(e.into()),
+            };
+            match tls {
+                Ok(tls) => {
+                    report(
+                        ProbeStage::Tls,
+                        ProbeOutcome::Passed(format!("certificate valid for {domain}, in {} ms", started.elapsed().as_millis())),
+                    )
+                    .await;
+                    report(ProbeStage::Handshake, ProbeOutcome::Running).await;
+                    handshake(tls).await
+                }
+                Err(e) => {
+                    report(ProbeStage::Tls, ProbeOutcome::Failed(e.to_string())).await;
+                    return skip_rest(&[ProbeStage::Handshake]).await;
+                }
+            }
+        }
+    };
+
+    match handshake {
+        Ok(server_version) => {
+            report(
+                ProbeStage::Handshake,
+                ProbeOutcome::Passed(format!("server runs version {server_version}")),
+            )
+            .await
+        }
+        Err(e) => report(ProbeStage::Handshake, ProbeOutcome::Failed(e.to_string())).await,
+    }
+}
+
+/// Runs a stage with the stage timeout
+async fn run_stage<T, E: Into<anyhow::Error>>(stage: impl Future<Output = std::result::Result<T, E>>) -> Result<T> {
+    match timeout(STAGE_TIMEOUT, stage).await {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => Err(anyhow!("no answer within {} seconds", STAGE_TIMEOUT.as_secs())),
+    }
+}
+
+/// Asks for the server info, a packet the server answers before logging in, and returns the version of the server
+async fn handshake(mut stream: impl AsyncRead + AsyncWrite + Send + Unpin) -> Result<String> {
+    Client::send_message(
+        &mut stream,
+        InteractedTimeStamp::new(),
+        ClientPacketType::ServerInfo,
+        ClientPayload::ServerInfo,
+    )
+    .await?;
+
+    let mut header_buffer: [u8; 10] = [0; 10];
+    let mut payload_buffer = vec![0; MAX_MESSAGE_LENGTH];
+    run_stage(async {
+        loop {
+            // Health checks the server sends in the meantime are not the answer
+            let (payload, _) = Client::read_message(&mut stream, InteractedTimeStamp::new(), &mut header_buffer, &mut payload_buffer).await?;
+            if let ServerPayload::ServerInfo(packet) = payload {
+                return Ok::<_, anyhow::Error>(packet.server_version);
+            }
+        }
+    })
+    .await
+}
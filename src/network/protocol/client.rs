@@ -1,6 +1,7 @@
 use crate::network::protocol::server::{HealthCheckPacket, HealthKind};
 use crate::network::protocol::{MediaType, UserStatus};
-use crate::tui::events::{ChannelId, MediaId, MessageId, UserId};
+use crate::storage::secrets::Secret;
+use crate::tui::events::{ChannelId, MediaId, MessageId, ProfilePicId, UserId};
 
 pub trait Serialize {
     fn serialize(self) -> Vec<u8>;
@@ -21,6 +22,17 @@ pub enum ClientPacketType {
     Media = 0x89,
     Typing = 0x8A,
     Status = 0x8B,
+    ReadStates = 0x8D,
+    MarkRead = 0x8E,
+    EditMessage = 0x8F,
+    ServerInfo = 0x90,
+    LoginCode = 0x91,
+    DirectChannels = 0x92,
+    OpenDirectChannel = 0x93,
+    Capabilities = 0x94,
+    Resume = 0x95,
+    TokenLogin = 0x96,
+    UserConfigSet = 0xB2,
 }
 
 impl Serialize for ClientPacketType {
@@ -43,6 +55,17 @@ pub enum ClientPayload {
     Media(GetMediaPacket),
     Typing(TypingPacket),
     Status(StatusPacket),
+    ReadStates,
+    MarkRead(MarkReadPacket),
+    EditMessage(EditMessagePacket),
+    ServerInfo,
+    LoginCode(LoginCodePacket),
+    DirectChannels,
+    OpenDirectChannel(OpenDirectChannelPacket),
+    Capabilities,
+    UserConfigSet(UserConfigSetPacket),
+    Resume(ResumePacket),
+    TokenLogin(TokenLoginPacket),
 }
 
 impl Serialize for ClientPayload {
@@ -61,6 +84,17 @@ impl Serialize for ClientPayload {
             Media(packet) => packet.serialize(),
             Typing(packet) => packet.serialize(),
             Status(packet) => packet.serialize(),
+            ReadStates => vec![],
+            MarkRead(packet) => packet.serialize(),
+            EditMessage(packet) => packet.serialize(),
+            ServerInfo => vec![],
+            LoginCode(packet) => packet.serialize(),
+            DirectChannels => vec![],
+            OpenDirectChannel(packet) => packet.serialize(),
+            Capabilities => vec![],
+            UserConfigSet(packet) => packet.serialize(),
+            Resume(packet) => packet.serialize(),
+            TokenLogin(packet) => packet.serialize(),
         }
     }
 }
@@ -80,7 +114,7 @@ impl Serialize for HealthCheckPacket {
 #[derive(Debug, Clone)]
 pub struct LoginPacket {
     pub username: String,
-    pub password: String,
+    pub password: Secret,
 }
 
 impl Serialize for LoginPacket {
@@ -88,11 +122,23 @@ impl Serialize for LoginPacket {
         let mut bytes = Vec::new();
         bytes.extend(self.username.as_bytes());
         bytes.push(b'\0');
-        bytes.extend(self.password.as_bytes());
+        bytes.extend(self.password.expose().as_bytes());
         bytes
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct LoginCodePacket {
+    pub code: String,
+}
+
+// [packet content]: [code]
+impl Serialize for LoginCodePacket {
+    fn serialize(self) -> Vec<u8> {
+        self.code.into_bytes()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct GetChannelsPacket {
     pub channel_ids: Vec<ChannelId>,
@@ -137,7 +183,7 @@ impl Serialize for Anchor {
     fn serialize(self) -> Vec<u8> {
         match self {
             Anchor::Timestamp(anchor) => anchor.to_be_bytes().to_vec(),
-            Anchor::MessageId(anchor) => todo!(),
+            Anchor::MessageId(anchor) => (anchor | 1 << 63).to_be_bytes().to_vec(),
         }
     }
 }
@@ -250,3 +296,141 @@ impl Serialize for StatusPacket {
         self.status.serialize()
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct MarkReadPacket {
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+}
+
+// [packet content]: [channel_id|8][message_id|8]
+impl Serialize for MarkReadPacket {
+    fn serialize(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16);
+        bytes.extend_from_slice(&self.channel_id.to_be_bytes());
+        bytes.extend_from_slice(&self.message_id.to_be_bytes());
+        bytes
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenDirectChannelPacket {
+    pub user_id: UserId,
+}
+
+// [packet content]: [user_id|8]
+impl Serialize for OpenDirectChannelPacket {
+    fn serialize(self) -> Vec<u8> {
+        self.user_id.to_be_bytes().to_vec()
+    }
+}
+
+/// Continues the session of an earlier connection instead of logging in,
+/// with the newest message seen per channel so the server only sends the messages after those
+#[derive(Debug, Clone)]
+pub struct ResumePacket {
+    pub session_token: Secret,
+    pub last_seen: Vec<(ChannelId, MessageId)>,
+}
+
+// [packet content]: [session_token][\0][channel_count|2]([channel_id|8][message_id|8])*
+impl Serialize for ResumePacket {
+    fn serialize(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.session_token.expose().len() + 3 + self.last_seen.len() * 16);
+        bytes.extend(self.session_token.expose().as_bytes());
+        bytes.push(b'\0');
+        bytes.extend((self.last_seen.len() as u16).to_be_bytes());
+        for (channel_id, message_id) in self.last_seen {
+            bytes.extend(channel_id.to_be_bytes());
+            bytes.extend(message_id.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+/// Logs in with the session token of an earlier login instead of the password, answered like a login.
+/// Servers that keep their tokens accept it after restarting, when the session itself can no longer be resumed
+#[derive(Debug, Clone)]
+pub struct TokenLoginPacket {
+    pub session_token: Secret,
+}
+
+// [packet content]: [session_token]
+impl Serialize for TokenLoginPacket {
+    fn serialize(self) -> Vec<u8> {
+        self.session_token.expose().as_bytes().to_vec()
+    }
+}
+
+/// Part of the profile of the user that is changed
+#[derive(Debug, Clone, PartialEq)]
+pub enum UserConfigField {
+    Username(String),
+    Bio(String),
+    /// 0 removes the profile picture
+    ProfilePicture(ProfilePicId),
+    /// Only changed when the current password is right
+    Password {
+        current: Secret,
+        new: Secret,
+    },
+}
+
+impl UserConfigField {
+    fn id(&self) -> u8 {
+        match self {
+            UserConfigField::Username(_) => 0x00,
+            UserConfigField::Bio(_) => 0x01,
+            UserConfigField::ProfilePicture(_) => 0x02,
+            UserConfigField::Password { .. } => 0x03,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UserConfigSetPacket {
+    pub fields: Vec<UserConfigField>,
+}
+
+// [packet content]: [num_fields|1][field1|1][value_length1|2][value1]...
+// the value of a password field is [current_length|2][current][new_length|2][new]
+impl Serialize for UserConfigSetPacket {
+    fn serialize(self) -> Vec<u8> {
+        let mut bytes = vec![self.fields.len() as u8];
+        for field in self.fields {
+            bytes.push(field.id());
+            let value = match field {
+                UserConfigField::Username(text) | UserConfigField::Bio(text) => text.into_bytes(),
+                UserConfigField::ProfilePicture(pfp_id) => pfp_id.to_be_bytes().to_vec(),
+                UserConfigField::Password { current, new } => {
+                    let (current, new) = (current.expose().as_bytes(), new.expose().as_bytes());
+                    let mut value = Vec::with_capacity(4 + current.len() + new.len());
+                    value.extend((current.len() as u16).to_be_bytes());
+                    value.extend(current);
+                    value.extend((new.len() as u16).to_be_bytes());
+                    value.extend(new);
+                    value
+                }
+            };
+            bytes.extend((value.len() as u16).to_be_bytes());
+            bytes.extend(value);
+        }
+        bytes
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EditMessagePacket {
+    pub message_id: MessageId,
+    pub message_text: String,
+}
+
+// [packet content]: [message_id|8][message_text]
+impl Serialize for EditMessagePacket {
+    fn serialize(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.message_text.len());
+        bytes.extend_from_slice(&self.message_id.to_be_bytes());
+        bytes.extend_from_slice(self.message_text.as_bytes());
+        bytes
+    }
+}
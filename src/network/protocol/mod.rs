@@ -9,7 +9,7 @@ pub mod header;
 pub mod server;
 
 #[repr(u8)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum MediaType {
     Raw = 0x00,
     Text = 0x01,
@@ -63,3 +63,62 @@ impl Serialize for UserStatus {
         vec![self as u8]
     }
 }
+
+/// Optional features a server advertises in answer to a capabilities request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+    pub reactions: bool,
+    pub editing: bool,
+    pub search: bool,
+    /// Media uploads, which are written to the server in chunks
+    pub chunked_media: bool,
+    /// Continuing a session after a reconnect with its session token, the server then only sends what was missed
+    pub resume: bool,
+    /// Logging in with the session token of an earlier login, reconnects then do not send the password again
+    pub token_login: bool,
+}
+
+impl Capabilities {
+    const REACTIONS: u32 = 1 << 0;
+    const EDITING: u32 = 1 << 1;
+    const SEARCH: u32 = 1 << 2;
+    const CHUNKED_MEDIA: u32 = 1 << 3;
+    const RESUME: u32 = 1 << 4;
+    const TOKEN_LOGIN: u32 = 1 << 5;
+
+    /// Assumed for servers that do not answer the capabilities request, the features the client used before servers advertised them
+    pub const ASSUMED: Capabilities = Capabilities {
+        reactions: false,
+        editing: true,
+        search: false,
+        chunked_media: true,
+        resume: false,
+        token_login: false,
+    };
+
+    pub fn from_bits(bits: u32) -> Self {
+        Capabilities {
+            reactions: bits & Self::REACTIONS != 0,
+            editing: bits & Self::EDITING != 0,
+            search: bits & Self::SEARCH != 0,
+            chunked_media: bits & Self::CHUNKED_MEDIA != 0,
+            resume: bits & Self::RESUME != 0,
+            token_login: bits & Self::TOKEN_LOGIN != 0,
+        }
+    }
+
+    /// Names of the supported features, for the logs
+    pub fn names(self) -> Vec<&'static str> {
+        [
+            (self.reactions, "reactions"),
+            (self.editing, "editing"),
+            (self.search, "search"),
+            (self.chunked_media, "chunked media"),
+            (self.resume, "resume"),
+            (self.token_login, "token login"),
+        ]
+        .into_iter()
+        .filter_map(|(supported, name)| supported.then_some(name))
+        .collect()
+    }
+}
@@ -2,7 +2,8 @@ use anyhow::{Result, anyhow};
 use log::{debug, error, info};
 
 use crate::network::client::MAX_MESSAGE_LENGTH;
-use crate::network::protocol::{MediaType, UserStatus};
+use crate::network::protocol::{Capabilities, MediaType, UserStatus};
+use crate::storage::secrets::Secret;
 use crate::tui::events::{ChannelId, IconId, MediaId, MessageId, ProfilePicId, UserId};
 
 pub trait Deserialize: Sized {
@@ -27,6 +28,15 @@ pub enum ServerPacketType {
     Media = 0x09,
     Typing = 0x0A,
     UserStatus = 0x0B,
+    ChannelEvent = 0x0C,
+    ReadStates = 0x0D,
+    MessageEdited = 0x0F,
+    ServerInfo = 0x10,
+    DirectChannels = 0x12,
+    Goodbye = 0x13,
+    Capabilities = 0x14,
+    ResumeAck = 0x15,
+    UserConfigAck = 0x32,
 }
 
 impl DeserializeByte for ServerPacketType {
@@ -45,6 +55,15 @@ impl DeserializeByte for ServerPacketType {
             0x09 => Ok(Media),
             0x0A => Ok(Typing),
             0x0B => Ok(UserStatus),
+            0x0C => Ok(ChannelEvent),
+            0x0D => Ok(ReadStates),
+            0x0F => Ok(MessageEdited),
+            0x10 => Ok(ServerInfo),
+            0x12 => Ok(DirectChannels),
+            0x13 => Ok(Goodbye),
+            0x14 => Ok(Capabilities),
+            0x15 => Ok(ResumeAck),
+            0x32 => Ok(UserConfigAck),
             other => Err(anyhow!("Unknown ServerPacketType: {}", other)),
         }
     }
@@ -64,6 +83,15 @@ pub enum ServerPayload {
     Media(MediaPacket),
     Typing(UserTypingPacket),
     Status(UserStatusPacket),
+    ChannelEvent(ChannelEventPacket),
+    ReadStates(ReadStatesPacket),
+    MessageEdited(MessageEditedPacket),
+    ServerInfo(ServerInfoPacket),
+    DirectChannels(DirectChannelsPacket),
+    Goodbye(GoodbyePacket),
+    Capabilities(CapabilitiesPacket),
+    UserConfigAck(UserConfigAckPacket),
+    Resume(ResumeAckPacket),
 }
 
 fn deserialize_error(bytes: &[u8], status: &ReturnStatus) -> Result<(Option<String>, usize)> {
@@ -98,6 +126,15 @@ impl ServerPayload {
             Media => deserialize_variant!(bytes, ServerPayload::Media, MediaPacket),
             Typing => deserialize_variant!(bytes, ServerPayload::Typing, UserTypingPacket),
             UserStatus => deserialize_variant!(bytes, ServerPayload::Status, UserStatusPacket),
+            ChannelEvent => deserialize_variant!(bytes, ServerPayload::ChannelEvent, ChannelEventPacket),
+            ReadStates => deserialize_variant!(bytes, ServerPayload::ReadStates, ReadStatesPacket),
+            MessageEdited => deserialize_variant!(bytes, ServerPayload::MessageEdited, MessageEditedPacket),
+            ServerInfo => deserialize_variant!(bytes, ServerPayload::ServerInfo, ServerInfoPacket),
+            DirectChannels => deserialize_variant!(bytes, ServerPayload::DirectChannels, DirectChannelsPacket),
+            Goodbye => deserialize_variant!(bytes, ServerPayload::Goodbye, GoodbyePacket),
+            Capabilities => deserialize_variant!(bytes, ServerPayload::Capabilities, CapabilitiesPacket),
+            UserConfigAck => deserialize_variant!(bytes, ServerPayload::UserConfigAck, UserConfigAckPacket),
+            ResumeAck => deserialize_variant!(bytes, ServerPayload::Resume, ResumeAckPacket),
         }
     }
 }
@@ -107,7 +144,7 @@ impl ServerPayload {
 pub enum ReturnStatus {
     Success = 0x00,
     Failed = 0x01,
-    Notification = 0x02, // Only used for HISTORY
+    Notification = 0x02, // Only used for HISTORY, READ STATES and MESSAGE EDITED
 }
 
 impl DeserializeByte for ReturnStatus {
@@ -123,7 +160,7 @@ impl DeserializeByte for ReturnStatus {
 
 impl Deserialize for String {
     fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
-        let length = bytes.iter().position(|&b| b == 0).unwrap_or(MAX_MESSAGE_LENGTH);
+        let length = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len().min(MAX_MESSAGE_LENGTH));
         let string = String::from_utf8(bytes[0..length].to_vec())?;
         Ok((string, length))
     }
@@ -158,19 +195,46 @@ impl Deserialize for HealthCheckPacket {
     }
 }
 
+/// Status byte of a login response asking for the code of the second factor
+const LOGIN_CODE_REQUIRED: u8 = 0x02;
+
 #[derive(Debug, Clone)]
 pub struct LoginAckPacket {
     pub status: ReturnStatus,
+    /// The password was accepted, a login code has to be sent next. The status is `Failed` then,
+    /// with an error message when an earlier code was rejected
+    pub code_required: bool,
     pub error_message: Option<String>,
+    /// Token to resume this session with after a reconnect, sent by servers that support resuming
+    pub session_token: Option<Secret>,
 }
 
 impl Deserialize for LoginAckPacket {
     fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
-        let status = ReturnStatus::deserialize_byte(bytes[0])?;
+        let code_required = bytes[0] == LOGIN_CODE_REQUIRED;
+        let status = if code_required {
+            ReturnStatus::Failed
+        } else {
+            ReturnStatus::deserialize_byte(bytes[0])?
+        };
         let mut byte_index = 1;
         let (error_message, error_len) = deserialize_error(&bytes[byte_index..], &status)?;
         byte_index += error_len;
-        Ok((LoginAckPacket { status, error_message }, byte_index))
+        let mut session_token = None;
+        if status == ReturnStatus::Success && bytes.len() > byte_index {
+            let (token, token_len) = String::deserialize(&bytes[byte_index..])?;
+            byte_index += token_len;
+            session_token = Some(token).filter(|token| !token.is_empty()).map(Secret::new);
+        }
+        Ok((
+            LoginAckPacket {
+                status,
+                code_required,
+                error_message,
+                session_token,
+            },
+            byte_index,
+        ))
     }
 }
 
@@ -526,6 +590,8 @@ pub struct MediaPacket {
     pub filename: String,
     pub media_type: MediaType,
     pub media_data: Vec<u8>,
+    /// SHA-256 of the media data, older servers do not send it
+    pub checksum: Option<[u8; 32]>,
     pub error_message: Option<String>,
 }
 
@@ -548,6 +614,14 @@ impl Deserialize for MediaPacket {
         let media_data = bytes[byte_index..byte_index + media_length].to_vec();
         byte_index += media_length;
 
+        let checksum = if status == ReturnStatus::Success && bytes.len() >= byte_index + 32 {
+            let checksum = bytes[byte_index..byte_index + 32].try_into()?;
+            byte_index += 32;
+            Some(checksum)
+        } else {
+            None
+        };
+
         let (error_message, error_len) = deserialize_error(&bytes[byte_index..], &status)?;
         byte_index += error_len;
 
@@ -557,6 +631,7 @@ impl Deserialize for MediaPacket {
                 filename,
                 media_type,
                 media_data,
+                checksum,
                 error_message,
             },
             byte_index,
@@ -612,3 +687,336 @@ impl Deserialize for UserStatusPacket {
         Ok((UserStatusPacket { status, user_id }, byte_index))
     }
 }
+
+#[repr(u8)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChannelEventKind {
+    UserJoined = 0x00,
+    UserLeft = 0x01,
+    TopicChanged = 0x02,
+    PinAdded = 0x03,
+}
+
+impl DeserializeByte for ChannelEventKind {
+    fn deserialize_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0x00 => Ok(ChannelEventKind::UserJoined),
+            0x01 => Ok(ChannelEventKind::UserLeft),
+            0x02 => Ok(ChannelEventKind::TopicChanged),
+            0x03 => Ok(ChannelEventKind::PinAdded),
+            other => Err(anyhow!("Unknown ChannelEventKind value: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelEventPacket {
+    pub kind: ChannelEventKind,
+    pub channel_id: ChannelId,
+    pub user_id: UserId,
+    pub timestamp: u64,
+    pub text: String,
+}
+
+// [event_kind|1][channel_id|8][user_id|8][timestamp|8][text_len|2][text]
+impl Deserialize for ChannelEventPacket {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
+        let header = bytes
+            .get(..27)
+            .ok_or_else(|| anyhow!("Not enough bytes to deserialize ChannelEventPacket"))?;
+        let kind = ChannelEventKind::deserialize_byte(header[0])?;
+        let channel_id = ChannelId::from_be_bytes(header[1..9].try_into()?);
+        let user_id = UserId::from_be_bytes(header[9..17].try_into()?);
+        let timestamp = u64::from_be_bytes(header[17..25].try_into()?);
+
+        let text_len = u16::from_be_bytes(header[25..27].try_into()?) as usize;
+        let text = bytes
+            .get(27..27 + text_len)
+            .ok_or_else(|| anyhow!("Not enough bytes to deserialize the text of ChannelEventPacket"))?;
+        let text = String::from_utf8(text.to_vec())?;
+
+        Ok((
+            ChannelEventPacket {
+                kind,
+                channel_id,
+                user_id,
+                timestamp,
+                text,
+            },
+            27 + text_len,
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReadStatesPacket {
+    pub status: ReturnStatus,
+    pub read_states: Vec<(ChannelId, MessageId)>,
+    pub error_message: Option<String>,
+}
+
+// [status|1][num_channels|2][channel_id1|8][message_id1|8]...[channel_idnum|8][message_idnum|8][error_message]
+impl Deserialize for ReadStatesPacket {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
+        let header = bytes
+            .get(..3)
+            .ok_or_else(|| anyhow!("Not enough bytes to deserialize ReadStatesPacket"))?;
+        let status = ReturnStatus::deserialize_byte(header[0])?;
+
+        let channels_count = u16::from_be_bytes(header[1..3].try_into()?) as usize;
+        let mut read_states = Vec::with_capacity(channels_count);
+
+        let mut byte_index = 3;
+        for _ in 0..channels_count {
+            let entry = bytes
+                .get(byte_index..byte_index + 16)
+                .ok_or_else(|| anyhow!("Not enough bytes to deserialize the read states of ReadStatesPacket"))?;
+            let channel_id = ChannelId::from_be_bytes(entry[..8].try_into()?);
+            let message_id = MessageId::from_be_bytes(entry[8..].try_into()?);
+            read_states.push((channel_id, message_id));
+            byte_index += 16;
+        }
+
+        let (error_message, error_len) = deserialize_error(&bytes[byte_index..], &status)?;
+        byte_index += error_len;
+        Ok((
+            ReadStatesPacket {
+                status,
+                read_states,
+                error_message,
+            },
+            byte_index,
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectChannelsPacket {
+    pub status: ReturnStatus,
+    /// Direct channels and the other user in them
+    pub direct_channels: Vec<(ChannelId, UserId)>,
+    pub error_message: Option<String>,
+}
+
+// [status|1][num_channels|2][channel_id1|8][user_id1|8]...[channel_idnum|8][user_idnum|8][error_message]
+impl Deserialize for DirectChannelsPacket {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
+        if bytes.len() < 3 {
+            return Err(anyhow!("Not enough bytes to deserialize DirectChannelsPacket"));
+        }
+        let status = ReturnStatus::deserialize_byte(bytes[0])?;
+
+        let channels_count = u16::from_be_bytes(bytes[1..3].try_into()?) as usize;
+        let mut direct_channels = Vec::with_capacity(channels_count);
+
+        let mut byte_index = 3;
+        for _ in 0..channels_count {
+            let entry = bytes
+                .get(byte_index..byte_index + 16)
+                .ok_or_else(|| anyhow!("Not enough bytes to deserialize DirectChannelsPacket"))?;
+            let channel_id = ChannelId::from_be_bytes(entry[0..8].try_into()?);
+            let user_id = UserId::from_be_bytes(entry[8..16].try_into()?);
+            direct_channels.push((channel_id, user_id));
+            byte_index += 16;
+        }
+
+        let (error_message, error_len) = deserialize_error(&bytes[byte_index..], &status)?;
+        byte_index += error_len;
+        Ok((
+            DirectChannelsPacket {
+                status,
+                direct_channels,
+                error_message,
+            },
+            byte_index,
+        ))
+    }
+}
+
+/// Why the server closed the connection
+#[repr(u8)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisconnectReason {
+    Shutdown = 0x00,
+    Kicked = 0x01,
+    Banned = 0x02,
+    IdleTimeout = 0x03,
+}
+
+impl DeserializeByte for DisconnectReason {
+    fn deserialize_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0x00 => Ok(DisconnectReason::Shutdown),
+            0x01 => Ok(DisconnectReason::Kicked),
+            0x02 => Ok(DisconnectReason::Banned),
+            0x03 => Ok(DisconnectReason::IdleTimeout),
+            other => Err(anyhow!("Unknown DisconnectReason value: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GoodbyePacket {
+    pub reason: DisconnectReason,
+    pub message: String,
+}
+
+// [reason|1][message]
+impl Deserialize for GoodbyePacket {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
+        let reason = DisconnectReason::deserialize_byte(*bytes.first().ok_or_else(|| anyhow!("Not enough bytes to deserialize GoodbyePacket"))?)?;
+        let (message, message_len) = String::deserialize(&bytes[1..])?;
+        Ok((GoodbyePacket { reason, message }, 1 + message_len))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CapabilitiesPacket {
+    pub status: ReturnStatus,
+    pub capabilities: Capabilities,
+    pub error_message: Option<String>,
+}
+
+// [status|1][capabilities|4][error_message]
+impl Deserialize for CapabilitiesPacket {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
+        let status = ReturnStatus::deserialize_byte(
+            *bytes
+                .first()
+                .ok_or_else(|| anyhow!("Not enough bytes to deserialize CapabilitiesPacket"))?,
+        )?;
+        let bits = bytes
+            .get(1..5)
+            .ok_or_else(|| anyhow!("Not enough bytes to deserialize CapabilitiesPacket"))?;
+        let capabilities = Capabilities::from_bits(u32::from_be_bytes(bits.try_into()?));
+        let (error_message, error_len) = deserialize_error(&bytes[5..], &status)?;
+        Ok((
+            CapabilitiesPacket {
+                status,
+                capabilities,
+                error_message,
+            },
+            5 + error_len,
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserConfigAckPacket {
+    pub status: ReturnStatus,
+    pub error_message: Option<String>,
+}
+
+// [status|1][error_message]
+impl Deserialize for UserConfigAckPacket {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
+        let status = ReturnStatus::deserialize_byte(
+            *bytes
+                .first()
+                .ok_or_else(|| anyhow!("Not enough bytes to deserialize UserConfigAckPacket"))?,
+        )?;
+        let (error_message, error_len) = deserialize_error(&bytes[1..], &status)?;
+        Ok((UserConfigAckPacket { status, error_message }, 1 + error_len))
+    }
+}
+
+/// Answer to a resume request, a failed resume means the session is gone and the client has to log in again
+#[derive(Debug, Clone)]
+pub struct ResumeAckPacket {
+    pub status: ReturnStatus,
+    pub error_message: Option<String>,
+}
+
+// [status|1][error_message]
+impl Deserialize for ResumeAckPacket {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
+        let status = ReturnStatus::deserialize_byte(*bytes.first().ok_or_else(|| anyhow!("Not enough bytes to deserialize ResumeAckPacket"))?)?;
+        let (error_message, error_len) = deserialize_error(&bytes[1..], &status)?;
+        Ok((ResumeAckPacket { status, error_message }, 1 + error_len))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageEditedPacket {
+    pub status: ReturnStatus,
+    pub message_id: MessageId,
+    pub channel_id: ChannelId,
+    pub message_text: String,
+    pub error_message: Option<String>,
+}
+
+// [status|1][message_id|8][channel_id|8][message_len|2][message_text][error_message]
+impl Deserialize for MessageEditedPacket {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
+        if bytes.len() < 19 {
+            return Err(anyhow!("Not enough bytes to deserialize MessageEditedPacket"));
+        }
+        let status = ReturnStatus::deserialize_byte(bytes[0])?;
+        let message_id = MessageId::from_be_bytes(bytes[1..9].try_into()?);
+        let channel_id = ChannelId::from_be_bytes(bytes[9..17].try_into()?);
+
+        let message_len = u16::from_be_bytes(bytes[17..19].try_into()?) as usize;
+        let mut byte_index = 19;
+        let message_text = bytes
+            .get(byte_index..byte_index + message_len)
+            .ok_or_else(|| anyhow!("Not enough bytes to deserialize the text of MessageEditedPacket"))?;
+        let message_text = String::from_utf8(message_text.to_vec())?;
+        byte_index += message_len;
+
+        let (error_message, error_len) = deserialize_error(&bytes[byte_index..], &status)?;
+        byte_index += error_len;
+        Ok((
+            MessageEditedPacket {
+                status,
+                message_id,
+                channel_id,
+                message_text,
+                error_message,
+            },
+            byte_index,
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerInfoPacket {
+    pub status: ReturnStatus,
+    pub server_version: String,
+    pub min_client_version: String,
+    pub error_message: Option<String>,
+}
+
+// [status|1][server_version_len|1][server_version][min_client_version_len|1][min_client_version][error_message]
+impl Deserialize for ServerInfoPacket {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize)> {
+        let status = ReturnStatus::deserialize_byte(*bytes.first().ok_or_else(|| anyhow!("Not enough bytes to deserialize ServerInfoPacket"))?)?;
+        let mut byte_index = 1;
+
+        let mut versions = Vec::with_capacity(2);
+        for _ in 0..2 {
+            let len = *bytes
+                .get(byte_index)
+                .ok_or_else(|| anyhow!("Not enough bytes to deserialize ServerInfoPacket"))? as usize;
+            byte_index += 1;
+            let version = bytes
+                .get(byte_index..byte_index + len)
+                .ok_or_else(|| anyhow!("Not enough bytes to deserialize ServerInfoPacket"))?;
+            versions.push(String::from_utf8(version.to_vec())?);
+            byte_index += len;
+        }
+        let min_client_version = versions.pop().unwrap_or_default();
+        let server_version = versions.pop().unwrap_or_default();
+
+        let (error_message, error_len) = deserialize_error(&bytes[byte_index..], &status)?;
+        byte_index += error_len;
+        Ok((
+            ServerInfoPacket {
+                status,
+                server_version,
+                min_client_version,
+                error_message,
+            },
+            byte_index,
+        ))
+    }
+}
@@ -0,0 +1,75 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use log::info;
+use rustls::pki_types::ServerName;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+use crate::network::client::{ConnectionType, ServerAddrInfo};
+
+pub type ReadHalf = Box<dyn AsyncRead + Send + Unpin>;
+pub type WriteHalf = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// A way of reaching a server. Connecting gives the stream split in the half packets are read from and the half they are written to,
+/// so the client does not care what is underneath
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn connect(&self, server: &ServerAddrInfo) -> Result<(ReadHalf, WriteHalf)>;
+}
+
+impl ConnectionType {
+    pub fn transport(&self) -> Box<dyn Transport> {
+        match self {
+            ConnectionType::Raw => Box::new(RawTcp),
+            ConnectionType::TLS => Box::new(Tls),
+        }
+    }
+}
+
+/// Packets straight over TCP
+pub struct RawTcp;
+
+#[async_trait]
+impl Transport for RawTcp {
+    async fn connect(&self, server: &ServerAddrInfo) -> Result<(ReadHalf, WriteHalf)> {
+        let target_addr = SocketAddr::new(server.ip, server.port);
+        let connection_tcp = TcpStream::connect(target_addr).await?;
+        info!("Connected to {target_addr} from {}", connection_tcp.local_addr()?);
+
+        let (read_stream, write_stream) = connection_tcp.into_split();
+        Ok((Box::new(read_stream), Box::new(write_stream)))
+    }
+}
+
+/// Packets over TLS on top of TCP, the certificate is checked against the domain of the server
+pub struct Tls;
+
+#[async_trait]
+impl Transport for Tls {
+    async fn connect(&self, server: &ServerAddrInfo) -> Result<(ReadHalf, WriteHalf)> {
+        let domain = server.domain.clone().ok_or_else(|| anyhow!("TLS requires a domain"))?;
+        let target_addr = SocketAddr::new(server.ip, server.port);
+        let connection_tcp = TcpStream::connect(target_addr).await?;
+        let src_addr = connection_tcp.local_addr()?;
+
+        let connection_tls = tls_connector().connect(ServerName::try_from(domain)?, connection_tcp).await?;
+        info!("Connected to {target_addr} from {src_addr} over TLS");
+
+        let (read_stream, write_stream) = tokio::io::split(connection_tls);
+        Ok((Box::new(read_stream), Box::new(write_stream)))
+    }
+}
+
+/// Connector that trusts the web PKI roots, without client certificates
+pub fn tls_connector() -> TlsConnector {
+    // Source: https://docs.rs/rustls/latest/rustls/
+    let root_store = rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    let config = rustls::ClientConfig::builder().with_root_certificates(root_store).with_no_client_auth();
+
+    TlsConnector::from(Arc::new(config))
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::Utc;
+use tokio::io::{AsyncBufReadExt, BufReader, stdin};
+use tokio::net::lookup_host;
+use tokio::sync::mpsc;
+
+use crate::cli::AppConfig;
+use crate::network::client::{Client, ConnectionType, ServerAddrInfo};
+use crate::network::protocol::UserStatus;
+use crate::network::protocol::client::{
+    Anchor, ClientPacketType, ClientPayload, EditMessagePacket, GetChannelsPacket, GetHistoryPacket, GetMediaPacket, GetUsersPacket, LoginCodePacket,
+    LoginPacket, MarkReadPacket, OpenDirectChannelPacket, SendMessagePacket, StatusPacket, TypingPacket, UserConfigField, UserConfigSetPacket,
+};
+use crate::network::protocol::server::{HealthCheckPacket, HealthKind, ServerPayload};
+use crate::tui::events::TuiEvent;
+
+const PROMPT: &str = "chatger> ";
+const HELP: &str = "\
+Packets, arguments are given as key=value and text with spaces is quoted:
+  login [username=penger] [password=epicpass4]    defaults to the configured account
+  login-code code=123456
+  ping
+  channels-list
+  channels ids=1,2
+  history channel=3 [back=20] [before=<unix seconds> | message=10]    negative back loads newer messages
+  statuses
+  users ids=1,2
+  media id=5
+  send channel=3 text=\"hello there\" [reply=0] [media=1,2]
+  typing channel=3 [on=true]
+  status value=online|idle|dnd|offline
+  read-states
+  mark-read channel=3 message=10
+  edit message=10 text=\"hello again\"
+  server-info
+  capabilities
+  direct-channels
+  open-direct user=2
+  user-config [name=penger] [bio=\"hello there\"] [pfp=5]    pfp=0 removes the profile picture
+Other commands:
+  help
+  quit";
+
+/// Connects to the configured server and sends packets typed on stdin, every packet the server sends back is printed decoded.
+/// Meant for debugging server behavior without going through the TUI.
+pub async fn run(config: AppConfig) -> Result<()> {
+    let server_address = resolve(&config).await?;
+
+    let (event_send, mut event_recv) = mpsc::channel::<TuiEvent>(10);
+    let (payload_send, mut payload_recv) = mpsc::channel::<ServerPayload>(10);
+    let mut client = Client::new(event_send).with_raw_payloads(payload_send);
+    client.connect(&server_address).await?;
+    println!("Connected to {server_address}, type help for the available packets");
+
+    let mut lines = BufReader::new(stdin()).lines();
+    prompt();
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    break;
+                };
+                match line.trim() {
+                    "" => {}
+                    "quit" | "exit" => break,
+                    "help" => println!("{HELP}"),
+                    line => match parse_packet(line, &config) {
+                        Ok((packet_type, payload)) => {
+                            if let Err(e) = client.send_packet(packet_type, payload).await {
+                                eprintln!("Failed to send: {e}");
+                            }
+                        }
+                        Err(e) => eprintln!("{e}"),
+                    },
+                }
+                prompt();
+            }
+            Some(payload) = payload_recv.recv() => {
+                print_payload(payload);
+                prompt();
+            }
+            Some(TuiEvent::Disconnected) = event_recv.recv() => {
+                eprintln!("\nDisconnected from {server_address}");
+                break;
+            }
+        }
+    }
+
+    client.disconnect()
+}
+
+pub async fn resolve(config: &AppConfig) -> Result<ServerAddrInfo> {
+    let addr = lookup_host((config.address.as_str(), config.port))
+        .await
+        .with_context(|| format!("Could not resolve address {}", config.address))?
+        .next()
+        .ok_or_else(|| anyhow!("Could not resolve address {}", config.address))?;
+    let domain = config.address.parse::<IpAddr>().is_err().then(|| config.address.clone());
+    if config.enable_tls && domain.is_none() {
+        return Err(anyhow!("Unable to make TLS connection without a domain"));
+    }
+
+    Ok(ServerAddrInfo {
+        ip: addr.ip(),
+        port: addr.port(),
+        domain,
+        connection_type: if config.enable_tls { ConnectionType::TLS } else { ConnectionType::Raw },
+    })
+}
+
+fn prompt() {
+    print!("{PROMPT}");
+    let _ = std::io::stdout().flush();
+}
+
+fn print_payload(payload: ServerPayload) {
+    match payload {
+        // Printing the media data byte by byte would bury everything else
+        ServerPayload::Media(mut packet) => {
+            let size = packet.media_data.len();
+            packet.media_data.clear();
+            println!("\n{:#?}\n({size} bytes of media data left out)", ServerPayload::Media(packet));
+        }
+        payload => println!("\n{payload:#?}"),
+    }
+}
+
+fn parse_packet(line: &str, config: &AppConfig) -> Result<(ClientPacketType, ClientPayload)> {
+    let words = split_words(line)?;
+    let (name, args) = words.split_first().ok_or_else(|| anyhow!("Type a packet"))?;
+    let mut args = Args::parse(args)?;
+
+    let packet = match name.as_str() {
+        "login" => (
+            ClientPacketType::Login,
+            ClientPayload::Login(LoginPacket {
+                username: args.take("username")?.unwrap_or_else(|| config.username.clone()),
+                password: args.take("password")?.unwrap_or_else(|| config.password.clone()),
+            }),
+        ),
+        "login-code" => (
+            ClientPacketType::LoginCode,
+            ClientPayload::LoginCode(LoginCodePacket { code: args.require("code")? }),
+        ),
+        "ping" => (
+            ClientPacketType::Healthcheck,
+            ClientPayload::Health(HealthCheckPacket { kind: HealthKind::Pong }),
+        ),
+        "channels-list" => (ClientPacketType::ChannelsList, ClientPayload::ChannelsList),
+        "channels" => (
+            ClientPacketType::Channels,
+            ClientPayload::Channels(GetChannelsPacket {
+                channel_ids: args.take_list("ids")?,
+            }),
+        ),
+        "history" => (
+            ClientPacketType::History,
+            ClientPayload::History(GetHistoryPacket {
+                channel_id: args.require("channel")?,
+                anchor: match args.take("message")? {
+                    Some(message_id) => Anchor::MessageId(message_id),
+                    None => Anchor::Timestamp(args.take("before")?.unwrap_or(Utc::now().timestamp() as u64)),
+                },
+                num_messages_back: args.take("back")?.unwrap_or(20),
+            }),
+        ),
+        "statuses" => (ClientPacketType::UserStatuses, ClientPayload::UserStatuses),
+        "users" => (
+            ClientPacketType::Users,
+            ClientPayload::Users(GetUsersPacket {
+                user_ids: args.take_list("ids")?,
+            }),
+        ),
+        "media" => (
+            ClientPacketType::Media,
+            ClientPayload::Media(GetMediaPacket {
+                media_id: args.require("id")?,
+            }),
+        ),
+        "send" => (
+            ClientPacketType::SendMessage,
+            ClientPayload::SendMessage(SendMessagePacket {
+                channel_id: args.require("channel")?,
+                reply_id: args.take("reply")?.unwrap_or(0),
+                media_ids: args.take_list("media")?,
+                message_text: args.require("text")?,
+            }),
+        ),
+        "typing" => (
+            ClientPacketType::Typing,
+            ClientPayload::Typing(TypingPacket {
+                channel_id: args.require("channel")?,
+                is_typing: args.take("on")?.unwrap_or(true),
+            }),
+        ),
+        "status" => {
+            let status = match args.require::<String>("value")?.as_str() {
+                "online" => UserStatus::Online,
+                "idle" => UserStatus::Idle,
+                "dnd" => UserStatus::DoNotDisturb,
+                "offline" => UserStatus::Offline,
+                other => return Err(anyhow!("Unknown status {other}, expected online, idle, dnd or offline")),
+            };
+            (ClientPacketType::Status, ClientPayload::Status(StatusPacket { status }))
+        }
+        "read-states" => (ClientPacketType::ReadStates, ClientPayload::ReadStates),
+        "mark-read" => (
+            ClientPacketType::MarkRead,
+            ClientPayload::MarkRead(MarkReadPacket {
+                channel_id: args.require("channel")?,
+                message_id: args.require("message")?,
+            }),
+        ),
+        "edit" => (
+            ClientPacketType::EditMessage,
+            ClientPayload::EditMessage(EditMessagePacket {
+                message_id: args.require("message")?,
+                message_text: args.require("text")?,
+            }),
+        ),
+        "server-info" => (ClientPacketType::ServerInfo, ClientPayload::ServerInfo),
+        "capabilities" => (ClientPacketType::Capabilities, ClientPayload::Capabilities),
+        "direct-channels" => (ClientPacketType::DirectChannels, ClientPayload::DirectChannels),
+        "open-direct" => (
+            ClientPacketType::OpenDirectChannel,
+            ClientPayload::OpenDirectChannel(OpenDirectChannelPacket {
+                user_id: args.require("user")?,
+            }),
+        ),
+        "user-config" => {
+            let mut fields = vec![];
+            if let Some(name) = args.take("name")? {
+                fields.push(UserConfigField::Username(name));
+            }
+            if let Some(bio) = args.take("bio")? {
+                fields.push(UserConfigField::Bio(bio));
+            }
+            if let Some(pfp_id) = args.take("pfp")? {
+                fields.push(UserConfigField::ProfilePicture(pfp_id));
+            }
+            (
+                ClientPacketType::UserConfigSet,
+                ClientPayload::UserConfigSet(UserConfigSetPacket { fields }),
+            )
+        }
+        _ => return Err(anyhow!("Unknown packet {name}, type help for the available packets")),
+    };
+
+    args.finish()?;
+    Ok(packet)
+}
+
+/// Splits on whitespace, except inside double quotes
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+    for chr in line.chars() {
+        match chr {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            chr if chr.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            chr => {
+                word.push(chr);
+                in_word = true;
+            }
+        }
+    }
+    if quoted {
+        return Err(anyhow!("Missing closing quote"));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// `key=value` arguments of a packet, every argument has to be used so typos do not go unnoticed
+struct Args(HashMap<String, String>);
+
+impl Args {
+    fn parse(words: &[String]) -> Result<Self> {
+        let mut args = HashMap::new();
+        for word in words {
+            let (key, value) = word.split_once('=').ok_or_else(|| anyhow!("Expected key=value, got {word}"))?;
+            args.insert(key.to_owned(), value.to_owned());
+        }
+        Ok(Args(args))
+    }
+
+    fn take<T: FromStr>(&mut self, key: &str) -> Result<Option<T>> {
+        match self.0.remove(key) {
+            Some(value) => value.parse().map(Some).map_err(|_| anyhow!("Invalid value for {key}: {value}")),
+            None => Ok(None),
+        }
+    }
+
+    fn require<T: FromStr>(&mut self, key: &str) -> Result<T> {
+        self.take(key)?.ok_or_else(|| anyhow!("Missing {key}="))
+    }
+
+    fn take_list<T: FromStr>(&mut self, key: &str) -> Result<Vec<T>> {
+        match self.0.remove(key) {
+            Some(value) => value
+                .split(',')
+                .filter(|item| !item.is_empty())
+                .map(|item| item.trim().parse().map_err(|_| anyhow!("Invalid value in {key}: {item}")))
+                .collect(),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        match self.0.keys().next() {
+            Some(key) => Err(anyhow!("Unknown argument {key}")),
+            None => Ok(()),
+        }
+    }
+}
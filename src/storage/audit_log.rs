@@ -0,0 +1,99 @@
+use anyhow::Result;
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::storage::{append_toml, state_file};
+use crate::tui::events::{ChannelId, MediaId, MessageId};
+
+const AUDIT_LOG_FILE: &str = "sent_messages.toml";
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// Handed to the server
+    Sent,
+    /// The server acknowledged the message and gave it an id
+    Confirmed,
+    /// The connection was lost before the server acknowledged the message
+    Failed,
+}
+
+/// Entry of the log of sent messages in `~/.local/state/chatger/sent_messages.toml`, kept apart from the history cache
+/// so it is never pruned. Every change is a new entry, the entries of a message share the id it had before the server confirmed it
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AuditEntry {
+    pub event: AuditEvent,
+    /// Unix timestamp in seconds
+    pub timestamp: i64,
+    pub server: String,
+    pub local_id: MessageId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<ChannelId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_id: Option<MessageId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub media_ids: Vec<MediaId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<MessageId>,
+}
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    messages: [&'a AuditEntry; 1],
+}
+
+impl AuditEntry {
+    fn new(event: AuditEvent, server: &str, local_id: MessageId) -> Self {
+        AuditEntry {
+            event,
+            timestamp: Utc::now().timestamp(),
+            server: server.to_owned(),
+            local_id,
+            channel_id: None,
+            channel: None,
+            reply_id: None,
+            text: None,
+            media_ids: vec![],
+            message_id: None,
+        }
+    }
+
+    pub fn sent(
+        server: &str,
+        local_id: MessageId,
+        channel_id: ChannelId,
+        channel: &str,
+        reply_id: MessageId,
+        text: &str,
+        media_ids: &[MediaId],
+    ) -> Self {
+        AuditEntry {
+            channel_id: Some(channel_id),
+            channel: Some(channel.to_owned()),
+            reply_id: (reply_id != 0).then_some(reply_id),
+            text: Some(text.to_owned()),
+            media_ids: media_ids.to_vec(),
+            ..AuditEntry::new(AuditEvent::Sent, server, local_id)
+        }
+    }
+
+    pub fn confirmed(server: &str, local_id: MessageId, message_id: MessageId) -> Self {
+        AuditEntry {
+            message_id: Some(message_id),
+            ..AuditEntry::new(AuditEvent::Confirmed, server, local_id)
+        }
+    }
+
+    pub fn failed(server: &str, local_id: MessageId) -> Self {
+        AuditEntry::new(AuditEvent::Failed, server, local_id)
+    }
+
+    /// Adds the entry to the end of the log, earlier entries are never rewritten
+    pub fn append(&self) -> Result<()> {
+        append_toml(state_file(AUDIT_LOG_FILE), &AuditRecord { messages: [self] })
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{config_file, load_toml, save_toml};
+
+const CHANNEL_GROUPS_FILE: &str = "channel_groups.toml";
+
+/// Locally defined channel groups and the collapse state of the groups for a single server.
+///
+/// ```toml
+/// ["chat.example.com:4348"]
+/// collapsed = ["Archive"]
+///
+/// ["chat.example.com:4348".groups]
+/// Archive = ["old-general", "old-random"]
+/// ```
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct ChannelGroups {
+    /// Group name mapped to the names of the channels it contains
+    #[serde(default)]
+    pub groups: BTreeMap<String, Vec<String>>,
+    /// Names of the groups that are collapsed in the sidebar
+    #[serde(default)]
+    pub collapsed: BTreeSet<String>,
+}
+
+type ChannelGroupsFile = BTreeMap<String, ChannelGroups>;
+
+impl ChannelGroups {
+    pub fn load(server_key: &str) -> Self {
+        load_toml::<ChannelGroupsFile>(config_file(CHANNEL_GROUPS_FILE))
+            .remove(server_key)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, server_key: &str) -> Result<()> {
+        let mut file = load_toml::<ChannelGroupsFile>(config_file(CHANNEL_GROUPS_FILE));
+        file.insert(server_key.to_owned(), self.clone());
+        save_toml(config_file(CHANNEL_GROUPS_FILE), &file)
+    }
+
+    /// Returns the locally defined group of a channel, if any
+    pub fn group_of(&self, channel_name: &str) -> Option<String> {
+        self.groups
+            .iter()
+            .find(|(_, channels)| channels.iter().any(|name| name == channel_name))
+            .map(|(group, _)| group.clone())
+    }
+
+    pub fn toggle_collapsed(&mut self, group: &str) {
+        if !self.collapsed.remove(group) {
+            self.collapsed.insert(group.to_owned());
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{load_toml, save_toml, state_file};
+use crate::tui::events::{ChannelId, MessageId};
+
+const CHECKPOINT_FILE: &str = "checkpoint.toml";
+
+/// UI state that changes too often to save on every change, written every few seconds so a crash or dropped SSH session loses little.
+/// Restored on the next login to the same server.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    /// Unsent input of every channel that has any
+    #[serde(default)]
+    pub drafts: Vec<Draft>,
+    #[serde(default)]
+    pub view: Option<ViewCheckpoint>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Draft {
+    pub channel_id: ChannelId,
+    pub text: String,
+}
+
+/// Where the open channel was scrolled to and which message was being replied to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ViewCheckpoint {
+    pub channel_id: ChannelId,
+    /// Newest message in view, unset when the view followed new messages
+    #[serde(default)]
+    pub newest_in_view: Option<MessageId>,
+    #[serde(default)]
+    pub replying_to: Option<MessageId>,
+}
+
+type CheckpointFile = BTreeMap<String, Checkpoint>;
+
+impl Checkpoint {
+    pub fn load(server_key: &str) -> Self {
+        load_toml::<CheckpointFile>(state_file(CHECKPOINT_FILE))
+            .remove(server_key)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, server_key: &str) -> Result<()> {
+        let mut file = load_toml::<CheckpointFile>(state_file(CHECKPOINT_FILE));
+        file.insert(server_key.to_owned(), self.clone());
+        save_toml(state_file(CHECKPOINT_FILE), &file)
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{load_toml, save_toml, state_file};
+use crate::tui::chat::{Attachment, ChannelStatus, ChatMessage, ChatMessageKind, ChatMessageStatus, DisplayChannel};
+use crate::tui::events::{ChannelId, MediaId, MessageId, UserId};
+
+const HISTORY_CACHE_DIR: &str = "history";
+/// Newest messages kept per channel when no retention rule applies, older ones are fetched from the server again when needed
+pub const MAX_CACHED_MESSAGES: usize = 500;
+
+/// `[[retention]]` entry of the config file, limits how much history is cached.
+/// The most specific rule matching a channel applies, a rule without limits keeps the history forever.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionRule {
+    /// `host:port` as typed on the login screen, every server when left out
+    pub server: Option<String>,
+    /// Channel name, every channel when left out
+    pub channel: Option<String>,
+    /// Newest messages kept
+    pub keep_messages: Option<usize>,
+    /// Messages older than this are dropped
+    pub keep_days: Option<u32>,
+}
+
+impl RetentionRule {
+    /// The rule for a channel, falls back to keeping the newest `MAX_CACHED_MESSAGES`
+    pub fn for_channel(rules: &[RetentionRule], server_key: &str, channel_name: &str) -> RetentionRule {
+        rules
+            .iter()
+            .filter(|rule| rule.server.as_ref().is_none_or(|server| server == server_key))
+            .filter(|rule| rule.channel.as_ref().is_none_or(|channel| channel == channel_name))
+            // A channel is more specific than a server
+            .max_by_key(|rule| (rule.channel.is_some(), rule.server.is_some()))
+            .cloned()
+            .unwrap_or(RetentionRule {
+                keep_messages: Some(MAX_CACHED_MESSAGES),
+                ..Default::default()
+            })
+    }
+
+    fn apply(&self, mut messages: Vec<CachedMessage>) -> Vec<CachedMessage> {
+        if let Some(keep_days) = self.keep_days {
+            let oldest = (Utc::now() - TimeDelta::days(keep_days.into())).timestamp();
+            messages.retain(|message| message.timestamp >= oldest);
+        }
+        if let Some(keep_messages) = self.keep_messages {
+            messages.drain(..messages.len().saturating_sub(keep_messages));
+        }
+        messages
+    }
+}
+
+/// Disk usage of the history cache, shown by `/cache stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheStats {
+    pub path: PathBuf,
+    /// Size of the cache of the current server, in bytes
+    pub size: u64,
+    /// Size of the caches of every server, in bytes
+    pub total_size: u64,
+    pub servers: usize,
+    pub channels: usize,
+    pub messages: usize,
+    pub oldest: Option<DateTime<Utc>>,
+}
+
+/// Messages fetched from a server, kept so they can be read before the history arrives and while disconnected.
+/// Only messages confirmed by the server are cached, one file per server.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct HistoryCache {
+    #[serde(default)]
+    pub channels: Vec<CachedChannel>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CachedChannel {
+    pub id: ChannelId,
+    pub name: String,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub last_read_id: MessageId,
+    #[serde(default)]
+    pub direct_user: Option<UserId>,
+    #[serde(default)]
+    pub messages: Vec<CachedMessage>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CachedMessage {
+    pub message_id: MessageId,
+    #[serde(default)]
+    pub reply_id: MessageId,
+    pub author_id: UserId,
+    pub author_name: String,
+    /// Unix timestamp in seconds
+    pub timestamp: i64,
+    pub message: String,
+    #[serde(default)]
+    pub media_ids: Vec<MediaId>,
+    #[serde(default)]
+    pub edited: bool,
+}
+
+impl HistoryCache {
+    pub fn load(server_key: &str) -> Self {
+        load_toml(cache_file(server_key))
+    }
+
+    pub fn save(&self, server_key: &str) -> Result<()> {
+        save_toml(cache_file(server_key), self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.iter().all(|channel| channel.messages.is_empty())
+    }
+
+    pub fn from_channels<'a>(
+        channels: &[DisplayChannel],
+        history: impl Fn(ChannelId) -> Option<&'a Vec<ChatMessage>>,
+        retention: impl Fn(&DisplayChannel) -> RetentionRule,
+    ) -> Self {
+        let channels = channels
+            .iter()
+            // Notes to self have a file of their own
+            .filter(|channel| !channel.is_local())
+            .map(|channel| {
+                let messages: Vec<CachedMessage> = history(channel.id)
+                    .into_iter()
+                    .flatten()
+                    .filter(|message| message.kind == ChatMessageKind::User && message.status == ChatMessageStatus::Send)
+                    .map(CachedMessage::from)
+                    .collect();
+                CachedChannel {
+                    id: channel.id,
+                    name: channel.name.clone(),
+                    group: channel.group.clone(),
+                    last_read_id: channel.last_read_id,
+                    direct_user: channel.direct_user,
+                    messages: retention(channel).apply(messages),
+                }
+            })
+            .collect();
+        HistoryCache { channels }
+    }
+
+    pub fn stats(server_key: &str) -> Result<CacheStats> {
+        let path = cache_file(server_key)?;
+        let cache = HistoryCache::load(server_key);
+        let (mut total_size, mut servers) = (0, 0);
+        if let Some(dir) = path.parent()
+            && let Ok(entries) = fs::read_dir(dir)
+        {
+            for entry in entries.flatten() {
+                if let Ok(metadata) = entry.metadata()
+                    && metadata.is_file()
+                {
+                    total_size += metadata.len();
+                    servers += 1;
+                }
+            }
+        }
+        let messages = cache.channels.iter().flat_map(|channel| &channel.messages);
+        Ok(CacheStats {
+            size: fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0),
+            total_size,
+            servers,
+            channels: cache.channels.len(),
+            messages: messages.clone().count(),
+            oldest: messages
+                .map(|message| message.timestamp)
+                .min()
+                .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0)),
+            path,
+        })
+    }
+}
+
+impl CachedChannel {
+    pub fn display_channel(&self) -> DisplayChannel {
+        DisplayChannel {
+            id: self.id,
+            name: self.name.clone(),
+            status: ChannelStatus::Read,
+            selection_offset: 0,
+            group: self.group.clone(),
+            last_read_id: self.last_read_id,
+            direct_user: self.direct_user,
+        }
+    }
+
+    pub fn chat_messages(&self) -> Vec<ChatMessage> {
+        self.messages.iter().filter_map(CachedMessage::chat_message).collect()
+    }
+}
+
+impl From<&ChatMessage> for CachedMessage {
+    fn from(message: &ChatMessage) -> Self {
+        CachedMessage {
+            message_id: message.message_id,
+            reply_id: message.reply_id,
+            author_id: message.author_id,
+            author_name: message.author_name.clone(),
+            timestamp: message.timestamp.timestamp(),
+            message: message.message.clone(),
+            media_ids: message.attachments.iter().map(|attachment| attachment.media_id).collect(),
+            edited: message.edited,
+        }
+    }
+}
+
+impl CachedMessage {
+    fn chat_message(&self) -> Option<ChatMessage> {
+        Some(ChatMessage {
+            message_id: self.message_id,
+            reply_id: self.reply_id,
+            author_name: self.author_name.clone(),
+            author_id: self.author_id,
+            timestamp: DateTime::from_timestamp(self.timestamp, 0)?,
+            message: self.message.clone(),
+            status: ChatMessageStatus::Send,
+            kind: ChatMessageKind::User,
+            attachments: self.media_ids.iter().copied().map(Attachment::new).collect(),
+            edited: self.edited,
+        })
+    }
+}
+
+/// Server keys contain characters like `:` that are not allowed in file names everywhere
+fn cache_file(server_key: &str) -> Result<PathBuf> {
+    let file_name: String = server_key
+        .chars()
+        .map(|chr| if chr.is_alphanumeric() || chr == '.' || chr == '-' { chr } else { '_' })
+        .collect();
+    state_file(HISTORY_CACHE_DIR).map(|dir| dir.join(format!("{file_name}.toml")))
+}
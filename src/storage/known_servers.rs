@@ -0,0 +1,67 @@
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{load_toml, save_toml, state_file};
+
+const KNOWN_SERVERS_FILE: &str = "known_servers.toml";
+/// One number key picks each of them on the login screen
+pub const MAX_KNOWN_SERVERS: usize = 9;
+
+/// Servers logged in to before, most recently used first, offered on the login screen. Passwords are never stored here
+///
+/// ```toml
+/// [[servers]]
+/// address = "chat.example.com:4348"
+/// enable_tls = true
+/// username = "penger"
+/// last_used = 1760000000
+/// ```
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct KnownServers {
+    #[serde(default)]
+    pub servers: Vec<KnownServer>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KnownServer {
+    /// `host:port`, as typed on the login screen
+    pub address: String,
+    pub enable_tls: bool,
+    /// Account that logged in last
+    pub username: String,
+    /// Unix seconds
+    pub last_used: i64,
+}
+
+impl KnownServers {
+    pub fn load() -> Self {
+        load_toml(state_file(KNOWN_SERVERS_FILE))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_toml(state_file(KNOWN_SERVERS_FILE), self)
+    }
+
+    /// Moves the server to the front after a successful login, the least recently used server is forgotten when the list is full
+    pub fn remember(&mut self, address: &str, enable_tls: bool, username: &str) {
+        let address = address.trim();
+        self.servers.retain(|server| server.address != address);
+        self.servers.insert(
+            0,
+            KnownServer {
+                address: address.to_owned(),
+                enable_tls,
+                username: username.to_owned(),
+                last_used: Utc::now().timestamp(),
+            },
+        );
+        self.servers.truncate(MAX_KNOWN_SERVERS);
+    }
+
+    pub fn forget(&mut self, idx: usize) {
+        if idx < self.servers.len() {
+            self.servers.remove(idx);
+        }
+    }
+}
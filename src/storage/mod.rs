@@ -0,0 +1,105 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Result, anyhow};
+use log::{debug, warn};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+pub mod audit_log;
+pub mod channel_groups;
+pub mod checkpoint;
+pub mod history_cache;
+pub mod known_servers;
+pub mod notes;
+pub mod preferences;
+pub mod profile;
+pub mod secrets;
+pub mod seen_version;
+pub mod status;
+pub mod tutorial;
+
+const APP_DIR_NAME: &str = "chatger";
+
+/// Set in safe mode, state files are then neither read nor written
+static PERSISTENCE_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Makes every state file load as its default value and turns saving into a no-op
+pub fn disable_persistence() {
+    PERSISTENCE_DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Path of a user editable file, e.g. `~/.config/chatger/<file_name>`.
+pub fn config_file(file_name: &str) -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join(APP_DIR_NAME).join(file_name))
+        .ok_or_else(|| anyhow!("Unable to determine the config directory"))
+}
+
+/// Path of a file managed by the client itself, e.g. `~/.local/state/chatger/<file_name>`.
+pub fn state_file(file_name: &str) -> Result<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join(APP_DIR_NAME).join(file_name))
+        .ok_or_else(|| anyhow!("Unable to determine the state directory"))
+}
+
+/// Loads a TOML file, falling back to the default value when the file does not exist or can not be parsed.
+pub fn load_toml<T: DeserializeOwned + Default>(path: Result<PathBuf>) -> T {
+    if PERSISTENCE_DISABLED.load(Ordering::Relaxed) {
+        return T::default();
+    }
+    let path = match path {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("{e}");
+            return T::default();
+        }
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
+            warn!("Failed to parse {}: {e}", path.display());
+            T::default()
+        }),
+        Err(_) => {
+            debug!("No file found at {}, using defaults", path.display());
+            T::default()
+        }
+    }
+}
+
+/// Writes a value as TOML, creating the parent directories if needed.
+/// The file is written next to its destination and renamed over it, so a crash halfway leaves the previous contents intact.
+pub fn save_toml<T: Serialize>(path: Result<PathBuf>, value: &T) -> Result<()> {
+    let path = path?;
+    if PERSISTENCE_DISABLED.load(Ordering::Relaxed) {
+        debug!("Not saving {} in safe mode", path.display());
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp_path = path.with_extension("toml.tmp");
+    fs::write(&temp_path, toml::to_string_pretty(value)?)?;
+    fs::rename(&temp_path, &path)?;
+    debug!("Saved {}", path.display());
+    Ok(())
+}
+
+/// Appends a value as TOML to the end of a file, creating the file and its parent directories if needed.
+/// Meant for files that are only ever added to, arrays of tables appended this way keep the file a valid document.
+pub fn append_toml<T: Serialize>(path: Result<PathBuf>, value: &T) -> Result<()> {
+    let path = path?;
+    if PERSISTENCE_DISABLED.load(Ordering::Relaxed) {
+        debug!("Not appending to {} in safe mode", path.display());
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    file.write_all(format!("{}\n", toml::to_string(value)?).as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{load_toml, save_toml, state_file};
+use crate::tui::chat::{ChatMessage, ChatMessageKind, ChatMessageStatus};
+use crate::tui::events::{MessageId, UserId};
+
+const NOTES_FILE: &str = "notes.toml";
+
+/// Messages of the notes to self channel, they never leave this machine and are shared by every server
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Notes {
+    #[serde(default)]
+    pub notes: Vec<Note>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Note {
+    pub id: MessageId,
+    /// Unix timestamp in seconds
+    pub timestamp: i64,
+    #[serde(default)]
+    pub reply_id: MessageId,
+    pub text: String,
+}
+
+impl Notes {
+    /// Loads the notes, notes older than `keep_days` destroy themselves. 0 keeps them forever.
+    /// Every change is made to freshly loaded notes, so saving them also purges the expired ones from the file
+    pub fn load(keep_days: u32) -> Self {
+        let mut notes: Notes = load_toml(state_file(NOTES_FILE));
+        if keep_days > 0 {
+            let oldest = (Utc::now() - TimeDelta::days(keep_days.into())).timestamp();
+            notes.notes.retain(|note| note.timestamp >= oldest);
+        }
+        notes
+    }
+
+    /// Appends a note to the file, read again first so notes written by other sessions are kept
+    pub fn add(text: String, reply_id: MessageId, keep_days: u32) -> Result<Note> {
+        let mut notes = Notes::load(keep_days);
+        let note = Note {
+            id: notes.notes.iter().map(|note| note.id).max().unwrap_or(0) + 1,
+            timestamp: Utc::now().timestamp(),
+            reply_id,
+            text,
+        };
+        notes.notes.push(note.clone());
+        save_toml(state_file(NOTES_FILE), &notes)?;
+        Ok(note)
+    }
+
+    pub fn edit(id: MessageId, text: String, keep_days: u32) -> Result<()> {
+        let mut notes = Notes::load(keep_days);
+        if let Some(note) = notes.notes.iter_mut().find(|note| note.id == id) {
+            note.text = text;
+        }
+        save_toml(state_file(NOTES_FILE), &notes)
+    }
+
+    /// Shown the same way as the messages of other channels, written by the current user
+    pub fn chat_messages(self, author_name: &str, author_id: UserId) -> Vec<ChatMessage> {
+        self.notes.into_iter().map(|note| note.chat_message(author_name, author_id)).collect()
+    }
+}
+
+impl Note {
+    pub fn chat_message(self, author_name: &str, author_id: UserId) -> ChatMessage {
+        ChatMessage {
+            message_id: self.id,
+            reply_id: self.reply_id,
+            author_name: author_name.to_owned(),
+            author_id,
+            timestamp: DateTime::from_timestamp(self.timestamp, 0).unwrap_or_default(),
+            message: self.text,
+            status: ChatMessageStatus::Send,
+            kind: ChatMessageKind::User,
+            attachments: vec![],
+            edited: false,
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{load_toml, save_toml, state_file};
+use crate::tui::chat::ChatMessage;
+use crate::tui::events::{ChannelId, MessageId, UserId};
+
+const PREFERENCES_FILE: &str = "preferences.toml";
+
+/// UI state remembered per server, restored on the next login to the same server.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct ServerPreferences {
+    /// Users whose presence changes are announced
+    #[serde(default)]
+    pub watched_users: BTreeSet<UserId>,
+    /// Whether the log panel is shown, keeps the current visibility when unset
+    #[serde(default)]
+    pub show_logs: Option<bool>,
+    /// Name of the channel that was open last
+    #[serde(default)]
+    pub active_channel: Option<String>,
+    #[serde(default)]
+    pub user_sort: UserSortOrder,
+    /// Channels that never show as unread
+    #[serde(default)]
+    pub muted_channels: BTreeSet<ChannelId>,
+    #[serde(default)]
+    pub channel_sort: ChannelSortOrder,
+    /// Channels listed at the top of the sidebar, whatever the sort order
+    #[serde(default)]
+    pub pinned_channels: BTreeSet<ChannelId>,
+    /// When the digest was opened last, as a unix timestamp in seconds
+    #[serde(default)]
+    pub digest_seen: Option<i64>,
+    /// Theme picked with `/theme`, overrides the one of the config file
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// Messages pinned with `p`, only on this machine
+    #[serde(default)]
+    pub pinned_messages: Vec<PinnedMessage>,
+}
+
+/// A pinned message, with enough of it to be listed while its history is not loaded
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PinnedMessage {
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    pub author_name: String,
+    /// When the message was sent, as a unix timestamp in seconds
+    pub timestamp: i64,
+    pub text: String,
+}
+
+impl PinnedMessage {
+    pub fn new(channel_id: ChannelId, message: &ChatMessage) -> Self {
+        PinnedMessage {
+            channel_id,
+            message_id: message.message_id,
+            author_name: message.author_name.clone(),
+            timestamp: message.timestamp.timestamp(),
+            text: message.message.clone(),
+        }
+    }
+}
+
+/// Order of the users within the online and offline sections of the users panel
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSortOrder {
+    #[default]
+    Name,
+    Status,
+}
+
+impl UserSortOrder {
+    pub fn next(&self) -> Self {
+        match self {
+            UserSortOrder::Name => UserSortOrder::Status,
+            UserSortOrder::Status => UserSortOrder::Name,
+        }
+    }
+}
+
+/// Order of the channels within the sidebar sections
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelSortOrder {
+    /// As listed by the server
+    #[default]
+    Server,
+    /// Most recent message first
+    Activity,
+}
+
+impl ChannelSortOrder {
+    pub fn next(&self) -> Self {
+        match self {
+            ChannelSortOrder::Server => ChannelSortOrder::Activity,
+            ChannelSortOrder::Activity => ChannelSortOrder::Server,
+        }
+    }
+}
+
+type PreferencesFile = BTreeMap<String, ServerPreferences>;
+
+impl ServerPreferences {
+    pub fn load(server_key: &str) -> Self {
+        load_toml::<PreferencesFile>(state_file(PREFERENCES_FILE))
+            .remove(server_key)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, server_key: &str) -> Result<()> {
+        let mut file = load_toml::<PreferencesFile>(state_file(PREFERENCES_FILE));
+        file.insert(server_key.to_owned(), self.clone());
+        save_toml(state_file(PREFERENCES_FILE), &file)
+    }
+}
@@ -0,0 +1,225 @@
+use std::fs;
+use std::io::{ErrorKind, IsTerminal, Write, stdin, stdout};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::cli::check_config;
+use crate::storage::config_file;
+
+const CHANNEL_GROUPS_FILE: &str = "channel_groups.toml";
+const PROFILE_VERSION: u32 = 1;
+
+/// Fields holding secrets, they are left out of exported profiles
+const SECRET_FIELDS: [&str; 2] = ["password", "lock_pin"];
+/// Arrays of tables holding a login with a password of its own
+const ACCOUNT_ARRAYS: [&str; 2] = ["accounts", "workspaces"];
+/// Fields that make the client run commands, as paths of keys. A profile of someone else could run anything with them,
+/// so they are only imported after asking. A table whose command is left out goes with it, there is nothing left to run
+const COMMAND_FIELDS: [&[&str]; 3] = [&["hooks"], &["startup_commands"], &["translation", "command"]];
+
+/// The user editable files of the client bundled into one file, to set up another machine the same way.
+///
+/// ```toml
+/// version = 1
+///
+/// [config]
+/// address = "chat.example.com"
+///
+/// [channel_groups."chat.example.com:4348".groups]
+/// Archive = ["old-general"]
+/// ```
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    version: u32,
+    #[serde(default)]
+    config: Table,
+    #[serde(default)]
+    channel_groups: Table,
+}
+
+/// Writes the config file and the channel groups to `path`, without passwords and the lock PIN
+pub fn export(config_path: &Path, path: &Path) -> Result<()> {
+    let mut config = read_table(config_path)?;
+    remove_secrets(&mut config);
+    let profile = Profile {
+        version: PROFILE_VERSION,
+        config,
+        channel_groups: read_table(&config_file(CHANNEL_GROUPS_FILE)?)?,
+    };
+    fs::write(path, toml::to_string_pretty(&profile)?).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Exported the profile to {}, passwords and the lock PIN are left out", path.display());
+    Ok(())
+}
+
+/// Replaces the config file and the channel groups with the ones in the profile at `path`.
+/// Secrets of the current config file are kept, the replaced files are backed up next to them.
+/// Commands in the profile are shown and only imported when confirmed, the ones of the current config file are kept otherwise
+pub fn import(config_path: &Path, path: &Path) -> Result<()> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let profile: Profile = toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?;
+    if profile.version > PROFILE_VERSION {
+        return Err(anyhow!(
+            "The profile was exported by a newer client (version {}), update before importing it",
+            profile.version
+        ));
+    }
+
+    let mut config = profile.config;
+    let current = read_table(config_path)?;
+    remove_secrets(&mut config);
+    keep_secrets(&mut config, &current);
+    filter_commands(&mut config, &current)?;
+    check_config(&config).context("The profile holds an invalid config")?;
+
+    write_with_backup(config_path, &config)?;
+    write_with_backup(&config_file(CHANNEL_GROUPS_FILE)?, &profile.channel_groups)?;
+    println!("Imported the profile from {}", path.display());
+    if !config.contains_key("password") {
+        println!(
+            "Passwords are not part of profiles, type them on the login screen or add them to {}",
+            config_path.display()
+        );
+    }
+    Ok(())
+}
+
+fn read_table(path: &Path) -> Result<Table> {
+    match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Table::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn remove_secrets(config: &mut Table) {
+    for field in SECRET_FIELDS {
+        config.remove(field);
+    }
+    for array in ACCOUNT_ARRAYS {
+        for account in accounts_mut(config, array) {
+            account.remove("password");
+        }
+    }
+}
+
+/// Copies the secrets of the current config into the imported one, accounts are matched on username and address
+fn keep_secrets(config: &mut Table, current: &Table) {
+    for field in SECRET_FIELDS {
+        if let Some(value) = current.get(field) {
+            config.insert(field.to_owned(), value.clone());
+        }
+    }
+    for array in ACCOUNT_ARRAYS {
+        let current_accounts: Vec<&Table> = match current.get(array) {
+            Some(Value::Array(accounts)) => accounts.iter().filter_map(Value::as_table).collect(),
+            _ => vec![],
+        };
+        for account in accounts_mut(config, array) {
+            let password = current_accounts
+                .iter()
+                .find(|current| current.get("username") == account.get("username") && current.get("address") == account.get("address"))
+                .and_then(|current| current.get("password"));
+            if let Some(password) = password {
+                account.insert("password".to_owned(), password.clone());
+            }
+        }
+    }
+}
+
+/// Asks whether the commands of the profile may be imported, without a terminal to ask on they are left out
+fn filter_commands(config: &mut Table, current: &Table) -> Result<()> {
+    let commands: Vec<(&[&str], Value)> = COMMAND_FIELDS
+        .iter()
+        .filter_map(|path| Some((*path, take_field(config, path)?)))
+        .collect();
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    println!("The profile runs these commands:");
+    for (path, value) in &commands {
+        println!("  {} = {value}", path.join("."));
+    }
+    let confirmed = stdin().is_terminal() && {
+        print!("Import them? Only do so when you trust where the profile came from [y/N] ");
+        stdout().flush()?;
+        let mut answer = String::new();
+        stdin().read_line(&mut answer)?;
+        matches!(answer.trim(), "y" | "Y" | "yes")
+    };
+
+    for (path, value) in commands {
+        match (confirmed, get_field(current, path)) {
+            (true, _) => set_field(config, path, value),
+            (false, Some(kept)) => set_field(config, path, kept.clone()),
+            (false, None) => {
+                if let [parent @ .., _] = path
+                    && !parent.is_empty()
+                {
+                    take_field(config, parent);
+                }
+            }
+        }
+    }
+    if !confirmed {
+        println!("Left the commands of the profile out, the ones already configured are kept");
+    }
+    Ok(())
+}
+
+fn get_field<'a>(table: &'a Table, path: &[&str]) -> Option<&'a Value> {
+    let (last, parents) = path.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get(*key)?.as_table()?;
+    }
+    table.get(*last)
+}
+
+fn take_field(table: &mut Table, path: &[&str]) -> Option<Value> {
+    let (last, parents) = path.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get_mut(*key)?.as_table_mut()?;
+    }
+    table.remove(*last)
+}
+
+fn set_field(table: &mut Table, path: &[&str], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut table = table;
+    for key in parents {
+        let entry = table.entry(key.to_string()).or_insert_with(|| Value::Table(Table::new()));
+        let Some(nested) = entry.as_table_mut() else {
+            return;
+        };
+        table = nested;
+    }
+    table.insert(last.to_string(), value);
+}
+
+fn accounts_mut<'a>(config: &'a mut Table, array: &str) -> impl Iterator<Item = &'a mut Table> {
+    match config.get_mut(array) {
+        Some(Value::Array(accounts)) => accounts.iter_mut().filter_map(Value::as_table_mut).collect(),
+        _ => vec![],
+    }
+    .into_iter()
+}
+
+fn write_with_backup(path: &Path, table: &Table) -> Result<()> {
+    if path.exists() {
+        let backup = PathBuf::from(format!("{}.bak", path.display()));
+        fs::copy(path, &backup).with_context(|| format!("Failed to back up {}", path.display()))?;
+        println!("Backed up {} to {}", path.display(), backup.display());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, toml::to_string_pretty(table)?).with_context(|| format!("Failed to write {}", path.display()))
+}
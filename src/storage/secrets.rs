@@ -0,0 +1,143 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+use zeroize::Zeroizing;
+
+/// A password kept in memory. It is wiped when dropped and left out of debug output, so it can not end up in the logs.
+/// The text is only reachable through `expose`, which makes every place that reads it easy to find
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    pub fn new(secret: String) -> Self {
+        Secret(Zeroizing::new(secret))
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// For editing the secret in place, like the password input of the login screen
+    pub fn expose_mut(&mut self) -> &mut String {
+        &mut self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(secret: String) -> Self {
+        Secret::new(secret)
+    }
+}
+
+impl FromStr for Secret {
+    type Err = Infallible;
+
+    fn from_str(secret: &str) -> Result<Self, Self::Err> {
+        Ok(Secret::new(secret.to_owned()))
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl Hash for Secret {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.expose().hash(state);
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Secret::new)
+    }
+}
+
+/// Login codes are 6 to 8 digits, anything else typed into the login code prompt is taken as the secret of the authenticator
+pub fn is_login_code(input: &str) -> bool {
+    (6..=8).contains(&input.len()) && input.chars().all(|chr| chr.is_ascii_digit())
+}
+
+/// Secrets of the second factor are kept in the keyring of the OS, so later logins fill in the code themselves
+#[cfg(feature = "keyring")]
+mod keyring_store {
+    use anyhow::{Result, anyhow};
+    use keyring::Entry;
+    use log::debug;
+    use totp_rs::{Algorithm, Secret, TOTP};
+
+    const KEYRING_SERVICE: &str = "chatger";
+    const CODE_DIGITS: usize = 6;
+    const CODE_STEP_SECONDS: u64 = 30;
+
+    fn entry(server_key: &str, username: &str) -> Result<Entry> {
+        Ok(Entry::new(KEYRING_SERVICE, &format!("totp:{username}@{server_key}"))?)
+    }
+
+    /// Fails when the keyring can not be reached, a missing entry is fine
+    pub fn keyring_available() -> Result<()> {
+        match entry("doctor", "chatger")?.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn totp_secret(server_key: &str, username: &str) -> Option<String> {
+        match entry(server_key, username).and_then(|entry| Ok(entry.get_password()?)) {
+            Ok(secret) => Some(secret),
+            Err(e) => {
+                debug!("No login code secret in the keyring: {e}");
+                None
+            }
+        }
+    }
+
+    pub fn save_totp_secret(server_key: &str, username: &str, secret: &str) -> Result<()> {
+        // Fail before storing a secret that can not generate codes
+        totp_code(secret)?;
+        entry(server_key, username)?.set_password(secret)?;
+        Ok(())
+    }
+
+    /// The secret is the base32 key shown when setting up an authenticator app, spaces and case do not matter
+    pub fn totp_code(secret: &str) -> Result<String> {
+        let secret: String = secret.chars().filter(|chr| !chr.is_whitespace()).collect::<String>().to_uppercase();
+        let bytes = Secret::Encoded(secret.trim_end_matches('=').to_owned())
+            .to_bytes()
+            .map_err(|_| anyhow!("The secret is not valid base32"))?;
+        let totp = TOTP::new_unchecked(Algorithm::SHA1, CODE_DIGITS, 1, CODE_STEP_SECONDS, bytes);
+        Ok(totp.generate_current()?)
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+mod keyring_store {
+    use anyhow::{Result, anyhow};
+
+    pub fn keyring_available() -> Result<()> {
+        Err(anyhow!("This build has no keyring support"))
+    }
+
+    pub fn totp_secret(_server_key: &str, _username: &str) -> Option<String> {
+        None
+    }
+
+    pub fn save_totp_secret(_server_key: &str, _username: &str, _secret: &str) -> Result<()> {
+        Err(anyhow!(
+            "Remembering login codes is not supported by this build, rebuild with `--features keyring`"
+        ))
+    }
+
+    pub fn totp_code(_secret: &str) -> Result<String> {
+        Err(anyhow!(
+            "Remembering login codes is not supported by this build, rebuild with `--features keyring`"
+        ))
+    }
+}
+
+pub use keyring_store::{keyring_available, save_totp_secret, totp_code, totp_secret};
@@ -0,0 +1,23 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{load_toml, save_toml, state_file};
+
+const SEEN_VERSION_FILE: &str = "seen_version.toml";
+
+/// Version of the client that was run last, the changes since then are shown once after an upgrade
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct SeenVersion {
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+impl SeenVersion {
+    pub fn load() -> Self {
+        load_toml(state_file(SEEN_VERSION_FILE))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_toml(state_file(SEEN_VERSION_FILE), self)
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+use std::fs;
+use std::sync::atomic::Ordering;
+
+use anyhow::Result;
+use log::debug;
+
+use crate::storage::{PERSISTENCE_DISABLED, state_file};
+
+const STATUS_FILE: &str = "status";
+
+/// Unread messages across all channels that are not muted, shown outside the client
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct UnreadSummary {
+    pub unread: usize,
+    pub mentions: usize,
+}
+
+impl fmt::Display for UnreadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} unread / {} mentions", self.unread, self.mentions)
+    }
+}
+
+/// Writes the summary to `~/.local/state/chatger/status`, so tmux status lines and shell prompts can show it with e.g. `cat`
+pub fn write_status(summary: &UnreadSummary) -> Result<()> {
+    let path = state_file(STATUS_FILE)?;
+    if PERSISTENCE_DISABLED.load(Ordering::Relaxed) {
+        debug!("Not writing {} in safe mode", path.display());
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, format!("{summary}\n"))?;
+    debug!("Updated {} to \"{summary}\"", path.display());
+    Ok(())
+}
+
+/// Removes the status file, so nothing stale is shown once the client is no longer logged in.
+/// In safe mode the file is left alone, it may belong to a session that is not in safe mode
+pub fn clear_status() -> Result<()> {
+    if PERSISTENCE_DISABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    match fs::remove_file(state_file(STATUS_FILE)?) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,28 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{load_toml, save_toml, state_file};
+use crate::tui::screens::chat::tutorial::TutorialHint;
+
+const TUTORIAL_FILE: &str = "tutorial.toml";
+
+/// Hints of the first-run tutorial that were dismissed, none are shown again once all of them were
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct TutorialProgress {
+    #[serde(default)]
+    pub dismissed: BTreeSet<TutorialHint>,
+    #[serde(default)]
+    pub completed: bool,
+}
+
+impl TutorialProgress {
+    pub fn load() -> Self {
+        load_toml(state_file(TUTORIAL_FILE))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_toml(state_file(TUTORIAL_FILE), self)
+    }
+}
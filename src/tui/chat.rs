@@ -1,8 +1,13 @@
+use std::collections::VecDeque;
+
 use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use tokio::time::Instant;
 
+use crate::network::client::UploadProgress;
 use crate::network::protocol::server::Channel;
 use crate::network::protocol::{MediaType, UserStatus};
-use crate::tui::events::{ChannelId, MessageId, UserId};
+use crate::tui::events::{ChannelId, MediaId, MessageId, ProfilePicId, UserId};
 
 #[derive(Clone, Debug)]
 pub struct DisplayChannel {
@@ -10,15 +15,60 @@ pub struct DisplayChannel {
     pub name: String,
     pub status: ChannelStatus,
     pub selection_offset: usize,
+    pub group: Option<String>,
+    /// Last message marked as read, shared with the other clients of the user through the server
+    pub last_read_id: MessageId,
+    /// The other user of a direct channel
+    pub direct_user: Option<UserId>,
+}
+
+/// Direct channels are listed in a group of their own, below the other groups
+pub const DIRECT_MESSAGES_GROUP: &str = "Direct Messages";
+/// The notes to self channel is kept on this machine, it is listed in a group of its own below the direct channels
+pub const LOCAL_GROUP: &str = "Local";
+/// Id of the notes to self channel, far above the ids servers hand out
+pub const NOTES_CHANNEL_ID: ChannelId = ChannelId::MAX;
+
+impl DisplayChannel {
+    /// The notes to self channel, its name has a space so it can not clash with the name of a server channel
+    pub fn notes() -> Self {
+        DisplayChannel {
+            id: NOTES_CHANNEL_ID,
+            name: "Notes to self".to_owned(),
+            status: ChannelStatus::Read,
+            selection_offset: 0,
+            group: Some(LOCAL_GROUP.to_owned()),
+            last_read_id: 0,
+            direct_user: None,
+        }
+    }
+
+    /// Whether the channel only exists on this machine, nothing about it is sent to the server
+    pub fn is_local(&self) -> bool {
+        self.id == NOTES_CHANNEL_ID
+    }
+
+    pub fn make_direct(&mut self, user_id: UserId) {
+        self.direct_user = Some(user_id);
+        self.group = Some(DIRECT_MESSAGES_GROUP.to_owned());
+    }
 }
 
 impl From<Channel> for DisplayChannel {
     fn from(channel: Channel) -> Self {
+        // Servers can group channels by prefixing the name with the group, e.g. "dev/backend"
+        let (group, name) = match channel.name.split_once('/') {
+            Some((group, name)) if !group.is_empty() && !name.is_empty() => (Some(group.to_owned()), name.to_owned()),
+            _ => (None, channel.name.clone()),
+        };
         DisplayChannel {
             id: channel.channel_id,
-            name: channel.name,
+            name,
             status: ChannelStatus::Read,
             selection_offset: 0,
+            group,
+            last_read_id: 0,
+            direct_user: None,
         }
     }
 }
@@ -32,6 +82,52 @@ pub struct ChatMessage {
     pub timestamp: DateTime<Utc>,
     pub message: String,
     pub status: ChatMessageStatus,
+    pub kind: ChatMessageKind,
+    pub attachments: Vec<Attachment>,
+    /// Only known for edits made while connected, the history does not say whether a message was edited
+    pub edited: bool,
+}
+
+/// Media attached to a message, the name and type are only known once the media has been fetched or when it was sent by us
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub media_id: MediaId,
+    pub filename: Option<String>,
+    pub media_type: Option<MediaType>,
+}
+
+impl Attachment {
+    pub fn new(media_id: MediaId) -> Self {
+        Attachment {
+            media_id,
+            filename: None,
+            media_type: None,
+        }
+    }
+}
+
+impl ChatMessage {
+    /// Creates a locally generated event line, which is rendered distinct from messages sent by users
+    pub fn system(message: String, timestamp: DateTime<Utc>) -> Self {
+        ChatMessage {
+            message_id: 0,
+            reply_id: 0,
+            author_name: String::new(),
+            author_id: 0,
+            timestamp,
+            message,
+            status: ChatMessageStatus::Send,
+            kind: ChatMessageKind::System,
+            attachments: vec![],
+            edited: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatMessageKind {
+    User,
+    System,
 }
 
 #[derive(Debug, Clone)]
@@ -39,6 +135,9 @@ pub struct User {
     pub id: UserId,
     pub name: String,
     pub status: UserStatus,
+    pub bio: String,
+    /// 0 when the user has no profile picture
+    pub pfp_id: ProfilePicId,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -55,9 +154,75 @@ pub enum ChannelStatus {
     Muted,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MediaMessage {
     pub filename: String,
     pub media_type: MediaType,
     pub media_data: Vec<u8>,
+    pub checksum: Option<[u8; 32]>,
+}
+
+impl MediaMessage {
+    /// Compares the data against the checksum sent by the server, media without a checksum is assumed to be intact
+    pub fn is_intact(&self) -> bool {
+        match &self.checksum {
+            Some(checksum) => Sha256::digest(&self.media_data).as_slice() == checksum,
+            None => true,
+        }
+    }
+}
+
+/// Media message whose files are being uploaded one after another, the message itself is sent once the server acknowledged all media
+#[derive(Debug, Clone)]
+pub struct MediaUpload {
+    /// Temporary id of the pending message shown in the history
+    pub message_id: MessageId,
+    pub channel_id: ChannelId,
+    pub reply_id: MessageId,
+    pub caption: String,
+    /// Progress of the file that is currently being uploaded
+    pub progress: UploadProgress,
+    /// Ids of the files the server already acknowledged
+    pub media_ids: Vec<MediaId>,
+    /// Files waiting for the current upload to finish
+    pub remaining: VecDeque<StagedAttachment>,
+}
+
+impl MediaUpload {
+    pub fn attachment_count(&self) -> usize {
+        self.media_ids.len() + 1 + self.remaining.len()
+    }
+}
+
+/// File that was picked as attachment but not checked yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingAttachment {
+    pub filename: String,
+    pub data: Vec<u8>,
+    /// Skips detecting the type from the file contents
+    pub media_type: Option<MediaType>,
+}
+
+/// Attachment that passed the upload checks and is sent with the next message
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagedAttachment {
+    pub filename: String,
+    pub data: Vec<u8>,
+    pub media_type: MediaType,
+}
+
+/// Short lived notification shown on top of the chat history
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub message: String,
+    pub created: Instant,
+}
+
+impl Toast {
+    pub fn new(message: String) -> Self {
+        Toast {
+            message,
+            created: Instant::now(),
+        }
+    }
 }
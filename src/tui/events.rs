@@ -1,9 +1,14 @@
-use crate::network::protocol::UserStatus;
-use crate::network::protocol::server::{Channel, HistoryMessage, UserData};
+use crate::media::clipboard::ClipboardImage;
+use crate::media::preview::{Avatar, ImagePreview};
+use crate::network::probe::{ProbeOutcome, ProbeStage};
+use crate::network::protocol::server::{Channel, ChannelEventPacket, DisconnectReason, HistoryMessage, UserData};
+use crate::network::protocol::{Capabilities, UserStatus};
+use crate::storage::secrets::Secret;
 use crate::tui::chat::MediaMessage;
 use crate::tui::framework::FromLog;
+use crate::tui::input::InputEdit;
 use crate::tui::logs::LogEntry;
-use crate::tui::screens::chat::ChatFocus;
+use crate::tui::screens::chat::{ChatFocus, Pane};
 use crate::tui::screens::login::LoginFocus;
 
 pub type UserId = u64;
@@ -17,44 +22,189 @@ pub type IconId = u64;
 pub enum TuiEvent {
     Log(LogEntry),
     Exit,
+    /// Quits without waiting for messages and media that are still on their way
+    ForceQuit,
+    /// Keeps the quit guard open and quits once nothing is pending anymore
+    WaitThenQuit,
     ChannelUp,
     ChannelDown,
+    JumpToChannel(usize),
+    CompleteChannelLink,
+    ToggleChannelGroup,
     ChatFocusChange(ChatFocus),
     LoginFocusChange(LoginFocus),
-    InputRight,
-    InputRightTab,
-    InputLeft,
-    InputLeftTab,
+    InputEdit(InputEdit),
     InputChar(char),
-    InputDelete,
     MessageSend,
     ToggleLogs,
+    ToggleLogRepeats,
+    TogglePane(Pane),
+    /// Hides every pane but the chat history and the message input, or shows them again
+    ToggleZen,
+    /// Switches message times between when and how long ago they were sent
+    ToggleRelativeTimestamps,
+    /// Closes the tutorial hint of the focused pane for good
+    DismissHint,
     LoginSuccess(UserId),
     Login,
     Logout,
     LoginFail(String),
+    /// The password was accepted and a code of the second factor is needed, with the reason an earlier code was rejected
+    LoginCodeRequired(Option<String>),
+    /// Token to resume the session with after a reconnect
+    SessionToken(Secret),
+    /// The server continued the session of the previous connection
+    SessionResumed,
+    /// The server does not know the session anymore, with its reason
+    ResumeRejected(Option<String>),
+    SubmitLoginCode,
+    CancelLoginCode,
+    /// Checks whether the server can be reached, without logging in
+    TestConnection,
+    /// Fills in a server of the known servers list on the login screen, logging in right away when an account has its password
+    PickKnownServer(usize),
+    ForgetKnownServer(usize),
+    ConnectionProbeUpdate(ProbeStage, ProbeOutcome),
     HealthCheckRecv,
     Disconnected,
+    /// The server closed the connection on purpose, it is not reconnected to
+    ServerGoodbye(DisconnectReason, String),
+    /// Answer to changing the profile, with the reason when it was refused
+    UserConfigAck(Result<(), String>),
     Channels(Vec<Channel>),
     ChannelIDs(Vec<ChannelId>),
     ScrollUp,
     ScrollDown,
+    ScrollToTop,
+    ScrollToBottom,
+    /// First `g` of the vim `gg`
+    VimPrefix,
     UserStatusesUpdate(Vec<(UserId, UserStatus)>),
     UserStatusUpdate(UserId, UserStatus),
     Users(Vec<UserData>),
     HistoryUpdate(Vec<HistoryMessage>),
+    HistoryNotification(Vec<HistoryMessage>),
+    ChannelEvent(ChannelEventPacket),
+    ReadStates(Vec<(ChannelId, MessageId)>),
     MessageSendAck(MessageId),
+    /// The server refused the oldest message waiting for an ack, with its reason
+    MessageSendFailed(Option<String>),
     MessageMediaAck(MediaId),
+    MessageEdited(ChannelId, MessageId, String),
+    MessageEditFailed(MessageId),
+    /// Version of the server and the oldest client version it supports
+    ServerInfo(String, String),
+    /// Optional features the server supports
+    Capabilities(Capabilities),
+    /// Direct channels of the user and the other user in them
+    DirectChannels(Vec<(ChannelId, UserId)>),
+    DirectChannelFailed(String),
+    OpenDirectMessage,
+    CancelUpload,
+    CompressAttachment,
+    /// Result of shrinking an attachment in the background, with the name and size of the original
+    AttachmentCompressed(String, usize, Result<Vec<u8>, String>),
+    /// Offers the image on the clipboard as an attachment
+    PasteImage,
+    /// Image read from the clipboard in the background, `None` when the clipboard holds something else
+    ClipboardRead(Result<Option<ClipboardImage>, String>),
+    AttachClipboardImage,
+    ToggleVoiceRecording,
+    AttachVoiceNote,
+    UnstageAttachment,
+    DiscardVoiceNote,
     Media(MediaMessage),
+    MediaFailed,
+    /// Thumbnail of an image attachment, decoded in the background
+    PreviewReady(MediaId, Result<ImagePreview, String>),
+    /// Profile picture of a user, decoded in the background
+    AvatarReady(ProfilePicId, Result<Avatar, String>),
     Typing(ChannelId, UserId, bool),
     TypingExpired,
     PossiblyUnhealthyConnection,
     Reconnect,
+    StartupStepTimeout,
     FocusGained,
     FocusLost,
     IdleUser,
+    /// Picks the next status by hand, Online, Idle, Do not disturb and Offline in turn
+    CycleStatus,
     Reply,
+    EditMessage,
+    /// Opens the selected message in full, for messages that are cut off in the history
+    ExpandMessage,
+    DownloadMedia,
+    TranslateMessage,
+    Translated(MessageId, Result<String, String>),
     ViewUsers,
+    ClosePopup,
+    Lock,
+    LockInputChar(char),
+    LockInputDelete,
+    Unlock,
+    OpenFilePicker,
+    FilePickerChar(char),
+    FilePickerDelete,
+    FilePickerComplete,
+    AttachFile,
+    ToggleWatchUser,
+    /// Folds the messages of a user in the open channel into stubs, or shows them again
+    ToggleCollapseUser,
+    OpenProfileEditor,
+    ProfileEditorChar(char),
+    ProfileEditorDelete,
+    ProfileEditorNextField,
+    ProfileEditorPreviousField,
+    SaveProfile,
+    PasswordEditorChar(char),
+    PasswordEditorDelete,
+    PasswordEditorNextField,
+    PasswordEditorPreviousField,
+    SavePassword,
+    CycleUserSort,
+    ToggleMuteChannel,
+    CycleChannelSort,
+    TogglePinChannel,
+    /// Moves the selected note into the draft of the channel that was open before the notes to self
+    CopyNoteToChannel,
+    OpenAccountSwitcher,
+    AccountSwitcherUp,
+    AccountSwitcherDown,
+    SwitchAccount,
+    OpenSearch,
+    OpenDigest,
+    DigestUp,
+    DigestDown,
+    ChangelogUp,
+    ChangelogDown,
+    ExpandedMessageUp,
+    ExpandedMessageDown,
+    SearchInputChar(char),
+    SearchInputDelete,
+    SearchUp,
+    SearchDown,
+    /// Loads older history page by page until the search finds a match
+    StartSearchBackfill,
+    CancelSearchBackfill,
+    JumpToMessage(usize, MessageId),
+    JumpToUnread,
+    /// Selects the message the selected reply quotes, loading older history until it is found
+    JumpToReplied,
+    /// Puts the selected message in the input as a quote
+    QuoteMessage,
+    /// Pins the selected message, or unpins it
+    TogglePinMessage,
+    OpenPins,
+    PinsUp,
+    PinsDown,
+    /// Selects the pinned message that is selected in the pins popup
+    JumpToPinned,
+    /// Unpins the message that is selected in the pins popup
+    UnpinSelected,
+    ToggleTimeTravel,
+    TimeTravelBack,
+    TimeTravelForward,
+    ToggleRenderStats,
 }
 
 impl FromLog for TuiEvent {
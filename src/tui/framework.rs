@@ -7,6 +7,7 @@ use std::vec;
 use anyhow::Result;
 use async_trait::async_trait;
 use log::{LevelFilter, debug, error, info};
+use ratatui::buffer::Buffer;
 use ratatui::crossterm::event::{DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture, Event, poll, read};
 use ratatui::crossterm::execute;
 use ratatui::crossterm::terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode};
@@ -31,6 +32,7 @@ pub struct TuiRunner<T: Tui<Update>, Update> {
     event_send: Sender<Event>,
     event_recv: Receiver<Event>,
     log_level: LevelFilter,
+    unsafe_logs: bool,
 }
 
 const LOG_CHANNEL_CAPACITY: usize = 100;
@@ -50,7 +52,8 @@ where
     /// - `update_recv`: Channel to receive updates for the TUI.
     /// - `update_send`: Channel to send updates (e.g., from logs or external sources).
     /// - `log_level`: Logging level for filtering logs.
-    pub fn new(app: T, client: Client, update_recv: Receiver<U>, update_send: Sender<U>, log_level: LevelFilter) -> Self {
+    /// - `unsafe_logs`: Logs passwords and media data instead of masking them.
+    pub fn new(app: T, client: Client, update_recv: Receiver<U>, update_send: Sender<U>, log_level: LevelFilter, unsafe_logs: bool) -> Self {
         let (log_send, log_recv) = mpsc::channel::<LogEntry>(LOG_CHANNEL_CAPACITY);
         let (event_send, event_recv) = mpsc::channel::<Event>(EVENT_CHANNEL_CAPACITY);
         Self {
@@ -63,6 +66,7 @@ where
             event_send,
             event_recv,
             log_level,
+            unsafe_logs,
         }
     }
 
@@ -86,7 +90,7 @@ where
         let update_send = self.update_send.clone();
 
         Self::init_event_handler_thread(self.event_send, stop_flag.clone()).await;
-        logs::init_logger(self.log_level, self.log_send)?;
+        logs::init_logger(self.log_level, self.unsafe_logs, self.log_send)?;
 
         let mut handles: Vec<JoinHandle<()>> = vec![];
         for task in tasks {
@@ -121,7 +125,10 @@ where
                   }
               }
               _ = tokio::time::sleep(Duration::from_millis(10)) => {
-                  terminal.draw(|f| self.app.draw_ui(f))?;
+                  let frame = terminal.draw(|f| self.app.draw_ui(f))?;
+                  if let Err(e) = self.app.draw_graphics(frame.buffer) {
+                      error!("Failed to draw graphics: {e:?}");
+                  }
                   if let Err(e) = self.app.on_tick(&update_send, &mut self.client).await {
                       error!("Failed during tick handler: {e:?}");
                   }
@@ -211,6 +218,11 @@ pub trait Tui<E> {
     /// Draws the UI using the current state. Should be purely visual with no side effects.
     fn draw_ui(&mut self, f: &mut Frame);
 
+    /// Draws what can not be expressed as cells, like images, on top of the frame that was just drawn.
+    fn draw_graphics(&mut self, _buffer: &Buffer) -> Result<()> {
+        Ok(())
+    }
+
     /// Handles a keyboard event and optionally returns an update to process.
     /// Should not mutate state directly.
     fn process_event(&mut self, event: Event) -> Option<E>;
@@ -0,0 +1,160 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::tui::events::TuiEvent;
+
+// Cursors in text inputs are byte offsets that always sit between two graphemes,
+// so an emoji sequence or a letter with combining accents is moved over and deleted as a whole.
+// A cursor left over from another text is first moved to the nearest grapheme end, instead of panicking on slicing
+
+/// Cursor movements and deletions shared by the text inputs of every screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEdit {
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    Home,
+    End,
+    DeleteBackward,
+    DeleteForward,
+    /// Deletes the word left of the cursor
+    DeleteWord,
+    /// Deletes everything left of the cursor
+    KillToStart,
+    /// Deletes everything right of the cursor
+    KillToEnd,
+}
+
+impl InputEdit {
+    pub fn changes_text(self) -> bool {
+        use InputEdit::*;
+        matches!(self, DeleteBackward | DeleteForward | DeleteWord | KillToStart | KillToEnd)
+    }
+}
+
+/// The edit a key stands for in a text input, with the readline keys next to the arrow keys
+fn input_edit(key_event: &KeyEvent) -> Option<InputEdit> {
+    use KeyCode::*;
+    let control = key_event.modifiers == KeyModifiers::CONTROL;
+    let edit = match key_event.code {
+        Left if control => InputEdit::WordLeft,
+        Right if control => InputEdit::WordRight,
+        Left => InputEdit::Left,
+        Right => InputEdit::Right,
+        Home => InputEdit::Home,
+        End => InputEdit::End,
+        Backspace => InputEdit::DeleteBackward,
+        Delete => InputEdit::DeleteForward,
+        Char('a') if control => InputEdit::Home,
+        Char('e') if control => InputEdit::End,
+        Char('w') if control => InputEdit::DeleteWord,
+        Char('u') if control => InputEdit::KillToStart,
+        Char('k') if control => InputEdit::KillToEnd,
+        _ => return None,
+    };
+    Some(edit)
+}
+
+/// Event for a key pressed in a text input. Editing keys go first, so `Ctrl + W` deletes a word instead of typing a `w`
+pub fn input_key_event(key_event: &KeyEvent) -> Option<TuiEvent> {
+    match (input_edit(key_event), key_event.code) {
+        (Some(edit), _) => Some(TuiEvent::InputEdit(edit)),
+        (None, KeyCode::Char(chr)) => Some(TuiEvent::InputChar(chr)),
+        _ => None,
+    }
+}
+
+/// Applies an edit to `text` and returns the cursor after it
+pub fn apply_edit(text: &mut String, cursor: usize, edit: InputEdit) -> usize {
+    let cursor = snap_to_grapheme(text, cursor);
+    match edit {
+        InputEdit::Left => previous_grapheme(text, cursor),
+        InputEdit::Right => next_grapheme(text, cursor),
+        InputEdit::WordLeft => previous_word(text, cursor),
+        InputEdit::WordRight => next_word(text, cursor),
+        InputEdit::Home => 0,
+        InputEdit::End => text.len(),
+        InputEdit::DeleteBackward => delete_previous_grapheme(text, cursor),
+        InputEdit::DeleteForward => {
+            let end = next_grapheme(text, cursor);
+            text.replace_range(cursor..end, "");
+            cursor
+        }
+        InputEdit::DeleteWord => {
+            let start = previous_word(text, cursor);
+            text.replace_range(start..cursor, "");
+            start
+        }
+        InputEdit::KillToStart => {
+            text.replace_range(..cursor, "");
+            0
+        }
+        InputEdit::KillToEnd => {
+            text.truncate(cursor);
+            cursor
+        }
+    }
+}
+
+/// Cursor position one grapheme to the left of `cursor`
+pub fn previous_grapheme(text: &str, cursor: usize) -> usize {
+    let cursor = snap_to_grapheme(text, cursor);
+    text[..cursor].grapheme_indices(true).next_back().map_or(0, |(idx, _)| idx)
+}
+
+/// Cursor position one grapheme to the right of `cursor`
+pub fn next_grapheme(text: &str, cursor: usize) -> usize {
+    let cursor = snap_to_grapheme(text, cursor);
+    text[cursor..].graphemes(true).next().map_or(cursor, |grapheme| cursor + grapheme.len())
+}
+
+/// Cursor position at the start of the word left of `cursor`
+pub fn previous_word(text: &str, cursor: usize) -> usize {
+    let cursor = snap_to_grapheme(text, cursor);
+    text[..cursor]
+        .grapheme_indices(true)
+        .rev()
+        .skip_while(|(_, grapheme)| grapheme.trim().is_empty())
+        .take_while(|(_, grapheme)| !grapheme.trim().is_empty())
+        .last()
+        .map_or(0, |(idx, _)| idx)
+}
+
+/// Cursor position at the end of the word right of `cursor`
+pub fn next_word(text: &str, cursor: usize) -> usize {
+    let cursor = snap_to_grapheme(text, cursor);
+    text[cursor..]
+        .grapheme_indices(true)
+        .skip_while(|(_, grapheme)| grapheme.trim().is_empty())
+        .find(|(_, grapheme)| grapheme.trim().is_empty())
+        .map_or(text.len(), |(idx, _)| cursor + idx)
+}
+
+/// Inserts `chr` at `cursor` and returns the cursor after it.
+/// A combining character merges with the grapheme before it, the cursor then moves past the merged grapheme
+pub fn insert_char(text: &mut String, cursor: usize, chr: char) -> usize {
+    let cursor = snap_to_grapheme(text, cursor);
+    text.insert(cursor, chr);
+    let inserted = cursor + chr.len_utf8();
+    snap_to_grapheme(text, inserted)
+}
+
+/// Removes the grapheme left of `cursor` and returns the cursor where it was
+pub fn delete_previous_grapheme(text: &mut String, cursor: usize) -> usize {
+    let cursor = snap_to_grapheme(text, cursor);
+    let start = previous_grapheme(text, cursor);
+    text.replace_range(start..cursor, "");
+    start
+}
+
+/// Moves a cursor that ended up inside a grapheme, or past the end of the text, to the end of that grapheme
+pub fn snap_to_grapheme(text: &str, cursor: usize) -> usize {
+    if cursor == 0 {
+        return 0;
+    }
+    text.grapheme_indices(true)
+        .map(|(idx, grapheme)| idx + grapheme.len())
+        .find(|end| *end >= cursor)
+        .unwrap_or(text.len())
+}
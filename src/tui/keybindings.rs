@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::tui::events::TuiEvent;
+
+/// Names of the actions that can be bound in the `[keybindings]` table of the config file
+const ACTIONS: [(&str, Action); 12] = [
+    ("quit", Action::Quit),
+    ("logout", Action::Logout),
+    ("lock", Action::Lock),
+    ("toggle_logs", Action::ToggleLogs),
+    ("search", Action::Search),
+    ("switch_account", Action::SwitchAccount),
+    ("jump_to_unread", Action::JumpToUnread),
+    ("digest", Action::Digest),
+    ("pins", Action::Pins),
+    ("cycle_status", Action::CycleStatus),
+    ("zen", Action::Zen),
+    ("relative_timestamps", Action::RelativeTimestamps),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Quit,
+    Logout,
+    Lock,
+    ToggleLogs,
+    Search,
+    SwitchAccount,
+    JumpToUnread,
+    Digest,
+    Pins,
+    CycleStatus,
+    Zen,
+    RelativeTimestamps,
+}
+
+impl Action {
+    fn event(self) -> TuiEvent {
+        match self {
+            Action::Quit => TuiEvent::Exit,
+            Action::Logout => TuiEvent::Logout,
+            Action::Lock => TuiEvent::Lock,
+            Action::ToggleLogs => TuiEvent::ToggleLogs,
+            Action::Search => TuiEvent::OpenSearch,
+            Action::SwitchAccount => TuiEvent::OpenAccountSwitcher,
+            Action::JumpToUnread => TuiEvent::JumpToUnread,
+            Action::Digest => TuiEvent::OpenDigest,
+            Action::Pins => TuiEvent::OpenPins,
+            Action::CycleStatus => TuiEvent::CycleStatus,
+            Action::Zen => TuiEvent::ToggleZen,
+            Action::RelativeTimestamps => TuiEvent::ToggleRelativeTimestamps,
+        }
+    }
+}
+
+/// Keys of the `[keybindings]` table, they work in every pane of the chat and take precedence over the default keys.
+/// A key is written like `ctrl+q`, `alt+shift+d` or `f5`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Keybindings {
+    bindings: Vec<(KeyCode, KeyModifiers, Action)>,
+}
+
+impl Keybindings {
+    /// Fails on unknown actions and keys, so a typo does not silently leave the default key in place
+    pub fn parse(table: &BTreeMap<String, String>) -> Result<Self> {
+        let mut bindings = Vec::with_capacity(table.len());
+        for (name, key) in table {
+            let action = ACTIONS
+                .iter()
+                .find(|(action_name, _)| action_name == name)
+                .map(|(_, action)| *action)
+                .ok_or_else(|| {
+                    let names: Vec<&str> = ACTIONS.iter().map(|(action_name, _)| *action_name).collect();
+                    anyhow!("Unknown keybinding action \"{name}\", expected one of {}", names.join(", "))
+                })?;
+            let (code, modifiers) = parse_key(key).map_err(|e| anyhow!("Invalid key \"{key}\" for {name}: {e}"))?;
+            if let Some((_, _, other)) = bindings
+                .iter()
+                .find(|(other_code, other_modifiers, _)| (*other_code, *other_modifiers) == (code, modifiers))
+            {
+                return Err(anyhow!("\"{key}\" is bound to both {name} and {}", action_name(*other)));
+            }
+            bindings.push((code, modifiers, action));
+        }
+        Ok(Keybindings { bindings })
+    }
+
+    pub fn event(&self, key_event: &KeyEvent) -> Option<TuiEvent> {
+        let (code, modifiers) = normalize(key_event.code, key_event.modifiers);
+        self.bindings
+            .iter()
+            .find(|(bound_code, bound_modifiers, _)| (*bound_code, *bound_modifiers) == (code, modifiers))
+            .map(|(_, _, action)| action.event())
+    }
+}
+
+fn action_name(action: Action) -> &'static str {
+    ACTIONS.iter().find(|(_, other)| *other == action).map_or("", |(name, _)| name)
+}
+
+fn parse_key(key: &str) -> Result<(KeyCode, KeyModifiers)> {
+    let key = key.trim().to_lowercase();
+    let mut parts: Vec<&str> = key.split('+').map(str::trim).collect();
+    // `ctrl++` binds the plus key itself
+    if key.ends_with("++") {
+        parts.truncate(parts.len() - 2);
+        parts.push("+");
+    }
+    let Some((name, modifier_names)) = parts.split_last() else {
+        return Err(anyhow!("no key given"));
+    };
+    let mut modifiers = KeyModifiers::NONE;
+    for modifier in modifier_names {
+        modifiers |= match *modifier {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            other => return Err(anyhow!("unknown modifier \"{other}\"")),
+        };
+    }
+    let code = match *name {
+        "esc" => KeyCode::Esc,
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "space" => KeyCode::Char(' '),
+        name if name.len() > 1 && name.starts_with('f') && name[1..].parse::<u8>().is_ok_and(|n| (1..=12).contains(&n)) => {
+            KeyCode::F(name[1..].parse()?)
+        }
+        name => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(chr), None) => KeyCode::Char(chr),
+                _ => return Err(anyhow!("unknown key \"{name}\"")),
+            }
+        }
+    };
+    // Other keys are typed into the message input or move around the panes
+    if !matches!(code, KeyCode::F(_)) && !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+        return Err(anyhow!("only function keys can be bound without ctrl or alt"));
+    }
+    Ok(normalize(code, modifiers))
+}
+
+/// Terminals report shifted letters as uppercase, with or without the shift modifier
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> (KeyCode, KeyModifiers) {
+    match code {
+        KeyCode::Char(chr) => (KeyCode::Char(chr.to_ascii_lowercase()), modifiers - KeyModifiers::SHIFT),
+        code => (code, modifiers),
+    }
+}
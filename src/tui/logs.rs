@@ -2,6 +2,7 @@ use chrono::{DateTime, Local};
 use log::{Level, LevelFilter, Metadata, Record, SetLoggerError};
 use ratatui::style::{Color, Style};
 use ratatui::text::{Line, Span};
+use ratatui::widgets::{Paragraph, Wrap};
 use tokio::sync::mpsc::Sender;
 
 /// Represents a single log entry captured for display in the TUI.
@@ -36,6 +37,135 @@ impl LogEntry {
             Span::styled(format!(" {message_str}"), style),
         ])
     }
+
+    /// Formats the entry followed by a `×N` counter when it was repeated
+    pub fn format_repeated(&self, count: usize) -> Line<'_> {
+        let mut line = self.format();
+        if count > 1 {
+            line.push_span(Span::styled(format!(" ×{count}"), Style::default().fg(Color::DarkGray)));
+        }
+        line
+    }
+
+    fn is_repeat_of(&self, other: &LogEntry) -> bool {
+        self.level == other.level && self.message == other.message
+    }
+}
+
+/// Selects the log lines that fill a panel of `width` x `height` cells once soft-wrapped,
+/// with `scroll_offset` counted in wrapped lines from the bottom.
+///
+/// Consecutive identical entries are shown once with a repeat counter when `collapse_repeats` is set.
+///
+/// Returns the lines together with the vertical scroll to pass to `Paragraph::scroll`,
+/// which hides the part of the topmost entry that does not fit.
+pub fn visible_log_lines(logs: &[LogEntry], scroll_offset: usize, width: u16, height: u16, collapse_repeats: bool) -> (Vec<Line<'_>>, u16) {
+    let needed_lines = scroll_offset + height as usize;
+    let mut wrapped_lines = 0;
+    let mut lines = vec![];
+
+    let mut entries = logs.iter().rev().peekable();
+    // Newest first, so a collapsed line shows the time of the latest repeat
+    let collapsed_entries = std::iter::from_fn(|| {
+        let entry = entries.next()?;
+        let mut count = 1;
+        while collapse_repeats && entries.next_if(|next| next.is_repeat_of(entry)).is_some() {
+            count += 1;
+        }
+        Some((entry, count))
+    });
+
+    for (entry, count) in collapsed_entries {
+        if wrapped_lines >= needed_lines {
+            break;
+        }
+        let line = entry.format_repeated(count);
+        wrapped_lines += Paragraph::new(line.clone()).wrap(Wrap { trim: true }).line_count(width).max(1);
+        lines.push(line);
+    }
+    lines.reverse();
+
+    let scroll = wrapped_lines.saturating_sub(needed_lines);
+    (lines, scroll.min(u16::MAX as usize) as u16)
+}
+
+/// Field names whose values are masked in log messages
+const SENSITIVE_FIELDS: [&str; 3] = ["password", "pin", "token"];
+/// Byte lists longer than this are left out, so headers stay readable while payloads do not end up in the logs
+const MAX_LOGGED_BYTES: usize = 16;
+
+/// Masks the values of password like fields and replaces long byte lists, such as media data and packet dumps,
+/// by their length.
+///
+/// Works on the `Debug` output of packets and on `key=value` pairs, so fields are recognized by name.
+pub fn redact(message: &str) -> String {
+    let mut redacted = String::with_capacity(message.len());
+    let mut rest = message;
+    let mut in_word = false;
+    while let Some(c) = rest.chars().next() {
+        if !in_word && let Some(len) = sensitive_field(rest) {
+            redacted.push_str(&rest[..len]);
+            rest = &rest[len..];
+            let value_len = value_length(rest);
+            redacted.push_str(if rest.starts_with('"') { "\"***\"" } else { "***" });
+            rest = &rest[value_len..];
+        } else if c == '['
+            && let Some((len, count)) = byte_list(rest)
+        {
+            redacted.push_str(&format!("<{count} bytes>"));
+            rest = &rest[len..];
+        } else {
+            redacted.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        in_word = c.is_alphanumeric() || c == '_';
+    }
+    redacted
+}
+
+/// Length of the `name: ` or `name=` prefix when `text` starts with a sensitive field that is not part of a longer name
+fn sensitive_field(text: &str) -> Option<usize> {
+    SENSITIVE_FIELDS.iter().find_map(|field| {
+        let after = text
+            .get(..field.len())
+            .filter(|name| name.eq_ignore_ascii_case(field))
+            .map(|_| &text[field.len()..])?;
+        let separator = [": ", ":", "="].iter().find(|separator| after.starts_with(**separator))?;
+        let value = &after[separator.len()..];
+        // Already redacted by its Debug impl, or nothing to hide
+        (!value.is_empty() && !value.starts_with("Secret(")).then_some(field.len() + separator.len())
+    })
+}
+
+/// Length of the value at the start of `text`, a quoted string or everything up to the next separator
+fn value_length(text: &str) -> usize {
+    if let Some(quoted) = text.strip_prefix('"') {
+        let mut escaped = false;
+        for (i, c) in quoted.char_indices() {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => return i + 2,
+                _ => escaped = false,
+            }
+        }
+        return text.len();
+    }
+    text.find(|c: char| c.is_whitespace() || matches!(c, ',' | '}' | ')' | ']'))
+        .unwrap_or(text.len())
+}
+
+/// Length and number of elements of the list of numbers at the start of `text`, when it has more than `MAX_LOGGED_BYTES`
+fn byte_list(text: &str) -> Option<(usize, usize)> {
+    let end = text.find(']')?;
+    let elements = text[1..end].split(',').map(str::trim).filter(|element| !element.is_empty());
+    let mut count = 0;
+    for element in elements {
+        if !element.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        count += 1;
+    }
+    (count > MAX_LOGGED_BYTES).then_some((end + 1, count))
 }
 
 /// Custom logger that implements the `log::Log` trait and sends log entries
@@ -45,6 +175,8 @@ pub struct TuiLogger {
     pub log_channel_send: Sender<LogEntry>,
     /// Minimum log level that should be recorded.
     pub log_level: Level,
+    /// Logs messages as they are, without masking passwords and media data
+    pub unsafe_logs: bool,
 }
 
 impl log::Log for TuiLogger {
@@ -54,10 +186,11 @@ impl log::Log for TuiLogger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
+            let message = format!("{}", record.args());
             let log_entry = LogEntry {
                 timestamp: Local::now(),
                 level: record.level(),
-                message: format!("{}", record.args()),
+                message: if self.unsafe_logs { message } else { redact(&message) },
             };
             if self.log_channel_send.try_send(log_entry.clone()).is_err() {
                 eprintln!(
@@ -65,7 +198,7 @@ impl log::Log for TuiLogger {
                     Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
                     std::thread::current().name().unwrap_or("unknown_thread"),
                     record.level(),
-                    record.args()
+                    log_entry.message
                 );
             }
         }
@@ -78,15 +211,17 @@ impl log::Log for TuiLogger {
 ///
 /// # Arguments
 /// * `log_level_filter` - The maximum log level to be captured.
+/// * `unsafe_logs` - Skips masking passwords and media data in the messages.
 /// * `sender` - A Tokio `Sender` that receives `LogEntry` items.
 ///
 /// # Returns
 /// * `Ok(())` if the logger was successfully set.
 /// * `Err(SetLoggerError)` if logger setup fails.
-pub fn init_logger(log_level_filter: LevelFilter, sender: Sender<LogEntry>) -> Result<(), SetLoggerError> {
+pub fn init_logger(log_level_filter: LevelFilter, unsafe_logs: bool, sender: Sender<LogEntry>) -> Result<(), SetLoggerError> {
     let logger = TuiLogger {
         log_channel_send: sender,
         log_level: log_level_filter.to_level().unwrap_or(log::Level::Error),
+        unsafe_logs,
     };
 
     log::set_boxed_logger(Box::new(logger))?;
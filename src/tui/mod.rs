@@ -3,6 +3,7 @@ use tokio::sync::mpsc;
 
 use crate::cli::AppConfig;
 use crate::network::client::{Client, ConnectionType};
+use crate::storage::known_servers::KnownServers;
 use crate::tui::events::TuiEvent;
 use crate::tui::framework::TuiRunner;
 use crate::tui::screens::login::{InputStatus, LoginFocus, LoginState};
@@ -10,32 +11,46 @@ use crate::tui::screens::{AppState, State};
 pub mod chat;
 pub mod events;
 pub mod framework;
+pub mod input;
+pub mod keybindings;
 pub mod logs;
 pub mod screens;
+pub mod theme;
 
 pub async fn run(config: AppConfig) -> Result<()> {
     let (event_send, event_recv) = mpsc::channel::<TuiEvent>(10);
 
     let tasks = vec![async move {}];
 
-    let login_state = AppState::Login(LoginState {
-        username_input: config.username,
-        password_input: config.password,
+    let mut login_state = LoginState {
+        username_input: config.username.clone(),
+        password_input: config.password.clone(),
         server_address_input: config.address.to_string(),
         server_address: None,
         focus: LoginFocus::Nothing,
         input_status: InputStatus::AllFine,
         enable_tls: config.enable_tls,
-    });
+        login_code: None,
+        login_pending: false,
+        goodbye: None,
+        connection_probe: vec![],
+        known_servers: KnownServers::load(),
+    };
+    login_state.prefill_last_login(&config);
+    // A remembered login of another user leaves the password to be typed
+    let auto_login = config.auto_login && !login_state.password_input.expose().is_empty();
+    let login_state = AppState::Login(Box::new(login_state));
 
     let client = Client::new(event_send.clone());
 
-    let tui = State::new(login_state);
+    let loglevel = config.loglevel;
+    let unsafe_logs = config.unsafe_logs;
+    let tui = State::new(login_state, config);
 
-    if config.auto_login {
+    if auto_login {
         event_send.send(TuiEvent::Login).await?;
     }
-    let tui_runner = TuiRunner::new(tui, client, event_recv, event_send, config.loglevel);
+    let tui_runner = TuiRunner::new(tui, client, event_recv, event_send, loglevel, unsafe_logs);
 
     tui_runner.run(tasks).await
 }
@@ -0,0 +1,41 @@
+use crate::cli::SavedAccount;
+
+/// Accounts that can be switched to, from the config file and the sessions of this run
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountSwitcher {
+    pub accounts: Vec<SavedAccount>,
+    /// Index of the account that is logged in
+    pub current: Option<usize>,
+    pub selected: usize,
+}
+
+impl AccountSwitcher {
+    /// Sessions fill in the passwords the config file leaves out, since they were typed on the login screen
+    pub fn new(saved: &[SavedAccount], sessions: impl IntoIterator<Item = SavedAccount>, current: SavedAccount) -> Self {
+        let mut accounts = saved.to_vec();
+        for session in sessions.into_iter().chain([current.clone()]) {
+            match accounts.iter_mut().find(|account| account.is_same(&session)) {
+                Some(account) if account.password.is_none() => account.password = session.password,
+                Some(_) => {}
+                None => accounts.push(session),
+            }
+        }
+        let current = accounts.iter().position(|account| account.is_same(&current));
+        let selected = (0..accounts.len()).find(|&idx| Some(idx) != current).unwrap_or(0);
+        AccountSwitcher { accounts, current, selected }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.accounts.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn selected_account(&self) -> Option<&SavedAccount> {
+        self.accounts.get(self.selected)
+    }
+}
@@ -0,0 +1,25 @@
+use std::io::{Write, stdout};
+
+use anyhow::Result;
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::SetTitle;
+
+const TITLE: &str = "chatger";
+
+/// Rings the terminal bell, most terminals flash or mark the tab instead of making a sound
+pub fn ring_bell() -> Result<()> {
+    let mut stdout = stdout();
+    stdout.write_all(b"\x07")?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Sets the terminal title to e.g. `chatger (3)`, with the number of messages that arrived while the terminal was not focused
+pub fn set_title(unseen: usize) -> Result<()> {
+    let title = match unseen {
+        0 => TITLE.to_owned(),
+        unseen => format!("{TITLE} ({unseen})"),
+    };
+    execute!(stdout(), SetTitle(title))?;
+    Ok(())
+}
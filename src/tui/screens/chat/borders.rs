@@ -1,15 +1,15 @@
-use ratatui::style::{Color, Style};
+use ratatui::style::Style;
 use ratatui::symbols::{border, line};
 use ratatui::widgets::Borders;
 
 use crate::tui::screens::GlobalState;
-use crate::tui::screens::chat::{ChatFocus, ChatState};
+use crate::tui::screens::chat::{ChatFocus, ChatState, Pane};
 
-pub fn borders_channel(state: &ChatState) -> (Borders, Style, border::Set) {
-    match state.focus {
+pub fn borders_channel(global_state: &GlobalState, state: &ChatState) -> (Borders, Style, border::Set) {
+    let (borders, style, set) = match state.focus {
         ChatFocus::Channels => (
             Borders::ALL,
-            Style::default().fg(Color::Cyan),
+            global_state.theme.focus,
             border::Set {
                 bottom_left: line::NORMAL.vertical_right,
                 bottom_right: line::NORMAL.cross,
@@ -25,11 +25,13 @@ pub fn borders_channel(state: &ChatState) -> (Borders, Style, border::Set) {
                 ..border::PLAIN
             },
         ),
-    }
+    };
+    (borders, style, global_state.theme.border_set(set))
 }
 
-pub fn borders_profile(state: &ChatState) -> (Borders, Style, border::Set) {
-    match state.focus {
+pub fn borders_profile(global_state: &GlobalState, state: &ChatState) -> (Borders, Style, border::Set) {
+    let hidden = global_state.hidden_panes;
+    let (borders, style, set) = match state.focus {
         ChatFocus::Channels => (
             Borders::LEFT | Borders::RIGHT | Borders::BOTTOM,
             Style::default(),
@@ -52,11 +54,17 @@ pub fn borders_profile(state: &ChatState) -> (Borders, Style, border::Set) {
                 ..border::PLAIN
             },
         ),
-    }
+    };
+    (
+        borders,
+        style,
+        global_state.theme.border_set(open_edges(set, false, false, hidden.hides(Pane::Info))),
+    )
 }
 
 pub fn borders_chat_history(global_state: &GlobalState, chat_state: &ChatState) -> (Borders, Style, border::Set) {
-    match chat_state.focus {
+    let hidden = global_state.hidden_panes;
+    let (borders, style, set) = match chat_state.focus {
         ChatFocus::Channels => (
             Borders::RIGHT | Borders::TOP,
             Style::default(),
@@ -67,20 +75,20 @@ pub fn borders_chat_history(global_state: &GlobalState, chat_state: &ChatState)
         ),
         ChatFocus::ChatHistory | ChatFocus::ChatHistorySelection => (
             Borders::ALL,
-            Style::default().fg(Color::Cyan),
+            global_state.theme.focus,
             border::Set {
-                bottom_left: if chat_state.replying_to.is_some() {
+                bottom_left: if chat_state.shows_reply_bar() {
                     line::NORMAL.vertical_right
                 } else {
                     line::NORMAL.cross
                 },
-                bottom_right: if chat_state.replying_to.is_some() {
-                    if global_state.show_logs {
+                bottom_right: if chat_state.shows_reply_bar() {
+                    if global_state.logs_shown() {
                         line::NORMAL.horizontal_up
                     } else {
                         line::NORMAL.vertical_left
                     }
-                } else if global_state.show_logs {
+                } else if global_state.logs_shown() {
                     line::NORMAL.horizontal_up
                 } else {
                     line::NORMAL.cross
@@ -119,7 +127,7 @@ pub fn borders_chat_history(global_state: &GlobalState, chat_state: &ChatState)
                 bottom_left: line::NORMAL.cross,
                 bottom_right: line::NORMAL.horizontal_up,
                 top_right: line::NORMAL.horizontal_down,
-                top_left: if chat_state.replying_to.is_some() {
+                top_left: if chat_state.shows_reply_bar() {
                     line::NORMAL.cross
                 } else {
                     line::NORMAL.horizontal_down
@@ -127,11 +135,22 @@ pub fn borders_chat_history(global_state: &GlobalState, chat_state: &ChatState)
                 ..border::PLAIN
             },
         ),
-    }
+    };
+    (
+        borders,
+        style,
+        global_state.theme.border_set(open_edges(
+            set,
+            hidden.hides(Pane::Channels),
+            hidden.hides(Pane::Users) && !global_state.logs_shown(),
+            false,
+        )),
+    )
 }
 
-pub fn borders_reply_bar(state: &ChatState) -> (Borders, Style, border::Set) {
-    match state.focus {
+pub fn borders_reply_bar(global_state: &GlobalState, state: &ChatState) -> (Borders, Style, border::Set) {
+    let hidden = global_state.hidden_panes;
+    let (borders, style, set) = match state.focus {
         ChatFocus::Channels => (
             Borders::RIGHT | Borders::TOP,
             Style::default(),
@@ -157,7 +176,7 @@ pub fn borders_reply_bar(state: &ChatState) -> (Borders, Style, border::Set) {
             Style::default(),
             border::Set {
                 bottom_left: line::NORMAL.cross,
-                bottom_right: if state.replying_to.is_some() {
+                bottom_right: if state.shows_reply_bar() {
                     line::NORMAL.vertical_right
                 } else {
                     line::NORMAL.cross
@@ -185,11 +204,19 @@ pub fn borders_reply_bar(state: &ChatState) -> (Borders, Style, border::Set) {
                 ..border::PLAIN
             },
         ),
-    }
+    };
+    (
+        borders,
+        style,
+        global_state
+            .theme
+            .border_set(open_edges(set, hidden.hides(Pane::Channels), hidden.hides(Pane::Users), false)),
+    )
 }
 
-pub fn borders_input(state: &ChatState) -> (Borders, Style, border::Set) {
-    match state.focus {
+pub fn borders_input(global_state: &GlobalState, state: &ChatState) -> (Borders, Style, border::Set) {
+    let hidden = global_state.hidden_panes;
+    let (borders, style, set) = match state.focus {
         ChatFocus::Channels => (
             Borders::RIGHT | Borders::BOTTOM | Borders::TOP,
             Style::default(),
@@ -212,7 +239,7 @@ pub fn borders_input(state: &ChatState) -> (Borders, Style, border::Set) {
         ),
         ChatFocus::ChatInput(_) => (
             Borders::ALL,
-            Style::default().fg(Color::Cyan),
+            global_state.theme.focus,
             border::Set {
                 bottom_left: line::NORMAL.horizontal_up,
                 bottom_right: line::NORMAL.horizontal_up,
@@ -230,11 +257,21 @@ pub fn borders_input(state: &ChatState) -> (Borders, Style, border::Set) {
                 ..border::PLAIN
             },
         ),
-    }
+    };
+    (
+        borders,
+        style,
+        global_state.theme.border_set(open_edges(
+            set,
+            hidden.hides(Pane::Channels),
+            hidden.hides(Pane::Users),
+            hidden.hides(Pane::Info),
+        )),
+    )
 }
 
-pub fn borders_users(state: &ChatState) -> (Borders, Style, border::Set) {
-    match state.focus {
+pub fn borders_users(global_state: &GlobalState, state: &ChatState) -> (Borders, Style, border::Set) {
+    let (borders, style, set) = match state.focus {
         ChatFocus::ChatHistory | ChatFocus::ChatHistorySelection => (
             Borders::RIGHT | Borders::TOP | Borders::BOTTOM,
             Style::default(),
@@ -263,7 +300,7 @@ pub fn borders_users(state: &ChatState) -> (Borders, Style, border::Set) {
         ),
         ChatFocus::Users(_) => (
             Borders::ALL,
-            Style::default().fg(Color::Cyan),
+            global_state.theme.focus,
             border::Set {
                 bottom_left: line::NORMAL.cross,
                 bottom_right: line::NORMAL.vertical_left,
@@ -280,11 +317,13 @@ pub fn borders_users(state: &ChatState) -> (Borders, Style, border::Set) {
                 ..border::PLAIN
             },
         ),
-    }
+    };
+    (borders, style, global_state.theme.border_set(set))
 }
 
-pub fn borders_logs(state: &ChatState) -> (Borders, Style, border::Set) {
-    match state.focus {
+pub fn borders_logs(global_state: &GlobalState, state: &ChatState) -> (Borders, Style, border::Set) {
+    let hidden = global_state.hidden_panes;
+    let (borders, style, set) = match state.focus {
         ChatFocus::Channels => (
             Borders::RIGHT | Borders::TOP,
             Style::default(),
@@ -298,7 +337,7 @@ pub fn borders_logs(state: &ChatState) -> (Borders, Style, border::Set) {
             Style::default(),
             border::Set {
                 bottom_left: line::NORMAL.cross,
-                bottom_right: if state.replying_to.is_some() {
+                bottom_right: if state.shows_reply_bar() {
                     line::NORMAL.vertical_left
                 } else {
                     line::NORMAL.cross
@@ -330,10 +369,10 @@ pub fn borders_logs(state: &ChatState) -> (Borders, Style, border::Set) {
         ),
         ChatFocus::Logs => (
             Borders::ALL,
-            Style::default().fg(Color::Cyan),
+            global_state.theme.focus,
             border::Set {
                 bottom_left: line::NORMAL.horizontal_up,
-                bottom_right: if state.replying_to.is_some() {
+                bottom_right: if state.shows_reply_bar() {
                     line::NORMAL.vertical_left
                 } else {
                     line::NORMAL.cross
@@ -343,11 +382,17 @@ pub fn borders_logs(state: &ChatState) -> (Borders, Style, border::Set) {
                 ..border::PLAIN
             },
         ),
-    }
+    };
+    (
+        borders,
+        style,
+        global_state.theme.border_set(open_edges(set, false, hidden.hides(Pane::Users), false)),
+    )
 }
 
-pub fn borders_server_status(state: &ChatState) -> (Borders, Style, border::Set) {
-    match state.focus {
+pub fn borders_server_status(global_state: &GlobalState, state: &ChatState) -> (Borders, Style, border::Set) {
+    let hidden = global_state.hidden_panes;
+    let (borders, style, set) = match state.focus {
         ChatFocus::Users(_) => (
             Borders::LEFT | Borders::RIGHT | Borders::BOTTOM,
             Style::default(),
@@ -370,5 +415,57 @@ pub fn borders_server_status(state: &ChatState) -> (Borders, Style, border::Set)
                 ..border::PLAIN
             },
         ),
+    };
+    (
+        borders,
+        style,
+        global_state.theme.border_set(open_edges(set, false, false, hidden.hides(Pane::Info))),
+    )
+}
+
+/// Turns the junctions on the outer edges of a pane into corners, for the panes hidden next to it.
+/// Only the arms pointing at a hidden pane are removed, `left` hides the left arms, `right` the right and `bottom` the lower ones
+fn open_edges(set: border::Set, left: bool, right: bool, bottom: bool) -> border::Set {
+    let open = |glyph: &'static str, left: bool, right: bool, bottom: bool| {
+        let glyph = if left { without_left(glyph) } else { glyph };
+        let glyph = if right { without_right(glyph) } else { glyph };
+        if bottom { without_bottom(glyph) } else { glyph }
+    };
+    border::Set {
+        top_left: open(set.top_left, left, false, false),
+        top_right: open(set.top_right, false, right, false),
+        bottom_left: open(set.bottom_left, left, false, bottom),
+        bottom_right: open(set.bottom_right, false, right, bottom),
+        ..set
+    }
+}
+
+fn without_left(glyph: &'static str) -> &'static str {
+    match glyph {
+        line::CROSS => line::VERTICAL_RIGHT,
+        line::HORIZONTAL_DOWN => line::TOP_LEFT,
+        line::HORIZONTAL_UP => line::BOTTOM_LEFT,
+        line::VERTICAL_LEFT => line::VERTICAL,
+        glyph => glyph,
+    }
+}
+
+fn without_right(glyph: &'static str) -> &'static str {
+    match glyph {
+        line::CROSS => line::VERTICAL_LEFT,
+        line::HORIZONTAL_DOWN => line::TOP_RIGHT,
+        line::HORIZONTAL_UP => line::BOTTOM_RIGHT,
+        line::VERTICAL_RIGHT => line::VERTICAL,
+        glyph => glyph,
+    }
+}
+
+fn without_bottom(glyph: &'static str) -> &'static str {
+    match glyph {
+        line::CROSS => line::HORIZONTAL_UP,
+        line::VERTICAL_RIGHT => line::BOTTOM_LEFT,
+        line::VERTICAL_LEFT => line::BOTTOM_RIGHT,
+        line::HORIZONTAL_DOWN => line::HORIZONTAL,
+        glyph => glyph,
     }
 }
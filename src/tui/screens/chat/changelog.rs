@@ -0,0 +1,86 @@
+use log::{info, warn};
+
+use crate::storage::seen_version::SeenVersion;
+use crate::tui::screens::chat::{CLIENT_VERSION, is_older_version};
+
+/// Embedded at build time, so it always describes the running version
+const CHANGELOG: &str = include_str!("../../../../CHANGELOG.md");
+
+/// Release notes shown in a popup, either everything or only what changed since the previous version that was run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Changelog {
+    pub title: String,
+    pub lines: Vec<&'static str>,
+    pub scroll: usize,
+}
+
+impl Changelog {
+    /// Every version, opened with `/changelog`
+    pub fn full() -> Self {
+        Changelog {
+            title: "Changelog".to_owned(),
+            lines: sections().into_iter().flat_map(|(_, lines)| lines).collect(),
+            scroll: 0,
+        }
+    }
+
+    /// The versions after `since` up to the running one, only the running version when it is not known what ran before
+    fn since(since: Option<&str>) -> Option<Self> {
+        let lines: Vec<&str> = sections()
+            .into_iter()
+            .filter(|(version, _)| !is_older_version(CLIENT_VERSION, version))
+            .filter(|(version, _)| match since {
+                Some(since) => is_older_version(since, version),
+                None => *version == CLIENT_VERSION,
+            })
+            .flat_map(|(_, lines)| lines)
+            .collect();
+        (!lines.is_empty()).then(|| Changelog {
+            title: format!("What's new in {CLIENT_VERSION}"),
+            lines,
+            scroll: 0,
+        })
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+
+    pub fn scroll_down(&mut self) {
+        self.scroll = (self.scroll + 1).min(self.lines.len().saturating_sub(1));
+    }
+}
+
+/// The changes since the version that ran before, once per upgrade. Records the running version as seen
+pub fn whats_new() -> Option<Changelog> {
+    let mut seen = SeenVersion::load();
+    if seen.version.as_deref() == Some(CLIENT_VERSION) {
+        return None;
+    }
+    let changelog = Changelog::since(seen.version.as_deref());
+    info!("Upgraded from {:?} to {CLIENT_VERSION}", seen.version);
+    seen.version = Some(CLIENT_VERSION.to_owned());
+    if let Err(e) = seen.save() {
+        warn!("Failed to remember the client version: {e}");
+    }
+    changelog
+}
+
+/// Lines of the changelog per version, starting at the `## <version>` heading
+fn sections() -> Vec<(&'static str, Vec<&'static str>)> {
+    let mut sections: Vec<(&str, Vec<&str>)> = vec![];
+    for line in CHANGELOG.lines() {
+        if let Some(version) = line.strip_prefix("## ") {
+            sections.push((version.trim(), vec![line]));
+        } else if let Some((_, lines)) = sections.last_mut() {
+            lines.push(line);
+        }
+    }
+    // Blank lines between versions are kept inside the sections, the trailing one of the last version is not needed
+    if let Some((_, lines)) = sections.last_mut() {
+        while lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
+    }
+    sections
+}
@@ -0,0 +1,88 @@
+use std::ops::Range;
+
+use crate::tui::chat::DisplayChannel;
+
+/// Suggestions shown below the input while completing a channel name
+pub const MAX_CHANNEL_SUGGESTIONS: usize = 5;
+
+fn is_name_char(chr: char) -> bool {
+    chr.is_alphanumeric() || chr == '-' || chr == '_'
+}
+
+/// `#name` tokens in a text that name a known channel, as the byte range of the token and the index of the channel
+pub fn find_channel_links(text: &str, channels: &[DisplayChannel]) -> Vec<(Range<usize>, usize)> {
+    let mut links = vec![];
+    for (start, _) in text.match_indices('#') {
+        // Only at the start of a word, so anchors in urls are left alone
+        if text[..start].chars().next_back().is_some_and(|chr| !chr.is_whitespace()) {
+            continue;
+        }
+        let name_start = start + 1;
+        let name_len = text[name_start..].find(|chr| !is_name_char(chr)).unwrap_or(text.len() - name_start);
+        let name = &text[name_start..name_start + name_len];
+        if name.is_empty() {
+            continue;
+        }
+        if let Some(idx) = channels.iter().position(|channel| channel.name.eq_ignore_ascii_case(name)) {
+            links.push((start..name_start + name_len, idx));
+        }
+    }
+    links
+}
+
+/// The `#name` being typed right before the cursor, as the byte index of its `#` and the name typed so far
+pub fn partial_channel_link(input: &str, cursor: usize) -> Option<(usize, &str)> {
+    let before = input.get(..cursor)?;
+    let start = before.rfind(|chr| !is_name_char(chr))?;
+    if !before[start..].starts_with('#') || before[..start].chars().next_back().is_some_and(|chr| !chr.is_whitespace()) {
+        return None;
+    }
+    Some((start, &before[start + 1..]))
+}
+
+/// Indices of the channels whose name starts with `prefix`, sorted by name
+pub fn channel_suggestions(channels: &[DisplayChannel], prefix: &str) -> Vec<usize> {
+    let prefix = prefix.to_lowercase();
+    let mut suggestions: Vec<usize> = channels
+        .iter()
+        .enumerate()
+        .filter(|(_, channel)| channel.name.to_lowercase().starts_with(&prefix))
+        .map(|(idx, _)| idx)
+        .collect();
+    suggestions.sort_by_key(|idx| &channels[*idx].name);
+    suggestions
+}
+
+/// Completes the name being typed up to what all suggestions share, or to the first suggestion when that adds nothing.
+/// Returns the new input and cursor position.
+pub fn complete_channel_link(input: &str, cursor: usize, channels: &[DisplayChannel]) -> Option<(String, usize)> {
+    let (start, prefix) = partial_channel_link(input, cursor)?;
+    let suggestions = channel_suggestions(channels, prefix);
+    let first = &channels[*suggestions.first()?].name;
+
+    let completion = if suggestions.len() == 1 {
+        format!("{first} ")
+    } else {
+        let common = suggestions.iter().fold(first.as_str(), |common, idx| {
+            let len = common
+                .char_indices()
+                .zip(channels[*idx].name.chars())
+                .take_while(|((_, a), b)| a.eq_ignore_ascii_case(b))
+                .last()
+                .map(|((idx, chr), _)| idx + chr.len_utf8())
+                .unwrap_or(0);
+            &common[..len]
+        });
+        if common.len() > prefix.len() {
+            common.to_owned()
+        } else {
+            format!("{first} ")
+        }
+    };
+
+    let mut completed = input[..start + 1].to_owned();
+    completed.push_str(&completion);
+    let new_cursor = completed.len();
+    completed.push_str(&input[cursor..]);
+    Some((completed, new_cursor))
+}
@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+
+/// Recent samples the estimate is the median of, so a single slow packet does not move it
+const MAX_SAMPLES: usize = 15;
+/// Skew in seconds above which the local clock is reported to be off
+const WARN_SKEW_SECONDS: i64 = 60;
+
+/// Estimates how far the server clock is ahead of the local clock.
+///
+/// The server stamps messages and channel events as they happen, so the ones that arrive live are compared against the local time.
+/// Network delay makes every sample a little early, which is negligible next to a clock that is minutes off.
+#[derive(Debug, Clone, Default)]
+pub struct ClockSkew {
+    samples: VecDeque<i64>,
+    /// Converts server timestamps to the local clock, so they line up with messages stamped locally
+    correct: bool,
+    warned: bool,
+}
+
+impl ClockSkew {
+    pub fn new(correct: bool) -> Self {
+        ClockSkew {
+            correct,
+            ..Default::default()
+        }
+    }
+
+    /// Records a timestamp the server stamped just now, returns the skew the first time it gets large
+    pub fn sample(&mut self, server_timestamp: u64) -> Option<i64> {
+        self.samples.push_back(server_timestamp as i64 - Utc::now().timestamp());
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        let skew = self.estimate();
+        if skew.abs() > WARN_SKEW_SECONDS && !self.warned {
+            self.warned = true;
+            return Some(skew);
+        }
+        None
+    }
+
+    /// Median of the recent samples in seconds, positive when the server is ahead
+    pub fn estimate(&self) -> i64 {
+        let mut samples: Vec<i64> = self.samples.iter().copied().collect();
+        samples.sort_unstable();
+        samples.get(samples.len() / 2).copied().unwrap_or(0)
+    }
+
+    /// Time of a server timestamp, on the local clock when correcting
+    pub fn local_time(&self, server_timestamp: u64) -> Result<DateTime<Utc>> {
+        let skew = if self.correct { self.estimate() } else { 0 };
+        DateTime::from_timestamp(server_timestamp as i64 - skew, 0).ok_or_else(|| anyhow!("Invalid timestamp"))
+    }
+}
+
+/// Describes the skew for a warning, e.g. "3 minutes behind"
+pub fn describe_skew(skew: i64) -> String {
+    let direction = if skew > 0 { "behind" } else { "ahead of" };
+    let seconds = skew.unsigned_abs();
+    let amount = match seconds {
+        0..120 => format!("{seconds} seconds"),
+        120..7200 => format!("{} minutes", seconds / 60),
+        _ => format!("{} hours", seconds / 3600),
+    };
+    format!("The local clock is {amount} {direction} the server")
+}
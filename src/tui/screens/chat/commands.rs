@@ -0,0 +1,92 @@
+use anyhow::{Result, anyhow};
+
+use crate::network::protocol::UserStatus;
+
+/// Slash commands typed into the chat input instead of a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Shows who is online and who was recently active
+    Who,
+    /// Hides the chat until the password is typed
+    Lock,
+    /// Opens the search popup with the rest of the input as the query
+    Search(String),
+    /// Lists the mentions and highlight words since the digest was opened before
+    Digest,
+    /// Lists the pinned messages of the open channel
+    Pins,
+    /// Shows how much disk space the history cache takes
+    CacheStats,
+    /// Opens the popup to change the name, bio and picture of the user
+    EditProfile,
+    /// Opens the popup to change the password, logging in again once it is changed
+    ChangePassword,
+    /// Shows the release notes of every version
+    Changelog,
+    /// Opens the notes to self
+    Notes,
+    /// Switches to a workspace of the config file by name
+    Workspace(String),
+    /// Opens a channel by name
+    Join(String),
+    /// Sets the status, it is then left alone by the focus and idle timers like one picked with `Alt + S`
+    Status(UserStatus),
+    /// Shows or hides the logs panel, toggles it without an argument
+    Logs(Option<bool>),
+    /// Switches the theme for the current server, goes back to the one of the config file without an argument
+    Theme(Option<String>),
+    /// A command registered by a plugin, or an unknown one when no plugin registered it
+    Plugin(String, String),
+}
+
+impl Command {
+    /// Returns `None` when the input is a regular message
+    pub fn parse(input: &str) -> Option<Result<Command>> {
+        let input = input.trim().strip_prefix('/')?;
+        let (name, args) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+
+        let command = match name {
+            "who" => Ok(Command::Who),
+            "lock" => Ok(Command::Lock),
+            "search" => Ok(Command::Search(args.trim().to_owned())),
+            "digest" => Ok(Command::Digest),
+            "pins" => Ok(Command::Pins),
+            "profile" => Ok(Command::EditProfile),
+            "password" => Ok(Command::ChangePassword),
+            "changelog" => Ok(Command::Changelog),
+            "notes" => Ok(Command::Notes),
+            "workspace" => match args.trim() {
+                "" => Err(anyhow!("Usage: /workspace <name>")),
+                name => Ok(Command::Workspace(name.to_owned())),
+            },
+            "join" => match args.trim().trim_start_matches('#') {
+                "" => Err(anyhow!("Usage: /join <channel>")),
+                name => Ok(Command::Join(name.to_owned())),
+            },
+            "status" => match args.trim() {
+                "online" => Ok(Command::Status(UserStatus::Online)),
+                "idle" => Ok(Command::Status(UserStatus::Idle)),
+                "dnd" => Ok(Command::Status(UserStatus::DoNotDisturb)),
+                "offline" => Ok(Command::Status(UserStatus::Offline)),
+                _ => Err(anyhow!("Usage: /status online|idle|dnd|offline")),
+            },
+            "logs" => match args.trim() {
+                "" => Ok(Command::Logs(None)),
+                "on" => Ok(Command::Logs(Some(true))),
+                "off" => Ok(Command::Logs(Some(false))),
+                _ => Err(anyhow!("Usage: /logs [on|off]")),
+            },
+            "theme" => match args.trim() {
+                "" => Ok(Command::Theme(None)),
+                name => Ok(Command::Theme(Some(name.to_owned()))),
+            },
+            "cache" => match args.trim() {
+                "stats" => Ok(Command::CacheStats),
+                _ => Err(anyhow!("Usage: /cache stats")),
+            },
+            "" => Err(anyhow!("Type a command after the /")),
+            _ => Ok(Command::Plugin(name.to_owned(), args.trim().to_owned())),
+        };
+        Some(command)
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::tui::chat::{ChatMessage, ChatMessageKind, DisplayChannel};
+use crate::tui::events::{ChannelId, UserId};
+use crate::tui::screens::chat::search::SearchResult;
+
+/// Messages listed at most, the oldest ones are left out
+pub const MAX_DIGEST_ENTRIES: usize = 200;
+
+/// Messages of others that mention the current user or contain a highlight word, listed with `Alt + D` to catch up
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Digest {
+    /// When the digest was opened before, only newer messages are listed
+    pub since: Option<DateTime<Utc>>,
+    /// Oldest first, so it reads like a single channel
+    pub entries: Vec<SearchResult>,
+    pub selected: usize,
+}
+
+impl Digest {
+    pub fn new(
+        since: Option<DateTime<Utc>>,
+        current_user: (UserId, &str),
+        highlight_words: &[String],
+        channels: &[DisplayChannel],
+        chat_history: &HashMap<ChannelId, Vec<ChatMessage>>,
+    ) -> Self {
+        let (user_id, username) = current_user;
+        let mut entries: Vec<(DateTime<Utc>, SearchResult)> = channels
+            .iter()
+            .enumerate()
+            .filter_map(|(channel_idx, channel)| Some((channel_idx, chat_history.get(&channel.id)?)))
+            .flat_map(|(channel_idx, messages)| {
+                messages
+                    .iter()
+                    .filter(|message| message.kind == ChatMessageKind::User && message.author_id != user_id)
+                    .filter(|message| since.is_none_or(|since| message.timestamp > since))
+                    .filter(|message| is_highlight(&message.message, username, highlight_words))
+                    .map(move |message| {
+                        (
+                            message.timestamp,
+                            SearchResult {
+                                channel_idx,
+                                message_id: message.message_id,
+                            },
+                        )
+                    })
+            })
+            .collect();
+        entries.sort_by_key(|(timestamp, _)| *timestamp);
+        let entries: Vec<SearchResult> = entries.into_iter().rev().take(MAX_DIGEST_ENTRIES).rev().map(|(_, entry)| entry).collect();
+
+        Digest { since, entries, selected: 0 }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn selected_entry(&self) -> Option<&SearchResult> {
+        self.entries.get(self.selected)
+    }
+}
+
+/// Mentions like `@penger` and highlight words match regardless of case
+pub fn is_highlight(text: &str, username: &str, highlight_words: &[String]) -> bool {
+    let text = text.to_lowercase();
+    text.contains(&format!("@{}", username.to_lowercase()))
+        || highlight_words
+            .iter()
+            .filter(|word| !word.trim().is_empty())
+            .any(|word| text.contains(&word.trim().to_lowercase()))
+}
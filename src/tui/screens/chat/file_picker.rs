@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::{MAIN_SEPARATOR, Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::tui::chat::PendingAttachment;
+
+/// Suggestions shown below the path, completion considers all of them
+pub const MAX_SUGGESTIONS: usize = 8;
+
+/// Path input of the attach file popup, suggests the entries of the directory that is being typed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePicker {
+    pub path: String,
+    pub suggestions: Vec<String>,
+}
+
+impl FilePicker {
+    /// Starts in the working directory
+    pub fn new() -> Self {
+        let path = std::env::current_dir()
+            .map(|dir| format!("{}{MAIN_SEPARATOR}", dir.display()))
+            .unwrap_or_default();
+        let mut picker = FilePicker { path, suggestions: vec![] };
+        picker.update_suggestions();
+        picker
+    }
+
+    pub fn push(&mut self, chr: char) {
+        self.path.push(chr);
+        self.update_suggestions();
+    }
+
+    pub fn pop(&mut self) {
+        self.path.pop();
+        self.update_suggestions();
+    }
+
+    /// Completes the path up to the longest prefix shared by all suggestions
+    pub fn complete(&mut self) {
+        let Some(first) = self.suggestions.first() else {
+            return;
+        };
+        let common = self.suggestions.iter().fold(first.as_str(), |common, suggestion| {
+            let len = common
+                .char_indices()
+                .zip(suggestion.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map(|((idx, chr), _)| idx + chr.len_utf8())
+                .unwrap_or(0);
+            &common[..len]
+        });
+        let (dir, _) = self.split();
+        self.path = format!("{dir}{common}");
+        self.update_suggestions();
+    }
+
+    /// Reads the selected file, its type is detected when it is staged
+    pub fn read(&self) -> Result<PendingAttachment> {
+        let path = expand_home(&self.path);
+        if path.is_dir() {
+            return Err(anyhow!("{} is a directory", path.display()));
+        }
+        let data = fs::read(&path).with_context(|| format!("Unable to read {}", path.display()))?;
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+        Ok(PendingAttachment {
+            filename,
+            data,
+            media_type: None,
+        })
+    }
+
+    /// Splits the typed path into the directory part, including its trailing separator, and the partial file name
+    fn split(&self) -> (&str, &str) {
+        match self.path.rfind(MAIN_SEPARATOR) {
+            Some(idx) => self.path.split_at(idx + 1),
+            None => ("", self.path.as_str()),
+        }
+    }
+
+    fn update_suggestions(&mut self) {
+        let (dir, prefix) = self.split();
+        let dir_path = if dir.is_empty() { PathBuf::from(".") } else { expand_home(dir) };
+        let Ok(entries) = fs::read_dir(dir_path) else {
+            self.suggestions.clear();
+            return;
+        };
+
+        let show_hidden = prefix.starts_with('.');
+        let mut suggestions: Vec<String> = entries
+            .flatten()
+            .map(|entry| {
+                let mut name = entry.file_name().to_string_lossy().into_owned();
+                if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    name.push(MAIN_SEPARATOR);
+                }
+                name
+            })
+            .filter(|name| name.starts_with(prefix) && (show_hidden || !name.starts_with('.')))
+            .collect();
+        suggestions.sort();
+        self.suggestions = suggestions;
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest.trim_start_matches(MAIN_SEPARATOR)),
+        _ => Path::new(path).to_path_buf(),
+    }
+}
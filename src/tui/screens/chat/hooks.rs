@@ -0,0 +1,146 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::time::timeout;
+
+use crate::tui::events::{ChannelId, MessageId, UserId};
+use crate::tui::screens::chat::translation::shell;
+
+/// Hooks still running after this long are killed, so a hanging command does not pile up processes
+const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `[[hooks]]` entry of the config file
+///
+/// ```toml
+/// [[hooks]]
+/// event = "mention"
+/// command = "notify-send \"$CHATGER_AUTHOR in #$CHATGER_CHANNEL\" \"$CHATGER_TEXT\""
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    pub event: HookEvent,
+    /// Shell command that is run every time the event happens, the event is passed in `CHATGER_*` variables and as JSON on stdin
+    pub command: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// A message of another user arrived
+    Message,
+    /// A message of another user mentions you or one of the highlight words
+    Mention,
+    /// The connection to the server was lost or closed by the server
+    Disconnect,
+}
+
+impl HookEvent {
+    fn name(self) -> &'static str {
+        match self {
+            HookEvent::Message => "message",
+            HookEvent::Mention => "mention",
+            HookEvent::Disconnect => "disconnect",
+        }
+    }
+}
+
+/// What a hook gets to know about the event, fields that do not apply to the event are left out
+#[derive(Serialize, Clone, Debug)]
+pub struct HookPayload {
+    pub event: HookEvent,
+    pub server: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<ChannelId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<MessageId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_id: Option<UserId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Unix seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl HookPayload {
+    pub fn new(event: HookEvent, server: String) -> Self {
+        HookPayload {
+            event,
+            server,
+            channel_id: None,
+            channel: None,
+            message_id: None,
+            author_id: None,
+            author: None,
+            text: None,
+            timestamp: None,
+            reason: None,
+        }
+    }
+
+    /// `CHATGER_` variables with the same fields as the JSON, for commands that do not want to parse it
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let env = [
+            ("CHATGER_EVENT", Some(self.event.name().to_owned())),
+            ("CHATGER_SERVER", Some(self.server.clone())),
+            ("CHATGER_CHANNEL_ID", self.channel_id.map(|id| id.to_string())),
+            ("CHATGER_CHANNEL", self.channel.clone()),
+            ("CHATGER_MESSAGE_ID", self.message_id.map(|id| id.to_string())),
+            ("CHATGER_AUTHOR_ID", self.author_id.map(|id| id.to_string())),
+            ("CHATGER_AUTHOR", self.author.clone()),
+            ("CHATGER_TEXT", self.text.clone()),
+            ("CHATGER_TIMESTAMP", self.timestamp.map(|timestamp| timestamp.to_string())),
+            ("CHATGER_REASON", self.reason.clone()),
+        ];
+        env.into_iter().filter_map(|(name, value)| Some((name, value?))).collect()
+    }
+}
+
+/// Starts every hook configured for the event in the background, failures are only logged
+pub fn run_hooks(hooks: &[HookConfig], payload: HookPayload) {
+    for hook in hooks.iter().filter(|hook| hook.event == payload.event) {
+        let command = hook.command.clone();
+        let payload = payload.clone();
+        tokio::spawn(async move {
+            match timeout(HOOK_TIMEOUT, run_hook(&command, &payload)).await {
+                Ok(Ok(())) => debug!("Ran the {:?} hook \"{command}\"", payload.event),
+                Ok(Err(e)) => warn!("Hook failed: {e:#}"),
+                Err(_) => warn!("Hook \"{command}\" was killed after running {} seconds", HOOK_TIMEOUT.as_secs()),
+            }
+        });
+    }
+}
+
+async fn run_hook(command: &str, payload: &HookPayload) -> Result<()> {
+    let json = serde_json::to_vec(payload)?;
+    let mut child = shell(command)
+        .envs(payload.env())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Unable to run \"{command}\""))?;
+
+    // Commands that only read the variables close stdin early, which is not an error
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(&json).await;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("\"{command}\" failed with {}: {}", output.status, stderr.trim()));
+    }
+    Ok(())
+}
@@ -1,18 +1,165 @@
+use std::time::Duration;
+
 use log::info;
-use ratatui::crossterm::event::{Event, KeyCode, KeyModifiers};
+use ratatui::crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 
+use crate::media::voice::VoiceNote;
+use crate::tui::chat::DisplayChannel;
 use crate::tui::events::TuiEvent;
+use crate::tui::input::input_key_event;
 use crate::tui::screens::GlobalState;
-use crate::tui::screens::chat::ChatFocus;
+use crate::tui::screens::chat::channel_links::find_channel_links;
+use crate::tui::screens::chat::{ChatFocus, ChatState, Pane, Popup};
+
+/// How long after a `g` a second `g` still counts as `gg`
+const VIM_SEQUENCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub fn handle_chat_key_event(event: Event, chat_state: &ChatState, global_state: &GlobalState) -> Option<TuiEvent> {
+    use KeyCode::*;
+    if chat_state.lock.is_some() {
+        return handle_lock_key_event(event);
+    }
+    if let Event::Key(key_event) = &event
+        && key_event.code == Char('l')
+        && key_event.modifiers == KeyModifiers::CONTROL
+    {
+        return Some(TuiEvent::Lock);
+    }
+    if let Some(popup) = &chat_state.popup {
+        return handle_popup_key_event(event, popup);
+    }
+    if let Event::Key(key_event) = &event
+        && let Some(event) = global_state.config.keybindings.event(key_event)
+    {
+        return Some(event);
+    }
+    if let Event::Key(key_event) = &event
+        && key_event.code == Esc
+        && global_state
+            .tutorial
+            .as_ref()
+            .is_some_and(|tutorial| tutorial.hint(chat_state.focus).is_some())
+    {
+        return Some(TuiEvent::DismissHint);
+    }
+    if let Event::Key(key_event) = &event
+        && key_event.code == Char('f')
+        && key_event.modifiers == KeyModifiers::CONTROL
+    {
+        return Some(TuiEvent::OpenSearch);
+    }
+    if let Event::Key(key_event) = &event
+        && key_event.code == F(2)
+    {
+        return Some(TuiEvent::OpenAccountSwitcher);
+    }
+    if let Event::Key(key_event) = &event
+        && matches!(key_event.code, Char('u') | Char('U'))
+        && key_event.modifiers == KeyModifiers::ALT
+    {
+        return Some(TuiEvent::JumpToUnread);
+    }
+    if let Event::Key(key_event) = &event
+        && matches!(key_event.code, Char('d') | Char('D'))
+        && key_event.modifiers == KeyModifiers::ALT
+    {
+        return Some(TuiEvent::OpenDigest);
+    }
+    if let Event::Key(key_event) = &event
+        && matches!(key_event.code, Char('p') | Char('P'))
+        && key_event.modifiers == KeyModifiers::ALT
+    {
+        return Some(TuiEvent::OpenPins);
+    }
+    if let Event::Key(key_event) = &event
+        && matches!(key_event.code, Char('s') | Char('S'))
+        && key_event.modifiers == KeyModifiers::ALT
+    {
+        return Some(TuiEvent::CycleStatus);
+    }
+    if let Event::Key(key_event) = &event
+        && key_event.modifiers == KeyModifiers::ALT
+    {
+        match key_event.code {
+            Char('1') => return Some(TuiEvent::TogglePane(Pane::Channels)),
+            Char('2') => return Some(TuiEvent::TogglePane(Pane::Users)),
+            Char('3') => return Some(TuiEvent::TogglePane(Pane::Info)),
+            Char('z') | Char('Z') => return Some(TuiEvent::ToggleZen),
+            Char('t') | Char('T') => return Some(TuiEvent::ToggleRelativeTimestamps),
+            _ => {}
+        }
+    }
+    if let Event::Key(key_event) = &event
+        && key_event.code == Char('x')
+        && key_event.modifiers == KeyModifiers::CONTROL
+        && chat_state.upload.is_some()
+    {
+        return Some(TuiEvent::CancelUpload);
+    }
+    if let Event::Key(key_event) = &event
+        && chat_state.voice_note.is_some()
+    {
+        match (key_event.code, &chat_state.voice_note) {
+            (Enter, Some(VoiceNote::Preview(_))) => return Some(TuiEvent::AttachVoiceNote),
+            (Esc, _) => return Some(TuiEvent::DiscardVoiceNote),
+            _ => {}
+        }
+    }
+    if global_state.config.vim_mode
+        && !matches!(chat_state.focus, ChatFocus::ChatInput(_))
+        && let Event::Key(key_event) = event
+    {
+        return handle_vim_normal_key_event(key_event, chat_state, global_state);
+    }
+    handle_pane_key_event(event, chat_state, global_state)
+}
+
+/// Normal mode of the vim keybindings, active in every pane but the message input.
+/// `hjkl` stand in for the arrow keys, and letters no longer jump to the input, `i` and `A` enter it instead
+fn handle_vim_normal_key_event(key_event: KeyEvent, chat_state: &ChatState, global_state: &GlobalState) -> Option<TuiEvent> {
+    use KeyCode::*;
+    let arrow = |code| handle_pane_key_event(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)), chat_state, global_state);
+    if key_event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+        return handle_pane_key_event(Event::Key(key_event), chat_state, global_state);
+    }
+    match key_event.code {
+        Char('h') => arrow(Left),
+        Char('j') => arrow(Down),
+        Char('k') => arrow(Up),
+        Char('l') => arrow(Right),
+        Char('i') => Some(TuiEvent::ChatFocusChange(ChatFocus::ChatInput(0))),
+        Char('A') => {
+            let draft_len = chat_state
+                .channels
+                .get(chat_state.active_channel_idx)
+                .and_then(|channel| chat_state.chat_inputs.get(&channel.id))
+                .map_or(0, String::len);
+            Some(TuiEvent::ChatFocusChange(ChatFocus::ChatInput(draft_len)))
+        }
+        Char('/') => Some(TuiEvent::OpenSearch),
+        Char('g') if chat_state.vim_g_pressed.is_some_and(|pressed| pressed.elapsed() < VIM_SEQUENCE_TIMEOUT) => Some(TuiEvent::ScrollToTop),
+        Char('g') => Some(TuiEvent::VimPrefix),
+        Char('G') => Some(TuiEvent::ScrollToBottom),
+        code => match handle_pane_key_event(Event::Key(key_event), chat_state, global_state) {
+            Some(TuiEvent::ChatFocusChange(ChatFocus::ChatInput(_))) if matches!(code, Char(_)) => None,
+            chat_event => chat_event,
+        },
+    }
+}
 
-pub fn handle_chat_key_event(event: Event, focus: ChatFocus, global_state: &GlobalState) -> Option<TuiEvent> {
+/// Keys of the focused pane, after the keys that work everywhere
+fn handle_pane_key_event(event: Event, chat_state: &ChatState, global_state: &GlobalState) -> Option<TuiEvent> {
     use KeyCode::*;
     match event {
-        Event::Key(key_event) => match focus {
+        Event::Key(key_event) => match chat_state.focus {
             ChatFocus::Channels => match key_event.code {
                 Up => Some(TuiEvent::ChannelUp),
                 Down => Some(TuiEvent::ChannelDown),
                 Right | Enter => Some(TuiEvent::ChatFocusChange(ChatFocus::ChatHistory)),
+                Char(' ') | Char('c') | Char('C') => Some(TuiEvent::ToggleChannelGroup),
+                Char('m') | Char('M') => Some(TuiEvent::ToggleMuteChannel),
+                Char('s') | Char('S') => Some(TuiEvent::CycleChannelSort),
+                Char('p') | Char('P') => Some(TuiEvent::TogglePinChannel),
                 Char('q') | Char('Q') => Some(TuiEvent::Exit),
                 Char('l') | Char('L') => Some(TuiEvent::ToggleLogs),
                 Char('x') | Char('X') => Some(TuiEvent::Logout),
@@ -20,7 +167,7 @@ pub fn handle_chat_key_event(event: Event, focus: ChatFocus, global_state: &Glob
             },
             ChatFocus::ChatHistory => match key_event.code {
                 Left => Some(TuiEvent::ChatFocusChange(ChatFocus::Channels)),
-                Right if global_state.show_logs => Some(TuiEvent::ChatFocusChange(ChatFocus::Logs)),
+                Right if global_state.logs_shown() => Some(TuiEvent::ChatFocusChange(ChatFocus::Logs)),
                 Right => Some(TuiEvent::ChatFocusChange(ChatFocus::Users(0))),
                 Up => Some(TuiEvent::ScrollUp),
                 Down => Some(TuiEvent::ScrollDown),
@@ -33,36 +180,62 @@ pub fn handle_chat_key_event(event: Event, focus: ChatFocus, global_state: &Glob
             },
             ChatFocus::ChatHistorySelection => match key_event.code {
                 Left => Some(TuiEvent::ChatFocusChange(ChatFocus::Channels)),
-                Right if global_state.show_logs => Some(TuiEvent::ChatFocusChange(ChatFocus::Logs)),
+                Right if global_state.logs_shown() => Some(TuiEvent::ChatFocusChange(ChatFocus::Logs)),
                 Right => Some(TuiEvent::ChatFocusChange(ChatFocus::Users(0))),
                 Up => Some(TuiEvent::ScrollUp),
                 Down => Some(TuiEvent::ScrollDown),
                 Char('s') | Char('S') | Esc => Some(TuiEvent::ChatFocusChange(ChatFocus::ChatHistory)),
                 Char('r') | Char('R') => Some(TuiEvent::Reply),
+                Char('e') => Some(TuiEvent::EditMessage),
+                Char('E') => Some(TuiEvent::ExpandMessage),
+                Char('d') | Char('D') => Some(TuiEvent::DownloadMedia),
+                Char('t') | Char('T') => Some(TuiEvent::TranslateMessage),
+                Char('z') | Char('Z') => Some(TuiEvent::ToggleCollapseUser),
+                Char('o') | Char('O') => Some(TuiEvent::JumpToReplied),
+                Char('>') => Some(TuiEvent::QuoteMessage),
+                Char('p') | Char('P') => Some(TuiEvent::TogglePinMessage),
+                Char('c') | Char('C')
+                    if chat_state
+                        .channels
+                        .get(chat_state.active_channel_idx)
+                        .is_some_and(DisplayChannel::is_local) =>
+                {
+                    Some(TuiEvent::CopyNoteToChannel)
+                }
                 Char('q') | Char('Q') => Some(TuiEvent::Exit),
                 Char('l') | Char('L') => Some(TuiEvent::ToggleLogs),
                 Char('x') | Char('X') => Some(TuiEvent::Logout),
-                Char(_) | Enter => Some(TuiEvent::ChatFocusChange(ChatFocus::ChatInput(0))),
+                // Enter follows the first channel link in the selected message
+                Enter => match chat_state
+                    .selected_message()
+                    .and_then(|message| find_channel_links(&message.message, &chat_state.channels).first().cloned())
+                {
+                    Some((_, channel_idx)) => Some(TuiEvent::JumpToChannel(channel_idx)),
+                    None => Some(TuiEvent::ChatFocusChange(ChatFocus::ChatInput(0))),
+                },
+                Char(_) => Some(TuiEvent::ChatFocusChange(ChatFocus::ChatInput(0))),
                 _ => None,
             },
             ChatFocus::ChatInput(_) => match key_event.code {
                 Up | Esc => Some(TuiEvent::ChatFocusChange(ChatFocus::ChatHistory)),
-                Left if key_event.modifiers == KeyModifiers::CONTROL => Some(TuiEvent::InputLeftTab),
-                Right if key_event.modifiers == KeyModifiers::CONTROL => Some(TuiEvent::InputRightTab),
-                Left => Some(TuiEvent::InputLeft),
-                Right => Some(TuiEvent::InputRight),
+                Tab => Some(TuiEvent::CompleteChannelLink),
                 Enter => Some(TuiEvent::MessageSend),
-                Char(chr) => Some(TuiEvent::InputChar(chr)),
-                Backspace => Some(TuiEvent::InputDelete),
-
-                _ => None,
+                Char('r') if key_event.modifiers == KeyModifiers::CONTROL && cfg!(feature = "voice") => Some(TuiEvent::ToggleVoiceRecording),
+                Char('o') if key_event.modifiers == KeyModifiers::CONTROL => Some(TuiEvent::OpenFilePicker),
+                Char('v') if key_event.modifiers == KeyModifiers::CONTROL => Some(TuiEvent::PasteImage),
+                Backspace if chat_state.focus == ChatFocus::ChatInput(0) && !chat_state.staged_attachments.is_empty() => {
+                    Some(TuiEvent::UnstageAttachment)
+                }
+                _ => input_key_event(&key_event),
             },
             ChatFocus::Users(_) => match key_event.code {
-                Left if global_state.show_logs => Some(TuiEvent::ChatFocusChange(ChatFocus::Logs)),
+                Left if global_state.logs_shown() => Some(TuiEvent::ChatFocusChange(ChatFocus::Logs)),
                 Left => Some(TuiEvent::ChatFocusChange(ChatFocus::ChatHistory)),
                 Up => Some(TuiEvent::ScrollUp),
                 Down => Some(TuiEvent::ScrollDown),
                 Char('v') | Char('V') => Some(TuiEvent::ViewUsers),
+                Char('s') | Char('S') => Some(TuiEvent::CycleUserSort),
+                Char('d') | Char('D') => Some(TuiEvent::OpenDirectMessage),
                 Char('q') | Char('Q') => Some(TuiEvent::Exit),
                 Char('l') | Char('L') => Some(TuiEvent::ToggleLogs),
                 Char(_) => Some(TuiEvent::ChatFocusChange(ChatFocus::ChatInput(0))),
@@ -76,6 +249,7 @@ pub fn handle_chat_key_event(event: Event, focus: ChatFocus, global_state: &Glob
                 Char('q') | Char('Q') => Some(TuiEvent::Exit),
                 Char('l') | Char('L') => Some(TuiEvent::ToggleLogs),
                 Char('x') | Char('X') => Some(TuiEvent::Logout),
+                Char('e') | Char('E') => Some(TuiEvent::ToggleLogRepeats),
                 Char(_) => Some(TuiEvent::ChatFocusChange(ChatFocus::ChatInput(0))),
 
                 _ => None,
@@ -86,3 +260,131 @@ pub fn handle_chat_key_event(event: Event, focus: ChatFocus, global_state: &Glob
         _ => None,
     }
 }
+
+fn handle_lock_key_event(event: Event) -> Option<TuiEvent> {
+    use KeyCode::*;
+    match event {
+        Event::Key(key_event) => match key_event.code {
+            Enter => Some(TuiEvent::Unlock),
+            Backspace => Some(TuiEvent::LockInputDelete),
+            Char(chr) => Some(TuiEvent::LockInputChar(chr)),
+            _ => None,
+        },
+        Event::FocusLost => Some(TuiEvent::FocusLost),
+        Event::FocusGained => Some(TuiEvent::FocusGained),
+        _ => None,
+    }
+}
+
+fn handle_popup_key_event(event: Event, popup: &Popup) -> Option<TuiEvent> {
+    use KeyCode::*;
+    match event {
+        Event::Key(key_event) => match popup {
+            Popup::Profile(_) => match key_event.code {
+                Esc | Char('q') | Char('Q') | Char('v') | Char('V') => Some(TuiEvent::ClosePopup),
+                Char('w') | Char('W') => Some(TuiEvent::ToggleWatchUser),
+                Char('z') | Char('Z') => Some(TuiEvent::ToggleCollapseUser),
+                Char('e') | Char('E') => Some(TuiEvent::OpenProfileEditor),
+                _ => None,
+            },
+            Popup::EditProfile(_) => match key_event.code {
+                Esc => Some(TuiEvent::ClosePopup),
+                Enter => Some(TuiEvent::SaveProfile),
+                Tab | Down => Some(TuiEvent::ProfileEditorNextField),
+                BackTab | Up => Some(TuiEvent::ProfileEditorPreviousField),
+                Backspace => Some(TuiEvent::ProfileEditorDelete),
+                Char(chr) => Some(TuiEvent::ProfileEditorChar(chr)),
+                _ => None,
+            },
+            Popup::ChangePassword(_) => match key_event.code {
+                Esc => Some(TuiEvent::ClosePopup),
+                Enter => Some(TuiEvent::SavePassword),
+                Tab | Down => Some(TuiEvent::PasswordEditorNextField),
+                BackTab | Up => Some(TuiEvent::PasswordEditorPreviousField),
+                Backspace => Some(TuiEvent::PasswordEditorDelete),
+                Char(chr) => Some(TuiEvent::PasswordEditorChar(chr)),
+                _ => None,
+            },
+            Popup::CompressAttachment(_) => match key_event.code {
+                Char('y') | Char('Y') | Enter => Some(TuiEvent::CompressAttachment),
+                Char('n') | Char('N') | Esc => Some(TuiEvent::ClosePopup),
+                _ => None,
+            },
+            Popup::QuitGuard { .. } => match key_event.code {
+                Char('w') | Char('W') | Enter => Some(TuiEvent::WaitThenQuit),
+                Char('f') | Char('F') => Some(TuiEvent::ForceQuit),
+                Char('c') | Char('C') | Esc => Some(TuiEvent::ClosePopup),
+                _ => None,
+            },
+            Popup::PasteImage(_) => match key_event.code {
+                Char('y') | Char('Y') | Enter => Some(TuiEvent::AttachClipboardImage),
+                Char('n') | Char('N') | Esc => Some(TuiEvent::ClosePopup),
+                _ => None,
+            },
+            Popup::Accounts(_) => match key_event.code {
+                Esc | Char('q') | Char('Q') => Some(TuiEvent::ClosePopup),
+                Enter => Some(TuiEvent::SwitchAccount),
+                Up => Some(TuiEvent::AccountSwitcherUp),
+                Down => Some(TuiEvent::AccountSwitcherDown),
+                _ => None,
+            },
+            Popup::Changelog(_) => match key_event.code {
+                Esc | Enter | Char('q') | Char('Q') => Some(TuiEvent::ClosePopup),
+                Up => Some(TuiEvent::ChangelogUp),
+                Down => Some(TuiEvent::ChangelogDown),
+                _ => None,
+            },
+            Popup::ExpandedMessage(_) => match key_event.code {
+                Esc | Enter | Char('q') | Char('Q') => Some(TuiEvent::ClosePopup),
+                Up => Some(TuiEvent::ExpandedMessageUp),
+                Down => Some(TuiEvent::ExpandedMessageDown),
+                _ => None,
+            },
+            Popup::Who | Popup::CacheStats(_) => match key_event.code {
+                Esc | Enter | Char('q') | Char('Q') => Some(TuiEvent::ClosePopup),
+                _ => None,
+            },
+            Popup::Search(search) => match key_event.code {
+                Esc if search.backfill.is_some() => Some(TuiEvent::CancelSearchBackfill),
+                Esc => Some(TuiEvent::ClosePopup),
+                Enter => match search.selected_result() {
+                    Some(result) => Some(TuiEvent::JumpToMessage(result.channel_idx, result.message_id)),
+                    None => Some(TuiEvent::StartSearchBackfill),
+                },
+                Up => Some(TuiEvent::SearchUp),
+                Down => Some(TuiEvent::SearchDown),
+                Backspace => Some(TuiEvent::SearchInputDelete),
+                Char(chr) => Some(TuiEvent::SearchInputChar(chr)),
+                _ => None,
+            },
+            Popup::Digest(digest) => match key_event.code {
+                Esc | Char('q') | Char('Q') => Some(TuiEvent::ClosePopup),
+                Enter => digest
+                    .selected_entry()
+                    .map(|entry| TuiEvent::JumpToMessage(entry.channel_idx, entry.message_id)),
+                Up => Some(TuiEvent::DigestUp),
+                Down => Some(TuiEvent::DigestDown),
+                _ => None,
+            },
+            Popup::Pins(_) => match key_event.code {
+                Esc | Char('q') | Char('Q') => Some(TuiEvent::ClosePopup),
+                Enter => Some(TuiEvent::JumpToPinned),
+                Up => Some(TuiEvent::PinsUp),
+                Down => Some(TuiEvent::PinsDown),
+                Char('u') | Char('U') | Delete => Some(TuiEvent::UnpinSelected),
+                _ => None,
+            },
+            Popup::AttachFile(_) => match key_event.code {
+                Esc => Some(TuiEvent::ClosePopup),
+                Enter => Some(TuiEvent::AttachFile),
+                Tab => Some(TuiEvent::FilePickerComplete),
+                Backspace => Some(TuiEvent::FilePickerDelete),
+                Char(chr) => Some(TuiEvent::FilePickerChar(chr)),
+                _ => None,
+            },
+        },
+        Event::FocusLost => Some(TuiEvent::FocusLost),
+        Event::FocusGained => Some(TuiEvent::FocusGained),
+        _ => None,
+    }
+}
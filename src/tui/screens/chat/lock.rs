@@ -0,0 +1,18 @@
+/// Screen that hides the chat until the account password, or the configured PIN, is typed
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LockScreen {
+    pub input: String,
+    pub failed_attempts: u32,
+}
+
+impl LockScreen {
+    /// Checks the typed secret and clears the input, the comparison takes the same time wherever the first difference is
+    pub fn try_unlock(&mut self, secret: &str) -> bool {
+        let input = std::mem::take(&mut self.input);
+        let matches = input.len() == secret.len() && input.bytes().zip(secret.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+        if !matches {
+            self.failed_attempts += 1;
+        }
+        matches
+    }
+}
@@ -0,0 +1,73 @@
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::cli::TimestampFormat;
+use crate::tui::chat::ChatMessage;
+
+/// Columns the text of a message is wrapped to in the expanded message popup
+pub const EXPANDED_MESSAGE_WIDTH: usize = 76;
+
+/// A message that was cut off in the history, shown in full in a popup opened with `E`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpandedMessage {
+    pub author_name: String,
+    pub timestamp: String,
+    pub lines: Vec<String>,
+    pub scroll: usize,
+}
+
+impl ExpandedMessage {
+    pub fn new(message: &ChatMessage, timestamps: &TimestampFormat) -> Self {
+        ExpandedMessage {
+            author_name: message.author_name.clone(),
+            timestamp: timestamps.format(message.timestamp, "%Y-%m-%d ", true),
+            lines: wrap_ranges(&message.message, EXPANDED_MESSAGE_WIDTH)
+                .into_iter()
+                .map(|range| message.message[range].to_owned())
+                .collect(),
+            scroll: 0,
+        }
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+
+    pub fn scroll_down(&mut self) {
+        self.scroll = (self.scroll + 1).min(self.lines.len().saturating_sub(1));
+    }
+}
+
+/// Byte ranges of the lines `text` takes up in `width` columns. Lines end at newlines and are wrapped after a space,
+/// a word longer than a whole line is split between two graphemes
+pub fn wrap_ranges(text: &str, width: usize) -> Vec<Range<usize>> {
+    let width = width.max(1);
+    let mut ranges = vec![];
+    let mut offset = 0;
+    for line in text.split('\n') {
+        let mut start = offset;
+        let mut used = 0;
+        // End of the last space on the current line, where it is wrapped preferably
+        let mut after_space = None;
+        for (idx, grapheme) in line.grapheme_indices(true) {
+            let idx = offset + idx;
+            let grapheme_width = grapheme.width();
+            if used + grapheme_width > width && idx > start {
+                let end = after_space.unwrap_or(idx);
+                ranges.push(start..end);
+                used = text[end..idx].graphemes(true).map(UnicodeWidthStr::width).sum();
+                start = end;
+                after_space = None;
+            }
+            used += grapheme_width;
+            if grapheme.trim().is_empty() {
+                after_space = Some(idx + grapheme.len());
+            }
+        }
+        ranges.push(start..offset + line.len());
+        offset += line.len() + 1;
+    }
+    ranges
+}
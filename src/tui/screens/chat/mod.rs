@@ -1,28 +1,110 @@
+pub mod accounts;
+pub mod alerts;
 pub mod borders;
+pub mod changelog;
+pub mod channel_links;
+pub mod clock_skew;
+pub mod commands;
+pub mod digest;
+pub mod file_picker;
+pub mod hooks;
 pub mod keys;
+pub mod lock;
+pub mod long_messages;
+pub mod notifications;
+pub mod pagination;
+pub mod password_editor;
+pub mod pins;
+pub mod plugins;
+pub mod profile_editor;
+pub mod search;
+pub mod startup;
+pub mod translation;
+pub mod tutorial;
 pub mod ui;
 
-use std::collections::{HashMap, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
 
 use anyhow::{Result, anyhow};
-use chrono::{DateTime, Utc};
-use log::{debug, error, info};
+use chrono::{DateTime, Local, Utc};
+use log::{debug, error, info, warn};
 use tokio::sync::mpsc::Sender;
 use tokio::time::Instant;
+use unicode_segmentation::UnicodeSegmentation;
 
-use crate::network::client::{Client, ServerAddrInfo, ServerConnectionStatus};
-use crate::network::protocol::UserStatus;
-use crate::tui::chat::{ChatMessage, ChatMessageStatus, DisplayChannel, User};
-use crate::tui::events::{ChannelId, MessageId, TuiEvent, UserId};
-use crate::tui::screens::Screen;
+use crate::cli::{AppConfig, ReconnectMode, SavedAccount};
+use crate::media::cache::MediaCache;
+use crate::media::clipboard::{ClipboardImage, paste_image};
+use crate::media::download::save_media;
+use crate::media::fetch::{FetchView, MediaFetcher, VISIBLE_MESSAGES};
+use crate::media::preview::{Avatar, ImagePreview};
+use crate::media::voice::{VoiceNote, VoiceRecorder};
+use crate::media::{MAX_MEDIA_SIZE, compress_image, detect_media_type, format_size, is_compressible_image};
+use crate::network::client::{Client, ConnectionType, Reauthentication, ServerAddrInfo, ServerConnectionStatus};
+use crate::network::protocol::client::{ResumePacket, UserConfigField};
+use crate::network::protocol::server::{ChannelEventKind, ChannelEventPacket, HistoryMessage};
+use crate::network::protocol::{Capabilities, MediaType, UserStatus};
+use crate::storage::audit_log::AuditEntry;
+use crate::storage::channel_groups::ChannelGroups;
+use crate::storage::checkpoint::{Checkpoint, Draft, ViewCheckpoint};
+use crate::storage::history_cache::{CacheStats, HistoryCache, RetentionRule};
+use crate::storage::notes::Notes;
+use crate::storage::preferences::{ChannelSortOrder, PinnedMessage, ServerPreferences, UserSortOrder};
+use crate::storage::secrets::{Secret, totp_code, totp_secret};
+use crate::storage::status::{UnreadSummary, clear_status};
+use crate::tui::chat::{
+    Attachment, ChannelStatus, ChatMessage, ChatMessageKind, ChatMessageStatus, DIRECT_MESSAGES_GROUP, DisplayChannel, LOCAL_GROUP, MediaMessage,
+    MediaUpload, NOTES_CHANNEL_ID, PendingAttachment, StagedAttachment, Toast, User,
+};
+use crate::tui::events::{ChannelId, MediaId, MessageId, ProfilePicId, TuiEvent, UserId};
+use crate::tui::input::{apply_edit, insert_char};
+use crate::tui::screens::chat::accounts::AccountSwitcher;
+use crate::tui::screens::chat::alerts::ring_bell;
+use crate::tui::screens::chat::changelog::Changelog;
+use crate::tui::screens::chat::channel_links::complete_channel_link;
+use crate::tui::screens::chat::clock_skew::{ClockSkew, describe_skew};
+use crate::tui::screens::chat::commands::Command;
+use crate::tui::screens::chat::digest::{Digest, is_highlight};
+use crate::tui::screens::chat::file_picker::FilePicker;
+use crate::tui::screens::chat::hooks::{HookEvent, HookPayload, run_hooks};
+use crate::tui::screens::chat::lock::LockScreen;
+use crate::tui::screens::chat::long_messages::ExpandedMessage;
+use crate::tui::screens::chat::pagination::HistoryPagination;
+use crate::tui::screens::chat::password_editor::PasswordEditor;
+use crate::tui::screens::chat::pins::Pins;
+use crate::tui::screens::chat::plugins::{PluginAction, PluginContext, PluginMessage, Plugins};
+use crate::tui::screens::chat::profile_editor::{ProfileEditor, ProfileUpdate};
+use crate::tui::screens::chat::search::{BACKFILL_INTERVAL, MAX_BACKFILL_PAGES, Search};
+use crate::tui::screens::chat::startup::{StartupSequence, StartupStep};
+use crate::tui::screens::chat::translation::{Translation, translate};
+use crate::tui::screens::login::{InputStatus, LoginFocus};
+use crate::tui::screens::{GlobalState, Screen};
+use crate::tui::theme::Theme;
 use crate::tui::{AppState, State};
 
+pub const VOICE_NOTE_WAVEFORM_WIDTH: usize = 40;
+pub const TOAST_DURATION: Duration = Duration::from_secs(5);
+/// How often user statuses are refreshed while the terminal is not focused, instead of on every health check of the server
+const UNFOCUSED_STATUS_REFRESH: Duration = Duration::from_secs(60);
+/// Least time between two reorderings of the users panel
+const USER_SORT_INTERVAL: Duration = Duration::from_secs(2);
+/// How long the syncing badge stays after history was asked for again, empty responses do not say which channel they are for
+const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+/// Characters of a message kept when it is quoted into the input, the reply bar is there for the full text
+const QUOTE_LENGTH: usize = 100;
+/// Pages of older history loaded to find a quoted or pinned message before giving up
+const MAX_JUMP_PAGES: u32 = 20;
+const CLIENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
 #[derive(Clone, Debug)]
 pub struct UserProfile {
     pub user_id: UserId,
     pub username: String,
-    pub password: String,
+    pub password: Secret,
     pub status: UserStatus,
 }
 
@@ -36,16 +118,93 @@ pub enum ChatFocus {
     Logs,
 }
 
+/// Panes that can be hidden to leave more room for the chat history
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Pane {
+    Channels,
+    Users,
+    Info,
+}
+
+/// Panes hidden one by one, or all at once in zen mode
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct HiddenPanes {
+    pub channels: bool,
+    pub users: bool,
+    pub info: bool,
+    /// Hides all panes without forgetting which were hidden one by one, so leaving zen mode restores them
+    pub zen: bool,
+}
+
+impl HiddenPanes {
+    pub fn hides(self, pane: Pane) -> bool {
+        self.zen
+            || match pane {
+                Pane::Channels => self.channels,
+                Pane::Users => self.users,
+                Pane::Info => self.info,
+            }
+    }
+
+    pub fn toggle(&mut self, pane: Pane) {
+        // Showing a pane in zen mode leaves zen mode with only the other panes hidden
+        if self.zen {
+            *self = HiddenPanes {
+                channels: true,
+                users: true,
+                info: true,
+                zen: false,
+            };
+        }
+        let hidden = match pane {
+            Pane::Channels => &mut self.channels,
+            Pane::Users => &mut self.users,
+            Pane::Info => &mut self.info,
+        };
+        *hidden = !*hidden;
+    }
+
+    /// Whether `focus` is on a hidden pane
+    pub fn hides_focus(self, focus: ChatFocus) -> bool {
+        match focus {
+            ChatFocus::Channels => self.hides(Pane::Channels),
+            ChatFocus::Users(_) => self.hides(Pane::Users),
+            ChatFocus::Logs => self.zen,
+            _ => false,
+        }
+    }
+}
+
+/// What the unread counts of a channel were counted from
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct UnreadKey {
+    last_read_id: MessageId,
+    history_length: usize,
+    newest_message_id: Option<MessageId>,
+    /// Changes once the users list has the current user
+    own_user_id: UserId,
+}
+
 #[derive(Clone, Debug)]
 pub struct ChatState {
     pub focus: ChatFocus,
     pub channels: Vec<DisplayChannel>,
     pub users: Vec<User>,
+    /// Order of the users panel, only recomputed every `USER_SORT_INTERVAL` so status storms do not reorder it on every update
+    pub user_order: Vec<UserId>,
+    pub users_changed: bool,
+    pub users_sorted: Instant,
+    /// Status updates that arrived since the last tick, applied together with the latest status per user
+    pub pending_statuses: HashMap<UserId, UserStatus>,
+    /// Unknown users whose names were asked for and did not arrive yet
+    pub requested_users: HashSet<UserId>,
     pub chat_history: HashMap<ChannelId, Vec<ChatMessage>>,
     pub chat_inputs: HashMap<ChannelId, String>,
     pub active_channel_idx: usize,
     pub current_user: UserProfile,
     pub chat_scroll_offset: usize,
+    /// Lines of the newest message in view that are scrolled below the history, for messages taller than the history
+    pub chat_line_offset: usize,
     pub server_address: ServerAddrInfo,
     pub server_connection_status: ServerConnectionStatus,
     pub waiting_message_acks_id: VecDeque<MessageId>,
@@ -54,7 +213,824 @@ pub struct ChatState {
     pub is_typing: bool,
     pub time_since_last_typing: Instant,
     pub time_since_last_focused: Option<Instant>,
-    pub replying_to: Option<ChatMessage>,
+    /// Set when the status was picked with `Alt + S`, focus and idle changes then leave it alone
+    pub manual_status: bool,
+    /// Whether the terminal has focus, messages arriving while it does not raise a desktop notification
+    pub terminal_focused: bool,
+    /// Last time user statuses were requested, they are requested less often while the terminal is not focused
+    pub user_statuses_requested: Instant,
+    /// When `g` was pressed in the vim normal mode, a second `g` shortly after jumps to the top
+    pub vim_g_pressed: Option<Instant>,
+    /// Messages that arrived while the terminal was not focused, shown in the terminal title
+    pub unseen_messages: usize,
+    /// Count last written to the terminal title, it is only rewritten when it changes
+    pub written_title: Option<usize>,
+    /// Messages the drafts reply to, per channel like the drafts themselves
+    pub reply_targets: HashMap<ChannelId, ChatMessage>,
+    /// Own messages whose text is loaded into the input of their channel, sending replaces their text instead of sending a new message
+    pub edits: HashMap<ChannelId, MessageEdit>,
+    /// Messages as they were before an edit that the server has not confirmed yet, restored when the edit fails
+    pub pending_edits: HashMap<MessageId, ChatMessage>,
+    pub channel_groups: ChannelGroups,
+    pub selected_channel_group: Option<String>,
+    pub startup: StartupSequence,
+    pub preferences: ServerPreferences,
+    pub popup: Option<Popup>,
+    pub toasts: VecDeque<Toast>,
+    pub upload: Option<MediaUpload>,
+    /// Profile changes sent with the edit profile popup, until the server answers
+    pub profile_update: Option<ProfileUpdate>,
+    /// A password change was sent with the change password popup and the server has not answered yet
+    pub password_change_pending: bool,
+    /// Attachments sent with the next message
+    pub staged_attachments: Vec<StagedAttachment>,
+    pub voice_note: Option<VoiceNote>,
+    pub media_fetcher: MediaFetcher,
+    pub media_cache: MediaCache,
+    /// Thumbnails of the image attachments that were fetched
+    pub previews: HashMap<MediaId, ImagePreview>,
+    /// Profile pictures that were fetched, by their id
+    pub avatars: HashMap<ProfilePicId, Avatar>,
+    /// Media that is saved to the download directory once it arrives
+    pub pending_downloads: HashSet<MediaId>,
+    /// Last time a user was seen with a status other than offline
+    pub last_seen: HashMap<UserId, DateTime<Utc>>,
+    /// Translations requested with `T`, shown under the original message
+    pub translations: HashMap<MessageId, Translation>,
+    /// Set while the chat is hidden behind the lock screen, the session keeps running underneath
+    pub lock: Option<LockScreen>,
+    /// Summary last written to the status file, it is only rewritten when it changes
+    pub written_status: Option<UnreadSummary>,
+    /// Unread counts of every channel with what they were counted from, only channels that changed are counted again
+    pub unread_counts: HashMap<ChannelId, (UnreadKey, UnreadSummary)>,
+    /// Shown in the info bar when the server wants a newer client
+    pub version_warning: Option<String>,
+    /// Optional features of the server, features it lacks are refused with a toast and left out of the key hints
+    pub capabilities: Capabilities,
+    /// History changed since it was last written to the cache
+    pub history_cache_dirty: bool,
+    pub history_cache_saved: Instant,
+    /// Rules of the config file that limit how much of the history is cached
+    pub retention: Vec<RetentionRule>,
+    /// Whether sent messages are recorded in the audit log
+    pub audit_log: bool,
+    /// Direct channels whose channel info was requested, made direct once it arrives
+    pub pending_direct_channels: HashMap<ChannelId, UserId>,
+    /// User a direct channel was asked for with, opened once the server answers
+    pub opening_direct_message: Option<UserId>,
+    pub history_pagination: HistoryPagination,
+    /// Jump to a quoted or pinned message, waiting for older history to load
+    pub message_jump: Option<MessageJump>,
+    pub clock_skew: ClockSkew,
+    /// Last checkpoint written to disk, an unchanged checkpoint is not written again
+    pub saved_checkpoint: Checkpoint,
+    pub checkpoint_saved: Instant,
+    /// View of the previous session, applied once the history it points at is loaded
+    pub pending_view: Option<ViewCheckpoint>,
+    /// Server channel that was open last, notes are copied into its draft
+    pub last_server_channel: Option<ChannelId>,
+    /// Channels whose shown history may miss messages since it was cached or the connection dropped,
+    /// with when fresh history was asked for, `None` while it can not be asked for yet
+    pub syncing: HashMap<ChannelId, Option<Instant>>,
+    /// Authors whose messages are folded into single line stubs in a channel, only for this session
+    pub collapsed_users: HashSet<(ChannelId, UserId)>,
+    /// Token of the last login, resumes the session or logs in again after a reconnect without sending the password
+    pub session_token: Option<Secret>,
+    /// A login with the session token is waiting for an answer, the password is sent when the server turns it down
+    pub token_login: bool,
+    /// The server accepted the login. Unset while the cached history is shown because the server could not be reached,
+    /// as the password was not checked yet
+    pub login_verified: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Popup {
+    Profile(UserId),
+    /// Offers to shrink an image that exceeds the upload limit
+    CompressAttachment(PendingAttachment),
+    /// Offers to attach the image on the clipboard, opened with `Ctrl + V`
+    PasteImage(ClipboardImage),
+    AttachFile(FilePicker),
+    /// Presence report opened by `/who`
+    Who,
+    /// Searches the loaded history of every channel, opened with `Ctrl + F` or `/search`
+    Search(Search),
+    /// Mentions and highlight words since it was opened before, opened with `Alt + D` or `/digest`
+    Digest(Digest),
+    /// Disk usage of the history cache, opened with `/cache stats`
+    CacheStats(CacheStats),
+    /// Switches to another account, opened with `F2`
+    Accounts(AccountSwitcher),
+    /// Changes the profile of the user, opened with `/profile` or `E` in their own profile
+    EditProfile(ProfileEditor),
+    /// Changes the password, opened with `/password`
+    ChangePassword(PasswordEditor),
+    /// Release notes, shown once after an upgrade and opened with `/changelog`
+    Changelog(Changelog),
+    /// A message that is cut off in the history, opened with `E` on the selected message
+    ExpandedMessage(ExpandedMessage),
+    /// Pinned messages of the open channel, opened with `Alt + P` or `/pins`
+    Pins(Pins),
+    /// Asks before quitting while messages or media are still on their way, `waiting` quits once they are done
+    QuitGuard {
+        waiting: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SidebarRow {
+    Group(String),
+    Channel(usize),
+}
+
+/// A message to jump to that is not loaded yet, older pages are requested until it shows up
+#[derive(Clone, Debug)]
+pub struct MessageJump {
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    pub pages: u32,
+}
+
+/// An edit of an own message, the draft it replaced in the input is put back once the edit is sent or cancelled
+#[derive(Clone, Debug)]
+pub struct MessageEdit {
+    pub message: ChatMessage,
+    pub draft: String,
+    pub reply_target: Option<ChatMessage>,
+}
+
+impl ChatState {
+    /// The bar above the input is shown while replying, editing or when attachments are staged
+    pub fn shows_reply_bar(&self) -> bool {
+        self.reply_target().is_some() || self.active_edit().is_some() || !self.staged_attachments.is_empty()
+    }
+
+    /// Message the draft of the open channel replies to
+    pub fn reply_target(&self) -> Option<&ChatMessage> {
+        let channel = self.channels.get(self.active_channel_idx)?;
+        self.reply_targets.get(&channel.id)
+    }
+
+    /// Edit in the input of the open channel
+    pub fn active_edit(&self) -> Option<&MessageEdit> {
+        let channel = self.channels.get(self.active_channel_idx)?;
+        self.edits.get(&channel.id)
+    }
+
+    /// Starts editing `message` in the input of its channel, the draft and reply target in there are set aside until the edit ends.
+    /// Switching to another message keeps what was set aside for the first one
+    fn start_edit(&mut self, channel_id: ChannelId, message: ChatMessage) {
+        let input = self.chat_inputs.entry(channel_id).or_default();
+        let text = std::mem::replace(input, message.message.clone());
+        let edit = match self.edits.remove(&channel_id) {
+            Some(previous) => MessageEdit { message, ..previous },
+            None => MessageEdit {
+                message,
+                draft: text,
+                reply_target: self.reply_targets.remove(&channel_id),
+            },
+        };
+        self.edits.insert(channel_id, edit);
+    }
+
+    /// Ends the edit in the input of `channel_id`, putting back the draft and reply target it set aside. Returns the edit and its text
+    fn end_edit(&mut self, channel_id: ChannelId) -> Option<(ChatMessage, String)> {
+        let edit = self.edits.remove(&channel_id)?;
+        let text = std::mem::replace(self.chat_inputs.entry(channel_id).or_default(), edit.draft);
+        if let Some(reply_target) = edit.reply_target {
+            self.reply_targets.insert(channel_id, reply_target);
+        }
+        Some((edit.message, text))
+    }
+
+    /// Users in the order they are listed in the users panel, online users first as of the last time the panel was sorted.
+    /// Users that arrived since are listed last
+    pub fn sorted_users(&self) -> Vec<&User> {
+        let users: HashMap<UserId, &User> = self.users.iter().map(|user| (user.id, user)).collect();
+        let mut sorted: Vec<&User> = self.user_order.iter().filter_map(|user_id| users.get(user_id).copied()).collect();
+        if sorted.len() < self.users.len() {
+            let listed: HashSet<&UserId> = self.user_order.iter().collect();
+            sorted.extend(self.users.iter().filter(|user| !listed.contains(&user.id)));
+        }
+        sorted
+    }
+
+    /// Recomputes the order of the users panel
+    pub fn sort_users(&mut self) {
+        let (mut online_users, mut offline_users): (Vec<&User>, Vec<&User>) = self.users.iter().partition(|user| user.status != UserStatus::Offline);
+        match self.preferences.user_sort {
+            UserSortOrder::Name => online_users.sort_by_key(|user| &user.name),
+            UserSortOrder::Status => online_users.sort_by_key(|user| (status_rank(&user.status), &user.name)),
+        }
+        offline_users.sort_by_key(|user| &user.name);
+        self.user_order = online_users.into_iter().chain(offline_users).map(|user| user.id).collect();
+        self.users_changed = false;
+        self.users_sorted = Instant::now();
+    }
+
+    /// Sorts the users panel when users changed and it was not sorted for a while, or not at all yet
+    pub fn sort_users_debounced(&mut self) {
+        if self.users_changed && (self.user_order.is_empty() || self.users_sorted.elapsed() >= USER_SORT_INTERVAL) {
+            self.sort_users();
+        }
+    }
+
+    /// Rows of the channels sidebar in display order, pinned channels come first and ungrouped channels after them.
+    /// Channels inside a collapsed group are left out.
+    pub fn sidebar_rows(&self) -> Vec<SidebarRow> {
+        let pinned = |idx: usize| self.preferences.pinned_channels.contains(&self.channels[idx].id);
+        let mut rows: Vec<SidebarRow> = (0..self.channels.len()).filter(|&idx| pinned(idx)).map(SidebarRow::Channel).collect();
+        rows.extend(
+            self.sorted_channels(|channel| channel.group.is_none())
+                .into_iter()
+                .filter(|&idx| !pinned(idx))
+                .map(SidebarRow::Channel),
+        );
+
+        let mut groups: Vec<&String> = vec![];
+        for channel in &self.channels {
+            if let Some(group) = &channel.group
+                && !groups.contains(&group)
+            {
+                groups.push(group);
+            }
+        }
+        // Stable, so only the direct channels move to the bottom
+        groups.sort_by_key(|group| (*group == LOCAL_GROUP, *group == DIRECT_MESSAGES_GROUP));
+
+        for group in groups {
+            rows.push(SidebarRow::Group(group.clone()));
+            if !self.channel_groups.collapsed.contains(group) {
+                rows.extend(
+                    self.sorted_channels(|channel| channel.group.as_ref() == Some(group))
+                        .into_iter()
+                        .filter(|&idx| !pinned(idx))
+                        .map(SidebarRow::Channel),
+                );
+            }
+        }
+        rows
+    }
+
+    /// `#name` of a channel, `@user` of a direct channel, or the plain name of the notes to self
+    pub fn channel_label(&self, channel: &DisplayChannel) -> String {
+        match channel.direct_user.and_then(|user_id| self.users.iter().find(|user| user.id == user_id)) {
+            Some(user) => format!("@{}", user.name),
+            None if channel.is_local() => channel.name.clone(),
+            None => format!("#{}", channel.name),
+        }
+    }
+
+    /// Indices of the channels matching the filter, in the sort order of the sidebar
+    fn sorted_channels(&self, filter: impl Fn(&DisplayChannel) -> bool) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.channels.len()).filter(|&idx| filter(&self.channels[idx])).collect();
+        if self.preferences.channel_sort == ChannelSortOrder::Activity {
+            // Stable, so channels without messages keep the server order among themselves.
+            // System lines such as watched users coming online or channel events are not chat activity
+            indices.sort_by_key(|&idx| {
+                let last_message = self
+                    .chat_history
+                    .get(&self.channels[idx].id)
+                    .and_then(|messages| messages.iter().rev().find(|message| message.kind != ChatMessageKind::System));
+                Reverse(last_message.map(|message| message.timestamp))
+            });
+        }
+        indices
+    }
+
+    /// Index of the selected row in `rows`, falls back to the group header when the active channel is collapsed
+    pub fn sidebar_position(&self, rows: &[SidebarRow]) -> usize {
+        rows.iter()
+            .position(|row| match (row, &self.selected_channel_group) {
+                (SidebarRow::Group(group), Some(selected)) => group == selected,
+                (SidebarRow::Channel(idx), None) => *idx == self.active_channel_idx,
+                _ => false,
+            })
+            .or_else(|| {
+                let group = self.channels.get(self.active_channel_idx)?.group.clone()?;
+                rows.iter().position(|row| *row == SidebarRow::Group(group.clone()))
+            })
+            .unwrap_or(0)
+    }
+
+    /// Whether the history of a channel is shown from before the latest history of the server arrived, while logging in or after a reconnect
+    pub fn is_syncing(&self, channel_id: ChannelId) -> bool {
+        if channel_id == NOTES_CHANNEL_ID {
+            return false;
+        }
+        !self.startup.is_done()
+            || self
+                .syncing
+                .get(&channel_id)
+                .is_some_and(|requested| requested.is_none_or(|requested| requested.elapsed() < SYNC_TIMEOUT))
+    }
+
+    pub fn is_pinned(&self, channel_id: ChannelId, message_id: MessageId) -> bool {
+        self.preferences
+            .pinned_messages
+            .iter()
+            .any(|pin| (pin.channel_id, pin.message_id) == (channel_id, message_id))
+    }
+
+    /// Whether a message is shown as a stub, because its author is collapsed in the channel
+    pub fn is_collapsed(&self, channel_id: ChannelId, message: &ChatMessage) -> bool {
+        message.kind != ChatMessageKind::System && self.collapsed_users.contains(&(channel_id, message.author_id))
+    }
+
+    /// What would be lost by quitting now, one line per kind of operation
+    pub fn pending_operations(&self) -> Vec<String> {
+        let mut pending = vec![];
+        let sending = self
+            .chat_history
+            .values()
+            .flatten()
+            .filter(|message| message.status == ChatMessageStatus::Sending)
+            .count();
+        match sending {
+            0 => {}
+            1 => pending.push("1 message is still sending".to_owned()),
+            count => pending.push(format!("{count} messages are still sending")),
+        }
+        if let Some(upload) = &self.upload {
+            let percentage = (upload.progress.written * 100).checked_div(upload.progress.total).unwrap_or(0);
+            pending.push(format!(
+                "Uploading attachment {} of {}, {percentage}% done",
+                upload.media_ids.len() + 1,
+                upload.attachment_count()
+            ));
+        }
+        if self.profile_update.is_some() {
+            pending.push("Your profile changes are not confirmed yet".to_owned());
+        }
+        if self.password_change_pending {
+            pending.push("Your new password is not confirmed yet".to_owned());
+        }
+        match self.pending_downloads.len() {
+            0 => {}
+            1 => pending.push("1 download has not arrived yet".to_owned()),
+            count => pending.push(format!("{count} downloads have not arrived yet")),
+        }
+        pending
+    }
+
+    /// Newest message the server confirmed per channel, for resuming the session where it was left.
+    /// Channels without any are left out, the server sends their latest history instead
+    pub fn last_seen(&self) -> Vec<(ChannelId, MessageId)> {
+        self.channels
+            .iter()
+            .filter(|channel| !channel.is_local())
+            .filter_map(|channel| {
+                self.chat_history
+                    .get(&channel.id)?
+                    .iter()
+                    .filter(|message| message.status == ChatMessageStatus::Send)
+                    .map(|message| message.message_id)
+                    .max()
+                    .map(|message_id| (channel.id, message_id))
+            })
+            .collect()
+    }
+
+    /// Latest moment a user was seen online or sent a message that is in the cached history
+    pub fn last_active(&self, user_id: UserId) -> Option<DateTime<Utc>> {
+        let last_message = self
+            .chat_history
+            .values()
+            .flatten()
+            .filter(|message| message.kind == ChatMessageKind::User && message.author_id == user_id)
+            .map(|message| message.timestamp)
+            .max();
+        last_message.max(self.last_seen.get(&user_id).copied())
+    }
+
+    /// The entry of the current user in the users list, users are matched on name as the login does not tell the user id
+    pub fn own_user(&self) -> Option<&User> {
+        self.users.iter().find(|user| user.name == self.current_user.username)
+    }
+
+    /// Id of the current user as the server knows it, the id of the login is used until the users list has the current user
+    pub fn own_user_id(&self) -> UserId {
+        self.own_user().map_or(self.current_user.user_id, |user| user.id)
+    }
+
+    /// What plugins can read about the session
+    pub fn plugin_context(&self) -> PluginContext {
+        PluginContext {
+            server: self.server_address.to_string(),
+            username: self.current_user.username.clone(),
+            channel: self
+                .channels
+                .get(self.active_channel_idx)
+                .map(|channel| channel.name.clone())
+                .unwrap_or_default(),
+            channels: self.channels.iter().map(|channel| channel.name.clone()).collect(),
+            users: self.users.iter().map(|user| user.name.clone()).collect(),
+        }
+    }
+
+    pub fn mentions_current_user(&self, text: &str) -> bool {
+        is_highlight(text, &self.current_user.username, &[])
+    }
+
+    /// Messages after the last read message of every unread channel, and how many of them mention the current user
+    pub fn unread_summary(&mut self) -> UnreadSummary {
+        let own_user_id = self.own_user_id();
+        let mut summary = UnreadSummary::default();
+        for channel in self.channels.iter().filter(|channel| matches!(channel.status, ChannelStatus::Unread)) {
+            let Some(messages) = self.chat_history.get(&channel.id) else {
+                continue;
+            };
+            let key = UnreadKey {
+                last_read_id: channel.last_read_id,
+                history_length: messages.len(),
+                newest_message_id: messages.last().map(|message| message.message_id),
+                own_user_id,
+            };
+            let counts = match self.unread_counts.get(&channel.id) {
+                Some((counted, counts)) if *counted == key => *counts,
+                _ => {
+                    let counts = self.count_unread(messages, channel.last_read_id, own_user_id);
+                    self.unread_counts.insert(channel.id, (key, counts));
+                    counts
+                }
+            };
+            summary.unread += counts.unread;
+            summary.mentions += counts.mentions;
+        }
+        summary
+    }
+
+    fn count_unread(&self, messages: &[ChatMessage], last_read_id: MessageId, own_user_id: UserId) -> UnreadSummary {
+        let unread_messages = messages
+            .iter()
+            .filter(|message| message.message_id > last_read_id && message.kind == ChatMessageKind::User && message.author_id != own_user_id);
+        let mut counts = UnreadSummary::default();
+        for message in unread_messages {
+            counts.unread += 1;
+            if self.mentions_current_user(&message.message) {
+                counts.mentions += 1;
+            }
+        }
+        counts
+    }
+
+    /// Message under the cursor while selecting messages in the chat history
+    pub fn selected_message(&self) -> Option<&ChatMessage> {
+        let channel = self.channels.get(self.active_channel_idx)?;
+        self.chat_history
+            .get(&channel.id)?
+            .get(self.chat_scroll_offset + channel.selection_offset)
+    }
+
+    /// Whether the selected message is highlighted, besides selecting it is marked while replying to or editing it
+    pub fn shows_selection(&self) -> bool {
+        self.focus == ChatFocus::ChatHistorySelection || self.reply_target().is_some() || self.active_edit().is_some()
+    }
+
+    /// Runs a change to the history of the active channel without moving the view or the selection.
+    /// Both offsets count messages, so they are anchored to the messages they point at and recomputed afterwards.
+    /// The line offset within the newest message in view is kept along with it.
+    fn keep_view<T>(&mut self, change: impl FnOnce(&mut Self) -> T) -> T {
+        let message_id_at = |chat_state: &Self, idx: usize| {
+            let channel = chat_state.channels.get(chat_state.active_channel_idx)?;
+            Some(chat_state.chat_history.get(&channel.id)?.get(idx)?.message_id)
+        };
+        let message_count = self.active_history().map_or(0, Vec::len);
+        // The view follows new messages when it is at the bottom
+        let newest_in_view = match self.chat_scroll_offset {
+            0 => None,
+            offset => message_count.checked_sub(offset + 1).and_then(|idx| message_id_at(self, idx)),
+        };
+        let newest_message = self
+            .active_history()
+            .and_then(|messages| messages.last())
+            .map(|message| message.message_id);
+        let line_offset = self.chat_line_offset;
+        let selected = self
+            .selected_message()
+            .filter(|_| self.shows_selection())
+            .map(|message| message.message_id);
+
+        let result = change(self);
+
+        let Some(channel) = self.channels.get_mut(self.active_channel_idx) else {
+            return result;
+        };
+        let Some(messages) = self.chat_history.get(&channel.id) else {
+            return result;
+        };
+        let position = |message_id: Option<MessageId>| messages.iter().position(|message| Some(message.message_id) == message_id);
+        if let Some(idx) = position(newest_in_view) {
+            self.chat_scroll_offset = messages.len() - idx - 1;
+        }
+        // The line offset is within the anchored message, it is dropped when the view follows new messages or the message is gone
+        let anchored = match newest_in_view {
+            Some(_) => position(newest_in_view).is_some(),
+            None => messages.last().map(|message| message.message_id) == newest_message,
+        };
+        self.chat_line_offset = if anchored { line_offset } else { 0 };
+        if let Some(idx) = position(selected) {
+            self.chat_scroll_offset = self.chat_scroll_offset.min(idx);
+            channel.selection_offset = idx - self.chat_scroll_offset;
+        }
+        result
+    }
+
+    /// Oldest unread message of the first channel with unread messages that comes after the active channel
+    fn first_unread(&self) -> Option<(usize, MessageId)> {
+        let channel_count = self.channels.len();
+        (1..=channel_count)
+            .map(|step| (self.active_channel_idx + step) % channel_count)
+            .filter(|&idx| matches!(self.channels[idx].status, ChannelStatus::Unread))
+            .find_map(|idx| {
+                let channel = &self.channels[idx];
+                let message = self
+                    .chat_history
+                    .get(&channel.id)?
+                    .iter()
+                    .find(|message| message.kind == ChatMessageKind::User && message.message_id > channel.last_read_id)?;
+                Some((idx, message.message_id))
+            })
+    }
+
+    fn active_history(&self) -> Option<&Vec<ChatMessage>> {
+        let channel = self.channels.get(self.active_channel_idx)?;
+        self.chat_history.get(&channel.id)
+    }
+
+    /// Id of the newest message in a channel that was confirmed by the server
+    fn latest_message_id(&self, channel_id: ChannelId) -> Option<MessageId> {
+        self.chat_history
+            .get(&channel_id)?
+            .iter()
+            .filter(|message| message.kind == ChatMessageKind::User && message.status == ChatMessageStatus::Send)
+            .map(|message| message.message_id)
+            .max()
+    }
+
+    /// Messages a user sent per day over the last `days` days of cached history, oldest day first
+    pub fn user_activity(&self, user_id: UserId, days: usize) -> Vec<usize> {
+        let today = Local::now().date_naive();
+        let mut activity = vec![0; days];
+        for message in self.chat_history.values().flatten() {
+            if message.kind != ChatMessageKind::User || message.author_id != user_id {
+                continue;
+            }
+            let age = (today - message.timestamp.with_timezone(&Local).date_naive()).num_days();
+            if let Ok(age) = usize::try_from(age)
+                && age < days
+            {
+                activity[days - 1 - age] += 1;
+            }
+        }
+        activity
+    }
+
+    /// Channels in which both the current user and `user_id` sent messages, as far as the cached history goes
+    pub fn channels_in_common(&self, user_id: UserId) -> Vec<&str> {
+        let has_posted = |channel_id, author_id| {
+            self.chat_history.get(&channel_id).is_some_and(|messages| {
+                messages
+                    .iter()
+                    .any(|message| message.kind == ChatMessageKind::User && message.author_id == author_id)
+            })
+        };
+        let own_user_id = self.own_user_id();
+        self.channels
+            .iter()
+            .filter(|channel| has_posted(channel.id, user_id) && has_posted(channel.id, own_user_id))
+            .map(|channel| channel.name.as_str())
+            .collect()
+    }
+
+    fn save_preferences(&self) -> Result<()> {
+        self.preferences.save(&self.server_address.to_string())
+    }
+
+    fn move_sidebar_selection(&mut self, up: bool) {
+        let rows = self.sidebar_rows();
+        if rows.is_empty() {
+            return;
+        }
+        let position = self.sidebar_position(&rows);
+        let next = if up {
+            (position + rows.len() - 1) % rows.len()
+        } else {
+            (position + 1) % rows.len()
+        };
+
+        match &rows[next] {
+            SidebarRow::Group(group) => self.selected_channel_group = Some(group.clone()),
+            SidebarRow::Channel(idx) => self.select_channel(*idx),
+        }
+    }
+
+    fn select_channel(&mut self, idx: usize) {
+        if let Some(channel) = self.channels.get(self.active_channel_idx)
+            && !channel.is_local()
+        {
+            self.last_server_channel = Some(channel.id);
+        }
+        self.selected_channel_group = None;
+        self.active_channel_idx = idx;
+        self.chat_line_offset = 0;
+        if let Some(channel) = self.channels.get_mut(idx) {
+            if let ChannelStatus::Unread = channel.status {
+                channel.status = ChannelStatus::Read;
+            }
+            self.preferences.active_channel = Some(channel.name.clone());
+        }
+    }
+
+    /// Shows the channels and history of an earlier session, the server history is merged in once it arrives
+    pub fn restore_history_cache(&mut self, history_cache: HistoryCache) {
+        for cached_channel in history_cache.channels {
+            if self.channels.iter().any(|channel| channel.id == cached_channel.id) {
+                continue;
+            }
+            if self.preferences.active_channel.as_ref() == Some(&cached_channel.name) {
+                self.active_channel_idx = self.channels.len();
+            }
+            self.chat_inputs.insert(cached_channel.id, String::new());
+            self.chat_history.insert(cached_channel.id, cached_channel.chat_messages());
+            let mut display_channel = cached_channel.display_channel();
+            if self.preferences.muted_channels.contains(&display_channel.id) {
+                display_channel.status = ChannelStatus::Muted;
+            }
+            self.channels.push(display_channel);
+        }
+    }
+
+    /// Adds an entry to the log of sent messages when it is enabled.
+    /// A failed write is shown, as the log can not be relied on from then on
+    pub fn audit(&mut self, entry: AuditEntry) {
+        if self.audit_log
+            && let Err(e) = entry.append()
+        {
+            warn!("Failed to write the log of sent messages: {e}");
+            self.toasts
+                .push_back(Toast::new(format!("Failed to write the log of sent messages: {e}")));
+        }
+    }
+
+    /// Marks messages the server did not confirm yet as failed, for when the connection they were sent over is gone
+    fn fail_unconfirmed_messages(&mut self) {
+        self.chat_history.values_mut().for_each(|messages| {
+            messages.iter_mut().for_each(|msg| {
+                if msg.status == ChatMessageStatus::Sending {
+                    msg.status = ChatMessageStatus::FailedToSend;
+                }
+            });
+        });
+        let server = self.server_address.to_string();
+        for local_id in std::mem::take(&mut self.waiting_message_acks_id) {
+            self.audit(AuditEntry::failed(&server, local_id));
+        }
+    }
+
+    /// Adds the notes to self channel, with the notes that did not expire yet
+    pub fn restore_notes(&mut self, keep_days: u32) {
+        let notes = Notes::load(keep_days).chat_messages(&self.current_user.username, self.current_user.user_id);
+        self.chat_history.insert(NOTES_CHANNEL_ID, notes);
+        self.chat_inputs.entry(NOTES_CHANNEL_ID).or_default();
+        self.channels.push(DisplayChannel::notes());
+    }
+
+    /// Drafts and the view of the previous session, the view is applied as soon as its channel and messages are loaded
+    pub fn restore_checkpoint(&mut self, checkpoint: Checkpoint) {
+        for draft in &checkpoint.drafts {
+            self.chat_inputs.insert(draft.channel_id, draft.text.clone());
+        }
+        self.saved_checkpoint = checkpoint.clone();
+        self.pending_view = checkpoint.view;
+        self.apply_pending_view();
+    }
+
+    fn apply_pending_view(&mut self) {
+        let Some(view) = self.pending_view.clone() else {
+            return;
+        };
+        let Some(channel_idx) = self.channels.iter().position(|channel| channel.id == view.channel_id) else {
+            return;
+        };
+        if channel_idx != self.active_channel_idx {
+            self.select_channel(channel_idx);
+        }
+        let Some(messages) = self.chat_history.get(&view.channel_id) else {
+            return;
+        };
+        let position = |message_id: MessageId| messages.iter().position(|message| message.message_id == message_id);
+        let newest_in_view = view.newest_in_view.map(position);
+        let replying_to = view.replying_to.map(|message_id| position(message_id).map(|idx| messages[idx].clone()));
+
+        if let Some(Some(idx)) = newest_in_view {
+            self.chat_scroll_offset = messages.len() - idx - 1;
+            self.chat_line_offset = 0;
+        }
+        if let Some(Some(message)) = replying_to.clone() {
+            let message_id = message.message_id;
+            self.reply_targets.insert(view.channel_id, message);
+            self.select_message(channel_idx, message_id);
+        }
+        // History that arrives after the startup is not waited for, the view would jump away from where the user went since
+        let resolved = !matches!(newest_in_view, Some(None)) && !matches!(replying_to, Some(None));
+        if resolved || self.startup.is_done() {
+            self.pending_view = None;
+        }
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        let mut drafts: Vec<Draft> = self
+            .chat_inputs
+            .iter()
+            .filter(|(_, text)| !text.is_empty())
+            .map(|(&channel_id, text)| Draft {
+                channel_id,
+                text: text.clone(),
+            })
+            .collect();
+        drafts.sort_by_key(|draft| draft.channel_id);
+
+        // A view that is still waiting for its history is kept as it was
+        let view = self.pending_view.clone().or_else(|| {
+            let channel = self.channels.get(self.active_channel_idx)?;
+            let messages = self.chat_history.get(&channel.id);
+            let newest_in_view = match self.chat_scroll_offset {
+                0 => None,
+                offset => messages.and_then(|messages| Some(messages.get(messages.len().checked_sub(offset + 1)?)?.message_id)),
+            };
+            Some(ViewCheckpoint {
+                channel_id: channel.id,
+                newest_in_view,
+                replying_to: self.reply_targets.get(&channel.id).map(|message| message.message_id),
+            })
+        });
+        Checkpoint { drafts, view }
+    }
+
+    /// Writes the checkpoint when it changed since it was last written
+    pub fn save_checkpoint(&mut self) {
+        let checkpoint = self.checkpoint();
+        if checkpoint != self.saved_checkpoint {
+            if let Err(e) = checkpoint.save(&self.server_address.to_string()) {
+                warn!("Failed to save the checkpoint: {e}");
+            }
+            self.saved_checkpoint = checkpoint;
+        }
+        self.checkpoint_saved = Instant::now();
+    }
+
+    fn history_cache(&self) -> HistoryCache {
+        let server_key = self.server_address.to_string();
+        HistoryCache::from_channels(
+            &self.channels,
+            |channel_id| self.chat_history.get(&channel_id),
+            |channel| RetentionRule::for_channel(&self.retention, &server_key, &channel.name),
+        )
+    }
+
+    /// Writes the cache before the session ends
+    pub fn save_history_cache(&mut self) {
+        if let Err(e) = self.history_cache().save(&self.server_address.to_string()) {
+            warn!("Failed to save the history cache: {e}");
+        }
+        self.history_cache_dirty = false;
+        self.history_cache_saved = Instant::now();
+    }
+
+    /// Writes the cache while the session goes on, serializing a large history would hold up the UI
+    pub fn save_history_cache_in_background(&mut self) {
+        let history_cache = self.history_cache();
+        let server_key = self.server_address.to_string();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = history_cache.save(&server_key) {
+                warn!("Failed to save the history cache: {e}");
+            }
+        });
+        self.history_cache_dirty = false;
+        self.history_cache_saved = Instant::now();
+    }
+
+    /// Opens a channel with a message selected, the chat history scrolls to keep the selection in view
+    fn select_message(&mut self, channel_idx: usize, message_id: MessageId) {
+        if channel_idx != self.active_channel_idx {
+            self.select_channel(channel_idx);
+        }
+        let Some(channel) = self.channels.get_mut(channel_idx) else {
+            return;
+        };
+        let Some(idx) = self
+            .chat_history
+            .get(&channel.id)
+            .and_then(|messages| messages.iter().position(|message| message.message_id == message_id))
+        else {
+            return;
+        };
+        self.chat_scroll_offset = 0;
+        self.chat_line_offset = 0;
+        channel.selection_offset = idx;
+        self.focus = ChatFocus::ChatHistorySelection;
+    }
 }
 
 pub async fn handle_chat_event(tui: &mut State, event: TuiEvent, client: &mut Client) -> Result<()> {
@@ -66,134 +1042,186 @@ pub async fn handle_chat_event(tui: &mut State, event: TuiEvent, client: &mut Cl
     use TuiEvent::*;
 
     match event {
-        Exit => {
+        Exit if !chat_state.pending_operations().is_empty() && !matches!(chat_state.popup, Some(Popup::QuitGuard { .. })) => {
+            chat_state.popup = Some(Popup::QuitGuard { waiting: false });
+        }
+        WaitThenQuit => {
+            if let Some(Popup::QuitGuard { waiting }) = &mut chat_state.popup {
+                *waiting = true;
+            }
+        }
+        Exit | ForceQuit => {
+            if let Err(e) = clear_status() {
+                warn!("Failed to remove the status file: {e}");
+            }
+            chat_state.save_history_cache();
+            chat_state.save_checkpoint();
             tui.global_state.should_quit = true;
             client.send_user_status(UserStatus::Offline).await?;
         }
         ToggleLogs => {
-            tui.global_state.show_logs = !tui.global_state.show_logs;
-            chat_state.focus = ChatFocus::ChatHistory;
+            let show = !tui.global_state.show_logs;
+            show_logs(chat_state, &mut tui.global_state, show)?;
+        }
+        ToggleLogRepeats => tui.global_state.expand_log_repeats = !tui.global_state.expand_log_repeats,
+        TogglePane(pane) => {
+            tui.global_state.hidden_panes.toggle(pane);
+            leave_hidden_pane(chat_state, &tui.global_state);
+        }
+        ToggleZen => {
+            tui.global_state.hidden_panes.zen = !tui.global_state.hidden_panes.zen;
+            leave_hidden_pane(chat_state, &tui.global_state);
+        }
+        ToggleRelativeTimestamps => tui.global_state.relative_timestamps = !tui.global_state.relative_timestamps,
+        DismissHint => {
+            if let Some(tutorial) = &mut tui.global_state.tutorial
+                && let Some(hint) = tutorial.hint(chat_state.focus)
+                && tutorial.dismiss(hint)
+            {
+                tui.global_state.tutorial = None;
+                chat_state
+                    .toasts
+                    .push_back(Toast::new("That was the last hint, have fun chatting!".to_owned()));
+            }
         }
         Log(entry) => tui.global_state.logs.push(entry),
         ChannelUp => {
-            if chat_state.active_channel_idx == 0 {
-                chat_state.active_channel_idx = chat_state.channels.len().saturating_sub(1);
-            } else {
-                chat_state.active_channel_idx -= 1;
-            }
+            let previous_channel = chat_state.preferences.active_channel.clone();
+            chat_state.move_sidebar_selection(true);
+            save_active_channel(chat_state, previous_channel);
+            mark_active_channel_read(chat_state, client).await?;
             if let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
                 && chat_state.is_typing
+                && !channel_id.is_local()
             {
                 client.send_typing(channel_id.id, false).await?;
             }
         }
         ChannelDown => {
-            chat_state.active_channel_idx = (chat_state.active_channel_idx + 1) % chat_state.channels.len();
+            let previous_channel = chat_state.preferences.active_channel.clone();
+            chat_state.move_sidebar_selection(false);
+            save_active_channel(chat_state, previous_channel);
+            mark_active_channel_read(chat_state, client).await?;
             if let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
                 && chat_state.is_typing
+                && !channel_id.is_local()
             {
                 client.send_typing(channel_id.id, false).await?;
             }
         }
-        ChatFocusChange(focus) => chat_state.focus = focus,
-        InputLeft => {
-            if let ChatFocus::ChatInput(i) = chat_state.focus
-                && i > 0
+        JumpToChannel(idx) => open_channel(chat_state, client, idx).await?,
+        CompleteChannelLink => {
+            if let ChatFocus::ChatInput(cursor) = chat_state.focus
+                && let Some(channel) = chat_state.channels.get(chat_state.active_channel_idx)
+                && let Some(input_line) = chat_state.chat_inputs.get(&channel.id)
+                && let Some((completed, cursor)) = complete_channel_link(input_line, cursor, &chat_state.channels)
             {
-                chat_state.focus = ChatFocus::ChatInput(i - 1)
+                chat_state.chat_inputs.insert(channel.id, completed);
+                chat_state.focus = ChatFocus::ChatInput(cursor);
             }
         }
-        InputRight => {
-            if let ChatFocus::ChatInput(i) = chat_state.focus
-                && let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
-                && let Some(input_line) = chat_state.chat_inputs.get(&channel_id.id)
-                && i < input_line.len()
-            {
-                chat_state.focus = ChatFocus::ChatInput(i + 1)
+        ToggleChannelGroup => {
+            let group = match &chat_state.selected_channel_group {
+                Some(group) => Some(group.clone()),
+                None => chat_state
+                    .channels
+                    .get(chat_state.active_channel_idx)
+                    .and_then(|channel| channel.group.clone()),
+            };
+            if let Some(group) = group {
+                chat_state.channel_groups.toggle_collapsed(&group);
+                chat_state.channel_groups.save(&chat_state.server_address.to_string())?;
             }
         }
-        InputLeftTab => {
-            if let ChatFocus::ChatInput(i) = chat_state.focus
-                && i > 0
-                && let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
-                && let Some(input_line) = chat_state.chat_inputs.get(&channel_id.id)
+        // Hidden panes can not be moved to
+        ChatFocusChange(focus) if tui.global_state.hidden_panes.hides_focus(focus) => {}
+        ChatFocusChange(focus) => {
+            // Start selecting at the newest message in view
+            if focus == ChatFocus::ChatHistorySelection
+                && chat_state.focus == ChatFocus::ChatHistory
+                && let Some(channel) = chat_state.channels.get_mut(chat_state.active_channel_idx)
             {
-                let idx = format!("{input_line} ")
-                    .char_indices()
-                    .take(i)
-                    .collect::<Vec<_>>()
-                    .into_iter()
-                    .rev()
-                    .skip_while(|(_, c)| *c != ' ')
-                    .map(|(idx, _)| idx)
-                    .next()
-                    .unwrap_or(0);
-
-                chat_state.focus = ChatFocus::ChatInput(idx)
+                let message_count = chat_state.chat_history.get(&channel.id).map_or(0, Vec::len);
+                let newest_in_view = message_count.saturating_sub(chat_state.chat_scroll_offset + 1);
+                channel.selection_offset = newest_in_view.saturating_sub(chat_state.chat_scroll_offset);
             }
+            chat_state.focus = focus;
         }
-        InputRightTab => {
-            if let ChatFocus::ChatInput(i) = chat_state.focus
-                && let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
-                && let Some(input_line) = chat_state.chat_inputs.get(&channel_id.id)
-                && i < input_line.len()
-            {
-                let idx = format!("{input_line} ")
-                    .char_indices()
-                    .skip(i + 1)
-                    .skip_while(|(_, c)| *c != ' ')
-                    // .skip_while(|(_, c)| *c == ' ')
-                    .map(|(idx, _)| idx)
-                    .next()
-                    .unwrap_or(chat_state.chat_inputs.len());
-                chat_state.focus = ChatFocus::ChatInput(idx)
-            }
-        }
-        InputDelete => {
-            if let ChatFocus::ChatInput(i) = chat_state.focus
-                && i > 0
-                && let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
-                && let Some(input_line) = chat_state.chat_inputs.get_mut(&channel_id.id)
+        InputEdit(edit) => {
+            if let ChatFocus::ChatInput(cursor) = chat_state.focus
+                && let Some(channel) = chat_state.channels.get(chat_state.active_channel_idx)
+                && let Some(input_line) = chat_state.chat_inputs.get_mut(&channel.id)
             {
-                input_line.remove(i - 1);
-                chat_state.focus = ChatFocus::ChatInput(i - 1)
+                chat_state.focus = ChatFocus::ChatInput(apply_edit(input_line, cursor, edit));
             }
         }
 
         MessageSend => {
-            if let Some(channel) = chat_state.channels.get(chat_state.active_channel_idx)
-                && let Some(input_line) = chat_state.chat_inputs.get_mut(&channel.id)
+            let command = chat_state
+                .channels
+                .get(chat_state.active_channel_idx)
+                .and_then(|channel| chat_state.chat_inputs.get(&channel.id))
+                .and_then(|input_line| Command::parse(input_line));
+            if let Some(command) = command {
+                match command {
+                    Ok(command) => {
+                        if let Some(channel) = chat_state.channels.get(chat_state.active_channel_idx) {
+                            chat_state.chat_inputs.insert(channel.id, String::new());
+                        }
+                        chat_state.focus = ChatFocus::ChatInput(0);
+                        if let Command::Workspace(name) = command {
+                            if let Some(state) = open_workspace(chat_state, &mut tui.global_state, &mut tui.state_map, client, &name).await? {
+                                tui.current_state = state;
+                            }
+                        } else {
+                            run_command(chat_state, &mut tui.global_state, client, command).await?;
+                        }
+                    }
+                    Err(e) => chat_state.toasts.push_back(Toast::new(e.to_string())),
+                }
+            } else if chat_state.active_edit().is_some() {
+                send_edit(chat_state, client, tui.global_state.config.notes_keep_days).await?;
+            } else if chat_state
+                .channels
+                .get(chat_state.active_channel_idx)
+                .is_some_and(DisplayChannel::is_local)
+            {
+                write_note(chat_state, tui.global_state.config.notes_keep_days);
+            } else if !chat_state.staged_attachments.is_empty() {
+                send_staged_attachments(chat_state, client)?;
+            } else if let Some(channel) = chat_state.channels.get(chat_state.active_channel_idx)
+                && let Some(input_line) = chat_state.chat_inputs.get(&channel.id)
                 && !input_line.trim().is_empty()
             // Don't send empty or whitespace-only messages
             {
-                let reply_id = if let Some(message) = &chat_state.replying_to {
-                    message.message_id
-                } else {
-                    0
-                };
-                let temp_message_id = chat_state.incrementing_ack_id;
-                let message = ChatMessage {
-                    message_id: temp_message_id,
-                    author_name: chat_state.current_user.username.to_owned(),
-                    author_id: chat_state.current_user.user_id,
+                let reply_id = chat_state.reply_targets.get(&channel.id).map_or(0, |message| message.message_id);
+                let message = PluginMessage {
+                    channel_id: channel.id,
+                    channel: channel.name.clone(),
+                    message_id: 0,
                     reply_id,
-                    timestamp: Utc::now(),
-                    message: input_line.clone(),
-                    status: ChatMessageStatus::Sending,
+                    author: chat_state.current_user.username.clone(),
+                    text: input_line.clone(),
+                    live: true,
                 };
-                chat_state.waiting_message_acks_id.push_back(temp_message_id);
-                chat_state.incrementing_ack_id += 1;
-
-                chat_state.chat_history.entry(channel.id).or_default().push(message);
-
-                client.send_chat_message(channel.id, reply_id, input_line.clone(), vec![]).await?; // TODO improve
-                chat_state.replying_to = None;
-                chat_state.focus = ChatFocus::ChatInput(0);
-                *input_line = "".to_owned();
+                let (text, actions) = tui.global_state.plugins.outgoing(&chat_state.plugin_context(), message);
+                match text {
+                    Some(text) => {
+                        let channel_id = channel.id;
+                        chat_state.chat_inputs.insert(channel_id, String::new());
+                        chat_state.reply_targets.remove(&channel_id);
+                        chat_state.focus = ChatFocus::ChatInput(0);
+                        send_message(chat_state, client, channel_id, reply_id, text).await?;
+                    }
+                    // The draft is kept, so nothing typed is lost
+                    None => chat_state.toasts.push_back(Toast::new("A plugin stopped the message".to_owned())),
+                }
+                run_plugin_actions(chat_state, client, actions).await?;
             }
         }
         MessageSendAck(message_id) => {
-            if let Some(temp_message_id) = chat_state.waiting_message_acks_id.pop_back() {
+            // The server acknowledges messages in the order they were sent
+            if let Some(temp_message_id) = chat_state.waiting_message_acks_id.pop_front() {
                 if let Some(message) = chat_state
                     .chat_history
                     .values_mut()
@@ -202,15 +1230,38 @@ pub async fn handle_chat_event(tui: &mut State, event: TuiEvent, client: &mut Cl
                 {
                     message.status = ChatMessageStatus::Send;
                     message.message_id = message_id;
+                    chat_state.history_cache_dirty = true;
+                    chat_state.audit(AuditEntry::confirmed(&chat_state.server_address.to_string(), temp_message_id, message_id));
                 } else {
-                    chat_state.waiting_message_acks_id.push_front(temp_message_id);
+                    error!("Message {temp_message_id} was acknowledged but is no longer in the history");
                 }
             } else {
                 // TODO more logic maybe
                 error!("No message is waiting for ack");
             }
         }
+        MessageSendFailed(reason) => {
+            let Some(temp_message_id) = chat_state.waiting_message_acks_id.pop_front() else {
+                error!("No message is waiting for ack");
+                return Ok(());
+            };
+            if let Some(message) = chat_state
+                .chat_history
+                .values_mut()
+                .flat_map(|messages| messages.iter_mut())
+                .find(|m| m.message_id == temp_message_id)
+            {
+                message.status = ChatMessageStatus::FailedToSend;
+            }
+            chat_state.audit(AuditEntry::failed(&chat_state.server_address.to_string(), temp_message_id));
+            let toast = match reason {
+                Some(reason) => format!("Failed to send the message: {reason}"),
+                None => "Failed to send the message".to_owned(),
+            };
+            chat_state.toasts.push_back(Toast::new(toast));
+        }
         ScrollDown => match chat_state.focus {
+            ChatFocus::ChatHistory if chat_state.chat_line_offset > 0 => chat_state.chat_line_offset -= 1,
             ChatFocus::ChatHistory => {
                 chat_state.chat_scroll_offset = chat_state.chat_scroll_offset.saturating_sub(1);
             }
@@ -234,13 +1285,23 @@ pub async fn handle_chat_event(tui: &mut State, event: TuiEvent, client: &mut Cl
             _ => {}
         },
         ScrollUp => match chat_state.focus {
+            // A message taller than the history is scrolled through line by line before moving on to the one above it
+            ChatFocus::ChatHistory
+                if chat_state.chat_line_offset + tui.global_state.render_stats.history_height
+                    < tui.global_state.render_stats.newest_in_view_lines =>
+            {
+                chat_state.chat_line_offset += 1;
+            }
             ChatFocus::ChatHistory => {
                 chat_state.chat_scroll_offset = chat_state.chat_scroll_offset.saturating_add(1);
+                chat_state.chat_line_offset = 0;
+                load_older_history(chat_state, client, tui.global_state.config.history_page_size).await?;
             }
             ChatFocus::ChatHistorySelection => {
                 if let Some(channel) = chat_state.channels.get_mut(chat_state.active_channel_idx) {
                     channel.selection_offset = channel.selection_offset.saturating_sub(1);
                 }
+                load_older_history(chat_state, client, tui.global_state.config.history_page_size).await?;
             }
             ChatFocus::Logs => {
                 tui.global_state.log_scroll_offset = tui.global_state.log_scroll_offset.saturating_add(1);
@@ -248,15 +1309,54 @@ pub async fn handle_chat_event(tui: &mut State, event: TuiEvent, client: &mut Cl
             ChatFocus::Users(i) if i > 0 => chat_state.focus = ChatFocus::Users(i - 1),
             _ => {}
         },
+        ScrollToTop => {
+            chat_state.vim_g_pressed = None;
+            match chat_state.focus {
+                ChatFocus::ChatHistory => {
+                    chat_state.chat_scroll_offset = chat_state.active_history().map_or(0, |messages| messages.len().saturating_sub(1));
+                    chat_state.chat_line_offset = 0;
+                    load_older_history(chat_state, client, tui.global_state.config.history_page_size).await?;
+                }
+                ChatFocus::ChatHistorySelection => {
+                    if let Some(channel) = chat_state.channels.get_mut(chat_state.active_channel_idx) {
+                        chat_state.chat_scroll_offset = 0;
+                        chat_state.chat_line_offset = 0;
+                        channel.selection_offset = 0;
+                    }
+                    load_older_history(chat_state, client, tui.global_state.config.history_page_size).await?;
+                }
+                ChatFocus::Logs => tui.global_state.log_scroll_offset = tui.global_state.logs.len(),
+                ChatFocus::Users(_) => chat_state.focus = ChatFocus::Users(0),
+                ChatFocus::Channels | ChatFocus::ChatInput(_) => {}
+            }
+        }
+        ScrollToBottom => match chat_state.focus {
+            ChatFocus::ChatHistory => {
+                chat_state.chat_scroll_offset = 0;
+                chat_state.chat_line_offset = 0;
+            }
+            ChatFocus::ChatHistorySelection => {
+                if let Some(channel) = chat_state.channels.get_mut(chat_state.active_channel_idx) {
+                    let message_count = chat_state.chat_history.get(&channel.id).map_or(0, Vec::len);
+                    chat_state.chat_scroll_offset = 0;
+                    chat_state.chat_line_offset = 0;
+                    channel.selection_offset = message_count.saturating_sub(1);
+                }
+            }
+            ChatFocus::Logs => tui.global_state.log_scroll_offset = 0,
+            // Same bound as scrolling down
+            ChatFocus::Users(_) => chat_state.focus = ChatFocus::Users(chat_state.users.len().saturating_sub(2)),
+            ChatFocus::Channels | ChatFocus::ChatInput(_) => {}
+        },
+        VimPrefix => chat_state.vim_g_pressed = Some(Instant::now()),
         InputChar(chr) => {
             if let ChatFocus::ChatInput(i) = chat_state.focus
                 && let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
                 && let Some(input_line) = chat_state.chat_inputs.get_mut(&channel_id.id)
             {
-                input_line.insert(i, chr);
-                chat_state.focus = ChatFocus::ChatInput(i + 1);
+                chat_state.focus = ChatFocus::ChatInput(insert_char(input_line, i, chr));
                 chat_state.time_since_last_typing = Instant::now();
-                if !chat_state.is_typing {
+                if !chat_state.is_typing && !channel_id.is_local() && !tui.global_state.config.private_typing.contains(&channel_id.name) {
                     chat_state.is_typing = true;
                     client.send_typing(channel_id.id, true).await?;
                 }
@@ -264,55 +1364,106 @@ pub async fn handle_chat_event(tui: &mut State, event: TuiEvent, client: &mut Cl
         }
 
         ChannelIDs(channel_ids) => {
-            if !channel_ids.is_empty() {
-                debug!("received channel ids {channel_ids:?}");
-                client.request_channels(channel_ids).await?
+            debug!("received channel ids {channel_ids:?}");
+            if !chat_state.startup.is_done() {
+                chat_state.startup.channel_ids_received(channel_ids, client).await?;
+            } else {
+                let new_channel_ids: Vec<ChannelId> = channel_ids
+                    .into_iter()
+                    .filter(|channel_id| !chat_state.channels.iter().any(|channel| channel.id == *channel_id))
+                    .collect();
+                if !new_channel_ids.is_empty() {
+                    client.request_channels(new_channel_ids).await?;
+                }
             }
         }
         HealthCheckRecv => {
+            // The server expects an answer to every health check, only the status refresh can wait
             client.send_healthcheck().await?;
-            client.request_user_statuses().await?; // TODO think about where this should go
+            if chat_state.terminal_focused || chat_state.user_statuses_requested.elapsed() > UNFOCUSED_STATUS_REFRESH {
+                chat_state.user_statuses_requested = Instant::now();
+                client.request_user_statuses().await?; // TODO think about where this should go
+            }
         }
 
         Channels(channels) => {
             for channel in channels {
-                // I want to add the channel first and only then request
-                // if I requested first to make the borrow checker happy it could fail and end up in a broken state
-                // history would be incoming for a channel which is not added
                 let channel_id = channel.channel_id;
-                chat_state.chat_inputs.insert(channel_id, "".to_owned());
-                chat_state.channels.push(channel.into());
-                client.request_history_by_timestamp(channel_id, Utc::now(), 50).await?;
+                if chat_state.channels.iter().any(|channel| channel.id == channel_id) {
+                    continue;
+                }
+                let mut display_channel: DisplayChannel = channel.into();
+                if let Some(group) = chat_state.channel_groups.group_of(&display_channel.name) {
+                    display_channel.group = Some(group);
+                }
+                if chat_state.preferences.muted_channels.contains(&channel_id) {
+                    display_channel.status = ChannelStatus::Muted;
+                }
+                if let Some(user_id) = chat_state.pending_direct_channels.remove(&channel_id) {
+                    display_channel.make_direct(user_id);
+                }
+                // Channels restored from the checkpoint may already have a draft
+                chat_state.chat_inputs.entry(channel_id).or_default();
+                // Reopen the channel that was open during the previous session
+                if !chat_state.startup.is_done() && chat_state.preferences.active_channel.as_ref() == Some(&display_channel.name) {
+                    chat_state.active_channel_idx = chat_state.channels.len();
+                }
+                chat_state.channels.push(display_channel);
+                // Channels showing up after startup did not get their history requested by the startup sequence
+                if chat_state.startup.is_done() {
+                    client
+                        .request_history_by_timestamp(channel_id, Utc::now(), tui.global_state.config.initial_history)
+                        .await?;
+                }
             }
+            chat_state.startup.channels_received(client).await?;
+            apply_workspace_pins(chat_state, &mut tui.global_state);
+            open_pending_direct_message(chat_state, client).await?;
         }
-        UserStatusesUpdate(status_updates) => {
-            // TODO what happens if a new user comes online? We dont get their name
-            debug!("received statuses{status_updates:?}");
-
-            let mut users_not_found = vec![];
-            'outer: for status_update in status_updates {
-                for user in &mut chat_state.users {
-                    if user.id == status_update.0 {
-                        user.status = status_update.1.clone();
-                        continue 'outer;
+        DirectChannels(direct_channels) => {
+            let mut unknown_channels = vec![];
+            for (channel_id, user_id) in direct_channels {
+                match chat_state.channels.iter_mut().find(|channel| channel.id == channel_id) {
+                    Some(channel) => channel.make_direct(user_id),
+                    None => {
+                        chat_state.pending_direct_channels.insert(channel_id, user_id);
+                        unknown_channels.push(channel_id);
                     }
                 }
-                // User not found in current users
-                users_not_found.push(status_update.0);
             }
-            if !users_not_found.is_empty() {
-                debug!("New users added, requesting names of users ids {users_not_found:?}");
-                client.request_users(users_not_found).await?;
+            if !unknown_channels.is_empty() {
+                client.request_channels(unknown_channels).await?;
             }
+            open_pending_direct_message(chat_state, client).await?;
         }
-        UserStatusUpdate(user_id, status) => {
-            info!("{:?}", chat_state.users);
-            if let Some(user) = chat_state.users.iter_mut().find(|user| user.id == user_id) {
-                user.status = status;
-            } else {
-                error!("Could not find user with id {user_id} to update their status");
+        DirectChannelFailed(message) => {
+            chat_state.opening_direct_message = None;
+            chat_state
+                .toasts
+                .push_back(Toast::new(format!("Failed to open a direct channel: {message}")));
+        }
+        OpenDirectMessage => {
+            if let ChatFocus::Users(idx) = chat_state.focus
+                && let Some(user_id) = chat_state.sorted_users().get(idx).map(|user| user.id)
+            {
+                chat_state.opening_direct_message = Some(user_id);
+                if !open_pending_direct_message(chat_state, client).await? {
+                    client.open_direct_channel(user_id).await?;
+                }
+            }
+        }
+        UserStatusesUpdate(status_updates) => {
+            debug!("received {} statuses", status_updates.len());
+            chat_state.pending_statuses.extend(status_updates);
+            // The startup sequence waits for these statuses, so they are applied right away
+            if chat_state.startup.step == StartupStep::UserStatuses {
+                let unknown_users = apply_status_updates(chat_state);
+                chat_state.startup.statuses_received(unknown_users, client).await?;
             }
         }
+        UserStatusUpdate(user_id, status) => {
+            chat_state.pending_statuses.insert(user_id, status);
+        }
         Users(users) => {
             let mut new_users: Vec<User> = users
                 .iter()
@@ -320,6 +1471,8 @@ pub async fn handle_chat_event(tui: &mut State, event: TuiEvent, client: &mut Cl
                     id: user.user_id,
                     name: user.username.clone(),
                     status: user.status.clone(),
+                    bio: user.bio.clone(),
+                    pfp_id: user.pfp_id,
                 })
                 .collect();
 
@@ -329,78 +1482,406 @@ pub async fn handle_chat_event(tui: &mut State, event: TuiEvent, client: &mut Cl
             for user in &mut chat_state.users {
                 if let Some(new_user) = new_users_map.remove(&user.id) {
                     user.status = new_user.status;
+                    user.bio = new_user.bio;
+                    user.pfp_id = new_user.pfp_id;
                 }
             }
             chat_state.users.extend(new_users_map.into_values());
+            for user in &users {
+                chat_state.requested_users.remove(&user.user_id);
+            }
+            chat_state.users_changed = true;
+            // Profile pictures may have changed
+            chat_state.media_fetcher.invalidate();
+            chat_state.startup.users_received(client).await?;
         }
         HistoryUpdate(messages) => {
-            for message in messages {
-                let author_name = chat_state
-                    .users
-                    .iter()
-                    .find(|user| user.id == message.user_id)
-                    .map(|user| user.name.clone())
-                    .unwrap_or_else(|| "Unknown".to_string());
-
-                let timestamp = DateTime::from_timestamp(message.sent_timestamp as i64, 0).ok_or_else(|| anyhow!("Invalid timestamp"))?;
-
-                let display_message = ChatMessage {
-                    message_id: message.message_id,
-                    reply_id: message.reply_id,
-                    author_name,
-                    author_id: message.user_id,
-                    timestamp,
-                    message: message.message_text,
-                    status: ChatMessageStatus::Send,
-                };
-
-                let channel_id = message.channel_id;
-                // TODO figure out what to do when we get message from channels we dont know the name off
-                let display_messages = chat_state.chat_history.entry(channel_id).or_default();
-
-                if !display_messages.iter().any(|m| m.message_id == display_message.message_id) {
-                    display_messages.push(display_message);
+            let channel_ids: Vec<ChannelId> = messages.iter().map(|message| message.channel_id).collect();
+            chat_state.history_pagination.history_received(&messages);
+            for channel_id in &channel_ids {
+                chat_state.syncing.remove(channel_id);
+            }
+            let messages = filter_incoming(chat_state, &tui.global_state.plugins, client, messages, false).await?;
+            chat_state.keep_view(|chat_state| insert_history(chat_state, messages))?;
+            if let Some(Popup::Search(search)) = &mut chat_state.popup
+                && search.backfill.is_some()
+            {
+                search.update(&chat_state.channels, &chat_state.chat_history);
+                if !search.results.is_empty() {
+                    search.backfill = None;
                 }
             }
+            let was_done = chat_state.startup.is_done();
+            chat_state.startup.history_received(&channel_ids);
+            chat_state.apply_pending_view();
+            follow_message_jump(chat_state, client, tui.global_state.config.history_page_size).await?;
+            if !was_done && chat_state.startup.is_done() {
+                finish_startup(chat_state, &mut tui.global_state, client).await?;
+            }
         }
-        Logout => {
-            if let Some(login_state) = tui.state_map.get(&Screen::Login).cloned() {
-                if let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
-                    && chat_state.is_typing
+        StartupStepTimeout => {
+            let was_done = chat_state.startup.is_done();
+            chat_state.startup.retry(client).await?;
+            if !was_done && chat_state.startup.is_done() {
+                finish_startup(chat_state, &mut tui.global_state, client).await?;
+            }
+        }
+        HistoryNotification(messages) => {
+            for message in &messages {
+                sample_clock_skew(chat_state, message.sent_timestamp);
+            }
+            let messages = filter_incoming(chat_state, &tui.global_state.plugins, client, messages, true).await?;
+            if tui.global_state.config.desktop_notifications {
+                notify_messages(chat_state, &messages);
+            }
+            alert_messages(chat_state, &messages, tui.global_state.config.bell);
+            run_message_hooks(chat_state, &tui.global_state.config, &messages);
+            let active_channel_id = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id);
+            for message in &messages {
+                if Some(message.channel_id) != active_channel_id
+                    && let Some(channel) = chat_state.channels.iter_mut().find(|channel| channel.id == message.channel_id)
+                    && let ChannelStatus::Read = channel.status
                 {
-                    client.send_typing(channel_id.id, false).await?;
+                    channel.status = ChannelStatus::Unread;
                 }
-                chat_state.chat_history.values_mut().for_each(|messages| {
-                    messages.iter_mut().for_each(|msg| {
-                        if msg.status == ChatMessageStatus::Sending {
-                            msg.status = ChatMessageStatus::FailedToSend;
-                        }
-                    });
-                });
-                chat_state.waiting_message_acks_id.clear();
-
-                client.disconnect()?;
-                let user = &chat_state.current_user;
-                tui.state_map.insert(
-                    Screen::Chat(
-                        user.username.trim().to_string(),
-                        user.password.trim().to_string(),
-                        chat_state.server_address.clone(),
-                    ),
-                    AppState::Chat(chat_state.clone()),
-                );
+            }
+            chat_state.keep_view(|chat_state| insert_history(chat_state, messages))?;
+            mark_active_channel_read(chat_state, client).await?;
+        }
+        ChannelEvent(event) => {
+            sample_clock_skew(chat_state, event.timestamp);
+            insert_channel_event(chat_state, event)?;
+        }
+        ReadStates(read_states) => {
+            for (channel_id, message_id) in read_states {
+                let latest_message_id = chat_state.latest_message_id(channel_id);
+                if let Some(channel) = chat_state.channels.iter_mut().find(|channel| channel.id == channel_id) {
+                    channel.last_read_id = channel.last_read_id.max(message_id);
+                    if !matches!(channel.status, ChannelStatus::Muted) {
+                        channel.status = match latest_message_id {
+                            Some(latest_message_id) if latest_message_id > channel.last_read_id => ChannelStatus::Unread,
+                            _ => ChannelStatus::Read,
+                        };
+                    }
+                }
+            }
+            mark_active_channel_read(chat_state, client).await?;
+        }
+        Logout => {
+            if let Err(e) = clear_status() {
+                warn!("Failed to remove the status file: {e}");
+            }
+            chat_state.written_status = None;
+            chat_state.save_history_cache();
+            chat_state.save_checkpoint();
+            if let Some(login_state) = tui.state_map.get(&Screen::Login).cloned() {
+                park_session(chat_state, &mut tui.state_map, client).await?;
                 tui.current_state = login_state;
                 info!("Logging out");
             } else {
                 tui.global_state.should_quit = true;
             }
         }
+        // The session is not parked, the server does not want it to continue
+        ServerGoodbye(reason, message) => {
+            warn!("The server closed the connection: {reason:?} {message}");
+            let mut payload = HookPayload::new(HookEvent::Disconnect, chat_state.server_address.to_string());
+            payload.reason = Some(if message.is_empty() {
+                format!("{reason:?}")
+            } else {
+                format!("{reason:?}: {message}")
+            });
+            run_hooks(&tui.global_state.config.hooks, payload);
+            if let Err(e) = clear_status() {
+                warn!("Failed to remove the status file: {e}");
+            }
+            chat_state.written_status = None;
+            chat_state.save_history_cache();
+            chat_state.save_checkpoint();
+            client.disconnect()?;
+            let user = &chat_state.current_user;
+            tui.state_map.remove(&Screen::Chat(
+                user.username.trim().to_string(),
+                Secret::new(user.password.expose().trim().to_owned()),
+                chat_state.server_address.clone(),
+            ));
+            match tui.state_map.get(&Screen::Login).cloned() {
+                Some(AppState::Login(mut login_state)) => {
+                    login_state.goodbye = Some(Box::new((reason, message)));
+                    tui.current_state = AppState::Login(login_state);
+                }
+                _ => tui.global_state.should_quit = true,
+            }
+        }
+        OpenAccountSwitcher => {
+            let sessions = tui.state_map.keys().filter_map(|screen| match screen {
+                Screen::Chat(username, password, server_address) => Some(SavedAccount {
+                    username: username.clone(),
+                    password: Some(password.clone()),
+                    address: server_address.to_string(),
+                    enable_tls: Some(server_address.connection_type == ConnectionType::TLS),
+                }),
+                Screen::Login => None,
+            });
+            let current = SavedAccount {
+                username: chat_state.current_user.username.clone(),
+                password: Some(chat_state.current_user.password.clone()),
+                address: chat_state.server_address.to_string(),
+                enable_tls: Some(chat_state.server_address.connection_type == ConnectionType::TLS),
+            };
+            let switcher = AccountSwitcher::new(&tui.global_state.config.accounts, sessions, current);
+            chat_state.popup = Some(Popup::Accounts(switcher));
+        }
+        AccountSwitcherUp => {
+            if let Some(Popup::Accounts(switcher)) = &mut chat_state.popup {
+                switcher.select_previous();
+            }
+        }
+        AccountSwitcherDown => {
+            if let Some(Popup::Accounts(switcher)) = &mut chat_state.popup {
+                switcher.select_next();
+            }
+        }
+        SwitchAccount => {
+            let Some(Popup::Accounts(switcher)) = chat_state.popup.take() else {
+                return Ok(());
+            };
+            let Some(account) = switcher.selected_account() else {
+                return Ok(());
+            };
+            if switcher.current == Some(switcher.selected) {
+                return Ok(());
+            }
+            if let Some(login_state) = switch_account(chat_state, &mut tui.state_map, tui.global_state.config.enable_tls, client, account).await? {
+                tui.current_state = login_state;
+            }
+        }
 
+        // A new profile picture is uploaded on its own, the profile changes are sent once the server has it
+        MessageMediaAck(media_id) if chat_state.profile_update.as_ref().is_some_and(|update| update.picture_upload.is_some()) => {
+            if let Some(update) = &mut chat_state.profile_update {
+                update.picture_upload = None;
+                update.fields.push(UserConfigField::ProfilePicture(media_id));
+                client.set_user_config(update.fields.clone()).await?;
+            }
+        }
         MessageMediaAck(media_id) => {
-            todo!()
+            if let Some(mut upload) = chat_state.upload.take() {
+                if let Some(attachment) = chat_state
+                    .chat_history
+                    .get_mut(&upload.channel_id)
+                    .and_then(|messages| messages.iter_mut().find(|message| message.message_id == upload.message_id))
+                    .and_then(|message| message.attachments.get_mut(upload.media_ids.len()))
+                {
+                    attachment.media_id = media_id;
+                    chat_state.media_fetcher.invalidate();
+                }
+                upload.media_ids.push(media_id);
+
+                if let Some(next) = upload.remaining.pop_front() {
+                    debug!("Media {media_id} uploaded, uploading {}", next.filename);
+                    upload.progress = client.start_media_upload(next.filename, next.media_type, next.data)?;
+                    chat_state.upload = Some(upload);
+                } else {
+                    debug!("Media {media_id} uploaded, sending message {}", upload.message_id);
+                    chat_state.waiting_message_acks_id.push_back(upload.message_id);
+                    let channel_name = chat_state
+                        .channels
+                        .iter()
+                        .find(|channel| channel.id == upload.channel_id)
+                        .map_or_else(String::new, |channel| channel.name.clone());
+                    let audit_entry = AuditEntry::sent(
+                        &chat_state.server_address.to_string(),
+                        upload.message_id,
+                        upload.channel_id,
+                        &channel_name,
+                        upload.reply_id,
+                        &upload.caption,
+                        &upload.media_ids,
+                    );
+                    client
+                        .send_chat_message(upload.channel_id, upload.reply_id, upload.caption, upload.media_ids)
+                        .await?;
+                    chat_state.audit(audit_entry);
+                }
+            } else {
+                debug!("Ignoring ack of media {media_id}, its upload was cancelled");
+            }
+        }
+        PasteImage if refuse_media(chat_state) => {}
+        PasteImage => {
+            // Encoding a screenshot as PNG takes long enough to be noticed in the UI
+            let event_send = client.event_sender();
+            tokio::task::spawn_blocking(move || {
+                let image = paste_image().map_err(|e| format!("{e:#}"));
+                let _ = event_send.blocking_send(TuiEvent::ClipboardRead(image));
+            });
+        }
+        ClipboardRead(image) => match image {
+            Ok(Some(image)) => chat_state.popup = Some(Popup::PasteImage(image)),
+            Ok(None) => chat_state.toasts.push_back(Toast::new("The clipboard holds no image".to_owned())),
+            Err(e) => {
+                warn!("Failed to read the clipboard: {e}");
+                chat_state.toasts.push_back(Toast::new(format!("Failed to read the clipboard: {e}")));
+            }
+        },
+        AttachClipboardImage => {
+            if let Some(Popup::PasteImage(image)) = chat_state.popup.take() {
+                stage_attachment(chat_state, image.attachment, tui.global_state.config.max_upload_size);
+            }
+        }
+        CompressAttachment => {
+            if let Some(Popup::CompressAttachment(attachment)) = chat_state.popup.take() {
+                let upload_limit = tui.global_state.config.max_upload_size.min(MAX_MEDIA_SIZE);
+                chat_state.toasts.push_back(Toast::new(format!("Compressing {}", attachment.filename)));
+                // Decoding and encoding a large image takes seconds, the UI keeps running meanwhile
+                let event_send = client.event_sender();
+                tokio::task::spawn_blocking(move || {
+                    let result = compress_image(&attachment.data, upload_limit).map_err(|e| format!("{e:#}"));
+                    let _ = event_send.blocking_send(TuiEvent::AttachmentCompressed(attachment.filename, attachment.data.len(), result));
+                });
+            }
+        }
+        AttachmentCompressed(original_filename, original_size, result) => match result {
+            Ok(data) => {
+                let filename = Path::new(&original_filename).with_extension("png").to_string_lossy().into_owned();
+                info!(
+                    "Compressed {original_filename} from {} to {}",
+                    format_size(original_size),
+                    format_size(data.len())
+                );
+                let compressed = PendingAttachment {
+                    filename,
+                    data,
+                    media_type: Some(MediaType::Image),
+                };
+                stage_attachment(chat_state, compressed, tui.global_state.config.max_upload_size);
+            }
+            Err(e) => {
+                warn!("Failed to compress {original_filename}: {e}");
+                chat_state.toasts.push_back(Toast::new(format!("Failed to compress {original_filename}")));
+            }
+        },
+        ToggleVoiceRecording if chat_state.voice_note.is_none() && refuse_media(chat_state) => {}
+        ToggleVoiceRecording => match chat_state.voice_note.take() {
+            None => match VoiceRecorder::start() {
+                Ok(recorder) => chat_state.voice_note = Some(VoiceNote::Recording(recorder)),
+                Err(e) => {
+                    warn!("Unable to record a voice note: {e}");
+                    chat_state.toasts.push_back(Toast::new(e.to_string()));
+                }
+            },
+            Some(VoiceNote::Recording(recorder)) => match recorder.finish(VOICE_NOTE_WAVEFORM_WIDTH) {
+                Ok(voice_note) => chat_state.voice_note = Some(VoiceNote::Preview(voice_note)),
+                Err(e) => {
+                    warn!("Unable to record a voice note: {e}");
+                    chat_state.toasts.push_back(Toast::new(e.to_string()));
+                }
+            },
+            preview => chat_state.voice_note = preview,
+        },
+        AttachVoiceNote => {
+            if let Some(VoiceNote::Preview(voice_note)) = chat_state.voice_note.take() {
+                let attachment = PendingAttachment {
+                    filename: format!("voice-note-{}.wav", Local::now().format("%Y%m%d-%H%M%S")),
+                    data: voice_note.wav,
+                    // The protocol only takes MP3 as audio, WAV is sent as a raw file like any other audio format
+                    media_type: Some(MediaType::Raw),
+                };
+                stage_attachment(chat_state, attachment, tui.global_state.config.max_upload_size);
+            }
+        }
+        UnstageAttachment => {
+            if let Some(attachment) = chat_state.staged_attachments.pop() {
+                debug!("Removed attachment {}", attachment.filename);
+            }
+        }
+        DiscardVoiceNote => {
+            if let Some(VoiceNote::Recording(recorder)) = chat_state.voice_note.take() {
+                recorder.cancel();
+            }
         }
-        Media(media_message) => {
-            todo!()
+        CancelUpload => {
+            if let Some(upload) = chat_state.upload.take() {
+                client.cancel_upload().await?;
+                chat_state.server_connection_status = client.connection_status.clone();
+                if let Some(message) = chat_state
+                    .chat_history
+                    .get_mut(&upload.channel_id)
+                    .and_then(|messages| messages.iter_mut().find(|message| message.message_id == upload.message_id))
+                {
+                    message.status = ChatMessageStatus::FailedToSend;
+                }
+                info!("Cancelled upload");
+            }
+        }
+        Media(media) => {
+            let Some(media_id) = chat_state.media_fetcher.complete() else {
+                warn!("Received media {} that was not requested", media.filename);
+                return Ok(());
+            };
+            if !media.is_intact() {
+                warn!("Checksum of media {media_id} does not match");
+                if !chat_state.media_fetcher.retry(media_id) {
+                    chat_state.pending_downloads.remove(&media_id);
+                    error!("Media {media_id} is still corrupted after retrying, not caching it");
+                    chat_state.toasts.push_back(Toast::new(format!("Failed to download {}", media.filename)));
+                }
+                return Ok(());
+            }
+            chat_state.media_fetcher.succeed(media_id);
+
+            for attachment in chat_state
+                .chat_history
+                .values_mut()
+                .flatten()
+                .flat_map(|message| message.attachments.iter_mut())
+                .filter(|attachment| attachment.media_id == media_id)
+            {
+                attachment.filename = Some(media.filename.clone());
+                attachment.media_type = Some(media.media_type.clone());
+            }
+            if media.media_type == MediaType::Image || is_compressible_image(&media.media_data) {
+                // Decoding and scaling a large image would hold up the UI
+                let (data, protocol) = (media.media_data.clone(), tui.global_state.graphics.protocol);
+                let event_send = client.event_sender();
+                tokio::task::spawn_blocking(move || {
+                    let preview = ImagePreview::new(media_id, &data, protocol).map_err(|e| e.to_string());
+                    let _ = event_send.blocking_send(TuiEvent::PreviewReady(media_id, preview));
+                });
+            }
+            if chat_state.users.iter().any(|user| user.pfp_id == media_id) {
+                let (data, protocol) = (media.media_data.clone(), tui.global_state.graphics.protocol);
+                let event_send = client.event_sender();
+                tokio::task::spawn_blocking(move || {
+                    let avatar = Avatar::new(media_id, &data, protocol).map_err(|e| e.to_string());
+                    let _ = event_send.blocking_send(TuiEvent::AvatarReady(media_id, avatar));
+                });
+            }
+            if chat_state.pending_downloads.remove(&media_id) {
+                save_download(chat_state, &tui.global_state.config.download_dir, media.clone());
+            }
+            chat_state.media_cache.insert(media_id, media);
+        }
+        PreviewReady(media_id, preview) => match preview {
+            Ok(preview) => {
+                chat_state.previews.insert(media_id, preview);
+            }
+            Err(e) => debug!("Unable to preview media {media_id}: {e}"),
+        },
+        AvatarReady(pfp_id, avatar) => match avatar {
+            Ok(avatar) => {
+                chat_state.avatars.insert(pfp_id, avatar);
+            }
+            Err(e) => debug!("Unable to show profile picture {pfp_id}: {e}"),
+        },
+        MediaFailed => {
+            if let Some(media_id) = chat_state.media_fetcher.complete() {
+                chat_state.media_fetcher.fail(media_id);
+                if chat_state.pending_downloads.remove(&media_id) {
+                    chat_state.toasts.push_back(Toast::new(format!("Failed to download media #{media_id}")));
+                }
+            }
         }
 
         Typing(channel_id, user_id, is_typing) => {
@@ -417,7 +1898,9 @@ pub async fn handle_chat_event(tui: &mut State, event: TuiEvent, client: &mut Cl
         }
         TypingExpired => {
             chat_state.is_typing = false;
-            if let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx) {
+            if let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
+                && !channel_id.is_local()
+            {
                 client.send_typing(channel_id.id, false).await?;
             }
         }
@@ -427,57 +1910,2042 @@ pub async fn handle_chat_event(tui: &mut State, event: TuiEvent, client: &mut Cl
         }
         Reconnect => {
             info!("Attempting to reconnect to {:?}", chat_state.server_address);
-            client
-                .reconnect(
-                    &chat_state.server_address,
-                    chat_state.current_user.username.clone(),
-                    chat_state.current_user.password.clone(),
-                )
-                .await?;
+            // A session that did not finish starting up has nothing to resume from
+            let resume = tui.global_state.config.reconnect == ReconnectMode::Resume && chat_state.capabilities.resume && chat_state.startup.is_done();
+            let reauthentication = match chat_state.session_token.clone() {
+                Some(session_token) if resume => Reauthentication::Resume(ResumePacket {
+                    session_token,
+                    last_seen: chat_state.last_seen(),
+                }),
+                Some(session_token) if chat_state.capabilities.token_login => Reauthentication::Token(session_token),
+                _ => Reauthentication::Password(chat_state.current_user.username.clone(), chat_state.current_user.password.clone()),
+            };
+            chat_state.token_login = matches!(reauthentication, Reauthentication::Token(_));
+            client.reconnect(&chat_state.server_address, reauthentication).await?;
 
             chat_state.server_connection_status = client.connection_status.clone(); // Somewhat ugly, but its works without requiring a large refactor            
         }
-        Disconnected => {
-            if chat_state.server_connection_status != ServerConnectionStatus::Reconnecting {
-                chat_state.chat_history.values_mut().for_each(|messages| {
-                    messages.iter_mut().for_each(|msg| {
-                        if msg.status == ChatMessageStatus::Sending {
-                            msg.status = ChatMessageStatus::FailedToSend;
-                        }
-                    });
-                });
-                chat_state.waiting_message_acks_id.clear();
-
-                client.disconnect()?;
-                chat_state.server_connection_status = ServerConnectionStatus::Reconnecting; // TODO figure out when to actually go in a Disconnected state
+        // Logged in again after a reconnect, or for the first time when the chat was opened from the cache while offline
+        LoginSuccess(_) => {
+            chat_state.token_login = false;
+            chat_state.login_verified = true;
+            chat_state.server_connection_status = client.connection_status.clone();
+            client.send_user_status(chat_state.current_user.status.clone()).await?;
+            // The server may have been upgraded while disconnected
+            client.request_capabilities().await?;
+            // Names asked for over the previous connection will not arrive anymore
+            chat_state.requested_users.clear();
+            if chat_state.startup.is_done() {
+                // Messages sent while disconnected are merged into the history
+                for channel in chat_state.channels.iter().filter(|channel| !channel.is_local()) {
+                    client
+                        .request_history_by_timestamp(channel.id, Utc::now(), tui.global_state.config.initial_history)
+                        .await?;
+                    chat_state.syncing.insert(channel.id, Some(Instant::now()));
+                }
+                client.request_read_states().await?;
+                client.request_direct_channels().await?;
+                // A parked session that is switched back to by a workspace already has its channels
+                apply_workspace_pins(chat_state, &mut tui.global_state);
+            } else {
+                chat_state.startup.request_current_step(client).await?;
+            }
+        }
+        SessionToken(token) => {
+            chat_state.session_token = Some(token);
+        }
+        // The server sends the messages after the last seen ones by itself, only what is not part of the history is asked for again
+        SessionResumed => {
+            chat_state.server_connection_status = client.connection_status.clone();
+            client.send_user_status(chat_state.current_user.status.clone()).await?;
+            chat_state.requested_users.clear();
+            for channel in chat_state.channels.iter().filter(|channel| !channel.is_local()) {
+                chat_state.syncing.insert(channel.id, Some(Instant::now()));
+            }
+            client.request_read_states().await?;
+            client.request_user_statuses().await?;
+        }
+        // The session expired or the server restarted, falls back to logging in, which is answered like any other reconnect.
+        // Servers that keep their tokens over a restart still accept the token for that
+        ResumeRejected(reason) => {
+            warn!(
+                "Could not resume the session, logging in again: {}",
+                reason.as_deref().unwrap_or("no reason given")
+            );
+            match chat_state.session_token.clone().filter(|_| chat_state.capabilities.token_login) {
+                Some(session_token) => {
+                    chat_state.token_login = true;
+                    client.login_with_token(session_token).await?;
+                }
+                None => {
+                    chat_state.session_token = None;
+                    client
+                        .login(chat_state.current_user.username.clone(), chat_state.current_user.password.clone())
+                        .await?;
+                }
+            }
+        }
+        // The token expired or was revoked, the password is the last resort
+        LoginFail(message) if chat_state.token_login => {
+            warn!("Could not log in with the session token, sending the password: {message}");
+            chat_state.token_login = false;
+            chat_state.session_token = None;
+            client
+                .login(chat_state.current_user.username.clone(), chat_state.current_user.password.clone())
+                .await?;
+        }
+        // The password changed elsewhere, or the chat was opened from the cache with a password the server does not accept
+        LoginFail(message) => {
+            warn!("The server refused the login: {message}");
+            match end_session(chat_state, &mut tui.state_map, client, InputStatus::from_login_failure(&message)).await? {
+                Some(login_state) => tui.current_state = login_state,
+                None => tui.global_state.should_quit = true,
+            }
+        }
+        // Reconnecting logs in again, which only gets through a second factor with a code from a remembered secret
+        LoginCodeRequired(message) => {
+            let secret = totp_secret(&chat_state.server_address.to_string(), &chat_state.current_user.username)
+                .filter(|_| message.is_none() && !tui.global_state.config.safe_mode);
+            match secret.map(|secret| totp_code(&secret)) {
+                Some(Ok(code)) => client.send_login_code(code).await?,
+                Some(Err(e)) => warn!("Failed to generate a login code: {e}"),
+                None => {
+                    warn!("The server wants a login code to reconnect");
+                    chat_state
+                        .toasts
+                        .push_back(Toast::new("Log out and in again to enter a login code".to_owned()));
+                }
+            }
+        }
+        Disconnected if chat_state.server_connection_status != ServerConnectionStatus::Reconnecting => {
+            let mut payload = HookPayload::new(HookEvent::Disconnect, chat_state.server_address.to_string());
+            payload.reason = Some("Connection lost".to_owned());
+            run_hooks(&tui.global_state.config.hooks, payload);
+            chat_state.fail_unconfirmed_messages();
+            chat_state.upload = None;
+            chat_state.profile_update = None;
+            chat_state.password_change_pending = false;
+            chat_state.media_fetcher.reset();
+            chat_state.save_history_cache();
+            for channel in chat_state.channels.iter().filter(|channel| !channel.is_local()) {
+                chat_state.syncing.insert(channel.id, None);
             }
+
+            client.disconnect()?;
+            chat_state.server_connection_status = ServerConnectionStatus::Reconnecting; // TODO figure out when to actually go in a Disconnected state
         }
         FocusGained => {
             chat_state.time_since_last_focused = None;
-            chat_state.current_user.status = UserStatus::Online;
-            client.send_user_status(UserStatus::Online).await?;
+            chat_state.terminal_focused = true;
+            chat_state.unseen_messages = 0;
+            // Statuses may have gone stale while they were refreshed less often
+            if chat_state.user_statuses_requested.elapsed() > Duration::from_secs(5)
+                && chat_state.startup.is_done()
+                && client.connection_status == ServerConnectionStatus::Connected
+            {
+                chat_state.user_statuses_requested = Instant::now();
+                client.request_user_statuses().await?;
+            }
+            if !chat_state.manual_status {
+                chat_state.current_user.status = UserStatus::Online;
+                client.send_user_status(UserStatus::Online).await?;
+            }
         }
         FocusLost => {
             chat_state.time_since_last_focused = Some(Instant::now());
+            chat_state.terminal_focused = false;
         }
+        IdleUser if chat_state.manual_status => {}
         IdleUser => {
             chat_state.current_user.status = UserStatus::Idle;
             client.send_user_status(UserStatus::Idle).await?;
         }
+        CycleStatus => {
+            let status = match chat_state.current_user.status {
+                UserStatus::Online => UserStatus::Idle,
+                UserStatus::Idle => UserStatus::DoNotDisturb,
+                UserStatus::DoNotDisturb => UserStatus::Offline,
+                UserStatus::Offline => UserStatus::Online,
+            };
+            set_status(chat_state, client, status).await?;
+        }
         Reply => {
-            if let Some(channel) = chat_state.channels.get(chat_state.active_channel_idx)
-                && let Some(chatlog) = chat_state.chat_history.get(&channel.id)
-                && let Some(message) = chatlog.get(chat_state.chat_scroll_offset + channel.selection_offset)
+            let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id) else {
+                return Ok(());
+            };
+            // Replying ends an edit, the draft it set aside is what the reply goes with
+            chat_state.end_edit(channel_id);
+            match chat_state.selected_message() {
+                Some(message) if message.kind == ChatMessageKind::User && chat_state.reply_targets.get(&channel_id) != Some(message) => {
+                    let message = message.clone();
+                    chat_state.reply_targets.insert(channel_id, message);
+                }
+                _ => {
+                    chat_state.reply_targets.remove(&channel_id);
+                }
+            }
+        }
+        EditMessage => {
+            let Some(message) = chat_state.selected_message() else {
+                return Ok(());
+            };
+            let channel_id = chat_state.channels[chat_state.active_channel_idx].id;
+            if chat_state.active_edit().is_some_and(|edit| edit.message.message_id == message.message_id) {
+                chat_state.end_edit(channel_id);
+                let cursor = chat_state.chat_inputs.get(&channel_id).map_or(0, String::len);
+                chat_state.focus = ChatFocus::ChatInput(cursor);
+            } else if !chat_state.capabilities.editing && !chat_state.channels[chat_state.active_channel_idx].is_local() {
+                chat_state
+                    .toasts
+                    .push_back(Toast::new("This server does not support editing messages".to_owned()));
+            } else if message.kind != ChatMessageKind::User
+                // Notes are all written by the current user
+                || (message.author_id != chat_state.own_user_id() && !chat_state.channels[chat_state.active_channel_idx].is_local())
+                || message.status != ChatMessageStatus::Send
             {
-                chat_state.replying_to = match &chat_state.replying_to {
-                    Some(replying_to) if message == replying_to => None,
-                    _ => Some(message.clone()),
-                };
+                chat_state
+                    .toasts
+                    .push_back(Toast::new("Only your own sent messages can be edited".to_owned()));
             } else {
-                chat_state.replying_to = None;
+                let message = message.clone();
+                chat_state.focus = ChatFocus::ChatInput(message.message.len());
+                chat_state.start_edit(channel_id, message);
+            }
+        }
+        Capabilities(capabilities) => chat_state.capabilities = capabilities,
+        ServerInfo(server_version, min_client_version) if is_older_version(CLIENT_VERSION, &min_client_version) => {
+            warn!("Server {server_version} supports clients from {min_client_version}, this is {CLIENT_VERSION}");
+            chat_state.version_warning = Some(format!(
+                "Update recommended: the server supports {min_client_version} and newer, this is {CLIENT_VERSION}"
+            ));
+        }
+        MessageEdited(channel_id, message_id, text) => {
+            chat_state.pending_edits.remove(&message_id);
+            if let Some(message) = chat_state
+                .chat_history
+                .get_mut(&channel_id)
+                .and_then(|messages| messages.iter_mut().find(|message| message.message_id == message_id))
+            {
+                message.message = text.clone();
+                message.edited = true;
+                chat_state.history_cache_dirty = true;
+                chat_state.unread_counts.remove(&channel_id);
+            }
+            if let Some(pin) = chat_state
+                .preferences
+                .pinned_messages
+                .iter_mut()
+                .find(|pin| (pin.channel_id, pin.message_id) == (channel_id, message_id))
+            {
+                pin.text = text;
+                chat_state.save_preferences()?;
+            }
+        }
+        MessageEditFailed(message_id) => {
+            if let Some(original) = chat_state.pending_edits.remove(&message_id)
+                && let Some(message) = chat_state
+                    .chat_history
+                    .values_mut()
+                    .flatten()
+                    .find(|message| message.message_id == message_id)
+            {
+                message.message = original.message;
+                message.edited = original.edited;
+            }
+            chat_state.toasts.push_back(Toast::new("Failed to edit message".to_owned()));
+        }
+        DownloadMedia => {
+            let media_ids: Vec<MediaId> = match chat_state.selected_message() {
+                Some(message) => message
+                    .attachments
+                    .iter()
+                    .map(|attachment| attachment.media_id)
+                    .filter(|media_id| *media_id != 0)
+                    .collect(),
+                None => vec![],
+            };
+            if media_ids.is_empty() {
+                chat_state.toasts.push_back(Toast::new("No media to download".to_owned()));
+            }
+            for media_id in media_ids {
+                match chat_state.media_cache.get(media_id).cloned() {
+                    Some(media) => save_download(chat_state, &tui.global_state.config.download_dir, media),
+                    None => {
+                        debug!("Downloading media {media_id} once it is fetched");
+                        chat_state.media_fetcher.forget_failure(media_id);
+                        chat_state.pending_downloads.insert(media_id);
+                    }
+                }
+            }
+        }
+        TranslateMessage => {
+            let Some(config) = tui.global_state.config.translation.clone() else {
+                chat_state.toasts.push_back(Toast::new("No translation command configured".to_owned()));
+                return Ok(());
             };
+            let Some(message) = chat_state.selected_message().filter(|message| message.kind == ChatMessageKind::User) else {
+                return Ok(());
+            };
+            let (message_id, text) = (message.message_id, message.message.clone());
+            // Translating a message again hides its translation
+            if chat_state.translations.remove(&message_id).is_some() {
+                return Ok(());
+            }
+            let channel_name = chat_state
+                .channels
+                .get(chat_state.active_channel_idx)
+                .map(|channel| channel.name.clone())
+                .unwrap_or_default();
+            let language = config.language_for(&channel_name).to_owned();
+            chat_state.translations.insert(message_id, Translation::Pending);
+
+            let event_send = client.event_sender();
+            tokio::spawn(async move {
+                let translation = translate(&config, &language, &text).await.map_err(|e| format!("{e:#}"));
+                let _ = event_send.send(TuiEvent::Translated(message_id, translation)).await;
+            });
+        }
+        Translated(message_id, translation) => match translation {
+            Ok(translation) => {
+                // The translation was hidden again while it was running
+                if let Some(entry) = chat_state.translations.get_mut(&message_id) {
+                    *entry = Translation::Done(translation);
+                }
+            }
+            Err(e) => {
+                warn!("Failed to translate message {message_id}: {e}");
+                chat_state.translations.remove(&message_id);
+                chat_state.toasts.push_back(Toast::new(format!("Failed to translate: {e}")));
+            }
+        },
+        ViewUsers => {
+            if let ChatFocus::Users(idx) = chat_state.focus
+                && let Some(user) = chat_state.sorted_users().get(idx)
+            {
+                chat_state.popup = Some(Popup::Profile(user.id));
+            }
+        }
+        ClosePopup => chat_state.popup = None,
+        Lock => lock(chat_state),
+        LockInputChar(chr) => {
+            if let Some(lock_screen) = &mut chat_state.lock {
+                lock_screen.input.push(chr);
+            }
+        }
+        LockInputDelete => {
+            if let Some(lock_screen) = &mut chat_state.lock {
+                lock_screen.input.pop();
+            }
+        }
+        Unlock => {
+            let secret = tui
+                .global_state
+                .config
+                .lock_pin
+                .as_ref()
+                .unwrap_or(&chat_state.current_user.password)
+                .expose();
+            if let Some(lock_screen) = &mut chat_state.lock {
+                if lock_screen.try_unlock(secret) {
+                    info!("Unlocked the chat");
+                    chat_state.lock = None;
+                } else {
+                    warn!("Failed attempt to unlock the chat ({})", lock_screen.failed_attempts);
+                }
+            }
+        }
+        OpenFilePicker if refuse_media(chat_state) => {}
+        OpenFilePicker => chat_state.popup = Some(Popup::AttachFile(FilePicker::new())),
+        OpenSearch => chat_state.popup = Some(Popup::Search(Search::default())),
+        OpenDigest => open_digest(chat_state, &tui.global_state.config.highlight_words),
+        ChangelogUp => {
+            if let Some(Popup::Changelog(changelog)) = &mut chat_state.popup {
+                changelog.scroll_up();
+            }
+        }
+        ChangelogDown => {
+            if let Some(Popup::Changelog(changelog)) = &mut chat_state.popup {
+                changelog.scroll_down();
+            }
+        }
+        ExpandMessage => {
+            if let Some(message) = chat_state.selected_message() {
+                chat_state.popup = Some(Popup::ExpandedMessage(ExpandedMessage::new(message, &tui.global_state.config.timestamps)));
+            }
+        }
+        ExpandedMessageUp => {
+            if let Some(Popup::ExpandedMessage(expanded)) = &mut chat_state.popup {
+                expanded.scroll_up();
+            }
+        }
+        ExpandedMessageDown => {
+            if let Some(Popup::ExpandedMessage(expanded)) = &mut chat_state.popup {
+                expanded.scroll_down();
+            }
+        }
+        DigestUp => {
+            if let Some(Popup::Digest(digest)) = &mut chat_state.popup {
+                digest.select_previous();
+            }
+        }
+        DigestDown => {
+            if let Some(Popup::Digest(digest)) = &mut chat_state.popup {
+                digest.select_next();
+            }
+        }
+        SearchInputChar(chr) => {
+            if let Some(Popup::Search(search)) = &mut chat_state.popup {
+                search.query.push(chr);
+                search.backfill = None;
+                search.backfill_outcome = None;
+                search.update(&chat_state.channels, &chat_state.chat_history);
+            }
+        }
+        SearchInputDelete => {
+            if let Some(Popup::Search(search)) = &mut chat_state.popup {
+                search.query.pop();
+                search.backfill = None;
+                search.backfill_outcome = None;
+                search.update(&chat_state.channels, &chat_state.chat_history);
+            }
+        }
+        SearchUp => {
+            if let Some(Popup::Search(search)) = &mut chat_state.popup {
+                search.select_previous();
+            }
+        }
+        SearchDown => {
+            if let Some(Popup::Search(search)) = &mut chat_state.popup {
+                search.select_next();
+            }
+        }
+        StartSearchBackfill => {
+            if let Some(Popup::Search(search)) = &mut chat_state.popup {
+                search.start_backfill();
+            }
+        }
+        CancelSearchBackfill => {
+            if let Some(Popup::Search(search)) = &mut chat_state.popup
+                && let Some(backfill) = &search.backfill
+            {
+                let outcome = format!("Cancelled after {} older pages", backfill.pages);
+                search.stop_backfill(outcome);
+            }
+        }
+        JumpToMessage(channel_idx, message_id) => {
+            jump_to_message(chat_state, client, channel_idx, message_id, tui.global_state.config.history_page_size).await?;
+        }
+        JumpToReplied => {
+            let page_size = tui.global_state.config.history_page_size;
+            let Some(message) = chat_state.selected_message().filter(|message| message.reply_id != 0) else {
+                return Ok(());
+            };
+            let (channel_idx, message_id) = (chat_state.active_channel_idx, message.reply_id);
+            jump_to_older_message(chat_state, client, channel_idx, message_id, page_size).await?;
+        }
+        JumpToPinned => {
+            let Some(Popup::Pins(pins)) = &chat_state.popup else {
+                return Ok(());
+            };
+            let Some(message_id) = pins.selected_pin().map(|pin| pin.message_id) else {
+                return Ok(());
+            };
+            let channel_idx = chat_state.active_channel_idx;
+            jump_to_older_message(chat_state, client, channel_idx, message_id, tui.global_state.config.history_page_size).await?;
+        }
+        TogglePinMessage => {
+            let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id) else {
+                return Ok(());
+            };
+            let Some(message) = chat_state
+                .selected_message()
+                .filter(|message| message.kind == ChatMessageKind::User && message.status == ChatMessageStatus::Send)
+            else {
+                return Ok(());
+            };
+            let pin = PinnedMessage::new(channel_id, message);
+            let pins = &mut chat_state.preferences.pinned_messages;
+            let toast = if let Some(idx) = pins
+                .iter()
+                .position(|other| (other.channel_id, other.message_id) == (channel_id, pin.message_id))
+            {
+                pins.remove(idx);
+                "Unpinned the message"
+            } else {
+                pins.push(pin);
+                "Pinned the message, Alt + P lists the pins of this channel"
+            };
+            chat_state.toasts.push_back(Toast::new(toast.to_owned()));
+            chat_state.save_preferences()?;
+        }
+        OpenPins => open_pins(chat_state),
+        PinsUp => {
+            if let Some(Popup::Pins(pins)) = &mut chat_state.popup {
+                pins.select_previous();
+            }
+        }
+        PinsDown => {
+            if let Some(Popup::Pins(pins)) = &mut chat_state.popup {
+                pins.select_next();
+            }
+        }
+        UnpinSelected => {
+            if let Some(Popup::Pins(pins)) = &mut chat_state.popup
+                && let Some(pin) = pins.selected_pin().cloned()
+            {
+                chat_state
+                    .preferences
+                    .pinned_messages
+                    .retain(|other| (other.channel_id, other.message_id) != (pin.channel_id, pin.message_id));
+                pins.remove_selected();
+                chat_state.save_preferences()?;
+            }
+        }
+        JumpToUnread => match chat_state.first_unread() {
+            Some((channel_idx, message_id)) => {
+                jump_to_message(chat_state, client, channel_idx, message_id, tui.global_state.config.history_page_size).await?;
+            }
+            None => chat_state.toasts.push_back(Toast::new("No unread messages".to_owned())),
+        },
+        FilePickerChar(chr) => {
+            if let Some(Popup::AttachFile(picker)) = &mut chat_state.popup {
+                picker.push(chr);
+            }
+        }
+        FilePickerDelete => {
+            if let Some(Popup::AttachFile(picker)) = &mut chat_state.popup {
+                picker.pop();
+            }
+        }
+        FilePickerComplete => {
+            if let Some(Popup::AttachFile(picker)) = &mut chat_state.popup {
+                picker.complete();
+            }
+        }
+        AttachFile => {
+            if let Some(Popup::AttachFile(picker)) = &chat_state.popup {
+                match picker.read() {
+                    Ok(attachment) => {
+                        chat_state.popup = None;
+                        stage_attachment(chat_state, attachment, tui.global_state.config.max_upload_size);
+                    }
+                    Err(e) => {
+                        warn!("{e}");
+                        chat_state.toasts.push_back(Toast::new(e.to_string()));
+                    }
+                }
+            }
+        }
+        OpenProfileEditor => match chat_state.popup {
+            Some(Popup::Profile(user_id)) if chat_state.own_user().is_none_or(|user| user.id != user_id) => {}
+            _ => open_profile_editor(chat_state),
+        },
+        ProfileEditorChar(chr) => {
+            if let Some(Popup::EditProfile(editor)) = &mut chat_state.popup {
+                editor.push(chr);
+            }
+        }
+        ProfileEditorDelete => {
+            if let Some(Popup::EditProfile(editor)) = &mut chat_state.popup {
+                editor.pop();
+            }
+        }
+        ProfileEditorNextField => {
+            if let Some(Popup::EditProfile(editor)) = &mut chat_state.popup {
+                editor.next_field();
+            }
+        }
+        ProfileEditorPreviousField => {
+            if let Some(Popup::EditProfile(editor)) = &mut chat_state.popup {
+                editor.previous_field();
+            }
+        }
+        SaveProfile => {
+            if let Err(e) = save_profile(chat_state, client).await {
+                warn!("Failed to update the profile: {e:#}");
+                chat_state.toasts.push_back(Toast::new(e.to_string()));
+            }
+        }
+        PasswordEditorChar(chr) => {
+            if let Some(Popup::ChangePassword(editor)) = &mut chat_state.popup {
+                editor.push(chr);
+            }
+        }
+        PasswordEditorDelete => {
+            if let Some(Popup::ChangePassword(editor)) = &mut chat_state.popup {
+                editor.pop();
+            }
+        }
+        PasswordEditorNextField => {
+            if let Some(Popup::ChangePassword(editor)) = &mut chat_state.popup {
+                editor.next_field();
+            }
+        }
+        PasswordEditorPreviousField => {
+            if let Some(Popup::ChangePassword(editor)) = &mut chat_state.popup {
+                editor.previous_field();
+            }
+        }
+        SavePassword => {
+            if let Err(e) = save_password(chat_state, client).await {
+                warn!("Failed to change the password: {e:#}");
+                chat_state.toasts.push_back(Toast::new(e.to_string()));
+            }
+        }
+        // Sessions of the old password are not kept, the server may end them and a reconnect would send the old password
+        UserConfigAck(result) if chat_state.password_change_pending => {
+            chat_state.password_change_pending = false;
+            match result {
+                Ok(()) => {
+                    info!("Changed the password, logging in again");
+                    match end_session(chat_state, &mut tui.state_map, client, InputStatus::PasswordChanged).await? {
+                        Some(login_state) => tui.current_state = login_state,
+                        None => tui.global_state.should_quit = true,
+                    }
+                }
+                Err(message) => chat_state
+                    .toasts
+                    .push_back(Toast::new(format!("Failed to change your password: {message}"))),
+            }
+        }
+        UserConfigAck(result) => {
+            let Some(update) = chat_state.profile_update.take() else {
+                warn!("Received an unexpected profile update answer");
+                return Ok(());
+            };
+            match result {
+                Ok(()) => apply_profile_update(chat_state, update.fields),
+                Err(message) => chat_state
+                    .toasts
+                    .push_back(Toast::new(format!("Failed to update your profile: {message}"))),
+            }
+        }
+        // Collapses the user of the open profile, or the author of the selected message, in the open channel
+        ToggleCollapseUser => {
+            let user_id = match chat_state.popup {
+                Some(Popup::Profile(user_id)) => Some(user_id),
+                _ => chat_state
+                    .selected_message()
+                    .filter(|message| message.kind != ChatMessageKind::System)
+                    .map(|message| message.author_id),
+            };
+            if let Some(user_id) = user_id
+                && let Some(channel) = chat_state.channels.get(chat_state.active_channel_idx)
+            {
+                let key = (channel.id, user_id);
+                let name = chat_state
+                    .users
+                    .iter()
+                    .find(|user| user.id == user_id)
+                    .map_or_else(|| format!("user #{user_id}"), |user| user.name.clone());
+                let toast = if chat_state.collapsed_users.remove(&key) {
+                    format!("Showing the messages of {name} in {} again", channel.name)
+                } else {
+                    chat_state.collapsed_users.insert(key);
+                    format!("Folded the messages of {name} in {}", channel.name)
+                };
+                chat_state.toasts.push_back(Toast::new(toast));
+            }
+        }
+        ToggleWatchUser => {
+            if let Some(Popup::Profile(user_id)) = chat_state.popup {
+                let watched_users = &mut chat_state.preferences.watched_users;
+                if !watched_users.remove(&user_id) {
+                    watched_users.insert(user_id);
+                }
+                chat_state.save_preferences()?;
+            }
+        }
+        CycleUserSort => {
+            chat_state.preferences.user_sort = chat_state.preferences.user_sort.next();
+            chat_state.sort_users();
+            chat_state.save_preferences()?;
+        }
+        CycleChannelSort => {
+            chat_state.preferences.channel_sort = chat_state.preferences.channel_sort.next();
+            chat_state.save_preferences()?;
+        }
+        CopyNoteToChannel => copy_note_to_channel(chat_state, client).await?,
+        QuoteMessage => quote_message(chat_state),
+        TogglePinChannel => {
+            if chat_state.selected_channel_group.is_some() {
+                return Ok(());
+            }
+            let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id) else {
+                return Ok(());
+            };
+            let pinned_channels = &mut chat_state.preferences.pinned_channels;
+            if !pinned_channels.remove(&channel_id) {
+                pinned_channels.insert(channel_id);
+            }
+            chat_state.save_preferences()?;
+        }
+        ToggleMuteChannel => {
+            // A selected group has no single channel to mute
+            if chat_state.selected_channel_group.is_some() {
+                return Ok(());
+            }
+            let active_channel_idx = chat_state.active_channel_idx;
+            let Some(channel_id) = chat_state.channels.get(active_channel_idx).map(|channel| channel.id) else {
+                return Ok(());
+            };
+            let latest_message_id = chat_state.latest_message_id(channel_id);
+            let muted = !chat_state.preferences.muted_channels.remove(&channel_id);
+            if muted {
+                chat_state.preferences.muted_channels.insert(channel_id);
+            }
+            if let Some(channel) = chat_state.channels.get_mut(active_channel_idx) {
+                channel.status = if muted {
+                    ChannelStatus::Muted
+                } else if latest_message_id.is_some_and(|message_id| message_id > channel.last_read_id) {
+                    ChannelStatus::Unread
+                } else {
+                    ChannelStatus::Read
+                };
+                let action = if muted { "Muted" } else { "Unmuted" };
+                chat_state.toasts.push_back(Toast::new(format!("{action} #{}", channel.name)));
+            }
+            chat_state.save_preferences()?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replaces the text of the message that is being edited with the input, shown right away and reverted if the server refuses it
+async fn send_edit(chat_state: &mut ChatState, client: &mut Client, notes_keep_days: u32) -> Result<()> {
+    let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id) else {
+        return Ok(());
+    };
+    let Some((editing, text)) = chat_state.end_edit(channel_id) else {
+        return Ok(());
+    };
+    let cursor = chat_state.chat_inputs.get(&channel_id).map_or(0, String::len);
+    chat_state.focus = ChatFocus::ChatInput(cursor);
+    let channel = &chat_state.channels[chat_state.active_channel_idx];
+    // Messages can carry only attachments, but an edit has to leave some text behind
+    if text.trim().is_empty() || text == editing.message {
+        return Ok(());
+    }
+
+    if let Some(message) = chat_state
+        .chat_history
+        .get_mut(&channel.id)
+        .and_then(|messages| messages.iter_mut().find(|message| message.message_id == editing.message_id))
+    {
+        // Notes are edited on disk right away, there is no server to confirm it
+        if channel.is_local() {
+            message.message = text.clone();
+            message.edited = true;
+            return Notes::edit(editing.message_id, text, notes_keep_days);
+        }
+        chat_state.pending_edits.insert(editing.message_id, message.clone());
+        message.message = text.clone();
+        message.edited = true;
+    }
+    client.send_edit_message(editing.message_id, text).await
+}
+
+/// Adds the input of the notes to self as a note, it is written to disk and never sent
+fn write_note(chat_state: &mut ChatState, keep_days: u32) {
+    if !chat_state.staged_attachments.is_empty() {
+        chat_state
+            .toasts
+            .push_back(Toast::new("Notes to self can not hold attachments".to_owned()));
+        return;
+    }
+    let Some(text) = chat_state
+        .chat_inputs
+        .get(&NOTES_CHANNEL_ID)
+        .filter(|text| !text.trim().is_empty())
+        .cloned()
+    else {
+        return;
+    };
+    let reply_id = chat_state.reply_targets.get(&NOTES_CHANNEL_ID).map_or(0, |message| message.message_id);
+    match Notes::add(text, reply_id, keep_days) {
+        Ok(note) => {
+            let message = note.chat_message(&chat_state.current_user.username, chat_state.current_user.user_id);
+            chat_state.chat_history.entry(NOTES_CHANNEL_ID).or_default().push(message);
+            chat_state.chat_inputs.insert(NOTES_CHANNEL_ID, String::new());
+            chat_state.reply_targets.remove(&NOTES_CHANNEL_ID);
+            chat_state.focus = ChatFocus::ChatInput(0);
+        }
+        Err(e) => {
+            warn!("Failed to save the note: {e}");
+            chat_state.toasts.push_back(Toast::new(format!("Failed to save the note: {e}")));
+        }
+    }
+}
+
+/// Moves the selected note into the draft of the server channel that was open last, to be looked over before sending it
+async fn copy_note_to_channel(chat_state: &mut ChatState, client: &mut Client) -> Result<()> {
+    let Some(note) = chat_state.selected_message().map(|message| message.message.clone()) else {
+        return Ok(());
+    };
+    let target = chat_state
+        .last_server_channel
+        .and_then(|channel_id| chat_state.channels.iter().position(|channel| channel.id == channel_id))
+        .or_else(|| chat_state.channels.iter().position(|channel| !channel.is_local()));
+    let Some(channel_idx) = target else {
+        chat_state
+            .toasts
+            .push_back(Toast::new("There is no channel to copy the note to".to_owned()));
+        return Ok(());
+    };
+    let channel_id = chat_state.channels[channel_idx].id;
+    let draft = chat_state.chat_inputs.entry(channel_id).or_default();
+    if !draft.is_empty() && !draft.ends_with(' ') {
+        draft.push(' ');
+    }
+    draft.push_str(&note);
+    let cursor = draft.len();
+
+    chat_state.select_channel(channel_idx);
+    chat_state.chat_scroll_offset = 0;
+    chat_state.focus = ChatFocus::ChatInput(cursor);
+    chat_state.save_preferences()?;
+    mark_active_channel_read(chat_state, client).await
+}
+
+/// Puts `> author: text` of the selected message in front of the draft, on one line as the input has no line breaks
+fn quote_message(chat_state: &mut ChatState) {
+    let Some(message) = chat_state.selected_message().filter(|message| message.kind != ChatMessageKind::System) else {
+        return;
+    };
+    let text = message.message.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut graphemes = text.graphemes(true);
+    let mut quoted: String = graphemes.by_ref().take(QUOTE_LENGTH).collect();
+    if graphemes.next().is_some() {
+        quoted.push('…');
+    }
+    let quote = format!("> {}: {quoted} ", message.author_name);
+    let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id) else {
+        return;
+    };
+    let draft = chat_state.chat_inputs.entry(channel_id).or_default();
+    draft.insert_str(0, &quote);
+    let cursor = draft.len();
+    chat_state.focus = ChatFocus::ChatInput(cursor);
+}
+
+/// Shows why attachments can not be added when the server does not accept media, returns whether they are refused
+fn refuse_media(chat_state: &mut ChatState) -> bool {
+    if !chat_state.capabilities.chunked_media {
+        chat_state
+            .toasts
+            .push_back(Toast::new("This server does not accept attachments".to_owned()));
+    }
+    !chat_state.capabilities.chunked_media
+}
+
+/// Checks a picked file and adds it to the attachments of the next message.
+///
+/// Files above `max_upload_size` are refused before anything is sent, images are offered to be compressed instead.
+pub fn stage_attachment(chat_state: &mut ChatState, attachment: PendingAttachment, max_upload_size: usize) {
+    let upload_limit = max_upload_size.min(MAX_MEDIA_SIZE);
+    if attachment.data.len() > upload_limit {
+        if is_compressible_image(&attachment.data) {
+            chat_state.popup = Some(Popup::CompressAttachment(attachment));
+        } else {
+            let PendingAttachment { filename, data, .. } = attachment;
+            let message = format!(
+                "{filename} is {}, which exceeds the upload limit of {}",
+                format_size(data.len()),
+                format_size(upload_limit)
+            );
+            warn!("{message}");
+            chat_state.toasts.push_back(Toast::new(message));
+        }
+        return;
+    }
+    let PendingAttachment { filename, data, media_type } = attachment;
+
+    let media_type = match media_type {
+        Some(media_type) => media_type,
+        None => {
+            let detected = detect_media_type(&filename, &data);
+            if let Some(warning) = detected.warning {
+                warn!("{warning}");
+                chat_state.toasts.push_back(Toast::new(warning));
+            }
+            detected.media_type
+        }
+    };
+    debug!("Attached {filename} ({})", format_size(data.len()));
+    chat_state.staged_attachments.push(StagedAttachment { filename, data, media_type });
+}
+
+/// Shows a pending message with the staged attachments in the open channel and starts uploading the first one.
+/// The uploads are advanced on every tick, the message itself is sent once the server acknowledged all media.
+fn send_staged_attachments(chat_state: &mut ChatState, client: &mut Client) -> Result<()> {
+    let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id) else {
+        return Err(anyhow!("No channel to send media to"));
+    };
+    if chat_state.upload.is_some() {
+        chat_state
+            .toasts
+            .push_back(Toast::new("Wait for the current upload to finish".to_owned()));
+        return Ok(());
+    }
+    let mut remaining: VecDeque<StagedAttachment> = chat_state.staged_attachments.drain(..).collect();
+    let Some(first) = remaining.pop_front() else {
+        return Ok(());
+    };
+    let caption = chat_state.chat_inputs.get_mut(&channel_id).map(std::mem::take).unwrap_or_default();
+    let attachments = std::iter::once(&first)
+        .chain(&remaining)
+        .map(|attachment| Attachment {
+            media_id: 0,
+            filename: Some(attachment.filename.clone()),
+            media_type: Some(attachment.media_type.clone()),
+        })
+        .collect();
+    let progress = client.start_media_upload(first.filename, first.media_type, first.data)?;
+
+    let reply_id = chat_state.reply_targets.get(&channel_id).map_or(0, |message| message.message_id);
+    let temp_message_id = chat_state.incrementing_ack_id;
+    chat_state.incrementing_ack_id += 1;
+
+    let message = ChatMessage {
+        message_id: temp_message_id,
+        reply_id,
+        author_name: chat_state.current_user.username.to_owned(),
+        author_id: chat_state.own_user_id(),
+        timestamp: Utc::now(),
+        message: caption.clone(),
+        status: ChatMessageStatus::Sending,
+        kind: ChatMessageKind::User,
+        attachments,
+        edited: false,
+    };
+    chat_state.chat_history.entry(channel_id).or_default().push(message);
+    chat_state.reply_targets.remove(&channel_id);
+    chat_state.focus = ChatFocus::ChatInput(0);
+    chat_state.upload = Some(MediaUpload {
+        message_id: temp_message_id,
+        channel_id,
+        reply_id,
+        caption,
+        progress,
+        media_ids: vec![],
+        remaining,
+    });
+    Ok(())
+}
+
+fn save_download(chat_state: &mut ChatState, download_dir: &Path, media: MediaMessage) {
+    match save_media(download_dir, &media.filename, &media.media_data) {
+        Ok(path) => chat_state.toasts.push_back(Toast::new(format!("Saved {}", path.display()))),
+        Err(e) => {
+            warn!("Failed to save {}: {e:#}", media.filename);
+            chat_state.toasts.push_back(Toast::new(format!("Failed to save {}", media.filename)));
+        }
+    }
+}
+
+/// Queues the attachments around the viewport of the open channel that were not fetched yet and requests as many as the fetcher allows.
+/// The profile picture of the user in the profile popup is fetched as well, and those of the authors around the viewport with `author_avatars`.
+/// The history is only scanned again once the view changed, e.g. by scrolling or new messages.
+pub async fn fetch_visible_media(chat_state: &mut ChatState, client: &mut Client, author_avatars: bool) -> Result<()> {
+    let Some(channel) = chat_state.channels.get(chat_state.active_channel_idx) else {
+        return Ok(());
+    };
+    let history = chat_state.chat_history.get(&channel.id).map(Vec::as_slice).unwrap_or_default();
+    let view = FetchView {
+        channel_id: channel.id,
+        history_length: history.len(),
+        newest_message_id: history.last().map(|message| message.message_id),
+        scroll_offset: chat_state.chat_scroll_offset,
+        profile: match chat_state.popup {
+            Some(Popup::Profile(user_id)) => Some(user_id),
+            _ => None,
+        },
+        downloads: chat_state.pending_downloads.len(),
+        author_avatars,
+    };
+    if chat_state.media_fetcher.view_changed(view) {
+        let wanted = visible_media(chat_state, history, author_avatars);
+        chat_state.media_fetcher.schedule(wanted);
+    }
+
+    for media_id in chat_state.media_fetcher.next_requests() {
+        client.request_media(media_id).await?;
+    }
+    Ok(())
+}
+
+/// Media wanted around the viewport, as `(media_id, distance)` from it
+fn visible_media(chat_state: &ChatState, history: &[ChatMessage], author_avatars: bool) -> Vec<(MediaId, usize)> {
+    let bottom = history.len().saturating_sub(chat_state.chat_scroll_offset);
+    let top = bottom.saturating_sub(VISIBLE_MESSAGES);
+
+    let distance = |index: usize| {
+        if index < top {
+            top - index
+        } else if index >= bottom {
+            index + 1 - bottom
+        } else {
+            0
+        }
+    };
+    let wanted = history.iter().enumerate().flat_map(|(index, message)| {
+        message
+            .attachments
+            .iter()
+            // Attachments of messages that are still being uploaded have no id yet
+            .filter(|attachment| attachment.media_id != 0 && !chat_state.media_cache.is_fetched(attachment.media_id))
+            .map(move |attachment| (attachment.media_id, distance(index)))
+    });
+    let pfp_id = |user_id: UserId| {
+        chat_state
+            .users
+            .iter()
+            .find(|user| user.id == user_id)
+            .map(|user| user.pfp_id)
+            .filter(|pfp_id| *pfp_id != 0 && !chat_state.avatars.contains_key(pfp_id) && !chat_state.media_cache.is_fetched(*pfp_id))
+    };
+    let profile = match chat_state.popup {
+        Some(Popup::Profile(user_id)) => pfp_id(user_id).map(|pfp_id| (pfp_id, 0)),
+        _ => None,
+    };
+    let authors = history
+        .iter()
+        .enumerate()
+        .filter(|_| author_avatars)
+        .filter_map(|(index, message)| pfp_id(message.author_id).map(|pfp_id| (pfp_id, distance(index))));
+    // Requested downloads are fetched even when they scrolled out of view
+    let downloads = chat_state.pending_downloads.iter().map(|media_id| (*media_id, 0));
+    downloads.chain(profile).chain(wanted).chain(authors).collect()
+}
+
+/// Reports the newest message of the open channel as read, so other clients of the user can clear their unread marker
+async fn mark_active_channel_read(chat_state: &mut ChatState, client: &mut Client) -> Result<()> {
+    let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id) else {
+        return Ok(());
+    };
+    let latest_message_id = chat_state.latest_message_id(channel_id);
+    if let Some(channel) = chat_state.channels.get_mut(chat_state.active_channel_idx) {
+        channel.status = match channel.status {
+            ChannelStatus::Muted => ChannelStatus::Muted,
+            _ => ChannelStatus::Read,
+        };
+        if let Some(message_id) = latest_message_id
+            && message_id > channel.last_read_id
+            && !channel.is_local()
+        {
+            channel.last_read_id = message_id;
+            client.send_mark_read(channel_id, message_id).await?;
+        }
+    }
+    Ok(())
+}
+
+fn status_rank(status: &UserStatus) -> u8 {
+    match status {
+        UserStatus::Online => 0,
+        UserStatus::Idle => 1,
+        UserStatus::DoNotDisturb => 2,
+        UserStatus::Offline => 3,
+    }
+}
+
+async fn run_command(chat_state: &mut ChatState, global_state: &mut GlobalState, client: &mut Client, command: Command) -> Result<()> {
+    debug!("Running command {command:?}");
+    let highlight_words = &global_state.config.highlight_words;
+    match command {
+        Command::Who => chat_state.popup = Some(Popup::Who),
+        Command::Lock => lock(chat_state),
+        Command::Search(query) => chat_state.popup = Some(Popup::Search(Search::new(query, &chat_state.channels, &chat_state.chat_history))),
+        Command::Digest => open_digest(chat_state, highlight_words),
+        Command::Pins => open_pins(chat_state),
+        Command::EditProfile => open_profile_editor(chat_state),
+        Command::ChangePassword => chat_state.popup = Some(Popup::ChangePassword(PasswordEditor::new())),
+        Command::Changelog => chat_state.popup = Some(Popup::Changelog(Changelog::full())),
+        Command::Notes => {
+            if let Some(channel_idx) = chat_state.channels.iter().position(DisplayChannel::is_local) {
+                chat_state.select_channel(channel_idx);
+                chat_state.channel_groups.collapsed.remove(LOCAL_GROUP);
+                chat_state.chat_scroll_offset = 0;
+                chat_state.focus = ChatFocus::ChatInput(0);
+            }
+        }
+        // Replaces the current state, opened by the caller
+        Command::Workspace(_) => {}
+        Command::Join(name) => match chat_state.channels.iter().position(|channel| channel.name == name) {
+            Some(idx) => open_channel(chat_state, client, idx).await?,
+            None => chat_state.toasts.push_back(Toast::new(format!("There is no channel #{name}"))),
+        },
+        Command::Status(status) => set_status(chat_state, client, status).await?,
+        Command::Logs(show) => show_logs(chat_state, global_state, show.unwrap_or(!global_state.show_logs))?,
+        Command::Theme(name) => set_theme(chat_state, global_state, name)?,
+        Command::Plugin(name, args) => {
+            let plugins = global_state.plugins.clone();
+            match plugins.run_command(chat_state.plugin_context(), &name, &args) {
+                Ok(actions) => run_plugin_actions(chat_state, client, actions).await?,
+                Err(e) => {
+                    warn!("{e:#}");
+                    chat_state.toasts.push_back(Toast::new(format!("{e:#}")));
+                }
+            }
+        }
+        Command::CacheStats => {
+            // Written first, so the stats include the latest history
+            chat_state.save_history_cache();
+            match HistoryCache::stats(&chat_state.server_address.to_string()) {
+                Ok(stats) => chat_state.popup = Some(Popup::CacheStats(stats)),
+                Err(e) => {
+                    warn!("{e}");
+                    chat_state.toasts.push_back(Toast::new(e.to_string()));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends a message to a channel of the server, it is shown right away and marked as sent once the server confirms it
+async fn send_message(chat_state: &mut ChatState, client: &mut Client, channel_id: ChannelId, reply_id: MessageId, text: String) -> Result<()> {
+    let temp_message_id = chat_state.incrementing_ack_id;
+    let message = ChatMessage {
+        message_id: temp_message_id,
+        author_name: chat_state.current_user.username.to_owned(),
+        author_id: chat_state.own_user_id(),
+        reply_id,
+        timestamp: Utc::now(),
+        message: text.clone(),
+        status: ChatMessageStatus::Sending,
+        kind: ChatMessageKind::User,
+        attachments: vec![],
+        edited: false,
+    };
+    chat_state.waiting_message_acks_id.push_back(temp_message_id);
+    chat_state.incrementing_ack_id += 1;
+
+    chat_state.chat_history.entry(channel_id).or_default().push(message);
+    let channel_name = chat_state
+        .channels
+        .iter()
+        .find(|channel| channel.id == channel_id)
+        .map(|channel| channel.name.clone())
+        .unwrap_or_default();
+    let audit_entry = AuditEntry::sent(
+        &chat_state.server_address.to_string(),
+        temp_message_id,
+        channel_id,
+        &channel_name,
+        reply_id,
+        &text,
+        &[],
+    );
+
+    client.send_chat_message(channel_id, reply_id, text, vec![]).await?; // TODO improve
+    chat_state.audit(audit_entry);
+    Ok(())
+}
+
+/// Passes messages from the server through the plugins, dropping those a plugin filtered out
+async fn filter_incoming(
+    chat_state: &mut ChatState,
+    plugins: &Plugins,
+    client: &mut Client,
+    messages: Vec<HistoryMessage>,
+    live: bool,
+) -> Result<Vec<HistoryMessage>> {
+    if plugins.is_empty() {
+        return Ok(messages);
+    }
+    let context = chat_state.plugin_context();
+    let mut kept = Vec::with_capacity(messages.len());
+    let mut actions = Vec::new();
+    for mut message in messages {
+        let plugin_message = PluginMessage {
+            channel_id: message.channel_id,
+            channel: chat_state
+                .channels
+                .iter()
+                .find(|channel| channel.id == message.channel_id)
+                .map(|channel| channel.name.clone())
+                .unwrap_or_default(),
+            message_id: message.message_id,
+            reply_id: message.reply_id,
+            author: chat_state
+                .users
+                .iter()
+                .find(|user| user.id == message.user_id)
+                .map_or_else(|| "Unknown".to_owned(), |user| user.name.clone()),
+            text: message.message_text.clone(),
+            live,
+        };
+        let (text, message_actions) = plugins.incoming(&context, plugin_message);
+        actions.extend(message_actions);
+        if let Some(text) = text {
+            message.message_text = text;
+            kept.push(message);
+        }
+    }
+    run_plugin_actions(chat_state, client, actions).await?;
+    Ok(kept)
+}
+
+/// Carries out what plugins asked for, messages go to the open channel
+async fn run_plugin_actions(chat_state: &mut ChatState, client: &mut Client, actions: Vec<PluginAction>) -> Result<()> {
+    for action in actions {
+        match action {
+            PluginAction::Toast(text) => chat_state.toasts.push_back(Toast::new(text)),
+            PluginAction::Send(text) => match chat_state.channels.get(chat_state.active_channel_idx) {
+                Some(channel) if !channel.is_local() => send_message(chat_state, client, channel.id, 0, text).await?,
+                _ => warn!("A plugin tried to send a message while no channel of the server is open"),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Runs the `startup_commands` of the config in order, once the channels and their history are in after logging in.
+/// Workspaces can not be opened from them, opening one logs in again and would run them once more
+async fn run_startup_commands(chat_state: &mut ChatState, global_state: &mut GlobalState, client: &mut Client) -> Result<()> {
+    for input in global_state.config.startup_commands.clone() {
+        match Command::parse(&input) {
+            Some(Ok(Command::Workspace(_))) => {
+                warn!("Skipped startup command {input}, workspaces can not be opened on startup");
+                chat_state.toasts.push_back(Toast::new(format!(
+                    "Startup command {input} skipped, workspaces can not be opened on startup"
+                )));
+            }
+            Some(Ok(command)) => run_command(chat_state, global_state, client, command).await?,
+            Some(Err(e)) => {
+                warn!("Startup command {input} failed: {e}");
+                chat_state.toasts.push_back(Toast::new(format!("Startup command {input} failed: {e}")));
+            }
+            None => {
+                warn!("Startup command {input} is not a command");
+                chat_state
+                    .toasts
+                    .push_back(Toast::new(format!("Startup command {input} is not a command, commands start with /")));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Requests what is only asked for once the startup sequence is done, and runs the startup commands
+async fn finish_startup(chat_state: &mut ChatState, global_state: &mut GlobalState, client: &mut Client) -> Result<()> {
+    // Every channel answered its history request or ran out of retries, empty channels included
+    chat_state.syncing.clear();
+    client.request_read_states().await?;
+    client.request_direct_channels().await?;
+    run_startup_commands(chat_state, global_state, client).await
+}
+
+async fn open_channel(chat_state: &mut ChatState, client: &mut Client, idx: usize) -> Result<()> {
+    if idx >= chat_state.channels.len() || idx == chat_state.active_channel_idx {
+        return Ok(());
+    }
+    if let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
+        && chat_state.is_typing
+        && !channel_id.is_local()
+    {
+        client.send_typing(channel_id.id, false).await?;
+    }
+    chat_state.select_channel(idx);
+    chat_state.chat_scroll_offset = 0;
+    chat_state.focus = ChatFocus::ChatHistory;
+    chat_state.save_preferences()?;
+    mark_active_channel_read(chat_state, client).await
+}
+
+/// Sets a status picked by the user, going back to online hands the status back to the focus and idle timers
+async fn set_status(chat_state: &mut ChatState, client: &mut Client, status: UserStatus) -> Result<()> {
+    chat_state.manual_status = status != UserStatus::Online;
+    chat_state.time_since_last_focused = None;
+    chat_state.current_user.status = status.clone();
+    info!("Changed the status to {status:?}");
+    client.send_user_status(status).await
+}
+
+fn show_logs(chat_state: &mut ChatState, global_state: &mut GlobalState, show: bool) -> Result<()> {
+    global_state.show_logs = show;
+    chat_state.focus = ChatFocus::ChatHistory;
+    chat_state.preferences.show_logs = Some(show);
+    chat_state.save_preferences()
+}
+
+/// Switches to a theme for the current server, `None` goes back to the theme of the config file
+fn set_theme(chat_state: &mut ChatState, global_state: &mut GlobalState, name: Option<String>) -> Result<()> {
+    let theme = match &name {
+        Some(name) => match Theme::load(name) {
+            Ok(theme) => theme,
+            Err(e) => {
+                warn!("{e:#}");
+                chat_state.toasts.push_back(Toast::new(format!("{e:#}")));
+                return Ok(());
+            }
+        },
+        None => global_state.config.theme.clone(),
+    };
+    chat_state.toasts.push_back(Toast::new(format!("Switched to the {} theme", theme.name)));
+    global_state.theme = theme;
+    chat_state.preferences.theme = name;
+    chat_state.save_preferences()
+}
+
+/// Moves the focus to the chat history when the pane it was on got hidden
+fn leave_hidden_pane(chat_state: &mut ChatState, global_state: &GlobalState) {
+    if global_state.hidden_panes.hides_focus(chat_state.focus) {
+        chat_state.focus = ChatFocus::ChatHistory;
+    }
+}
+
+fn open_profile_editor(chat_state: &mut ChatState) {
+    let editor = ProfileEditor::new(&chat_state.current_user.username, chat_state.own_user());
+    chat_state.popup = Some(Popup::EditProfile(editor));
+}
+
+/// Sends the changes of the edit profile popup, a picked picture is uploaded first
+async fn save_profile(chat_state: &mut ChatState, client: &mut Client) -> Result<()> {
+    let Some(Popup::EditProfile(editor)) = &chat_state.popup else {
+        return Ok(());
+    };
+    if chat_state.profile_update.is_some() || chat_state.password_change_pending {
+        return Err(anyhow!("Your previous profile change is still being saved"));
+    }
+    let fields = editor.changes(&chat_state.current_user.username, chat_state.own_user())?;
+    let picture = editor.read_picture()?;
+
+    let picture_upload = match picture {
+        Some(_) if chat_state.upload.is_some() => return Err(anyhow!("Wait for the upload to finish before changing your picture")),
+        Some((filename, data)) => Some(client.start_media_upload(filename, MediaType::Image, data)?),
+        None if fields.is_empty() => {
+            chat_state.popup = None;
+            return Ok(());
+        }
+        None => {
+            client.set_user_config(fields.clone()).await?;
+            None
+        }
+    };
+    chat_state.profile_update = Some(ProfileUpdate { fields, picture_upload });
+    chat_state.popup = None;
+    Ok(())
+}
+
+/// Sends the password change of the change password popup, the server checks the current password
+async fn save_password(chat_state: &mut ChatState, client: &mut Client) -> Result<()> {
+    let Some(Popup::ChangePassword(editor)) = &chat_state.popup else {
+        return Ok(());
+    };
+    if chat_state.profile_update.is_some() || chat_state.password_change_pending {
+        return Err(anyhow!("Your previous profile change is still being saved"));
+    }
+    let field = editor.change()?;
+    client.set_user_config(vec![field]).await?;
+    chat_state.password_change_pending = true;
+    chat_state.popup = None;
+    Ok(())
+}
+
+/// Leaves the session for the login screen when its password is no longer accepted, with the password left to be typed again.
+/// The session is not parked, switching back to it would log in with the same password
+async fn end_session(
+    chat_state: &mut ChatState,
+    state_map: &mut HashMap<Screen, AppState>,
+    client: &mut Client,
+    input_status: InputStatus,
+) -> Result<Option<AppState>> {
+    if let Err(e) = clear_status() {
+        warn!("Failed to remove the status file: {e}");
+    }
+    chat_state.written_status = None;
+    chat_state.save_history_cache();
+    chat_state.save_checkpoint();
+    chat_state.fail_unconfirmed_messages();
+    client.disconnect()?;
+    let user = &chat_state.current_user;
+    state_map.remove(&Screen::Chat(
+        user.username.trim().to_string(),
+        Secret::new(user.password.expose().trim().to_owned()),
+        chat_state.server_address.clone(),
+    ));
+    let Some(AppState::Login(mut login_state)) = state_map.get(&Screen::Login).cloned() else {
+        return Ok(None);
+    };
+    login_state.username_input = chat_state.current_user.username.clone();
+    login_state.password_input = Secret::default();
+    login_state.input_status = input_status;
+    login_state.login_code = None;
+    login_state.goodbye = None;
+    login_state.focus = LoginFocus::PasswordInput(0);
+    Ok(Some(AppState::Login(login_state)))
+}
+
+/// Shows the accepted changes right away, other clients learn about them from the server
+fn apply_profile_update(chat_state: &mut ChatState, fields: Vec<UserConfigField>) {
+    // Found once, a new username in the same update would hide the user from a lookup by name
+    let username = chat_state.current_user.username.clone();
+    let mut user = chat_state.users.iter_mut().find(|user| user.name == username);
+    for field in fields {
+        let user = user.as_deref_mut();
+        match field {
+            UserConfigField::Username(name) => {
+                if let Some(user) = user {
+                    user.name = name.clone();
+                }
+                chat_state.current_user.username = name;
+                // Mentions are matched against the username
+                chat_state.unread_counts.clear();
+            }
+            UserConfigField::Bio(bio) => {
+                if let Some(user) = user {
+                    user.bio = bio;
+                }
+            }
+            UserConfigField::ProfilePicture(pfp_id) => {
+                if let Some(user) = user {
+                    user.pfp_id = pfp_id;
+                }
+                chat_state.media_fetcher.invalidate();
+            }
+            UserConfigField::Password { .. } => {}
+        }
+    }
+    info!("Updated the profile");
+    chat_state.toasts.push_back(Toast::new("Profile updated".to_owned()));
+}
+
+/// Leaves the current session for the login screen, filled in with the account and logging in right away when its password is known.
+/// The session is parked, switching back to it continues where it was left
+async fn switch_account(
+    chat_state: &mut ChatState,
+    state_map: &mut HashMap<Screen, AppState>,
+    default_tls: bool,
+    client: &mut Client,
+    account: &SavedAccount,
+) -> Result<Option<AppState>> {
+    let Some(AppState::Login(mut login_state)) = state_map.get(&Screen::Login).cloned() else {
+        return Ok(None);
+    };
+    info!("Switching to {} on {}", account.username, account.address);
+
+    if let Err(e) = clear_status() {
+        warn!("Failed to remove the status file: {e}");
+    }
+    chat_state.written_status = None;
+    chat_state.save_history_cache();
+    chat_state.save_checkpoint();
+    // Let the others see this account leave instead of timing out
+    if let Err(e) = client.send_user_status(UserStatus::Offline).await {
+        warn!("Failed to announce going offline: {e}");
+    }
+    park_session(chat_state, state_map, client).await?;
+
+    login_state.username_input = account.username.clone();
+    login_state.password_input = account.password.clone().unwrap_or_default();
+    login_state.server_address_input = account.address.clone();
+    login_state.enable_tls = account.enable_tls.unwrap_or(default_tls);
+    login_state.input_status = InputStatus::AllFine;
+    login_state.login_code = None;
+    login_state.login_pending = account.password.is_some();
+    login_state.focus = match account.password {
+        Some(_) => LoginFocus::Nothing,
+        None => LoginFocus::PasswordInput(0),
+    };
+    Ok(Some(AppState::Login(login_state)))
+}
+
+/// Opens a workspace of the config file: applies its layout and switches to its account when another one is logged in
+async fn open_workspace(
+    chat_state: &mut ChatState,
+    global_state: &mut GlobalState,
+    state_map: &mut HashMap<Screen, AppState>,
+    client: &mut Client,
+    name: &str,
+) -> Result<Option<AppState>> {
+    let Some(workspace) = global_state
+        .config
+        .workspaces
+        .iter()
+        .find(|workspace| workspace.name.eq_ignore_ascii_case(name))
+        .cloned()
+    else {
+        let names: Vec<&str> = global_state.config.workspaces.iter().map(|workspace| workspace.name.as_str()).collect();
+        let message = if names.is_empty() {
+            "No workspaces in the config file".to_owned()
+        } else {
+            format!("Unknown workspace {name}, pick one of {}", names.join(", "))
+        };
+        chat_state.toasts.push_back(Toast::new(message));
+        return Ok(None);
+    };
+    info!("Opening workspace {}", workspace.name);
+    if let Some(show_logs) = workspace.show_logs {
+        global_state.show_logs = show_logs;
+    }
+    if let Some(zen) = workspace.zen {
+        global_state.hidden_panes.zen = zen;
+        leave_hidden_pane(chat_state, global_state);
+    }
+    let account = workspace.account();
+    global_state.workspace = Some(workspace);
+    global_state.workspace_pins_pending = true;
+
+    let current = SavedAccount {
+        username: chat_state.current_user.username.clone(),
+        password: None,
+        address: chat_state.server_address.to_string(),
+        enable_tls: None,
+    };
+    if account.is_same(&current) {
+        apply_workspace_pins(chat_state, global_state);
+        return Ok(None);
+    }
+    switch_account(chat_state, state_map, global_state.config.enable_tls, client, &account).await
+}
+
+/// Pins the channels of the open workspace, once its chat has channels.
+/// Channels arrive in batches, so the pins are reapplied until the startup sequence is done
+fn apply_workspace_pins(chat_state: &mut ChatState, global_state: &mut GlobalState) {
+    let Some(workspace) = &global_state.workspace else {
+        return;
+    };
+    let current = SavedAccount {
+        username: chat_state.current_user.username.clone(),
+        password: None,
+        address: chat_state.server_address.to_string(),
+        enable_tls: None,
+    };
+    if !global_state.workspace_pins_pending || !workspace.account().is_same(&current) || chat_state.channels.is_empty() {
+        return;
+    }
+    if !workspace.pinned_channels.is_empty() {
+        chat_state.preferences.pinned_channels = chat_state
+            .channels
+            .iter()
+            .filter(|channel| workspace.pinned_channels.contains(&channel.name))
+            .map(|channel| channel.id)
+            .collect();
+        if let Err(e) = chat_state.save_preferences() {
+            warn!("Failed to save the preferences: {e}");
+        }
+    }
+    if chat_state.startup.is_done() {
+        global_state.workspace_pins_pending = false;
+    }
+}
+
+/// Lists the pinned messages of the open channel
+fn open_pins(chat_state: &mut ChatState) {
+    let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id) else {
+        return;
+    };
+    chat_state.popup = Some(Popup::Pins(Pins::new(channel_id, &chat_state.preferences.pinned_messages)));
+}
+
+/// Lists the highlights since the digest was opened before, the next digest starts from now
+fn open_digest(chat_state: &mut ChatState, highlight_words: &[String]) {
+    let since = chat_state
+        .preferences
+        .digest_seen
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0));
+    let digest = Digest::new(
+        since,
+        (chat_state.own_user_id(), &chat_state.current_user.username),
+        highlight_words,
+        &chat_state.channels,
+        &chat_state.chat_history,
+    );
+    chat_state.popup = Some(Popup::Digest(digest));
+    chat_state.preferences.digest_seen = Some(Utc::now().timestamp());
+    if let Err(e) = chat_state.save_preferences() {
+        warn!("Failed to save the preferences: {e}");
+    }
+}
+
+/// Whether a "major.minor.patch" version is older than another, versions that can not be parsed are never considered older.
+/// Missing parts count as 0, so "1.2" and "1.2.0" are the same version
+fn is_older_version(version: &str, than: &str) -> bool {
+    let parse = |version: &str| -> Option<Vec<u64>> { version.trim().trim_start_matches('v').split('.').map(|part| part.parse().ok()).collect() };
+    let (Some(mut version), Some(mut than)) = (parse(version), parse(than)) else {
+        return false;
+    };
+    let len = version.len().max(than.len());
+    version.resize(len, 0);
+    than.resize(len, 0);
+    version < than
+}
+
+fn lock(chat_state: &mut ChatState) {
+    info!("Locked the chat");
+    chat_state.popup = None;
+    chat_state.lock = Some(LockScreen::default());
+}
+
+/// Applies the status updates that arrived since they were last applied in one pass over the users, with only the latest status per user.
+/// Returns the users that are not known yet
+fn apply_status_updates(chat_state: &mut ChatState) -> Vec<UserId> {
+    let positions: HashMap<UserId, usize> = chat_state.users.iter().enumerate().map(|(idx, user)| (user.id, idx)).collect();
+    let mut unknown_users = vec![];
+    for (user_id, status) in std::mem::take(&mut chat_state.pending_statuses) {
+        let Some(&idx) = positions.get(&user_id) else {
+            unknown_users.push(user_id);
+            continue;
+        };
+        let previous_status = std::mem::replace(&mut chat_state.users[idx].status, status.clone());
+        record_presence(chat_state, user_id, &previous_status, &status);
+        announce_presence(chat_state, user_id, &previous_status, &status);
+        chat_state.users_changed |= previous_status != status;
+    }
+    unknown_users
+}
+
+/// Applies the status updates of the last tick, the names of unknown users are asked for together in a single request
+pub async fn flush_status_updates(chat_state: &mut ChatState, client: &mut Client) -> Result<()> {
+    if chat_state.pending_statuses.is_empty() {
+        return Ok(());
+    }
+    let mut unknown_users = apply_status_updates(chat_state);
+    unknown_users.retain(|user_id| !chat_state.requested_users.contains(user_id));
+    // During the startup sequence unknown users are picked up by its own status request
+    if !unknown_users.is_empty() && chat_state.startup.is_done() {
+        debug!("New users added, requesting names of users ids {unknown_users:?}");
+        client.request_users(unknown_users.clone()).await?;
+        chat_state.requested_users.extend(unknown_users);
+    }
+    Ok(())
+}
+
+/// Remembers when a user was last seen online, for the away report of `/who`
+fn record_presence(chat_state: &mut ChatState, user_id: UserId, previous_status: &UserStatus, status: &UserStatus) {
+    if *previous_status != UserStatus::Offline || *status != UserStatus::Offline {
+        chat_state.last_seen.insert(user_id, Utc::now());
+    }
+}
+
+/// Shows a toast when a watched user comes online or goes offline
+fn announce_presence(chat_state: &mut ChatState, user_id: UserId, previous_status: &UserStatus, status: &UserStatus) {
+    let was_online = *previous_status != UserStatus::Offline;
+    let is_online = *status != UserStatus::Offline;
+    if was_online == is_online || !chat_state.preferences.watched_users.contains(&user_id) {
+        return;
+    }
+
+    if let Some(user) = chat_state.users.iter().find(|user| user.id == user_id) {
+        let message = if is_online {
+            format!("{} is now online", user.name)
+        } else {
+            format!("{} went offline", user.name)
+        };
+        info!("{message}");
+        if let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id) {
+            chat_state.keep_view(|chat_state| {
+                chat_state
+                    .chat_history
+                    .entry(channel_id)
+                    .or_default()
+                    .push(ChatMessage::system(message.clone(), Utc::now()))
+            });
+        }
+        chat_state.toasts.push_back(Toast::new(message));
+    }
+}
+
+fn insert_channel_event(chat_state: &mut ChatState, event: ChannelEventPacket) -> Result<()> {
+    let user_name = chat_state
+        .users
+        .iter()
+        .find(|user| user.id == event.user_id)
+        .map(|user| user.name.clone())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let message = match event.kind {
+        ChannelEventKind::UserJoined => format!("{user_name} joined the channel"),
+        ChannelEventKind::UserLeft => format!("{user_name} left the channel"),
+        ChannelEventKind::TopicChanged => format!("{user_name} changed the topic to \"{}\"", event.text),
+        ChannelEventKind::PinAdded => format!("{user_name} pinned \"{}\"", event.text),
+    };
+
+    let timestamp = chat_state.clock_skew.local_time(event.timestamp)?;
+    chat_state.keep_view(|chat_state| {
+        chat_state
+            .chat_history
+            .entry(event.channel_id)
+            .or_default()
+            .push(ChatMessage::system(message, timestamp))
+    });
+    Ok(())
+}
+
+/// Disconnects and keeps the chat state in the state map, logging in to the same account again continues where it was left
+async fn park_session(chat_state: &mut ChatState, state_map: &mut HashMap<Screen, AppState>, client: &mut Client) -> Result<()> {
+    if let Some(channel_id) = chat_state.channels.get(chat_state.active_channel_idx)
+        && chat_state.is_typing
+        && !channel_id.is_local()
+    {
+        client.send_typing(channel_id.id, false).await?;
+    }
+    chat_state.fail_unconfirmed_messages();
+
+    client.disconnect()?;
+    let user = &chat_state.current_user;
+    state_map.insert(
+        Screen::Chat(
+            user.username.trim().to_string(),
+            Secret::new(user.password.expose().trim().to_owned()),
+            chat_state.server_address.clone(),
+        ),
+        AppState::Chat(Box::new(chat_state.clone())),
+    );
+    Ok(())
+}
+
+/// Opens a channel with a message selected and loads the messages around it
+async fn jump_to_message(chat_state: &mut ChatState, client: &mut Client, channel_idx: usize, message_id: MessageId, page_size: i8) -> Result<()> {
+    if let Some(channel) = chat_state.channels.get(chat_state.active_channel_idx)
+        && channel_idx != chat_state.active_channel_idx
+        && chat_state.is_typing
+        && !channel.is_local()
+    {
+        client.send_typing(channel.id, false).await?;
+    }
+    let previous_channel = chat_state.preferences.active_channel.clone();
+    chat_state.popup = None;
+    chat_state.select_message(channel_idx, message_id);
+    request_context(chat_state, client, channel_idx, message_id, page_size).await?;
+    save_active_channel(chat_state, previous_channel);
+    mark_active_channel_read(chat_state, client).await
+}
+
+/// Remembers the opened channel for the next session, the preferences are only written when it changed.
+/// A failed write is only logged, so switching channels still goes on
+fn save_active_channel(chat_state: &ChatState, previous_channel: Option<String>) {
+    if chat_state.preferences.active_channel != previous_channel
+        && let Err(e) = chat_state.save_preferences()
+    {
+        warn!("Failed to save the preferences: {e}");
+    }
+}
+
+/// Jumps to a message of a channel, loading older history first when the message is older than what is loaded
+async fn jump_to_older_message(
+    chat_state: &mut ChatState,
+    client: &mut Client,
+    channel_idx: usize,
+    message_id: MessageId,
+    page_size: i8,
+) -> Result<()> {
+    let Some(channel_id) = chat_state.channels.get(channel_idx).map(|channel| channel.id) else {
+        return Ok(());
+    };
+    let loaded = chat_state
+        .chat_history
+        .get(&channel_id)
+        .is_some_and(|messages| messages.iter().any(|message| message.message_id == message_id));
+    if loaded {
+        return jump_to_message(chat_state, client, channel_idx, message_id, page_size).await;
+    }
+    chat_state.message_jump = Some(MessageJump {
+        channel_id,
+        message_id,
+        pages: 0,
+    });
+    chat_state.toasts.push_back(Toast::new("Loading older messages to find it...".to_owned()));
+    follow_message_jump(chat_state, client, page_size).await
+}
+
+/// Selects the message that is jumped to once it is loaded, requests the next older page otherwise.
+/// Gives up when the start of the channel is reached, the message was deleted then
+async fn follow_message_jump(chat_state: &mut ChatState, client: &mut Client, page_size: i8) -> Result<()> {
+    let Some(jump) = chat_state.message_jump.clone() else {
+        return Ok(());
+    };
+    let Some(channel_idx) = chat_state.channels.iter().position(|channel| channel.id == jump.channel_id) else {
+        chat_state.message_jump = None;
+        return Ok(());
+    };
+    let messages = chat_state.chat_history.get(&jump.channel_id);
+    if messages.is_some_and(|messages| messages.iter().any(|message| message.message_id == jump.message_id)) {
+        chat_state.message_jump = None;
+        return jump_to_message(chat_state, client, channel_idx, jump.message_id, page_size).await;
+    }
+    let oldest = messages
+        .and_then(|messages| messages.iter().find(|message| message.kind == ChatMessageKind::User))
+        .map(|message| message.message_id);
+    // Notes are all loaded, and the server knows nothing about them
+    let fully_loaded = chat_state.channels[channel_idx].is_local() || chat_state.history_pagination.is_exhausted(jump.channel_id);
+    let gave_up = match oldest {
+        _ if fully_loaded => Some("The message was deleted"),
+        _ if jump.pages >= MAX_JUMP_PAGES => Some("The message is too far back"),
+        None => Some("The message is not loaded"),
+        Some(_) if chat_state.history_pagination.in_flight() => None,
+        Some(oldest) => {
+            chat_state
+                .history_pagination
+                .request_older(client, jump.channel_id, oldest, page_size)
+                .await?;
+            if let Some(jump) = &mut chat_state.message_jump {
+                jump.pages += 1;
+            }
+            None
+        }
+    };
+    if let Some(reason) = gave_up {
+        chat_state.message_jump = None;
+        chat_state.toasts.push_back(Toast::new(reason.to_owned()));
+    }
+    Ok(())
+}
+
+/// Requests the page before the oldest loaded message once the top of the active channel comes within a page of the view
+async fn load_older_history(chat_state: &mut ChatState, client: &mut Client, page_size: i8) -> Result<()> {
+    // Every note is loaded already
+    let Some(channel) = chat_state
+        .channels
+        .get(chat_state.active_channel_idx)
+        .filter(|channel| !channel.is_local())
+    else {
+        return Ok(());
+    };
+    let Some(messages) = chat_state.chat_history.get(&channel.id) else {
+        return Ok(());
+    };
+    // Index of the newest message in view, or of the selected one
+    let position = match chat_state.focus {
+        ChatFocus::ChatHistorySelection => chat_state.chat_scroll_offset + channel.selection_offset,
+        _ => messages.len().saturating_sub(chat_state.chat_scroll_offset),
+    };
+    if position > page_size as usize {
+        return Ok(());
+    }
+    // Channel events are generated locally and are unknown to the server
+    let Some(oldest) = messages.iter().find(|message| message.kind == ChatMessageKind::User) else {
+        return Ok(());
+    };
+    let (channel_id, oldest) = (channel.id, oldest.message_id);
+    chat_state.history_pagination.request_older(client, channel_id, oldest, page_size).await
+}
+
+/// Requests the next page of older history for a search without matches, once the previous page arrived and `BACKFILL_INTERVAL` passed.
+/// Channels take turns, so the match is found in whichever channel it is
+pub async fn backfill_search(chat_state: &mut ChatState, client: &mut Client, page_size: i8) -> Result<()> {
+    let Some(Popup::Search(search)) = &mut chat_state.popup else {
+        return Ok(());
+    };
+    let Some(backfill) = &mut search.backfill else {
+        return Ok(());
+    };
+    if backfill.last_request.is_some_and(|requested| requested.elapsed() < BACKFILL_INTERVAL) || chat_state.history_pagination.in_flight() {
+        return Ok(());
+    }
+    if backfill.pages >= MAX_BACKFILL_PAGES {
+        search.stop_backfill(format!("No matches in {MAX_BACKFILL_PAGES} older pages"));
+        return Ok(());
+    }
+    // Channel events are generated locally and are unknown to the server, channels without messages have nothing older to ask for
+    let candidates: Vec<(ChannelId, MessageId)> = chat_state
+        .channels
+        .iter()
+        .filter(|channel| !channel.is_local() && !chat_state.history_pagination.is_exhausted(channel.id))
+        .filter_map(|channel| {
+            let messages = chat_state.chat_history.get(&channel.id)?;
+            let oldest = messages.iter().find(|message| message.kind == ChatMessageKind::User)?;
+            Some((channel.id, oldest.message_id))
+        })
+        .collect();
+    let Some(&(channel_id, oldest)) = candidates.get(backfill.pages % candidates.len().max(1)) else {
+        search.stop_backfill("Reached the start of every channel without a match");
+        return Ok(());
+    };
+    backfill.pages += 1;
+    backfill.last_request = Some(Instant::now());
+    chat_state.history_pagination.request_older(client, channel_id, oldest, page_size).await
+}
+
+/// Loads the messages around one that was jumped to in both directions, when less than a page of them is loaded on either side
+async fn request_context(chat_state: &ChatState, client: &mut Client, channel_idx: usize, message_id: MessageId, page_size: i8) -> Result<()> {
+    let Some(channel) = chat_state.channels.get(channel_idx).filter(|channel| !channel.is_local()) else {
+        return Ok(());
+    };
+    let Some((before, after)) = chat_state.chat_history.get(&channel.id).and_then(|messages| {
+        let idx = messages.iter().position(|message| message.message_id == message_id)?;
+        Some((idx, messages.len() - idx - 1))
+    }) else {
+        return Ok(());
+    };
+    if before < page_size as usize {
+        client.request_history_by_message(channel.id, message_id, page_size).await?;
+    }
+    // Nothing newer than the newest message is missing, it arrives as a notification
+    if after > 0 && after < page_size as usize {
+        client.request_history_by_message(channel.id, message_id, -page_size).await?;
+    }
+    Ok(())
+}
+
+/// Opens the direct channel that was asked for once it is known, returns whether it was
+async fn open_pending_direct_message(chat_state: &mut ChatState, client: &mut Client) -> Result<bool> {
+    let Some(user_id) = chat_state.opening_direct_message else {
+        return Ok(false);
+    };
+    let Some(channel_idx) = chat_state.channels.iter().position(|channel| channel.direct_user == Some(user_id)) else {
+        return Ok(false);
+    };
+    if let Some(channel) = chat_state.channels.get(chat_state.active_channel_idx)
+        && channel_idx != chat_state.active_channel_idx
+        && chat_state.is_typing
+        && !channel.is_local()
+    {
+        client.send_typing(channel.id, false).await?;
+    }
+    chat_state.opening_direct_message = None;
+    chat_state.select_channel(channel_idx);
+    // The group of direct channels may be collapsed
+    chat_state.channel_groups.collapsed.remove(DIRECT_MESSAGES_GROUP);
+    chat_state.chat_scroll_offset = 0;
+    chat_state.focus = ChatFocus::ChatInput(0);
+    mark_active_channel_read(chat_state, client).await?;
+    Ok(true)
+}
+
+/// Compares a timestamp the server stamped just now with the local clock, warns once when they are far apart
+fn sample_clock_skew(chat_state: &mut ChatState, server_timestamp: u64) {
+    if let Some(skew) = chat_state.clock_skew.sample(server_timestamp) {
+        let warning = describe_skew(skew);
+        warn!("{warning}");
+        chat_state.toasts.push_back(Toast::new(warning));
+    }
+}
+
+/// Counts messages arriving while the terminal is not focused and rings the bell for new messages.
+/// Own messages and muted channels are left out, and the bell stays silent while set to do not disturb.
+fn alert_messages(chat_state: &mut ChatState, messages: &[HistoryMessage], bell: bool) {
+    let own_user_id = chat_state.own_user_id();
+    let new_messages = messages
+        .iter()
+        .filter(|message| message.user_id != own_user_id)
+        .filter(|message| {
+            chat_state
+                .channels
+                .iter()
+                .any(|channel| channel.id == message.channel_id && !matches!(channel.status, ChannelStatus::Muted))
+        })
+        .count();
+    if new_messages == 0 {
+        return;
+    }
+    if !chat_state.terminal_focused {
+        chat_state.unseen_messages += new_messages;
+    }
+    if bell
+        && !matches!(chat_state.current_user.status, UserStatus::DoNotDisturb)
+        && let Err(e) = ring_bell()
+    {
+        warn!("Failed to ring the bell: {e}");
+    }
+}
+
+/// Raises a desktop notification for messages that mention the current user, or for any message while the terminal is not focused.
+/// Nothing is shown while set to do not disturb, or for muted channels.
+fn notify_messages(chat_state: &ChatState, messages: &[HistoryMessage]) {
+    if matches!(chat_state.current_user.status, UserStatus::DoNotDisturb) {
+        return;
+    }
+    let own_user_id = chat_state.own_user_id();
+    for message in messages {
+        let unseen = chat_state.mentions_current_user(&message.message_text) || !chat_state.terminal_focused;
+        if message.user_id == own_user_id || !unseen {
+            continue;
+        }
+        let Some(channel) = chat_state.channels.iter().find(|channel| channel.id == message.channel_id) else {
+            continue;
+        };
+        if matches!(channel.status, ChannelStatus::Muted) {
+            continue;
+        }
+        let author_name = chat_state
+            .users
+            .iter()
+            .find(|user| user.id == message.user_id)
+            .map_or("Unknown", |user| user.name.as_str());
+        notifications::notify(format!("{author_name} in #{}", channel.name), &message.message_text);
+    }
+}
+
+/// Runs the message hooks for messages of others, and the mention hooks for those that also mention the current user or a highlight word
+fn run_message_hooks(chat_state: &ChatState, config: &AppConfig, messages: &[HistoryMessage]) {
+    if config.hooks.is_empty() {
+        return;
+    }
+    let own_user_id = chat_state.own_user_id();
+    for message in messages.iter().filter(|message| message.user_id != own_user_id) {
+        let mut payload = HookPayload::new(HookEvent::Message, chat_state.server_address.to_string());
+        payload.channel_id = Some(message.channel_id);
+        payload.channel = chat_state
+            .channels
+            .iter()
+            .find(|channel| channel.id == message.channel_id)
+            .map(|channel| channel.name.clone());
+        payload.message_id = Some(message.message_id);
+        payload.author_id = Some(message.user_id);
+        payload.author = chat_state
+            .users
+            .iter()
+            .find(|user| user.id == message.user_id)
+            .map(|user| user.name.clone());
+        payload.text = Some(message.message_text.clone());
+        payload.timestamp = Some(message.sent_timestamp as i64);
+        run_hooks(&config.hooks, payload.clone());
+
+        if is_highlight(&message.message_text, &chat_state.current_user.username, &config.highlight_words) {
+            payload.event = HookEvent::Mention;
+            run_hooks(&config.hooks, payload);
+        }
+    }
+}
+
+fn insert_history(chat_state: &mut ChatState, messages: Vec<HistoryMessage>) -> Result<()> {
+    for message in messages {
+        let author_name = chat_state
+            .users
+            .iter()
+            .find(|user| user.id == message.user_id)
+            .map(|user| user.name.clone())
+            .unwrap_or_else(|| "Unknown".to_string());
+
+        let timestamp = chat_state.clock_skew.local_time(message.sent_timestamp)?;
+
+        let display_message = ChatMessage {
+            message_id: message.message_id,
+            reply_id: message.reply_id,
+            author_name,
+            author_id: message.user_id,
+            timestamp,
+            message: message.message_text,
+            status: ChatMessageStatus::Send,
+            kind: ChatMessageKind::User,
+            attachments: message.media_ids.into_iter().map(Attachment::new).collect(),
+            edited: false,
+        };
+
+        let channel_id = message.channel_id;
+        // TODO figure out what to do when we get message from channels we dont know the name off
+        let display_messages = chat_state.chat_history.entry(channel_id).or_default();
+
+        if let Some(existing) = display_messages.iter_mut().find(|m| m.message_id == display_message.message_id) {
+            // Cached messages may have been edited in the meantime
+            if existing.message != display_message.message {
+                existing.message = display_message.message;
+                existing.edited = true;
+                chat_state.history_cache_dirty = true;
+                chat_state.unread_counts.remove(&channel_id);
+            }
+        } else {
+            chat_state.history_cache_dirty = true;
+            display_messages.push(display_message);
+            // Pages of older messages arrive after newer ones
+            if !display_messages.is_sorted_by_key(|m| m.timestamp) {
+                display_messages.sort_by_key(|m| m.timestamp);
+            }
         }
-        _ => {}
     }
     Ok(())
 }
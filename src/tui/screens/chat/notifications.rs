@@ -0,0 +1,39 @@
+use log::warn;
+
+/// Longest message text shown in a notification, the rest is cut off
+const MAX_BODY_CHARS: usize = 200;
+
+/// Shows a system notification, without waiting for the notification daemon to answer
+pub fn notify(summary: String, body: &str) {
+    let body = match body.char_indices().nth(MAX_BODY_CHARS) {
+        Some((end, _)) => format!("{}…", &body[..end]),
+        None => body.to_owned(),
+    };
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = backend::show(&summary, &body) {
+            warn!("Failed to show a notification: {e}");
+        }
+    });
+}
+
+#[cfg(feature = "notifications")]
+mod backend {
+    use anyhow::Result;
+    use notify_rust::Notification;
+
+    pub fn show(summary: &str, body: &str) -> Result<()> {
+        Notification::new().appname("chatger").summary(summary).body(body).show()?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "notifications"))]
+mod backend {
+    use anyhow::Result;
+    use log::debug;
+
+    pub fn show(summary: &str, _body: &str) -> Result<()> {
+        debug!("Not showing notification \"{summary}\", this build has no notification support");
+        Ok(())
+    }
+}
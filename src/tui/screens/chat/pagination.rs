@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::debug;
+use tokio::time::Instant;
+
+use crate::network::client::Client;
+use crate::network::protocol::server::HistoryMessage;
+use crate::tui::events::{ChannelId, MessageId};
+
+/// A page that got no response in time is requested again on the next scroll
+const PAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+struct PendingPage {
+    channel_id: ChannelId,
+    /// Oldest message known when the page was requested, the page ends right before it
+    anchor: MessageId,
+    requested_at: Instant,
+}
+
+/// Loads older history a page at a time while scrolling towards the top of a channel.
+/// Only one page is in flight at a time, so scrolling quickly does not flood the server.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryPagination {
+    pending: Option<PendingPage>,
+    /// Channels whose oldest message is loaded, nothing is requested for them anymore
+    exhausted: HashSet<ChannelId>,
+}
+
+impl HistoryPagination {
+    pub fn is_loading(&self, channel_id: ChannelId) -> bool {
+        self.pending
+            .as_ref()
+            .is_some_and(|pending| pending.channel_id == channel_id && pending.requested_at.elapsed() < PAGE_TIMEOUT)
+    }
+
+    pub fn in_flight(&self) -> bool {
+        self.pending.as_ref().is_some_and(|pending| pending.requested_at.elapsed() < PAGE_TIMEOUT)
+    }
+
+    pub fn is_exhausted(&self, channel_id: ChannelId) -> bool {
+        self.exhausted.contains(&channel_id)
+    }
+
+    /// Requests the page before `oldest`, unless one is already on its way or the channel has no older messages
+    pub async fn request_older(&mut self, client: &mut Client, channel_id: ChannelId, oldest: MessageId, page_size: i8) -> Result<()> {
+        if self.in_flight() || self.is_exhausted(channel_id) {
+            return Ok(());
+        }
+        debug!("Requesting {page_size} messages before {oldest} in channel {channel_id}");
+        client.request_history_by_message(channel_id, oldest, page_size).await?;
+        self.pending = Some(PendingPage {
+            channel_id,
+            anchor: oldest,
+            requested_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// History responses do not say what they answer, a response that is empty or only holds messages
+    /// of the channel a page was requested for is taken as that page
+    pub fn history_received(&mut self, messages: &[HistoryMessage]) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        if messages.iter().any(|message| message.channel_id != pending.channel_id) {
+            return;
+        }
+        if messages.iter().all(|message| message.message_id == pending.anchor) {
+            debug!("Reached the start of the history of channel {}", pending.channel_id);
+            self.exhausted.insert(pending.channel_id);
+        }
+        self.pending = None;
+    }
+}
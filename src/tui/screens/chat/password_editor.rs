@@ -0,0 +1,96 @@
+use anyhow::{Result, anyhow};
+
+use crate::network::protocol::client::UserConfigField;
+use crate::storage::secrets::Secret;
+
+/// Longest password the login packet takes
+const MAX_PASSWORD_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasswordField {
+    Current,
+    New,
+    Confirm,
+}
+
+/// Inputs of the change password popup, the new password is typed twice as it is never shown
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordEditor {
+    pub current: Secret,
+    pub new: Secret,
+    pub confirm: Secret,
+    pub field: PasswordField,
+}
+
+impl PasswordEditor {
+    pub fn new() -> Self {
+        PasswordEditor {
+            current: Secret::default(),
+            new: Secret::default(),
+            confirm: Secret::default(),
+            field: PasswordField::Current,
+        }
+    }
+
+    fn input(&mut self) -> &mut Secret {
+        match self.field {
+            PasswordField::Current => &mut self.current,
+            PasswordField::New => &mut self.new,
+            PasswordField::Confirm => &mut self.confirm,
+        }
+    }
+
+    /// NUL separates the username from the password in the login packet, a password holding it could never log in
+    pub fn push(&mut self, chr: char) {
+        if chr == '\0' {
+            return;
+        }
+        self.input().expose_mut().push(chr);
+    }
+
+    pub fn pop(&mut self) {
+        self.input().expose_mut().pop();
+    }
+
+    pub fn next_field(&mut self) {
+        self.field = match self.field {
+            PasswordField::Current => PasswordField::New,
+            PasswordField::New => PasswordField::Confirm,
+            PasswordField::Confirm => PasswordField::Current,
+        };
+    }
+
+    pub fn previous_field(&mut self) {
+        self.field = match self.field {
+            PasswordField::Current => PasswordField::Confirm,
+            PasswordField::New => PasswordField::Current,
+            PasswordField::Confirm => PasswordField::New,
+        };
+    }
+
+    /// The field to send, the server checks the current password itself
+    pub fn change(&self) -> Result<UserConfigField> {
+        if self.current.expose().is_empty() {
+            return Err(anyhow!("Type your current password"));
+        }
+        if self.new.expose().is_empty() {
+            return Err(anyhow!("The new password is empty"));
+        }
+        if self.new != self.confirm {
+            return Err(anyhow!("The new passwords do not match"));
+        }
+        if self.new == self.current {
+            return Err(anyhow!("The new password is the same as the current one"));
+        }
+        if self.new.expose().contains('\0') {
+            return Err(anyhow!("Passwords can not contain NUL characters"));
+        }
+        if self.current.expose().len() > MAX_PASSWORD_LENGTH || self.new.expose().len() > MAX_PASSWORD_LENGTH {
+            return Err(anyhow!("Passwords can be at most {MAX_PASSWORD_LENGTH} bytes long"));
+        }
+        Ok(UserConfigField::Password {
+            current: self.current.clone(),
+            new: self.new.clone(),
+        })
+    }
+}
@@ -0,0 +1,39 @@
+use crate::storage::preferences::PinnedMessage;
+use crate::tui::events::ChannelId;
+
+/// Pinned messages of the open channel, listed with `Alt + P` or `/pins`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Pins {
+    /// Oldest message first, like the history
+    pub entries: Vec<PinnedMessage>,
+    pub selected: usize,
+}
+
+impl Pins {
+    pub fn new(channel_id: ChannelId, pinned_messages: &[PinnedMessage]) -> Self {
+        let mut entries: Vec<PinnedMessage> = pinned_messages.iter().filter(|pin| pin.channel_id == channel_id).cloned().collect();
+        entries.sort_by_key(|pin| pin.message_id);
+        Pins { entries, selected: 0 }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn selected_pin(&self) -> Option<&PinnedMessage> {
+        self.entries.get(self.selected)
+    }
+
+    pub fn remove_selected(&mut self) {
+        if self.selected < self.entries.len() {
+            self.entries.remove(self.selected);
+        }
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+    }
+}
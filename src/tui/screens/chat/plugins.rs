@@ -0,0 +1,326 @@
+use std::fs;
+use std::path::PathBuf;
+
+use log::warn;
+
+use crate::storage::config_file;
+use crate::tui::events::{ChannelId, MessageId};
+
+pub use backend::Plugins;
+
+const PLUGIN_DIR: &str = "plugins";
+const PLUGIN_EXTENSION: &str = "rhai";
+
+/// What plugins can read about the session, taken right before a plugin function is called
+#[derive(Debug, Clone, Default)]
+pub struct PluginContext {
+    pub server: String,
+    pub username: String,
+    /// Name of the open channel
+    pub channel: String,
+    pub channels: Vec<String>,
+    /// Names of the users of the server
+    pub users: Vec<String>,
+}
+
+/// Message passed to the `on_incoming` and `on_outgoing` functions of plugins
+#[derive(Debug, Clone)]
+pub struct PluginMessage {
+    pub channel_id: ChannelId,
+    pub channel: String,
+    pub message_id: MessageId,
+    pub reply_id: MessageId,
+    pub author: String,
+    pub text: String,
+    /// Arrived or sent just now, instead of loaded with older history
+    pub live: bool,
+}
+
+/// Asked for by a plugin, carried out by the chat once the plugin function returned
+#[derive(Debug, Clone, PartialEq)]
+pub enum PluginAction {
+    Toast(String),
+    /// Sends a message to the open channel, without passing it through the `on_outgoing` functions
+    Send(String),
+}
+
+/// Scripts in the plugins directory next to the config file, in the order of their file names
+fn plugin_files() -> Vec<PathBuf> {
+    let dir = match config_file(PLUGIN_DIR) {
+        Ok(dir) => dir,
+        Err(e) => {
+            warn!("Not loading plugins: {e}");
+            return Vec::new();
+        }
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == PLUGIN_EXTENSION))
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(feature = "plugins")]
+mod backend {
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::{Result, anyhow};
+    use log::{debug, info, warn};
+    use rhai::{AST, Array, CallFnOptions, Dynamic, Engine, Map, Scope};
+
+    use super::{PluginAction, PluginContext, PluginMessage, plugin_files};
+    use crate::cli::AppConfig;
+
+    /// Operations a single call may take before it is stopped, so an endless loop in a plugin does not freeze the client
+    const MAX_OPERATIONS: u64 = 1_000_000;
+
+    /// State shared with the functions plugins call into
+    #[derive(Default)]
+    struct Shared {
+        context: PluginContext,
+        actions: Vec<PluginAction>,
+        /// Plugin whose top level is running, commands it registers belong to it
+        loading: usize,
+        /// Command names mapped to the plugin and the function that runs them
+        commands: Vec<(String, usize, String)>,
+    }
+
+    struct Plugin {
+        name: String,
+        ast: AST,
+        /// Map bound to `this` in every call, functions can not see the variables of the top level so this is where plugins keep state
+        state: Mutex<Dynamic>,
+    }
+
+    impl Plugin {
+        fn defines(&self, function: &str) -> bool {
+            self.ast.iter_functions().any(|f| f.name == function)
+        }
+    }
+
+    pub struct Plugins {
+        engine: Engine,
+        plugins: Vec<Plugin>,
+        shared: Arc<Mutex<Shared>>,
+    }
+
+    impl Plugins {
+        /// Compiles every script and runs its top level, a script that fails is skipped. Nothing is loaded in safe mode
+        pub fn load(config: &AppConfig) -> Plugins {
+            let shared = Arc::new(Mutex::new(Shared::default()));
+            let mut plugins = Plugins {
+                engine: engine(&shared),
+                plugins: Vec::new(),
+                shared,
+            };
+            if config.safe_mode {
+                return plugins;
+            }
+            for path in plugin_files() {
+                match plugins.load_plugin(&path) {
+                    Ok(plugin) => {
+                        info!("Loaded plugin {}", plugin.name);
+                        plugins.plugins.push(plugin);
+                    }
+                    Err(e) => warn!("Failed to load plugin {}: {e:#}", path.display()),
+                }
+            }
+            plugins
+        }
+
+        fn load_plugin(&self, path: &Path) -> Result<Plugin> {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            let ast = self.engine.compile_file(path.to_owned()).map_err(|e| anyhow!("{e}"))?;
+            let mut scope = Scope::new();
+            let mut shared = self.shared.lock().expect("plugin state poisoned");
+            shared.loading = self.plugins.len();
+            let registered = shared.commands.len();
+            drop(shared);
+            if let Err(e) = self.engine.run_ast_with_scope(&mut scope, &ast) {
+                self.shared.lock().expect("plugin state poisoned").commands.truncate(registered);
+                return Err(anyhow!("{e}"));
+            }
+            Ok(Plugin {
+                name,
+                ast,
+                state: Mutex::new(Map::new().into()),
+            })
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.plugins.is_empty()
+        }
+
+        /// Runs a command registered with `register_command`, a returned string is shown as a toast
+        pub fn run_command(&self, context: PluginContext, name: &str, args: &str) -> Result<Vec<PluginAction>> {
+            let command = self
+                .shared
+                .lock()
+                .expect("plugin state poisoned")
+                .commands
+                .iter()
+                .find(|(command, _, _)| command == name)
+                .map(|(_, plugin, function)| (*plugin, function.clone()));
+            let Some((plugin, function)) = command else {
+                return Err(anyhow!("Unknown command /{name}"));
+            };
+            let result = self.call(context, plugin, &function, (args.to_owned(),))?;
+            if let Ok(text) = result.into_string() {
+                self.shared.lock().expect("plugin state poisoned").actions.push(PluginAction::Toast(text));
+            }
+            Ok(self.take_actions())
+        }
+
+        /// Passes a message that arrived through the `on_incoming` function of every plugin.
+        /// Returns the text to show, or `None` when a plugin dropped the message
+        pub fn incoming(&self, context: &PluginContext, message: PluginMessage) -> (Option<String>, Vec<PluginAction>) {
+            let text = self.filter("on_incoming", context, message);
+            (text, self.take_actions())
+        }
+
+        /// Passes a message about to be sent through the `on_outgoing` function of every plugin.
+        /// Returns the text to send, or `None` when a plugin stopped the message
+        pub fn outgoing(&self, context: &PluginContext, message: PluginMessage) -> (Option<String>, Vec<PluginAction>) {
+            let text = self.filter("on_outgoing", context, message);
+            (text, self.take_actions())
+        }
+
+        /// A plugin returning a string replaces the text, `false` drops the message and anything else leaves it alone.
+        /// A failing plugin leaves the message alone, so a broken plugin can not eat messages
+        fn filter(&self, function: &str, context: &PluginContext, mut message: PluginMessage) -> Option<String> {
+            for (idx, plugin) in self.plugins.iter().enumerate().filter(|(_, plugin)| plugin.defines(function)) {
+                match self.call(context.clone(), idx, function, (message_map(&message),)) {
+                    Ok(result) if result.as_bool() == Ok(false) => {
+                        debug!("Plugin {} dropped message {}", plugin.name, message.message_id);
+                        return None;
+                    }
+                    Ok(result) => {
+                        if let Ok(text) = result.into_string() {
+                            message.text = text;
+                        }
+                    }
+                    Err(e) => warn!("{e:#}"),
+                }
+            }
+            Some(message.text)
+        }
+
+        fn call(&self, context: PluginContext, idx: usize, function: &str, args: impl rhai::FuncArgs) -> Result<Dynamic> {
+            let plugin = &self.plugins[idx];
+            self.shared.lock().expect("plugin state poisoned").context = context;
+            let mut state = plugin.state.lock().expect("plugin state poisoned");
+            let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut state);
+            self.engine
+                .call_fn_with_options(options, &mut Scope::new(), &plugin.ast, function, args)
+                .map_err(|e| anyhow!("Plugin {} failed in {function}: {e}", plugin.name))
+        }
+
+        fn take_actions(&self) -> Vec<PluginAction> {
+            std::mem::take(&mut self.shared.lock().expect("plugin state poisoned").actions)
+        }
+    }
+
+    fn message_map(message: &PluginMessage) -> Map {
+        let mut map = Map::new();
+        map.insert("channel_id".into(), (message.channel_id as i64).into());
+        map.insert("channel".into(), message.channel.clone().into());
+        map.insert("message_id".into(), (message.message_id as i64).into());
+        map.insert("reply_id".into(), (message.reply_id as i64).into());
+        map.insert("author".into(), message.author.clone().into());
+        map.insert("text".into(), message.text.clone().into());
+        map.insert("live".into(), message.live.into());
+        map
+    }
+
+    /// The functions plugins can call, printing goes to the logs as stdout is the terminal the chat is drawn on
+    fn engine(shared: &Arc<Mutex<Shared>>) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!("Plugin: {text}"));
+        engine.on_debug(|text, _, position| debug!("Plugin {position}: {text}"));
+
+        let state = shared.clone();
+        engine.register_fn("register_command", move |name: &str, function: &str| {
+            let mut state = state.lock().expect("plugin state poisoned");
+            let plugin = state.loading;
+            let name = name.trim_start_matches('/').to_owned();
+            state.commands.retain(|(command, _, _)| *command != name);
+            state.commands.push((name, plugin, function.to_owned()));
+        });
+        let state = shared.clone();
+        engine.register_fn("toast", move |text: &str| {
+            state
+                .lock()
+                .expect("plugin state poisoned")
+                .actions
+                .push(PluginAction::Toast(text.to_owned()));
+        });
+        let state = shared.clone();
+        engine.register_fn("send", move |text: &str| {
+            state
+                .lock()
+                .expect("plugin state poisoned")
+                .actions
+                .push(PluginAction::Send(text.to_owned()));
+        });
+
+        let state = shared.clone();
+        engine.register_fn("server", move || state.lock().expect("plugin state poisoned").context.server.clone());
+        let state = shared.clone();
+        engine.register_fn("username", move || state.lock().expect("plugin state poisoned").context.username.clone());
+        let state = shared.clone();
+        engine.register_fn("channel", move || state.lock().expect("plugin state poisoned").context.channel.clone());
+        let state = shared.clone();
+        engine.register_fn("channels", move || -> Array {
+            let state = state.lock().expect("plugin state poisoned");
+            state.context.channels.iter().cloned().map(Dynamic::from).collect()
+        });
+        let state = shared.clone();
+        engine.register_fn("users", move || -> Array {
+            let state = state.lock().expect("plugin state poisoned");
+            state.context.users.iter().cloned().map(Dynamic::from).collect()
+        });
+        engine
+    }
+}
+
+#[cfg(not(feature = "plugins"))]
+mod backend {
+    use anyhow::{Result, anyhow};
+    use log::warn;
+
+    use super::{PluginAction, PluginContext, PluginMessage, plugin_files};
+    use crate::cli::AppConfig;
+
+    pub struct Plugins;
+
+    impl Plugins {
+        pub fn load(config: &AppConfig) -> Plugins {
+            if !config.safe_mode && !plugin_files().is_empty() {
+                warn!("Not loading plugins, this build has no plugin support");
+            }
+            Plugins
+        }
+
+        pub fn is_empty(&self) -> bool {
+            true
+        }
+
+        pub fn run_command(&self, _context: PluginContext, name: &str, _args: &str) -> Result<Vec<PluginAction>> {
+            Err(anyhow!("Unknown command /{name}"))
+        }
+
+        pub fn incoming(&self, _context: &PluginContext, message: PluginMessage) -> (Option<String>, Vec<PluginAction>) {
+            (Some(message.text), Vec::new())
+        }
+
+        pub fn outgoing(&self, _context: &PluginContext, message: PluginMessage) -> (Option<String>, Vec<PluginAction>) {
+            (Some(message.text), Vec::new())
+        }
+    }
+}
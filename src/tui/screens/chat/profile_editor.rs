@@ -0,0 +1,121 @@
+use std::fs;
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::media::{MAX_MEDIA_SIZE, format_size};
+use crate::network::client::UploadProgress;
+use crate::network::protocol::client::UserConfigField;
+use crate::tui::chat::User;
+
+/// Longest bio the protocol can carry, the value length is a 2 byte field
+const MAX_BIO_LENGTH: usize = u16::MAX as usize;
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProfileField {
+    Name,
+    Bio,
+    Picture,
+}
+
+/// Inputs of the edit profile popup, filled with the current profile of the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileEditor {
+    pub name: String,
+    pub bio: String,
+    /// Path of a PNG to use as profile picture, empty keeps the current picture
+    pub picture: String,
+    pub field: ProfileField,
+}
+
+impl ProfileEditor {
+    pub fn new(username: &str, user: Option<&User>) -> Self {
+        ProfileEditor {
+            name: username.to_owned(),
+            bio: user.map(|user| user.bio.clone()).unwrap_or_default(),
+            picture: String::new(),
+            field: ProfileField::Name,
+        }
+    }
+
+    fn input(&mut self) -> &mut String {
+        match self.field {
+            ProfileField::Name => &mut self.name,
+            ProfileField::Bio => &mut self.bio,
+            ProfileField::Picture => &mut self.picture,
+        }
+    }
+
+    pub fn push(&mut self, chr: char) {
+        self.input().push(chr);
+    }
+
+    pub fn pop(&mut self) {
+        self.input().pop();
+    }
+
+    pub fn next_field(&mut self) {
+        self.field = match self.field {
+            ProfileField::Name => ProfileField::Bio,
+            ProfileField::Bio => ProfileField::Picture,
+            ProfileField::Picture => ProfileField::Name,
+        };
+    }
+
+    pub fn previous_field(&mut self) {
+        self.field = match self.field {
+            ProfileField::Name => ProfileField::Picture,
+            ProfileField::Bio => ProfileField::Name,
+            ProfileField::Picture => ProfileField::Bio,
+        };
+    }
+
+    /// The fields that differ from the current profile, the picture is read separately as it has to be uploaded first
+    pub fn changes(&self, username: &str, user: Option<&User>) -> Result<Vec<UserConfigField>> {
+        let name = self.name.trim();
+        if !(3..=128).contains(&name.len()) {
+            return Err(anyhow!("Names have to be between 3 and 128 bytes long"));
+        }
+        if self.bio.len() > MAX_BIO_LENGTH {
+            return Err(anyhow!("The bio is longer than {MAX_BIO_LENGTH} bytes"));
+        }
+
+        let mut fields = vec![];
+        if name != username {
+            fields.push(UserConfigField::Username(name.to_owned()));
+        }
+        if user.is_none_or(|user| user.bio != self.bio) {
+            fields.push(UserConfigField::Bio(self.bio.clone()));
+        }
+        Ok(fields)
+    }
+
+    /// Reads the picked profile picture, `None` when no picture was picked
+    pub fn read_picture(&self) -> Result<Option<(String, Vec<u8>)>> {
+        let path = self.picture.trim();
+        if path.is_empty() {
+            return Ok(None);
+        }
+        let data = fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+        // The server only accepts PNG images
+        if !data.starts_with(PNG_SIGNATURE) {
+            return Err(anyhow!("Profile pictures have to be PNG images"));
+        }
+        if data.len() > MAX_MEDIA_SIZE {
+            return Err(anyhow!(
+                "The picture is {}, the upload limit is {}",
+                format_size(data.len()),
+                format_size(MAX_MEDIA_SIZE)
+            ));
+        }
+        let filename = path.rsplit(['/', '\\']).next().unwrap_or(path).to_owned();
+        Ok(Some((filename, data)))
+    }
+}
+
+/// Profile changes that wait for the server, a new picture is uploaded before the changes are sent
+#[derive(Debug, Clone)]
+pub struct ProfileUpdate {
+    pub fields: Vec<UserConfigField>,
+    pub picture_upload: Option<UploadProgress>,
+}
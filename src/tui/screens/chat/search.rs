@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::tui::chat::{ChatMessage, ChatMessageKind, DisplayChannel};
+use crate::tui::events::{ChannelId, MessageId};
+
+/// Results beyond this are not listed, the query should be narrowed down instead
+pub const MAX_SEARCH_RESULTS: usize = 200;
+/// Pages of older history loaded for a search without matches before giving up
+pub const MAX_BACKFILL_PAGES: usize = 20;
+/// Time between the pages of a backfill, so a search does not hammer the server
+pub const BACKFILL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub channel_idx: usize,
+    pub message_id: MessageId,
+}
+
+/// Query and results of the search popup, only the history that was loaded is searched
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Search {
+    pub query: String,
+    pub results: Vec<SearchResult>,
+    pub selected: usize,
+    /// Set while older history is loaded to find a match
+    pub backfill: Option<Backfill>,
+    /// Why the last backfill stopped without a match
+    pub backfill_outcome: Option<String>,
+}
+
+/// Progress of loading older history for a search, a page at a time and round robin over the channels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backfill {
+    pub pages: usize,
+    pub last_request: Option<Instant>,
+}
+
+impl Search {
+    pub fn new(query: String, channels: &[DisplayChannel], chat_history: &HashMap<ChannelId, Vec<ChatMessage>>) -> Self {
+        let mut search = Search { query, ..Default::default() };
+        search.update(channels, chat_history);
+        search
+    }
+
+    /// Searches every channel again, results are grouped by channel in sidebar order and newest first within a channel
+    pub fn update(&mut self, channels: &[DisplayChannel], chat_history: &HashMap<ChannelId, Vec<ChatMessage>>) {
+        self.selected = 0;
+        self.results.clear();
+        let query = self.query.trim().to_lowercase();
+        if query.is_empty() {
+            return;
+        }
+
+        for (channel_idx, channel) in channels.iter().enumerate() {
+            let Some(messages) = chat_history.get(&channel.id) else {
+                continue;
+            };
+            let matches = messages
+                .iter()
+                .rev()
+                .filter(|message| message.kind == ChatMessageKind::User && message.message.to_lowercase().contains(&query))
+                .map(|message| SearchResult {
+                    channel_idx,
+                    message_id: message.message_id,
+                });
+            self.results.extend(matches);
+        }
+        self.results.truncate(MAX_SEARCH_RESULTS);
+    }
+
+    /// Starts loading older history, only when the query has no matches in the loaded history
+    pub fn start_backfill(&mut self) {
+        if self.query.trim().is_empty() || !self.results.is_empty() || self.backfill.is_some() {
+            return;
+        }
+        self.backfill_outcome = None;
+        self.backfill = Some(Backfill {
+            pages: 0,
+            last_request: None,
+        });
+    }
+
+    pub fn stop_backfill(&mut self, outcome: impl Into<String>) {
+        self.backfill = None;
+        self.backfill_outcome = Some(outcome.into());
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.results.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn selected_result(&self) -> Option<&SearchResult> {
+        self.results.get(self.selected)
+    }
+}
@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use log::{debug, warn};
+use tokio::time::Instant;
+
+use crate::network::client::Client;
+use crate::tui::events::{ChannelId, UserId};
+
+pub const STARTUP_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+const STARTUP_MAX_RETRIES: u32 = 3;
+
+/// The steps of the handshake performed after logging in, in the order they are executed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartupStep {
+    ChannelIds,
+    Channels,
+    UserStatuses,
+    Users,
+    History { received: usize, total: usize },
+    Done,
+}
+
+/// Requests the initial server state one step at a time, so that e.g. user names are known
+/// before the history referencing them arrives. Each step is retried when no response arrives in time.
+#[derive(Debug, Clone)]
+pub struct StartupSequence {
+    pub step: StartupStep,
+    step_started: Instant,
+    retries: u32,
+    channel_ids: Vec<ChannelId>,
+    user_ids: Vec<UserId>,
+    pending_history: Vec<ChannelId>,
+    history_length: i8,
+}
+
+impl StartupSequence {
+    pub fn new(history_length: i8) -> Self {
+        StartupSequence {
+            step: StartupStep::ChannelIds,
+            step_started: Instant::now(),
+            retries: 0,
+            channel_ids: vec![],
+            user_ids: vec![],
+            pending_history: vec![],
+            history_length,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.step == StartupStep::Done
+    }
+
+    pub fn timed_out(&self) -> bool {
+        !self.is_done() && self.step_started.elapsed() > STARTUP_STEP_TIMEOUT
+    }
+
+    pub fn reset_timeout(&mut self) {
+        self.step_started = Instant::now();
+    }
+
+    /// Sends the request belonging to the current step
+    pub async fn request_current_step(&mut self, client: &mut Client) -> Result<()> {
+        self.step_started = Instant::now();
+        debug!("Startup step {:?}", self.step);
+        match &self.step {
+            StartupStep::ChannelIds => client.request_channel_ids().await,
+            StartupStep::Channels => client.request_channels(self.channel_ids.clone()).await,
+            StartupStep::UserStatuses => client.request_user_statuses().await,
+            StartupStep::Users => client.request_users(self.user_ids.clone()).await,
+            StartupStep::History { .. } => {
+                for channel_id in &self.pending_history {
+                    client.request_history_by_timestamp(*channel_id, Utc::now(), self.history_length).await?;
+                }
+                Ok(())
+            }
+            StartupStep::Done => Ok(()),
+        }
+    }
+
+    async fn advance(&mut self, step: StartupStep, client: &mut Client) -> Result<()> {
+        self.step = step;
+        self.retries = 0;
+        self.request_current_step(client).await
+    }
+
+    pub async fn channel_ids_received(&mut self, channel_ids: Vec<ChannelId>, client: &mut Client) -> Result<()> {
+        if self.step != StartupStep::ChannelIds {
+            return Ok(());
+        }
+        if channel_ids.is_empty() {
+            self.advance(StartupStep::UserStatuses, client).await
+        } else {
+            self.channel_ids = channel_ids;
+            self.advance(StartupStep::Channels, client).await
+        }
+    }
+
+    pub async fn channels_received(&mut self, client: &mut Client) -> Result<()> {
+        if self.step != StartupStep::Channels {
+            return Ok(());
+        }
+        self.advance(StartupStep::UserStatuses, client).await
+    }
+
+    pub async fn statuses_received(&mut self, unknown_user_ids: Vec<UserId>, client: &mut Client) -> Result<()> {
+        if self.step != StartupStep::UserStatuses {
+            return Ok(());
+        }
+        if unknown_user_ids.is_empty() {
+            self.start_history(client).await
+        } else {
+            self.user_ids = unknown_user_ids;
+            self.advance(StartupStep::Users, client).await
+        }
+    }
+
+    pub async fn users_received(&mut self, client: &mut Client) -> Result<()> {
+        if self.step != StartupStep::Users {
+            return Ok(());
+        }
+        self.start_history(client).await
+    }
+
+    /// Counts the channels whose history is in, a packet can hold several channels or repeat one that was already loaded.
+    /// An empty history does not name its channel, it is taken to answer the oldest request as the server replies in order
+    pub fn history_received(&mut self, channel_ids: &[ChannelId]) {
+        if let StartupStep::History { received, total } = &mut self.step {
+            if channel_ids.is_empty() {
+                if !self.pending_history.is_empty() {
+                    self.pending_history.remove(0);
+                }
+            } else {
+                self.pending_history.retain(|channel_id| !channel_ids.contains(channel_id));
+            }
+            *received = *total - self.pending_history.len();
+            if self.pending_history.is_empty() {
+                self.step = StartupStep::Done;
+                debug!("Startup sequence finished");
+            }
+        }
+    }
+
+    async fn start_history(&mut self, client: &mut Client) -> Result<()> {
+        if self.channel_ids.is_empty() {
+            self.step = StartupStep::Done;
+            return Ok(());
+        }
+        self.pending_history = self.channel_ids.clone();
+        let total = self.pending_history.len();
+        self.advance(StartupStep::History { received: 0, total }, client).await
+    }
+
+    /// Re-sends the request of the current step, or skips the step once it ran out of retries
+    pub async fn retry(&mut self, client: &mut Client) -> Result<()> {
+        if self.retries < STARTUP_MAX_RETRIES {
+            self.retries += 1;
+            warn!(
+                "No response during startup step {:?}, retrying ({}/{STARTUP_MAX_RETRIES})",
+                self.step, self.retries
+            );
+            return self.request_current_step(client).await;
+        }
+
+        warn!("Giving up on startup step {:?}", self.step);
+        match self.step {
+            StartupStep::ChannelIds | StartupStep::Channels => self.advance(StartupStep::UserStatuses, client).await,
+            StartupStep::UserStatuses | StartupStep::Users => self.start_history(client).await,
+            StartupStep::History { .. } | StartupStep::Done => {
+                self.step = StartupStep::Done;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn progress_label(&self) -> Option<String> {
+        match &self.step {
+            StartupStep::ChannelIds => Some("Loading channel list…".to_owned()),
+            StartupStep::Channels => Some(format!("Loading channels… {}", self.channel_ids.len())),
+            StartupStep::UserStatuses => Some("Loading user statuses…".to_owned()),
+            StartupStep::Users => Some(format!("Loading users… {}", self.user_ids.len())),
+            StartupStep::History { received, total } => Some(format!("Loading history… {received}/{total}")),
+            StartupStep::Done => None,
+        }
+    }
+}
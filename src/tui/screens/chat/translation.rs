@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::process::Stdio;
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+const DEFAULT_LANGUAGE: &str = "en";
+
+/// `[translation]` table of the config file
+///
+/// ```toml
+/// [translation]
+/// command = "trans -brief :{language}"
+/// language = "en"
+///
+/// [translation.channels]
+/// nederlands = "nl"
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TranslationConfig {
+    /// Shell command that reads the message from stdin and writes the translation to stdout, `{language}` is replaced by the target language
+    pub command: String,
+    /// Language messages are translated to [default: en]
+    pub language: Option<String>,
+    /// Channel names mapped to the language messages in that channel are translated to
+    #[serde(default)]
+    pub channels: BTreeMap<String, String>,
+}
+
+impl TranslationConfig {
+    pub fn language_for(&self, channel_name: &str) -> &str {
+        self.channels
+            .get(channel_name)
+            .or(self.language.as_ref())
+            .map_or(DEFAULT_LANGUAGE, String::as_str)
+    }
+}
+
+/// Translation shown under a message in the chat history
+#[derive(Debug, Clone, PartialEq)]
+pub enum Translation {
+    Pending,
+    Done(String),
+}
+
+/// Runs the translation command, the message is passed on stdin so it never has to be escaped for the shell
+pub async fn translate(config: &TranslationConfig, language: &str, text: &str) -> Result<String> {
+    let command = config.command.replace("{language}", language);
+    let mut child = shell(&command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Unable to run \"{command}\""))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("\"{command}\" failed with {}: {}", output.status, stderr.trim()));
+    }
+
+    let translation = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if translation.is_empty() {
+        return Err(anyhow!("\"{command}\" did not output a translation"));
+    }
+    Ok(translation)
+}
+
+/// Runs the command through the shell of the platform, so users can use pipes and variables
+#[cfg(windows)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
@@ -0,0 +1,81 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::tutorial::TutorialProgress;
+use crate::tui::screens::chat::ChatFocus;
+
+/// A hint bubble of the first-run tutorial, shown the first time its pane is focused
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum TutorialHint {
+    Channels,
+    History,
+    Selection,
+    Input,
+    Users,
+    Logs,
+}
+
+impl TutorialHint {
+    const ALL: [TutorialHint; 6] = [
+        TutorialHint::Channels,
+        TutorialHint::History,
+        TutorialHint::Selection,
+        TutorialHint::Input,
+        TutorialHint::Users,
+        TutorialHint::Logs,
+    ];
+
+    fn for_focus(focus: ChatFocus) -> Self {
+        match focus {
+            ChatFocus::Channels => TutorialHint::Channels,
+            ChatFocus::ChatHistory => TutorialHint::History,
+            ChatFocus::ChatHistorySelection => TutorialHint::Selection,
+            ChatFocus::ChatInput(_) => TutorialHint::Input,
+            ChatFocus::Users(_) => TutorialHint::Users,
+            ChatFocus::Logs => TutorialHint::Logs,
+        }
+    }
+
+    pub fn text(self) -> &'static str {
+        match self {
+            TutorialHint::Channels => "↑↓ switch channels, → or Enter moves to the chat. ←→ move between the panes",
+            TutorialHint::History => "↑↓ scroll the chat, S selects messages to reply to. Typing starts a message, L opens the logs",
+            TutorialHint::Selection => "↑↓ pick a message, R replies to it and E edits your own. Esc stops selecting",
+            TutorialHint::Input => "Enter sends, Ctrl + O attaches a file and / starts a command. Esc goes back to the chat",
+            TutorialHint::Users => "↑↓ pick a user, D opens a direct channel with them and V lists everyone",
+            TutorialHint::Logs => "The client logs what it does here, E shows repeated lines and L closes the logs",
+        }
+    }
+}
+
+/// Hint bubbles for new users, started when there was no config file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tutorial {
+    progress: TutorialProgress,
+}
+
+impl Tutorial {
+    /// `None` once every hint was dismissed. A tutorial that was started keeps going after a config file is written
+    pub fn new(first_run: bool) -> Option<Self> {
+        let progress = TutorialProgress::load();
+        let started = first_run || !progress.dismissed.is_empty();
+        (started && !progress.completed).then_some(Tutorial { progress })
+    }
+
+    /// The hint of the focused pane, while it was not dismissed
+    pub fn hint(&self, focus: ChatFocus) -> Option<TutorialHint> {
+        let hint = TutorialHint::for_focus(focus);
+        (!self.progress.dismissed.contains(&hint)).then_some(hint)
+    }
+
+    /// Never shows the hint again, returns whether that completed the tutorial
+    pub fn dismiss(&mut self, hint: TutorialHint) -> bool {
+        self.progress.dismissed.insert(hint);
+        self.progress.completed = TutorialHint::ALL.iter().all(|hint| self.progress.dismissed.contains(hint));
+        if let Err(e) = self.progress.save() {
+            warn!("Failed to remember the tutorial progress: {e}");
+        }
+        self.progress.completed
+    }
+}
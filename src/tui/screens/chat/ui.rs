@@ -1,54 +1,125 @@
 use std::collections::HashMap;
+use std::ops::Range;
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, TimeDelta, Utc};
 use ratatui::Frame;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span, Text};
-use ratatui::widgets::{Block, Borders, Padding, Paragraph, Wrap};
-
+use ratatui::widgets::{Block, Borders, Clear, Padding, Paragraph, Wrap};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::cli::HistoryLayout;
+use crate::media::clipboard::ClipboardImage;
+use crate::media::preview::{HalfBlockImage, ImagePlacement};
+use crate::media::voice::VoiceNote;
+use crate::media::{MAX_MEDIA_SIZE, format_size};
 use crate::network::client::ServerConnectionStatus;
-use crate::network::protocol::UserStatus;
-use crate::tui::chat::{ChannelStatus, ChatMessageStatus, User};
+use crate::network::protocol::{MediaType, UserStatus};
+use crate::storage::history_cache::CacheStats;
+use crate::storage::preferences::ChannelSortOrder;
+use crate::tui::chat::{ChannelStatus, ChatMessage, ChatMessageKind, ChatMessageStatus, DisplayChannel, MediaUpload, PendingAttachment, User};
+use crate::tui::events::{MediaId, UserId};
+use crate::tui::input::snap_to_grapheme;
+use crate::tui::logs::visible_log_lines;
 use crate::tui::screens::GlobalState;
+use crate::tui::screens::chat::accounts::AccountSwitcher;
 use crate::tui::screens::chat::borders::{
     borders_channel, borders_chat_history, borders_input, borders_logs, borders_profile, borders_reply_bar, borders_server_status, borders_users,
 };
-use crate::tui::screens::chat::{ChatFocus, ChatState};
-
-const HEADER_STYLE: Style = Style {
-    fg: None,
-    bg: None,
-    underline_color: None,
-    add_modifier: Modifier::BOLD,
-    sub_modifier: Modifier::empty(),
-};
-
+use crate::tui::screens::chat::changelog::Changelog;
+use crate::tui::screens::chat::channel_links::{MAX_CHANNEL_SUGGESTIONS, channel_suggestions, find_channel_links, partial_channel_link};
+use crate::tui::screens::chat::digest::{Digest, MAX_DIGEST_ENTRIES};
+use crate::tui::screens::chat::file_picker::{FilePicker, MAX_SUGGESTIONS};
+use crate::tui::screens::chat::lock::LockScreen;
+use crate::tui::screens::chat::long_messages::{EXPANDED_MESSAGE_WIDTH, ExpandedMessage, wrap_ranges};
+use crate::tui::screens::chat::password_editor::{PasswordEditor, PasswordField};
+use crate::tui::screens::chat::pins::Pins;
+use crate::tui::screens::chat::profile_editor::{ProfileEditor, ProfileField};
+use crate::tui::screens::chat::search::{MAX_BACKFILL_PAGES, MAX_SEARCH_RESULTS, Search};
+use crate::tui::screens::chat::translation::Translation;
+use crate::tui::screens::chat::tutorial::TutorialHint;
+use crate::tui::screens::chat::{ChatFocus, ChatState, Pane, Popup, SidebarRow, VOICE_NOTE_WAVEFORM_WIDTH};
+use crate::tui::screens::render_stats::RenderStats;
+use crate::tui::theme::Theme;
+
+const ACTIVITY_DAYS: usize = 28;
+const RECENTLY_ACTIVE: TimeDelta = TimeDelta::hours(1);
+const WHO_LIST_LENGTH: usize = 8;
+
+/// Shown next to channels where someone is typing
+const TYPING_GLYPH: &str = "…";
+const SEARCH_POPUP_HEIGHT: u16 = 24;
+/// Longest time between two messages of an author that are grouped under one header in compact mode
+const GROUP_WINDOW: TimeDelta = TimeDelta::minutes(5);
+/// Widest a tutorial hint bubble gets, narrow panes get narrower bubbles
+const TUTORIAL_HINT_WIDTH: u16 = 36;
 const PADDING: Padding = Padding::new(1, 1, 0, 0);
 
-pub fn draw_main(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame) {
+/// Returns the image previews that have to be drawn through a graphics protocol after the frame
+pub fn draw_main(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, stats: &mut RenderStats) -> Vec<ImagePlacement> {
     let main_area = frame.area();
-    let (app_area, info_area) = split_app_info_areas(global_state, main_area);
+    if let Some(lock_screen) = &chat_state.lock {
+        render_lock_screen(global_state, lock_screen, frame, main_area);
+        return vec![];
+    }
+    let hidden = global_state.hidden_panes;
+    let (app_area, info_area) = if hidden.hides(Pane::Info) {
+        (main_area, Rect::default())
+    } else {
+        split_app_info_areas(global_state, main_area)
+    };
     let (channels_area, chat_area, users_area) = split_channel_chat_user_areas(global_state, chat_state, app_area);
     let (users_area, server_status_area) = split_users_server_areas(global_state, chat_state, users_area);
     let (channels_area, profile_area) = split_channels_profile_areas(global_state, chat_state, channels_area);
     let (chat_history_area, reply_bar_area, chat_input_area) = split_chatlog_replybar_chatinput_areas(global_state, chat_state, chat_area);
 
-    let chat_history_area = if global_state.show_logs {
+    let (chat_history_area, logs_area) = if global_state.logs_shown() {
         let (chat_history_area, logs_area) = split_chat_log_areas(global_state, chat_state, chat_history_area);
-        render_logs(global_state, chat_state, frame, logs_area);
-        chat_history_area
+        stats.time("logs", || render_logs(global_state, chat_state, frame, logs_area));
+        (chat_history_area, logs_area)
     } else {
-        chat_history_area
+        (chat_history_area, Rect::default())
     };
 
-    render_channels(global_state, chat_state, frame, channels_area);
-    render_profile(global_state, chat_state, frame, profile_area);
-    render_chat_history(global_state, chat_state, frame, chat_history_area);
-    render_reply_bar(global_state, chat_state, frame, reply_bar_area);
-    render_chat_input(global_state, chat_state, frame, chat_input_area);
-    render_users(global_state, chat_state, frame, users_area);
-    render_server_status(global_state, chat_state, frame, server_status_area);
-    render_info(global_state, chat_state, frame, info_area);
+    if !hidden.hides(Pane::Channels) {
+        stats.time("channels", || render_channels(global_state, chat_state, frame, channels_area));
+        stats.time("profile", || render_profile(global_state, chat_state, frame, profile_area));
+    }
+    let start = Instant::now();
+    let placements = render_chat_history(global_state, chat_state, frame, chat_history_area, stats);
+    stats.record("chat history", start);
+    stats.time("reply bar", || render_reply_bar(global_state, chat_state, frame, reply_bar_area));
+    stats.time("input", || render_chat_input(global_state, chat_state, frame, chat_input_area));
+    if !hidden.hides(Pane::Users) {
+        stats.time("users", || render_users(global_state, chat_state, frame, users_area));
+        stats.time("server status", || {
+            render_server_status(global_state, chat_state, frame, server_status_area)
+        });
+    }
+    if !hidden.hides(Pane::Info) {
+        stats.time("info", || render_info(global_state, chat_state, frame, info_area));
+    }
+    stats.time("toasts", || render_toasts(global_state, chat_state, frame, chat_history_area));
+    if chat_state.popup.is_none()
+        && let Some(hint) = global_state.tutorial.as_ref().and_then(|tutorial| tutorial.hint(chat_state.focus))
+    {
+        // The input is too low for a bubble, its hint sits at the bottom of the chat history right above it
+        let pane_area = match chat_state.focus {
+            ChatFocus::Channels => channels_area,
+            ChatFocus::ChatHistory | ChatFocus::ChatHistorySelection | ChatFocus::ChatInput(_) => chat_history_area,
+            ChatFocus::Users(_) => users_area,
+            ChatFocus::Logs => logs_area,
+        };
+        render_tutorial_hint(global_state, hint, frame, pane_area);
+    }
+    let mut placements = placements;
+    if let Some(popup) = &chat_state.popup {
+        placements.extend(stats.time("popup", || render_popup(global_state, chat_state, popup, frame, main_area)));
+    }
+    placements
 }
 
 pub fn split_app_info_areas(_global_state: &GlobalState, area: Rect) -> (Rect, Rect) {
@@ -60,18 +131,25 @@ pub fn split_app_info_areas(_global_state: &GlobalState, area: Rect) -> (Rect, R
     (chunks[0], chunks[1])
 }
 
-fn split_channel_chat_user_areas(_global_state: &GlobalState, chat_state: &ChatState, area: Rect) -> (Rect, Rect, Rect) {
+fn split_channel_chat_user_areas(global_state: &GlobalState, chat_state: &ChatState, area: Rect) -> (Rect, Rect, Rect) {
     let channel_width_offset = if chat_state.focus == ChatFocus::Channels { 0 } else { 1 };
     let users_width_offset = if matches!(chat_state.focus, ChatFocus::Users(_)) { 1 } else { 0 };
+    // Hidden panes take no room, the chat column then draws the outer border itself
+    let channels_width = if global_state.hidden_panes.hides(Pane::Channels) {
+        0
+    } else {
+        30 - channel_width_offset
+    };
+    let users_width = if global_state.hidden_panes.hides(Pane::Users) {
+        0
+    } else {
+        30 + users_width_offset
+    };
 
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .margin(0)
-        .constraints([
-            Constraint::Length(30 - channel_width_offset),
-            Constraint::Fill(10),
-            Constraint::Length(30 + users_width_offset),
-        ])
+        .constraints([Constraint::Length(channels_width), Constraint::Fill(10), Constraint::Length(users_width)])
         .split(area);
     (chunks[0], chunks[1], chunks[2])
 }
@@ -101,8 +179,10 @@ fn split_chatlog_replybar_chatinput_areas(_global_state: &GlobalState, chat_stat
         } else {
             5
         };
-    let (history_height, reply_height) = if chat_state.replying_to.is_some() {
-        (area.height - input_height - 2, 2)
+    let bar_lines =
+        chat_state.reply_target().is_some() as u16 + chat_state.active_edit().is_some() as u16 + !chat_state.staged_attachments.is_empty() as u16;
+    let (history_height, reply_height) = if bar_lines > 0 {
+        (area.height - input_height - bar_lines - 1, bar_lines + 1)
     } else {
         (area.height - input_height, 0)
     };
@@ -145,51 +225,121 @@ fn split_chat_log_areas(_global_state: &GlobalState, chat_state: &ChatState, are
     (left, right)
 }
 
-fn render_channels(_global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+fn render_channels(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
     let channels: Vec<Line> = if chat_state.channels.is_empty() {
-        vec![Line::from(Span::styled(
-            "This server has no channels",
-            Style::default().add_modifier(Modifier::DIM | Modifier::ITALIC),
-        ))]
+        vec![Line::from(Span::styled("This server has no channels", theme.placeholder))]
     } else {
-        chat_state
-            .channels
-            .iter()
-            .map(|channel| {
-                let mut style = match channel.status {
-                    ChannelStatus::Read => Style::default(),
-                    ChannelStatus::Unread => Style::default().add_modifier(Modifier::BOLD),
-                    ChannelStatus::Muted => Style::default().add_modifier(Modifier::DIM),
-                };
-                if channel.id == chat_state.channels.get(chat_state.active_channel_idx).unwrap().id {
-                    style = style.bg(Color::DarkGray);
-                }
+        let rows = chat_state.sidebar_rows();
+        let selected_row = chat_state.sidebar_position(&rows);
+        // The active channel already shows who is typing below the chat log
+        let someone_typing = |idx: usize| {
+            idx != chat_state.active_channel_idx
+                && chat_state
+                    .channels
+                    .get(idx)
+                    .is_some_and(|channel| chat_state.users_typing.get(&channel.id).is_some_and(|typing| !typing.is_empty()))
+        };
+        let typing_span = |style: Style| Span::styled(format!(" {TYPING_GLYPH}"), style.patch(theme.typing));
 
-                Line::from(Span::styled(format!("# {:15}", channel.name.clone()), style))
+        rows.iter()
+            .enumerate()
+            .filter_map(|(row_idx, row)| {
+                let selected_style = |style: Style| if row_idx == selected_row { style.patch(theme.selection) } else { style };
+                match row {
+                    SidebarRow::Group(group) => {
+                        let grouped_channels = chat_state.channels.iter().filter(|channel| channel.group.as_ref() == Some(group));
+                        let unread_count = grouped_channels.filter(|channel| matches!(channel.status, ChannelStatus::Unread)).count();
+                        let collapsed = chat_state.channel_groups.collapsed.contains(group);
+                        let symbol = if collapsed { "▸" } else { "▾" };
+
+                        let mut spans = vec![Span::styled(format!("{symbol} {group}"), selected_style(theme.header))];
+                        if unread_count > 0 {
+                            spans.push(Span::styled(format!(" ({unread_count})"), selected_style(theme.attention)));
+                        }
+                        // Channels of a collapsed group are hidden, so their typing shows on the group
+                        let group_typing =
+                            (0..chat_state.channels.len()).any(|idx| chat_state.channels[idx].group.as_ref() == Some(group) && someone_typing(idx));
+                        if collapsed && group_typing {
+                            spans.push(typing_span(selected_style(Style::default())));
+                        }
+                        Some(Line::from(spans))
+                    }
+                    SidebarRow::Channel(idx) => {
+                        let channel = chat_state.channels.get(*idx)?;
+                        let style = match channel.status {
+                            ChannelStatus::Read => Style::default(),
+                            ChannelStatus::Unread => theme.unread,
+                            ChannelStatus::Muted => theme.muted,
+                        };
+                        // Pinned channels are listed above the groups, so they are not indented and get a highlighted #
+                        let pinned = chat_state.preferences.pinned_channels.contains(&channel.id);
+                        // Direct channels are named after the other user
+                        let (symbol, channel_name) = match channel.direct_user {
+                            Some(user_id) => (
+                                "@",
+                                chat_state
+                                    .users
+                                    .iter()
+                                    .find(|user| user.id == user_id)
+                                    .map_or(channel.name.as_str(), |user| user.name.as_str()),
+                            ),
+                            None if channel.is_local() => ("~", channel.name.as_str()),
+                            None => ("#", channel.name.as_str()),
+                        };
+                        let (indent, name) = if channel.group.is_some() && !pinned {
+                            ("  ", format!(" {}", pad_to_width(channel_name, 13)))
+                        } else {
+                            ("", format!(" {}", pad_to_width(channel_name, 15)))
+                        };
+                        let symbol_style = if pinned { style.patch(theme.attention) } else { style };
+                        let mut spans = vec![
+                            Span::styled(indent, selected_style(style)),
+                            Span::styled(symbol, selected_style(symbol_style)),
+                            Span::styled(name, selected_style(style)),
+                        ];
+                        if someone_typing(*idx) {
+                            spans.push(typing_span(selected_style(Style::default())));
+                        }
+                        Some(Line::from(spans))
+                    }
+                }
             })
             .collect()
     };
 
-    let (borders, border_style, border_corners) = borders_channel(chat_state);
+    let (borders, border_style, border_corners) = borders_channel(global_state, chat_state);
     let widget = Paragraph::new(Text::from(channels)).block(
         Block::default()
             .padding(PADDING)
             .border_set(border_corners)
             .borders(borders)
             .border_style(border_style)
-            .title(Span::styled("Channels".to_string(), HEADER_STYLE)),
+            .title(Span::styled(
+                match chat_state.preferences.channel_sort {
+                    ChannelSortOrder::Server => "Channels".to_owned(),
+                    ChannelSortOrder::Activity => "Channels [recent]".to_owned(),
+                },
+                theme.header,
+            )),
     );
     frame.render_widget(widget, area);
 }
 
-fn render_profile(_global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
-    let (borders, border_style, border_corners) = borders_profile(chat_state);
+fn render_profile(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let (borders, border_style, border_corners) = borders_profile(global_state, chat_state);
 
-    let (symbol, user_status_style) = user_status(&chat_state.current_user.status);
+    let (symbol, user_status_style) = user_status(theme, &chat_state.current_user.status);
 
     let username = Span::styled(format!("{symbol} {}", chat_state.current_user.username), user_status_style);
+    let status = if chat_state.manual_status {
+        format!("{} [Alt+S]", status_label(&chat_state.current_user.status))
+    } else {
+        String::new()
+    };
 
-    let lines = vec![Line::from(Span::from("")), Line::from(username)];
+    let lines = vec![Line::from(Span::styled(status, theme.hint)), Line::from(username)];
 
     let widget = Paragraph::new(Text::from(lines)).block(
         Block::default()
@@ -201,16 +351,23 @@ fn render_profile(_global_state: &GlobalState, chat_state: &ChatState, frame: &m
     frame.render_widget(widget, area);
 }
 
-fn render_server_status(_global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
-    let (borders, border_style, border_corners) = borders_server_status(chat_state);
+fn render_server_status(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let (borders, border_style, border_corners) = borders_server_status(global_state, chat_state);
     let connection_status = match chat_state.server_connection_status {
-        ServerConnectionStatus::Connected => Span::styled("Server: [Connected]".to_owned(), Style::default().fg(Color::Green)),
-        ServerConnectionStatus::Unhealthy => Span::styled("Server: [Unhealthy]".to_owned(), Style::default().fg(Color::LightYellow)),
-        ServerConnectionStatus::Disconnected => Span::styled("Server: [Disconnected]".to_owned(), Style::default().fg(Color::LightRed)),
-        ServerConnectionStatus::Reconnecting => Span::styled("Server: [Reconnecting]".to_owned(), Style::default().fg(Color::LightYellow)),
+        ServerConnectionStatus::Connected => Span::styled("Server: [Connected]".to_owned(), theme.success),
+        ServerConnectionStatus::Unhealthy => Span::styled("Server: [Unhealthy]".to_owned(), theme.warning),
+        ServerConnectionStatus::Disconnected => Span::styled("Server: [Disconnected]".to_owned(), theme.error),
+        ServerConnectionStatus::Reconnecting => Span::styled("Server: [Reconnecting]".to_owned(), theme.warning),
     };
 
-    let lines = vec![Line::from(Span::from("")), Line::from(connection_status)];
+    let startup_progress = match chat_state.startup.progress_label() {
+        _ if !chat_state.login_verified => Span::styled("Cached, login not verified", theme.warning),
+        Some(label) => Span::styled(label, theme.placeholder),
+        None => Span::from(""),
+    };
+
+    let lines = vec![Line::from(startup_progress), Line::from(connection_status)];
 
     let widget = Paragraph::new(Text::from(lines)).block(
         Block::default()
@@ -222,117 +379,319 @@ fn render_server_status(_global_state: &GlobalState, chat_state: &ChatState, fra
     frame.render_widget(widget, area);
 }
 
-fn render_chat_history(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+/// Returns where image previews were laid out, when they are drawn through a graphics protocol
+fn render_chat_history(
+    global_state: &GlobalState,
+    chat_state: &ChatState,
+    frame: &mut Frame,
+    area: Rect,
+    stats: &mut RenderStats,
+) -> Vec<ImagePlacement> {
+    let theme = &global_state.theme;
     // TODO make less ugly
     let empty = &vec![];
-
-    let (channel_id, channel_name, selection_offset) = if let Some(channel) = &chat_state.channels.get(chat_state.active_channel_idx) {
-        (channel.id, channel.name.clone(), channel.selection_offset)
+    // Lines rendered so far and the lines at which image previews start
+    let mut rendered_lines = 0;
+    let mut preview_slots: Vec<(usize, MediaId)> = vec![];
+    let mut laid_out_messages = 0;
+    // Lines at which the newest message in view starts and ends
+    let mut newest_in_view_start = 0;
+    let mut newest_in_view_end = None;
+
+    let (channel_id, channel_name, channel_label, selection_offset) = if let Some(channel) = &chat_state.channels.get(chat_state.active_channel_idx) {
+        let channel_name = match channel.direct_user {
+            Some(_) => chat_state.channel_label(channel),
+            None => channel.name.clone(),
+        };
+        (channel.id, channel_name, chat_state.channel_label(channel), channel.selection_offset)
     } else {
-        (0, "Should not be shown".to_string(), 0)
+        (0, "Should not be shown".to_string(), "Should not be shown".to_string(), 0)
     };
 
     let chat_log = chat_state.chat_history.get(&channel_id).unwrap_or(empty);
+    let layout = global_state.layout();
+    let indent = " ".repeat(layout.indent as usize);
 
-    let chatlog_lines: Vec<Line> = if chat_log.is_empty() {
+    let chatlog_lines: Vec<Line> = if chat_log.is_empty() && chat_state.is_syncing(channel_id) {
         vec![Line::from(Span::styled(
-            format!("Be the first to message in #{channel_name}"),
-            Style::default().add_modifier(Modifier::DIM | Modifier::ITALIC),
+            format!("Syncing the history of {channel_label}…"),
+            theme.placeholder,
+        ))]
+    } else if chat_log.is_empty() {
+        vec![Line::from(Span::styled(
+            format!("Be the first to message in {channel_label}"),
+            theme.placeholder,
         ))]
     } else {
         let current_message_line_count = chat_log.len();
+        // Messages drawn without a header, below the header of an earlier message of the same author.
+        // Pinned messages keep theirs, it is where they are marked
+        let grouped: Vec<bool> = (0..chat_log.len())
+            .map(|index| {
+                layout.compact
+                    && index > 0
+                    && continues_group(&chat_log[index - 1], &chat_log[index])
+                    && !chat_state.is_pinned(channel_id, chat_log[index].message_id)
+            })
+            .collect();
+
+        // Assumes the usual message of a header and a single line body
+        let end_index = current_message_line_count.saturating_sub(chat_state.chat_scroll_offset);
+        let messages_in_view = if layout.compact {
+            let mut room = area.height.saturating_sub(2) as usize;
+            let fitting = grouped[..end_index]
+                .iter()
+                .rev()
+                .zip(&chat_log[..end_index])
+                .map(|(&grouped, message)| match grouped {
+                    true => 1,
+                    false if chat_state.is_collapsed(channel_id, message) => 1 + layout.header_spacing as usize,
+                    false => 2 + layout.header_spacing as usize,
+                })
+                .take_while(|&lines| {
+                    let fits = lines <= room;
+                    room = room.saturating_sub(lines);
+                    fits
+                })
+                .count();
+            fitting.max(1)
+        } else {
+            let lines_per_message = 2 + layout.header_spacing;
+            (area.height.div_ceil(lines_per_message)).saturating_sub(1) as usize
+        };
+        let mut start_index = end_index.saturating_sub(messages_in_view);
+
+        let shows_selection = chat_state.shows_selection();
+        let selected_index = chat_state.chat_scroll_offset + selection_offset;
+        // Follow the selection, for when it was moved past the edge of the view or jumped to
+        if shows_selection && selected_index < current_message_line_count {
+            if selected_index < start_index {
+                start_index = selected_index;
+            } else if selected_index >= start_index + messages_in_view.saturating_sub(1) {
+                start_index = selected_index + 2 - messages_in_view.max(2);
+            }
+        }
 
-        let start_index = current_message_line_count
-            .saturating_sub((area.height.div_ceil(2)).saturating_sub(1) as usize)
-            .saturating_sub(chat_state.chat_scroll_offset);
-
-        let text_width: usize = area.width.saturating_sub(3).into();
+        let text_width: usize = area.width.saturating_sub(1 + 2 * layout.padding + layout.gutter_width()).into();
 
         chat_log
             .iter()
-            .skip(start_index)
             .enumerate()
+            .skip(start_index)
             .flat_map(|(index, message)| {
                 use ChatMessageStatus::*;
-                let message_is_focused =
-                    (chat_state.focus == ChatFocus::ChatHistorySelection || chat_state.replying_to.is_some()) && index == selection_offset;
+                if index + 1 == end_index {
+                    newest_in_view_start = rendered_lines;
+                } else if index == end_index {
+                    newest_in_view_end = Some(rendered_lines);
+                }
+                let message_is_focused = shows_selection && index == selected_index;
+                laid_out_messages += 1;
+
+                let timestamp = global_state.message_time(message.timestamp);
+
+                if message.kind == ChatMessageKind::System {
+                    let mut event_style = theme.event;
+                    if message_is_focused {
+                        event_style = event_style.patch(theme.selection);
+                    }
+                    let timestamp = Span::styled(format!("[{timestamp}]"), event_style);
+                    let event = Span::styled(
+                        truncate_to_width(&format!("── {} ", message.message), text_width.saturating_sub(timestamp.width())),
+                        event_style,
+                    );
+                    let padding = Span::styled(
+                        pad_to_width("", text_width.saturating_sub(display_width(&event.content) + timestamp.width())),
+                        event_style,
+                    );
+                    let mut lines = vec![Line::from(vec![event, timestamp, padding])];
+                    apply_layout(layout, &mut lines, Some(0), Span::raw(""));
+                    rendered_lines += lines.len();
+                    return lines.into_iter();
+                }
 
-                let timestamp = message.timestamp.format("%H:%M:%S").to_string();
+                // Folded into a stub that only says who sent it and when
+                if chat_state.is_collapsed(channel_id, message) {
+                    let mut stub_style = theme.placeholder;
+                    if message_is_focused {
+                        stub_style = stub_style.patch(theme.selection);
+                    }
+                    let stub = format!("▸ {} [{timestamp}] folded", message.author_name);
+                    let mut lines = vec![Line::from(Span::styled(padtruncate(&stub, text_width), stub_style))];
+                    apply_layout(layout, &mut lines, (!grouped[index]).then_some(0), Span::raw(""));
+                    rendered_lines += lines.len();
+                    return lines.into_iter();
+                }
 
                 let mut header_style = match message.status {
-                    Send => Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
-                    Sending => Style::default().fg(Color::Yellow).add_modifier(Modifier::DIM | Modifier::ITALIC),
-                    FailedToSend => Style::default().fg(Color::LightRed).add_modifier(Modifier::DIM | Modifier::ITALIC),
+                    Send => theme.author.patch(theme.header),
+                    Sending => theme.author.patch(theme.pending),
+                    FailedToSend => theme.error.patch(theme.pending),
                 };
 
                 let mut body_style = match message.status {
-                    Send => Style::default().fg(Color::Gray),
-                    Sending => Style::default().fg(Color::Gray).add_modifier(Modifier::DIM | Modifier::ITALIC),
-                    FailedToSend => Style::default().fg(Color::LightRed).add_modifier(Modifier::DIM | Modifier::ITALIC),
+                    Send => theme.text,
+                    Sending => theme.text.patch(theme.pending),
+                    FailedToSend => theme.error.patch(theme.pending),
                 };
 
                 let mut timestamp_style = match message.status {
-                    Send => Style::default().fg(Color::DarkGray),
-                    Sending | ChatMessageStatus::FailedToSend => Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC),
+                    Send => theme.timestamp,
+                    Sending | ChatMessageStatus::FailedToSend => theme.timestamp.patch(theme.annotation),
                 };
 
                 if message_is_focused {
-                    header_style = header_style.bg(Color::DarkGray);
-                    body_style = body_style.bg(Color::DarkGray);
-                    timestamp_style = timestamp_style.bg(Color::DarkGray).fg(Color::Gray);
+                    header_style = header_style.patch(theme.selection);
+                    body_style = body_style.patch(theme.selection);
+                    timestamp_style = timestamp_style.patch(theme.selected_timestamp);
                 };
 
-                let username = Span::styled(message.author_name.to_string(), header_style);
+                let avatar = author_avatar(global_state, chat_state, message.author_id);
                 let timestamp = Span::styled(format!(" [{timestamp}]"), timestamp_style);
+                let edited = match (message.edited, chat_state.is_pinned(channel_id, message.message_id)) {
+                    (true, true) => " (edited, pinned)",
+                    (true, false) => " (edited)",
+                    (false, true) => " (pinned)",
+                    (false, false) => "",
+                };
+                let edited = Span::styled(edited, timestamp_style.patch(theme.annotation));
+                // Long names are cut so the timestamp stays in view
+                let name_width = text_width
+                    .saturating_sub(avatar.iter().map(Span::width).sum())
+                    .saturating_sub(timestamp.width() + edited.width());
+                let username = Span::styled(truncate_to_width(&message.author_name, name_width), header_style);
                 let padding = Span::styled(
-                    pad_to_width("", text_width.saturating_sub(username.width()).saturating_sub(timestamp.width())),
+                    pad_to_width(
+                        "",
+                        text_width
+                            .saturating_sub(avatar.iter().map(Span::width).sum())
+                            .saturating_sub(display_width(&username.content))
+                            .saturating_sub(timestamp.width())
+                            .saturating_sub(edited.width()),
+                    ),
                     timestamp_style,
                 );
-                let header = Line::from(vec![
+                let header = Line::from_iter(avatar.into_iter().chain([
                     username,
                     timestamp,
+                    edited,
                     padding,
                     (match message.status {
                         Send => Span::raw(""),
-                        Sending => Span::styled("sending...", Style::default().add_modifier(Modifier::DIM | Modifier::ITALIC)),
-                        FailedToSend => Span::styled(
-                            "failed to send",
-                            Style::default().fg(Color::LightRed).add_modifier(Modifier::DIM | Modifier::ITALIC),
-                        ),
+                        // The gutter shows the status instead, only the upload progress is still spelled out
+                        FailedToSend if layout.gutter => Span::raw(""),
+                        Sending => match &chat_state.upload {
+                            Some(upload) if upload.message_id == message.message_id => {
+                                Span::styled(upload_progress_bar(upload), theme.attention.patch(theme.hint))
+                            }
+                            _ if layout.gutter => Span::raw(""),
+                            _ => Span::styled("sending...", theme.placeholder),
+                        },
+                        FailedToSend => Span::styled("failed to send", theme.error.patch(theme.pending)),
                     }),
-                ]);
+                ]));
 
-                let body = Line::from(Span::styled(pad_to_width(&format!("  {}", &message.message), text_width), body_style));
+                let max_lines = match global_state.config.max_message_lines {
+                    0 => usize::MAX,
+                    max_lines => max_lines,
+                };
+                let (mut body, hidden_lines) =
+                    message_body(theme, &indent, &message.message, &chat_state.channels, body_style, text_width, max_lines);
+                if hidden_lines > 0 {
+                    let more = match hidden_lines {
+                        1 => "1 more line".to_owned(),
+                        hidden_lines => format!("{hidden_lines} more lines"),
+                    };
+                    // Expanding works on the selected message
+                    let expand = if message_is_focused { ", expand (E)" } else { "" };
+                    body.push(Line::from(Span::styled(
+                        pad_to_width(&format!("{indent}… {more}{expand}"), text_width),
+                        body_style.patch(theme.placeholder),
+                    )));
+                }
+                let body_lines = body.len();
+                let translation = chat_state.translations.get(&message.message_id).map(|translation| {
+                    let tag_style = timestamp_style.patch(theme.placeholder);
+                    match translation {
+                        Translation::Pending => Line::from(Span::styled(pad_to_width(&format!("{indent}translating..."), text_width), tag_style)),
+                        Translation::Done(text) => {
+                            let tag = Span::styled(" translated", tag_style);
+                            let text = format!("{indent}{}", padtruncate(text, text_width.saturating_sub(tag.width() + indent.len())));
+                            Line::from(vec![Span::styled(text, body_style.patch(theme.annotation)), tag])
+                        }
+                    }
+                });
+                let mut attachments = vec![];
+                let mut previews = vec![];
+                for attachment in &message.attachments {
+                    let name = attachment.filename.clone().unwrap_or_else(|| format!("media #{}", attachment.media_id));
+                    attachments.push(Line::from(Span::styled(
+                        pad_to_width(&format!("{indent}{} {name}", media_icon(attachment.media_type.as_ref())), text_width),
+                        body_style.patch(theme.annotation),
+                    )));
+                    // Blank lines the preview is drawn over
+                    if let Some(preview) = chat_state.previews.get(&attachment.media_id) {
+                        previews.push((attachments.len(), attachment.media_id));
+                        attachments.extend((0..preview.rows).map(|_| Line::default()));
+                    }
+                }
+                let attachment_lines = attachments.len();
+                let translation_lines = translation.is_some() as usize;
 
-                if message.reply_id != 0
+                let mut lines = if grouped[index] {
+                    body.into_iter().chain(translation).chain(attachments).collect::<Vec<_>>()
+                } else if message.reply_id != 0
                     && let Some(reply_message) = chat_log.iter().find(|m| m.message_id == message.reply_id)
                 {
-                    let mut author_style = Style::default().fg(Color::Yellow).add_modifier(Modifier::DIM);
-                    let mut timestamp_style = Style::default().fg(Color::DarkGray);
-                    let mut message_style = Style::default().fg(Color::Gray).add_modifier(Modifier::DIM);
-                    let mut bar_style = Style::default().fg(Color::Gray).add_modifier(Modifier::DIM);
+                    let mut author_style = theme.author.patch(theme.quote);
+                    let mut timestamp_style = theme.timestamp;
+                    let mut message_style = theme.text.patch(theme.quote);
+                    let mut bar_style = theme.text.patch(theme.quote);
 
                     if message_is_focused {
-                        author_style = author_style.bg(Color::DarkGray);
-                        timestamp_style = timestamp_style.bg(Color::DarkGray).fg(Color::Gray).add_modifier(Modifier::DIM);
-                        message_style = message_style.bg(Color::DarkGray);
-                        bar_style = bar_style.bg(Color::DarkGray);
+                        author_style = author_style.patch(theme.selection);
+                        timestamp_style = timestamp_style.patch(theme.selected_timestamp).patch(theme.quote);
+                        message_style = message_style.patch(theme.selection);
+                        bar_style = bar_style.patch(theme.selection);
                     };
 
+                    let bar_span = Span::styled(" ┌── ", bar_style);
                     let author_span = Span::styled(reply_message.author_name.to_string(), author_style);
-                    let timestamp_span = Span::styled(format!(" [{}]", reply_message.timestamp.format("%H:%M:%S")), timestamp_style);
-                    let message_text_width = text_width.saturating_sub(author_span.width()).saturating_sub(timestamp_span.width());
-                    let message_span = Span::styled(format!(" {}", padtruncate(&reply_message.message, message_text_width)), message_style);
-
-                    let reply = Line::from(vec![Span::styled(" ┌── ", bar_style), author_span, timestamp_span, message_span]);
-                    vec![reply, header, body].into_iter()
+                    let timestamp_span = Span::styled(format!(" [{}] ", global_state.message_time(reply_message.timestamp)), timestamp_style);
+                    let message_text_width =
+                        text_width.saturating_sub(bar_span.width() + display_width(&author_span.content) + timestamp_span.width());
+                    let message_span = Span::styled(padtruncate(&reply_message.message.replace('\n', " "), message_text_width), message_style);
+
+                    let reply = Line::from(vec![bar_span, author_span, timestamp_span, message_span]);
+                    [reply, header]
+                        .into_iter()
+                        .chain(body)
+                        .chain(translation)
+                        .chain(attachments)
+                        .collect::<Vec<_>>()
                 } else {
-                    vec![header, body].into_iter()
-                }
+                    [header].into_iter().chain(body).chain(translation).chain(attachments).collect::<Vec<_>>()
+                };
+                let status_glyph = match message.status {
+                    Send => Span::raw(""),
+                    Sending => Span::styled("◌", theme.attention.patch(theme.hint)),
+                    FailedToSend => Span::styled("✗", theme.error),
+                };
+                // The header is preceded by the quoted message of replies
+                let header_idx = (!grouped[index]).then(|| lines.len() - attachment_lines - translation_lines - body_lines - 1);
+                apply_layout(layout, &mut lines, header_idx, status_glyph);
+
+                let attachments_start = rendered_lines + lines.len() - attachment_lines;
+                preview_slots.extend(previews.into_iter().map(|(offset, media_id)| (attachments_start + offset, media_id)));
+                rendered_lines += lines.len();
+                lines.into_iter()
             })
             .collect()
     };
 
+    stats.history_messages = laid_out_messages;
+    stats.history_lines = chatlog_lines.len();
+
     let (borders, border_style, border_corners) = borders_chat_history(global_state, chat_state);
 
     //     .title(
@@ -344,11 +703,16 @@ fn render_chat_history(global_state: &GlobalState, chat_state: &ChatState, frame
     // );
 
     let mut block = Block::default()
-        .padding(PADDING)
+        .padding(Padding::horizontal(layout.padding))
         .border_set(border_corners)
         .borders(borders)
         .border_style(border_style)
-        .title(Span::styled(format!("Chat Log [{}]", &channel_name), HEADER_STYLE));
+        .title(Span::styled(format!("Chat Log [{}]", &channel_name), theme.header));
+    if chat_state.history_pagination.is_loading(channel_id) {
+        block = block.title(Span::styled(" Loading older messages… ", theme.placeholder));
+    } else if chat_state.is_syncing(channel_id) {
+        block = block.title(Span::styled(" syncing… ", theme.placeholder));
+    }
 
     let users_typing = match chat_state.focus {
         ChatFocus::ChatInput(_) => "".to_owned(),
@@ -364,31 +728,151 @@ fn render_chat_history(global_state: &GlobalState, chat_state: &ChatState, frame
     };
 
     if !users_typing.is_empty() {
-        block = block.title_bottom(Span::styled(users_typing, Modifier::ITALIC | Modifier::DIM));
+        block = block.title_bottom(Span::styled(users_typing, theme.placeholder));
     };
 
-    let widget = Paragraph::new(Text::from(chatlog_lines)).block(block);
+    let inner = block.inner(area);
+    let height = inner.height as usize;
+    let newest_in_view_end = newest_in_view_end.unwrap_or(chatlog_lines.len());
+    let newest_in_view_lines = newest_in_view_end - newest_in_view_start;
+    stats.newest_in_view_lines = newest_in_view_lines;
+    stats.history_height = height;
+    // The view ends at the last line of the newest message in view, or the line offset above it when scrolled into a
+    // message taller than the history. The selection is followed instead while it is shown
+    let line_offset = chat_state.chat_line_offset.min(newest_in_view_lines.saturating_sub(height));
+    let scroll = match chat_state.shows_selection() {
+        true => 0,
+        false => (newest_in_view_end - line_offset).saturating_sub(height),
+    };
+    let widget = Paragraph::new(Text::from(chatlog_lines)).block(block).scroll((scroll as u16, 0));
     frame.render_widget(widget, area);
-}
 
-fn render_reply_bar(_global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
-    let (borders, border_style, border_corners) = borders_reply_bar(chat_state);
+    // Graphics protocols draw on top of everything, so previews are hidden while something overlaps the history
+    let overlapped = chat_state.popup.is_some() || !chat_state.toasts.is_empty();
+    let mut placements = vec![];
+    let preview_indent = layout.gutter_width() + layout.indent;
+    for (line, media_id) in preview_slots {
+        let Some(preview) = chat_state.previews.get(&media_id) else {
+            continue;
+        };
+        // Previews that are cut off by the top or the bottom of the history are left out
+        let Some(line) = line.checked_sub(scroll) else {
+            continue;
+        };
+        if line + preview.rows as usize > height {
+            continue;
+        }
+        let preview_area = Rect {
+            x: inner.x + preview_indent,
+            y: inner.y + line as u16,
+            width: preview.columns.min(inner.width.saturating_sub(preview_indent)),
+            height: preview.rows,
+        };
+        if global_state.graphics.uses_half_blocks() {
+            frame.render_widget(HalfBlockImage(preview), preview_area);
+        } else if !overlapped {
+            placements.push(ImagePlacement {
+                media_id,
+                area: preview_area,
+                avatar: false,
+            });
+        }
+    }
+    placements
+}
 
-    let (replying_to, timestamp, message) = match &chat_state.replying_to {
-        Some(message) => (
-            &message.author_name,
-            message.timestamp.format("%H:%M:%S").to_string(),
-            message.message.clone(),
-        ),
-        None => (&"unknown".to_owned(), "".to_owned(), "".to_owned()),
+/// Two half block cells with the colors of the profile picture of the author and a space, empty when the picture is off or not fetched
+fn author_avatar(global_state: &GlobalState, chat_state: &ChatState, author_id: UserId) -> Vec<Span<'static>> {
+    if !global_state.config.author_avatars {
+        return vec![];
+    }
+    let Some(avatar) = chat_state
+        .users
+        .iter()
+        .find(|user| user.id == author_id)
+        .and_then(|user| chat_state.avatars.get(&user.pfp_id))
+    else {
+        return vec![];
     };
+    let mut spans: Vec<Span> = avatar
+        .glyph
+        .iter()
+        .map(|(top, bottom)| Span::styled("▀", Style::default().fg(*top).bg(*bottom)))
+        .collect();
+    spans.push(Span::raw(" "));
+    spans
+}
 
-    let lines = vec![Line::from(vec![
-        Span::from("> Replying to "),
-        Span::styled(replying_to.to_string(), Style::default().fg(Color::Yellow)),
-        Span::styled(format!(" [{timestamp}]"), Style::default().add_modifier(Modifier::DIM)),
-        Span::styled(format!(" > {message}"), Style::default().add_modifier(Modifier::DIM)),
-    ])];
+/// Adds the blank lines above a message and the gutter in front of its lines, the status glyph goes in the gutter of the header
+fn apply_layout<'a>(layout: &HistoryLayout, lines: &mut Vec<Line<'a>>, header_idx: Option<usize>, status_glyph: Span<'a>) {
+    if layout.gutter {
+        let gutter_width = layout.gutter_width() as usize;
+        let mut status_glyph = Some(status_glyph);
+        for (idx, line) in lines.iter_mut().enumerate() {
+            let glyph = match status_glyph.take_if(|_| Some(idx) == header_idx) {
+                Some(glyph) => {
+                    let padding = gutter_width.saturating_sub(glyph.width());
+                    vec![glyph, Span::raw(" ".repeat(padding))]
+                }
+                None => vec![Span::raw(" ".repeat(gutter_width))],
+            };
+            line.spans.splice(0..0, glyph);
+        }
+    }
+    // Grouped messages stay right below the message before them
+    if header_idx.is_some() {
+        lines.splice(0..0, (0..layout.header_spacing).map(|_| Line::default()));
+    }
+}
+
+/// Whether `message` goes under the header of `previous` in compact mode, for a plain message of the same author shortly after.
+/// Replies, edits and messages that are not confirmed keep their header, as it is where they are marked
+fn continues_group(previous: &ChatMessage, message: &ChatMessage) -> bool {
+    previous.kind != ChatMessageKind::System
+        && message.kind != ChatMessageKind::System
+        && previous.author_id == message.author_id
+        && message.reply_id == 0
+        && !message.edited
+        && message.status == ChatMessageStatus::Send
+        && message.timestamp - previous.timestamp <= GROUP_WINDOW
+}
+
+fn render_reply_bar(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let (borders, border_style, border_corners) = borders_reply_bar(global_state, chat_state);
+
+    let mut lines = vec![];
+    if let Some(message) = chat_state.reply_target() {
+        lines.push(Line::from(vec![
+            Span::from("> Replying to "),
+            Span::styled(message.author_name.to_string(), theme.author),
+            Span::styled(format!(" [{}]", global_state.message_time(message.timestamp)), theme.hint),
+            Span::styled(format!(" > {}", message.message), theme.hint),
+        ]));
+    }
+    if let Some(message) = chat_state.active_edit().map(|edit| &edit.message) {
+        lines.push(Line::from(vec![
+            Span::from("> Editing message"),
+            Span::styled(format!(" [{}]", global_state.message_time(message.timestamp)), theme.hint),
+            Span::styled(format!(" > {}", message.message), theme.hint),
+        ]));
+    }
+    if !chat_state.staged_attachments.is_empty() {
+        let mut spans = vec![Span::from("> Attached ")];
+        for (idx, attachment) in chat_state.staged_attachments.iter().enumerate() {
+            if idx > 0 {
+                spans.push(Span::styled(" · ", theme.hint));
+            }
+            spans.push(Span::from(format!(
+                "{} {}",
+                media_icon(Some(&attachment.media_type)),
+                attachment.filename
+            )));
+            spans.push(Span::styled(format!(" ({})", format_size(attachment.data.len())), theme.hint));
+        }
+        spans.push(Span::styled(" [Backspace] Remove", theme.hint));
+        lines.push(Line::from(spans));
+    }
 
     let widget = Paragraph::new(Text::from(lines)).block(
         Block::default()
@@ -400,24 +884,28 @@ fn render_reply_bar(_global_state: &GlobalState, chat_state: &ChatState, frame:
     frame.render_widget(widget, area);
 }
 
-fn render_chat_input(_global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
-    let (channel_id, channel_name) = match chat_state.channels.get(chat_state.active_channel_idx) {
-        Some(channel) => (channel.id, channel.name.clone()),
+fn render_chat_input(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let (channel_id, channel_label) = match chat_state.channels.get(chat_state.active_channel_idx) {
+        Some(channel) => (channel.id, chat_state.channel_label(channel)),
         None => (0, "Should not be seen".to_owned()),
     };
 
-    let input_line = match chat_state.chat_inputs.get(&channel_id) {
-        Some(line) if !line.is_empty() => {
+    let input_line = match (&chat_state.voice_note, chat_state.chat_inputs.get(&channel_id)) {
+        (Some(voice_note), _) => render_voice_note(theme, voice_note),
+        (None, Some(line)) if !line.is_empty() => {
             if matches!(chat_state.focus, ChatFocus::ChatInput(_)) {
+                let cursor = match chat_state.focus {
+                    ChatFocus::ChatInput(cursor) => snap_to_grapheme(line, cursor),
+                    _ => usize::MAX,
+                };
                 format!("{line} ")
-                    .char_indices()
-                    .map(|(idx, chr)| {
-                        if let ChatFocus::ChatInput(focussed_idx) = chat_state.focus
-                            && focussed_idx == idx
-                        {
-                            Span::styled(chr.to_string(), Modifier::UNDERLINED)
+                    .grapheme_indices(true)
+                    .map(|(idx, grapheme)| {
+                        if idx == cursor {
+                            Span::styled(grapheme.to_owned(), theme.cursor)
                         } else {
-                            Span::from(chr.to_string())
+                            Span::from(grapheme.to_owned())
                         }
                     })
                     .collect()
@@ -425,11 +913,12 @@ fn render_chat_input(_global_state: &GlobalState, chat_state: &ChatState, frame:
                 vec![Span::from(line)]
             }
         }
-        _ => {
-            vec![Span::styled(
-                format!("Message #{channel_name}"),
-                Style::default().add_modifier(Modifier::DIM | Modifier::ITALIC),
-            )]
+        (None, _) => {
+            let placeholder = match chat_state.channels.get(chat_state.active_channel_idx) {
+                Some(channel) if channel.is_local() => "Write a note, it stays on this machine".to_owned(),
+                _ => format!("Message {channel_label}"),
+            };
+            vec![Span::styled(placeholder, theme.placeholder)]
         }
     };
 
@@ -446,17 +935,33 @@ fn render_chat_input(_global_state: &GlobalState, chat_state: &ChatState, frame:
         _ => "".to_owned(),
     };
 
-    let (borders, border_style, border_corners) = borders_input(chat_state);
+    let (borders, border_style, border_corners) = borders_input(global_state, chat_state);
     let mut block = Block::default()
         .padding(PADDING)
         .border_set(border_corners)
         .borders(borders)
         .border_style(border_style);
 
+    if let ChatFocus::ChatInput(cursor) = chat_state.focus
+        && let Some(input) = chat_state.chat_inputs.get(&channel_id)
+        && let Some((_, prefix)) = partial_channel_link(input, cursor)
+    {
+        let suggestions = channel_suggestions(&chat_state.channels, prefix);
+        if !suggestions.is_empty() {
+            let mut spans: Vec<Span> = suggestions
+                .iter()
+                .take(MAX_CHANNEL_SUGGESTIONS)
+                .map(|idx| Span::styled(format!(" #{} ", chat_state.channels[*idx].name), theme.link))
+                .collect();
+            spans.push(Span::styled("[Tab] Complete", theme.hint));
+            block = block.title_bottom(Line::from(spans));
+        }
+    }
+
     let input_text = if users_typing.is_empty() {
         vec![Line::raw(""), Line::from(input_line)]
     } else {
-        block = block.title(Span::styled(users_typing, Modifier::ITALIC | Modifier::DIM));
+        block = block.title(Span::styled(users_typing, theme.placeholder));
         vec![Line::raw(""), Line::from(input_line)]
     };
 
@@ -464,25 +969,18 @@ fn render_chat_input(_global_state: &GlobalState, chat_state: &ChatState, frame:
     frame.render_widget(widget, area);
 }
 
-fn render_users(_global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
-    let (mut online_users, mut offline_users): (Vec<&User>, Vec<&User>) = chat_state
-        .users
-        .iter()
+fn render_users(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let (online_users, offline_users): (Vec<&User>, Vec<&User>) = chat_state
+        .sorted_users()
+        .into_iter()
         .partition(|user| matches!(user.status, UserStatus::Online | UserStatus::Idle | UserStatus::DoNotDisturb));
 
-    online_users.sort_by_key(|user| &user.name);
-    offline_users.sort_by_key(|user| &user.name);
-
     let format_user_line = |user: &User, index, selected_index| {
-        let (symbol, mut symbol_style) = match user.status {
-            UserStatus::Offline => ("●", Style::default().fg(Color::Gray).add_modifier(Modifier::DIM)),
-            UserStatus::Online => ("●", Style::default().fg(Color::Green)),
-            UserStatus::Idle => ("●", Style::default().fg(Color::Yellow)),
-            UserStatus::DoNotDisturb => ("●", Style::default().fg(Color::Red)),
-        };
+        let (symbol, mut symbol_style) = user_status(theme, &user.status);
 
         let mut name_style = if let UserStatus::Offline = user.status {
-            Style::default().fg(Color::Gray).add_modifier(Modifier::DIM)
+            theme.offline
         } else {
             Style::default()
         };
@@ -490,8 +988,8 @@ fn render_users(_global_state: &GlobalState, chat_state: &ChatState, frame: &mut
         if let Some(idx) = selected_index
             && idx == index
         {
-            symbol_style = symbol_style.bg(Color::DarkGray);
-            name_style = name_style.bg(Color::DarkGray);
+            symbol_style = symbol_style.patch(theme.selection);
+            name_style = name_style.patch(theme.selection);
         }
 
         Line::from(vec![
@@ -505,10 +1003,7 @@ fn render_users(_global_state: &GlobalState, chat_state: &ChatState, frame: &mut
     let mut lines = vec![];
 
     if !online_users.is_empty() {
-        lines.push(Line::from(Span::styled(
-            "Online",
-            Style::default().fg(Color::Green).add_modifier(Modifier::UNDERLINED),
-        )));
+        lines.push(Line::from(Span::styled("Online", theme.online.patch(theme.section))));
         for (i, user) in online_users.iter().enumerate() {
             lines.push(format_user_line(user, i, selected_index));
         }
@@ -518,15 +1013,12 @@ fn render_users(_global_state: &GlobalState, chat_state: &ChatState, frame: &mut
     let online_users_count = online_users.len();
 
     if !offline_users.is_empty() {
-        lines.push(Line::from(Span::styled(
-            "Offline",
-            Style::default().fg(Color::Gray).add_modifier(Modifier::UNDERLINED),
-        )));
+        lines.push(Line::from(Span::styled("Offline", theme.text.patch(theme.section))));
         for (i, user) in offline_users.iter().enumerate() {
             lines.push(format_user_line(user, online_users_count + i, selected_index));
         }
     }
-    let (borders, border_style, border_corners) = borders_users(chat_state);
+    let (borders, border_style, border_corners) = borders_users(global_state, chat_state);
 
     let widget = Paragraph::new(Text::from(lines)).block(
         Block::default()
@@ -534,55 +1026,981 @@ fn render_users(_global_state: &GlobalState, chat_state: &ChatState, frame: &mut
             .border_set(border_corners)
             .borders(borders)
             .border_style(border_style)
-            .title(Span::styled("Users".to_string(), HEADER_STYLE)),
+            .title(Span::styled("Users".to_string(), theme.header)),
     );
     frame.render_widget(widget, area);
 }
 
 fn render_info(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    // Left out when the server does not support them
+    let edit = if chat_state.capabilities.editing { "[e]dit | " } else { "" };
+    let original = match chat_state.selected_message() {
+        Some(message) if message.reply_id != 0 => "[O]riginal | ",
+        _ => "",
+    };
+    let attach = match (chat_state.capabilities.chunked_media, cfg!(feature = "voice")) {
+        (false, _) => "",
+        (true, true) => "[Ctrl + O] Attach File | [Ctrl + R] Voice Note | ",
+        (true, false) => "[Ctrl + O] Attach File | ",
+    };
     let info_text = match chat_state.focus {
-        ChatFocus::Channels => "[↑↓] Change Channel | [Enter | →] Chat log | [L]ogs | [Q]uit",
-        ChatFocus::ChatHistory if global_state.show_logs => "[Enter | Space ] Input Input | [S]elect |[←] Channels | [→] Logs | [L]ogs | [Q]uit",
+        ChatFocus::Channels => {
+            "[↑↓] Change Channel | [Enter | →] Chat log | [C]ollapse Group | [M]ute | [P]in | [S]ort | [Ctrl + L] Lock | [L]ogs | [Q]uit"
+        }
+        ChatFocus::ChatHistory if global_state.logs_shown() => "[Enter | Space ] Input Input | [S]elect |[←] Channels | [→] Logs | [L]ogs | [Q]uit",
         ChatFocus::ChatHistory => "[Enter | Space ] Input | [S]elect | [←] Channels | [→] Users | [L]ogs | [Q]uit",
-        ChatFocus::ChatHistorySelection => {
-            "[Enter | Space ] Input | [↑↓] Move Selection | [R]eply | [S]elect | [←] Channels | [→] Users | [L]ogs | [Q]uit"
+        ChatFocus::ChatHistorySelection => &format!(
+            "[Enter | Space ] Input or Follow #channel | [↑↓] Move Selection | [R]eply | [>] Quote | [P]in | {original}{edit}[E]xpand | [D]ownload | [T]ranslate | [Z] Fold Author | [S]elect | [←] Channels | [→] Users | [L]ogs | [Q]uit"
+        ),
+        ChatFocus::ChatInput(_) => &format!(
+            "[Enter] Send Message | [Backspace] Delete | [←→] Move Cursor | [Ctrl + ←→] Tab move Cursor | {attach}[↑] Chatlog | [L]ogs | [Q]uit"
+        ),
+        ChatFocus::Users(_) => "[←] Chat log | [↑↓] Move Selection | [V]iew | [D]irect Message | [S]ort | [L]ogs | [Q]uit",
+        ChatFocus::Logs if global_state.expand_log_repeats => "[E] Collapse Repeats | [L]ogs | [Q]uit",
+        ChatFocus::Logs => "[E]xpand Repeats | [L]ogs | [Q]uit",
+    };
+
+    let info_text = if chat_state.upload.is_some() {
+        format!("[Ctrl + X] Cancel Upload | {info_text}")
+    } else {
+        info_text.to_owned()
+    };
+
+    let border_style = Style::default();
+    let mut block = Block::default()
+        .padding(PADDING)
+        .borders(Borders::LEFT | Borders::RIGHT | Borders::BOTTOM)
+        .border_style(border_style);
+    if let Some(warning) = &chat_state.version_warning {
+        block = block.title_bottom(Line::from(Span::styled(format!(" {warning} "), theme.attention)).right_aligned());
+    }
+    let widget = Paragraph::new(Text::from(info_text)).block(block);
+    frame.render_widget(widget, area);
+}
+
+fn render_logs(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let (borders, border_style, border_corners) = borders_logs(global_state, chat_state);
+
+    let block = Block::default()
+        .padding(PADDING)
+        .border_set(border_corners)
+        .borders(borders)
+        .border_style(border_style)
+        .title(Span::styled("Log".to_string(), theme.header));
+
+    let inner_area = block.inner(area);
+    let (logs, scroll) = visible_log_lines(
+        &global_state.logs,
+        global_state.log_scroll_offset,
+        inner_area.width,
+        inner_area.height,
+        !global_state.expand_log_repeats,
+    );
+
+    let widget = Paragraph::new(Text::from(logs))
+        .wrap(Wrap { trim: true })
+        .scroll((scroll, 0))
+        .block(block);
+    frame.render_widget(widget, area);
+}
+
+fn media_icon(media_type: Option<&MediaType>) -> &'static str {
+    match media_type {
+        Some(MediaType::Image) => "🖼",
+        Some(MediaType::Audio) => "🎵",
+        Some(MediaType::Video) => "🎬",
+        Some(MediaType::Text) => "📄",
+        Some(MediaType::Raw) | None => "📎",
+    }
+}
+
+fn upload_progress_bar(upload: &MediaUpload) -> String {
+    const BAR_WIDTH: usize = 10;
+    let progress = &upload.progress;
+    let count = match upload.attachment_count() {
+        1 => String::new(),
+        count => format!("{}/{count} ", upload.media_ids.len() + 1),
+    };
+    if progress.is_done() {
+        return format!("{count}processing...");
+    }
+    let filled = (progress.written * BAR_WIDTH).checked_div(progress.total).unwrap_or(0);
+    let percentage = (progress.written * 100).checked_div(progress.total).unwrap_or(0);
+    format!("{count}{}{} {percentage:>3}%", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled))
+}
+
+fn render_voice_note(theme: &Theme, voice_note: &VoiceNote) -> Vec<Span<'static>> {
+    let format_duration = |duration: Duration| format!("{}:{:02}", duration.as_secs() / 60, duration.as_secs() % 60);
+    match voice_note {
+        VoiceNote::Recording(recorder) => vec![
+            Span::styled("● REC ", theme.recording.patch(theme.header)),
+            Span::from(format!("{} ", format_duration(recorder.elapsed()))),
+            Span::styled(recorder.recent_waveform(3.0, VOICE_NOTE_WAVEFORM_WIDTH), theme.recording),
+            Span::styled(" [Ctrl + R] Stop | [Esc] Discard", theme.hint),
+        ],
+        VoiceNote::Preview(voice_note) => vec![
+            Span::styled("▶ ", theme.success),
+            Span::from(format!("{} ", format_duration(voice_note.duration))),
+            Span::styled(voice_note.waveform.clone(), theme.success),
+            Span::styled(" [Enter] Attach | [Esc] Discard", theme.hint),
+        ],
+    }
+}
+
+fn render_toasts(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let toast_width = 40.min(area.width.saturating_sub(2));
+    for (idx, toast) in chat_state.toasts.iter().rev().take(3).enumerate() {
+        let toast_area = Rect {
+            x: area.x + area.width.saturating_sub(toast_width + 1),
+            y: area.y + 1 + idx as u16 * 3,
+            width: toast_width,
+            height: 3,
         }
-        ChatFocus::ChatInput(_) => {
-            "[Enter] Send Message | [Backspace] Delete | [←→] Move Cursor | [Ctrl + ←→] Tab move Cursor | [↑] Chatlog | [L]ogs | [Q]uit"
+        .intersection(area);
+
+        let widget = Paragraph::new(Span::from(toast.message.clone())).block(
+            Block::default()
+                .padding(PADDING)
+                .borders(Borders::ALL)
+                .border_type(theme.borders)
+                .border_style(theme.attention),
+        );
+        frame.render_widget(Clear, toast_area);
+        frame.render_widget(widget, toast_area);
+    }
+}
+
+/// A bubble at the bottom of the focused pane, wrapped to its width
+fn render_tutorial_hint(global_state: &GlobalState, hint: TutorialHint, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let width = TUTORIAL_HINT_WIDTH.min(area.width.saturating_sub(2));
+    let lines = wrap_ranges(hint.text(), width.saturating_sub(4) as usize).len() as u16;
+    let hint_area = Rect {
+        x: area.x + 1,
+        y: (area.y + area.height).saturating_sub(lines + 3),
+        width,
+        height: lines + 2,
+    }
+    .intersection(area);
+
+    let widget = Paragraph::new(hint.text()).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.attention)
+            .title(Span::styled("Tip", theme.header))
+            .title_bottom(Span::styled("[Esc] Got it", theme.hint)),
+    );
+    frame.render_widget(Clear, hint_area);
+    frame.render_widget(widget, hint_area);
+}
+
+/// Returns where the profile picture was laid out, when it is drawn through a graphics protocol
+fn render_popup(global_state: &GlobalState, chat_state: &ChatState, popup: &Popup, frame: &mut Frame, area: Rect) -> Option<ImagePlacement> {
+    match popup {
+        Popup::Profile(user_id) => return render_profile_popup(global_state, chat_state, *user_id, frame, area),
+        Popup::CompressAttachment(attachment) => render_compress_popup(global_state, attachment, frame, area),
+        Popup::PasteImage(image) => render_paste_image_popup(global_state, image, frame, area),
+        Popup::AttachFile(picker) => render_file_picker_popup(global_state, picker, frame, area),
+        Popup::Who => render_who_popup(global_state, chat_state, frame, area),
+        Popup::Search(search) => render_search_popup(global_state, chat_state, search, frame, area),
+        Popup::Digest(digest) => render_digest_popup(global_state, chat_state, digest, frame, area),
+        Popup::CacheStats(stats) => render_cache_stats_popup(global_state, stats, frame, area),
+        Popup::Accounts(switcher) => render_accounts_popup(global_state, switcher, frame, area),
+        Popup::EditProfile(editor) => render_profile_editor_popup(global_state, editor, frame, area),
+        Popup::ChangePassword(editor) => render_password_editor_popup(global_state, editor, frame, area),
+        Popup::Changelog(changelog) => render_changelog_popup(global_state, changelog, frame, area),
+        Popup::ExpandedMessage(expanded) => render_expanded_message_popup(global_state, expanded, frame, area),
+        Popup::Pins(pins) => render_pins_popup(global_state, chat_state, pins, frame, area),
+        Popup::QuitGuard { waiting } => render_quit_guard_popup(global_state, chat_state, *waiting, frame, area),
+    }
+    None
+}
+
+fn render_accounts_popup(global_state: &GlobalState, switcher: &AccountSwitcher, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let mut lines: Vec<Line> = switcher
+        .accounts
+        .iter()
+        .enumerate()
+        .map(|(idx, account)| {
+            let style = if idx == switcher.selected { theme.selection } else { Style::default() };
+            let marker = if switcher.current == Some(idx) { "● " } else { "  " };
+            let mut spans = vec![
+                Span::styled(marker, style.patch(theme.success)),
+                Span::styled(account.username.clone(), style.patch(theme.author)),
+                Span::styled(format!(" {}", account.address), style.patch(theme.text)),
+            ];
+            if account.password.is_none() {
+                spans.push(Span::styled(" (asks for the password)", style.patch(theme.placeholder)));
+            }
+            Line::from(spans)
+        })
+        .collect();
+    if switcher.accounts.len() < 2 {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            "Add accounts to the config file to switch between them",
+            theme.placeholder,
+        )));
+    }
+
+    let popup_area = centered_rect(area, 60, lines.len() as u16 + 4);
+    let widget = Paragraph::new(Text::from(lines)).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.focus)
+            .title(Span::styled("Accounts", theme.header))
+            .title_bottom(Span::styled("[Enter] Switch | [Esc] Close", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_search_popup(global_state: &GlobalState, chat_state: &ChatState, search: &Search, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let popup_area = centered_rect(area, 80, SEARCH_POPUP_HEIGHT);
+    // Borders, padding and the query take up the rest
+    let text_width = popup_area.width.saturating_sub(4) as usize;
+    let list_height = popup_area.height.saturating_sub(4) as usize;
+
+    let mut results = vec![];
+    let mut selected_line = 0;
+    let mut previous_channel = None;
+    for (idx, result) in search.results.iter().enumerate() {
+        let Some(channel) = chat_state.channels.get(result.channel_idx) else {
+            continue;
+        };
+        let Some(message) = chat_state
+            .chat_history
+            .get(&channel.id)
+            .and_then(|messages| messages.iter().find(|message| message.message_id == result.message_id))
+        else {
+            continue;
+        };
+        if previous_channel != Some(result.channel_idx) {
+            results.push(Line::from(Span::styled(format!("#{}", channel.name), theme.header)));
+            previous_channel = Some(result.channel_idx);
+        }
+        let style = if idx == search.selected {
+            selected_line = results.len();
+            theme.selection
+        } else {
+            Style::default()
+        };
+        let author = Span::styled(format!("  {} ", message.author_name), style.patch(theme.author));
+        let timestamp = Span::styled(
+            format!("[{}] ", global_state.config.timestamps.format(message.timestamp, "%d-%m ", false)),
+            style.patch(theme.timestamp),
+        );
+        let body = Span::styled(
+            padtruncate(
+                &message.message.replace('\n', " "),
+                text_width.saturating_sub(author.width() + timestamp.width()),
+            ),
+            style,
+        );
+        results.push(Line::from(vec![author, timestamp, body]));
+    }
+
+    let mut lines = vec![Line::from(vec![Span::from(search.query.clone()), Span::styled(" ", theme.cursor)])];
+    if search.query.trim().is_empty() {
+        lines.push(Line::from(Span::styled(
+            "Type to search the loaded history of every channel",
+            theme.placeholder,
+        )));
+    } else if results.is_empty() {
+        let status = match (&search.backfill, &search.backfill_outcome) {
+            (Some(backfill), _) => format!(
+                "No messages found yet, loading older history {}/{MAX_BACKFILL_PAGES} (Esc cancels)",
+                backfill.pages
+            ),
+            (None, Some(outcome)) => outcome.clone(),
+            (None, None) => "No messages found, Enter searches older history".to_owned(),
+        };
+        lines.push(Line::from(Span::styled(status, theme.placeholder)));
+    } else {
+        // Keep the selected result in view
+        let skip = selected_line.saturating_sub(list_height.saturating_sub(1));
+        lines.extend(results.into_iter().skip(skip).take(list_height));
+    }
+
+    let count = match search.results.len() {
+        0 => String::new(),
+        MAX_SEARCH_RESULTS => format!(" ({MAX_SEARCH_RESULTS}+ results)"),
+        1 => " (1 result)".to_owned(),
+        count => format!(" ({count} results)"),
+    };
+    let widget = Paragraph::new(Text::from(lines)).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.focus)
+            .title(Span::styled(format!("Search{count}"), theme.header))
+            .title_bottom(Span::styled("[Enter] Jump to message | [Esc] Close", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_digest_popup(global_state: &GlobalState, chat_state: &ChatState, digest: &Digest, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let popup_area = centered_rect(area, 80, SEARCH_POPUP_HEIGHT);
+    // Borders and padding take up the rest
+    let text_width = popup_area.width.saturating_sub(4) as usize;
+    let list_height = popup_area.height.saturating_sub(2) as usize;
+
+    let entries: Vec<Line> = digest
+        .entries
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, entry)| {
+            let channel = chat_state.channels.get(entry.channel_idx)?;
+            let message = chat_state
+                .chat_history
+                .get(&channel.id)?
+                .iter()
+                .find(|message| message.message_id == entry.message_id)?;
+            let style = if idx == digest.selected { theme.selection } else { Style::default() };
+            let timestamp = Span::styled(
+                format!("[{}] ", global_state.config.timestamps.format(message.timestamp, "%d-%m ", false)),
+                style.patch(theme.timestamp),
+            );
+            let channel = Span::styled(format!("#{} ", channel.name), style.patch(theme.accent));
+            let author = Span::styled(format!("{} ", message.author_name), style.patch(theme.author));
+            let body = Span::styled(
+                padtruncate(
+                    &message.message.replace('\n', " "),
+                    text_width.saturating_sub(timestamp.width() + channel.width() + author.width()),
+                ),
+                style,
+            );
+            Some(Line::from(vec![timestamp, channel, author, body]))
+        })
+        .collect();
+
+    let lines = if entries.is_empty() {
+        vec![Line::from(Span::styled(
+            "Nothing mentioned you since the digest was opened last",
+            theme.placeholder,
+        ))]
+    } else {
+        // Keep the selected entry in view
+        let skip = digest.selected.saturating_sub(list_height.saturating_sub(1));
+        entries.into_iter().skip(skip).take(list_height).collect()
+    };
+
+    let since = match digest.since {
+        Some(since) => format!(" since {}", since.format("%d-%m %H:%M")),
+        None => String::new(),
+    };
+    let count = match digest.entries.len() {
+        0 => String::new(),
+        MAX_DIGEST_ENTRIES => format!(" ({MAX_DIGEST_ENTRIES}+)"),
+        count => format!(" ({count})"),
+    };
+    let widget = Paragraph::new(Text::from(lines)).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.focus)
+            .title(Span::styled(format!("Digest{since}{count}"), theme.header))
+            .title_bottom(Span::styled("[Enter] Jump to message | [Esc] Close", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_pins_popup(global_state: &GlobalState, chat_state: &ChatState, pins: &Pins, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let popup_area = centered_rect(area, 80, SEARCH_POPUP_HEIGHT);
+    // Borders and padding take up the rest
+    let text_width = popup_area.width.saturating_sub(4) as usize;
+    let list_height = popup_area.height.saturating_sub(2) as usize;
+
+    let lines = if pins.entries.is_empty() {
+        vec![Line::from(Span::styled(
+            "No pinned messages, pin one with P on a selected message",
+            theme.placeholder,
+        ))]
+    } else {
+        let entries = pins.entries.iter().enumerate().map(|(idx, pin)| {
+            let style = if idx == pins.selected { theme.selection } else { Style::default() };
+            let sent = DateTime::from_timestamp(pin.timestamp, 0).unwrap_or_default();
+            let timestamp = Span::styled(
+                format!("[{}] ", global_state.config.timestamps.format(sent, "%d-%m ", false)),
+                style.patch(theme.timestamp),
+            );
+            let author = Span::styled(format!("{} ", pin.author_name), style.patch(theme.author));
+            let body = Span::styled(
+                padtruncate(
+                    &pin.text.replace('\n', " "),
+                    text_width.saturating_sub(timestamp.width() + author.width()),
+                ),
+                style,
+            );
+            Line::from(vec![timestamp, author, body])
+        });
+        // Keep the selected entry in view
+        let skip = pins.selected.saturating_sub(list_height.saturating_sub(1));
+        entries.skip(skip).take(list_height).collect()
+    };
+
+    let channel = chat_state
+        .channels
+        .get(chat_state.active_channel_idx)
+        .map(|channel| chat_state.channel_label(channel))
+        .unwrap_or_default();
+    let widget = Paragraph::new(Text::from(lines)).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.focus)
+            .title(Span::styled(format!("Pinned in {channel} ({})", pins.entries.len()), theme.header))
+            .title_bottom(Span::styled("[Enter] Jump to message | [U]npin | [Esc] Close", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_profile_popup(
+    global_state: &GlobalState,
+    chat_state: &ChatState,
+    user_id: UserId,
+    frame: &mut Frame,
+    area: Rect,
+) -> Option<ImagePlacement> {
+    let theme = &global_state.theme;
+    let user = chat_state.users.iter().find(|user| user.id == user_id)?;
+    let (symbol, status_style) = user_status(theme, &user.status);
+    let watched = chat_state.preferences.watched_users.contains(&user_id);
+    let avatar = chat_state.avatars.get(&user.pfp_id);
+
+    // The profile picture goes on top, the lines start below the space left for it
+    let mut lines = vec![Line::from(""); avatar.map_or(0, |avatar| avatar.preview.rows as usize + 1)];
+    lines.extend([
+        Line::from(Span::styled(format!("{symbol} {:?}", user.status), status_style)),
+        Line::from(Span::styled(format!("id: {}", user.id), theme.hint)),
+        Line::from(""),
+    ]);
+    if user.bio.is_empty() {
+        lines.push(Line::from(Span::styled("No bio", theme.placeholder)));
+    } else {
+        lines.extend(user.bio.lines().map(|line| Line::from(line.to_owned())));
+    }
+
+    let activity = chat_state.user_activity(user_id, ACTIVITY_DAYS);
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::from(format!("Activity ({ACTIVITY_DAYS} days): ")),
+        Span::styled(format!("{} messages", activity.iter().sum::<usize>()), theme.hint),
+    ]));
+    lines.push(Line::from(Span::styled(sparkline(&activity), theme.accent)));
+
+    let channels = chat_state.channels_in_common(user_id);
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::from("Channels in common: "),
+        if channels.is_empty() {
+            Span::styled("none", theme.hint)
+        } else {
+            Span::from(channels.iter().map(|name| format!("#{name}")).collect::<Vec<_>>().join(", "))
+        },
+    ]));
+
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::from("Announce presence: "),
+        if watched {
+            Span::styled("on", theme.success)
+        } else {
+            Span::styled("off", theme.hint)
+        },
+    ]));
+
+    let popup_area = centered_rect(area, 40, lines.len() as u16 + 4);
+    let controls = if chat_state.own_user().is_some_and(|own_user| own_user.id == user_id) {
+        "[W]atch | [E]dit | [Esc] Close"
+    } else {
+        "[W]atch | [Z] Fold | [Esc] Close"
+    };
+    let widget = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.focus)
+            .title(Span::styled(user.name.clone(), theme.header))
+            .title_bottom(Span::styled(controls, theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+
+    let avatar = avatar?;
+    let inner = Block::default().padding(PADDING).borders(Borders::ALL).inner(popup_area);
+    let avatar_area = Rect {
+        width: avatar.preview.columns.min(inner.width),
+        height: avatar.preview.rows.min(inner.height),
+        ..inner
+    };
+    if global_state.graphics.uses_half_blocks() {
+        frame.render_widget(HalfBlockImage(&avatar.preview), avatar_area);
+        None
+    } else {
+        Some(ImagePlacement {
+            media_id: user.pfp_id,
+            area: avatar_area,
+            avatar: true,
+        })
+    }
+}
+
+/// Scales the counts to block characters, the busiest entry gets a full block
+fn sparkline(counts: &[usize]) -> String {
+    const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    counts.iter().map(|count| LEVELS[count * (LEVELS.len() - 1) / max]).collect()
+}
+
+fn render_cache_stats_popup(global_state: &GlobalState, stats: &CacheStats, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let row = |label: &str, value: String| Line::from(vec![Span::styled(format!("{label:<16}"), theme.text), Span::from(value)]);
+    let lines = vec![
+        row("file", stats.path.display().to_string()),
+        row("size", format_size(stats.size as usize)),
+        row("channels", stats.channels.to_string()),
+        row("messages", stats.messages.to_string()),
+        row(
+            "oldest message",
+            stats
+                .oldest
+                .map_or("none".to_owned(), |oldest| oldest.format("%d-%m-%Y %H:%M").to_string()),
+        ),
+        Line::from(""),
+        row(
+            "all servers",
+            format!(
+                "{} in {} file{}",
+                format_size(stats.total_size as usize),
+                stats.servers,
+                if stats.servers == 1 { "" } else { "s" }
+            ),
+        ),
+    ];
+
+    let popup_area = centered_rect(area, 80, lines.len() as u16 + 4);
+    let widget = Paragraph::new(Text::from(lines)).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.focus)
+            .title(Span::styled("History cache", theme.header))
+            .title_bottom(Span::styled("[Esc] Close", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_who_popup(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let now = Utc::now();
+    let mut counts = vec![];
+    for status in [UserStatus::Online, UserStatus::Idle, UserStatus::DoNotDisturb, UserStatus::Offline] {
+        let count = chat_state.users.iter().filter(|user| user.status == status).count();
+        if !counts.is_empty() {
+            counts.push(Span::styled(" · ", theme.hint));
         }
-        ChatFocus::Users(_) => "[←] Chat log | [↑↓] Move Selection | [V]iew | [L]ogs | [Q]uit",
-        ChatFocus::Logs => "[L]ogs | [Q]uit",
+        let (symbol, style) = user_status(theme, &status);
+        counts.push(Span::styled(format!("{symbol} {count} "), style));
+        counts.push(Span::from(status_label(&status)));
+    }
+
+    let user_line = |user: &User, last_active: Option<DateTime<Utc>>| {
+        let (symbol, style) = user_status(theme, &user.status);
+        Line::from(vec![
+            Span::styled(format!("  {symbol} "), style),
+            Span::from(user.name.clone()),
+            Span::styled(
+                match last_active {
+                    Some(timestamp) => format!(" {}", time_ago(now - timestamp)),
+                    None => " never seen".to_owned(),
+                },
+                theme.hint,
+            ),
+        ])
     };
 
-    let border_style = Style::default();
-    let widget = Paragraph::new(Text::from(info_text)).block(
+    let mut active: Vec<(&User, DateTime<Utc>)> = chat_state
+        .users
+        .iter()
+        .filter(|user| user.status != UserStatus::Offline)
+        .filter_map(|user| chat_state.last_active(user.id).map(|timestamp| (user, timestamp)))
+        .filter(|(_, timestamp)| now - *timestamp < RECENTLY_ACTIVE)
+        .collect();
+    active.sort_by_key(|(_, timestamp)| std::cmp::Reverse(*timestamp));
+
+    let mut away: Vec<(&User, Option<DateTime<Utc>>)> = chat_state
+        .users
+        .iter()
+        .filter(|user| matches!(user.status, UserStatus::Idle | UserStatus::DoNotDisturb | UserStatus::Offline))
+        .map(|user| (user, chat_state.last_active(user.id)))
+        .collect();
+    away.sort_by_key(|(_, timestamp)| std::cmp::Reverse(*timestamp));
+
+    let mut lines = vec![
+        Line::from(counts),
+        Line::from(""),
+        Line::from(Span::styled("Recently active", theme.header)),
+    ];
+    if active.is_empty() {
+        lines.push(Line::from(Span::styled("  nobody", theme.hint)));
+    }
+    lines.extend(
+        active
+            .iter()
+            .take(WHO_LIST_LENGTH)
+            .map(|(user, timestamp)| user_line(user, Some(*timestamp))),
+    );
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled("Away", theme.header)));
+    if away.is_empty() {
+        lines.push(Line::from(Span::styled("  nobody", theme.hint)));
+    }
+    lines.extend(away.iter().take(WHO_LIST_LENGTH).map(|(user, timestamp)| user_line(user, *timestamp)));
+
+    let popup_area = centered_rect(area, 60, lines.len() as u16 + 4);
+    let widget = Paragraph::new(Text::from(lines)).block(
         Block::default()
             .padding(PADDING)
-            .borders(Borders::LEFT | Borders::RIGHT | Borders::BOTTOM)
-            .border_style(border_style),
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.focus)
+            .title(Span::styled(format!("Who [{}]", chat_state.users.len()), theme.header))
+            .title_bottom(Span::styled("[Esc] Close", theme.hint)),
     );
-    frame.render_widget(widget, area);
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
 }
 
-fn render_logs(global_state: &GlobalState, chat_state: &ChatState, frame: &mut Frame, area: Rect) {
-    let current_log_count = global_state.logs.len();
-    let start_index = current_log_count
-        .saturating_sub(area.height.saturating_sub(2) as usize)
-        .saturating_sub(global_state.log_scroll_offset);
+fn render_lock_screen(global_state: &GlobalState, lock_screen: &LockScreen, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let secret = if global_state.config.lock_pin.is_some() { "PIN" } else { "password" };
+    let mut lines = vec![
+        Line::from(format!("Type your {secret} to unlock")),
+        Line::from(""),
+        Line::from(Span::styled("*".repeat(lock_screen.input.chars().count()), theme.attention)),
+    ];
+    if lock_screen.failed_attempts > 0 {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            format!("Wrong {secret}, {} failed attempts", lock_screen.failed_attempts),
+            theme.error,
+        )));
+    }
 
-    let logs: Vec<Line> = global_state.logs.iter().skip(start_index).map(|entry| entry.format()).collect();
+    let popup_area = centered_rect(area, 40, lines.len() as u16 + 4);
+    let widget = Paragraph::new(Text::from(lines)).centered().block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.focus)
+            .title(Span::styled("Locked", theme.header))
+            .title_bottom(Span::styled("[Enter] Unlock", theme.hint)),
+    );
+    frame.render_widget(widget, popup_area);
+}
+
+fn status_label(status: &UserStatus) -> &'static str {
+    match status {
+        UserStatus::Offline => "offline",
+        UserStatus::Online => "online",
+        UserStatus::Idle => "idle",
+        UserStatus::DoNotDisturb => "do not disturb",
+    }
+}
 
-    let (borders, border_style, border_corners) = borders_logs(chat_state);
+fn time_ago(elapsed: TimeDelta) -> String {
+    match elapsed.num_seconds() {
+        ..60 => "just now".to_owned(),
+        seconds @ ..3600 => format!("{}m ago", seconds / 60),
+        seconds @ ..86400 => format!("{}h ago", seconds / 3600),
+        seconds => format!("{}d ago", seconds / 86400),
+    }
+}
 
-    let widget = Paragraph::new(Text::from(logs)).wrap(Wrap { trim: true }).block(
+fn render_compress_popup(global_state: &GlobalState, attachment: &PendingAttachment, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let lines = vec![
+        Line::from(format!(
+            "{} is {}, the upload limit is {}.",
+            attachment.filename,
+            format_size(attachment.data.len()),
+            format_size(global_state.config.max_upload_size.min(MAX_MEDIA_SIZE))
+        )),
+        Line::from(""),
+        Line::from("Downscale the image so it fits?"),
+    ];
+
+    let popup_area = centered_rect(area, 50, lines.len() as u16 + 6);
+    let widget = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: false }).block(
         Block::default()
             .padding(PADDING)
-            .border_set(border_corners)
-            .borders(borders)
-            .border_style(border_style)
-            .title(Span::styled("Log".to_string(), HEADER_STYLE)),
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.attention)
+            .title(Span::styled("Attachment too large", theme.header))
+            .title_bottom(Span::styled("[Y]es | [N]o", theme.hint)),
     );
-    frame.render_widget(widget, area);
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_quit_guard_popup(global_state: &GlobalState, chat_state: &ChatState, waiting: bool, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let mut lines: Vec<Line> = chat_state
+        .pending_operations()
+        .into_iter()
+        .map(|operation| Line::from(format!("• {operation}")))
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(if waiting {
+        Line::styled("Quitting once these are done...", theme.hint)
+    } else {
+        Line::from("Quitting now loses them.")
+    });
+
+    let popup_area = centered_rect(area, 50, lines.len() as u16 + 6);
+    let widget = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.attention)
+            .title(Span::styled("Still sending", theme.header))
+            .title_bottom(Span::styled("[W]ait | [F]orce quit | [C]ancel", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_paste_image_popup(global_state: &GlobalState, image: &ClipboardImage, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let lines = vec![
+        Line::from(format!(
+            "The clipboard holds a {}x{} image ({} as PNG).",
+            image.width,
+            image.height,
+            format_size(image.attachment.data.len())
+        )),
+        Line::from(""),
+        Line::from("Attach it to the message?"),
+    ];
+
+    let popup_area = centered_rect(area, 50, lines.len() as u16 + 6);
+    let widget = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .border_style(theme.attention)
+            .title(Span::styled("Paste image", theme.header))
+            .title_bottom(Span::styled("[Y]es | [N]o", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_changelog_popup(global_state: &GlobalState, changelog: &Changelog, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let lines: Vec<Line> = changelog
+        .lines
+        .iter()
+        .skip(changelog.scroll)
+        .map(|line| {
+            if let Some(version) = line.strip_prefix("## ") {
+                Line::from(Span::styled(version, theme.attention.patch(theme.header)))
+            } else if let Some(heading) = line.strip_prefix("### ") {
+                Line::from(Span::styled(heading, theme.accent))
+            } else if let Some(item) = line.strip_prefix("- ") {
+                Line::from(format!(" • {item}"))
+            } else {
+                Line::from(*line)
+            }
+        })
+        .collect();
+
+    let popup_area = centered_rect(area, 80, (changelog.lines.len() as u16 + 4).min(SEARCH_POPUP_HEIGHT));
+    let widget = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .title(Span::styled(changelog.title.clone(), theme.header))
+            .title_bottom(Span::styled("[↑↓] Scroll | [Esc] Close", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_expanded_message_popup(global_state: &GlobalState, expanded: &ExpandedMessage, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let lines: Vec<Line> = expanded
+        .lines
+        .iter()
+        .skip(expanded.scroll)
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    let title = Line::from(vec![
+        Span::styled(expanded.author_name.clone(), theme.header),
+        Span::styled(format!(" [{}]", expanded.timestamp), theme.timestamp),
+    ]);
+
+    // Wide enough for the wrapped lines, with the borders and the padding around them
+    let popup_area = centered_rect(
+        area,
+        EXPANDED_MESSAGE_WIDTH as u16 + 4,
+        (expanded.lines.len() as u16 + 2).min(SEARCH_POPUP_HEIGHT),
+    );
+    let widget = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .title(title)
+            .title_bottom(Span::styled(
+                format!("[↑↓] Scroll {}/{} | [Esc] Close", expanded.scroll + 1, expanded.lines.len()),
+                theme.hint,
+            )),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_profile_editor_popup(global_state: &GlobalState, editor: &ProfileEditor, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let fields = [
+        ("Name", ProfileField::Name, editor.name.as_str()),
+        ("Bio", ProfileField::Bio, editor.bio.as_str()),
+        (
+            "Picture (PNG file, empty keeps the current one)",
+            ProfileField::Picture,
+            editor.picture.as_str(),
+        ),
+    ];
+    let mut lines = vec![];
+    for (label, field, value) in fields {
+        let focused = editor.field == field;
+        let label_style = if focused { theme.attention.patch(theme.header) } else { theme.attention };
+        lines.push(Line::from(Span::styled(label, label_style)));
+        let mut input = vec![Span::from(value.to_owned())];
+        if focused {
+            input.push(Span::styled(" ", theme.cursor));
+        }
+        lines.push(Line::from(input));
+        lines.push(Line::from(""));
+    }
+
+    let popup_area = centered_rect(area, 60, 13);
+    let widget = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .title(Span::styled("Edit Profile", theme.header))
+            .title_bottom(Span::styled("[Enter] Save | [Tab] Next field | [Esc] Cancel", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+/// Passwords are drawn as one `*` per character
+fn render_password_editor_popup(global_state: &GlobalState, editor: &PasswordEditor, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let fields = [
+        ("Current password", PasswordField::Current, &editor.current),
+        ("New password", PasswordField::New, &editor.new),
+        ("New password again", PasswordField::Confirm, &editor.confirm),
+    ];
+    let mut lines = vec![];
+    for (label, field, value) in fields {
+        let focused = editor.field == field;
+        let label_style = if focused { theme.attention.patch(theme.header) } else { theme.attention };
+        lines.push(Line::from(Span::styled(label, label_style)));
+        let mut input = vec![Span::from("*".repeat(value.expose().chars().count()))];
+        if focused {
+            input.push(Span::styled(" ", theme.cursor));
+        }
+        lines.push(Line::from(input));
+        lines.push(Line::from(""));
+    }
+    lines.push(Line::from(Span::styled("You log in again once it is changed", theme.placeholder)));
+
+    let popup_area = centered_rect(area, 60, 14);
+    let widget = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .title(Span::styled("Change Password", theme.header))
+            .title_bottom(Span::styled("[Enter] Change | [Tab] Next field | [Esc] Cancel", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn render_file_picker_popup(global_state: &GlobalState, picker: &FilePicker, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let mut lines = vec![
+        Line::from(vec![Span::from(picker.path.clone()), Span::styled(" ", theme.cursor)]),
+        Line::from(""),
+    ];
+    lines.extend(
+        picker
+            .suggestions
+            .iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|suggestion| Line::from(Span::styled(suggestion.clone(), theme.hint))),
+    );
+    if picker.suggestions.len() > MAX_SUGGESTIONS {
+        lines.push(Line::from(Span::styled(
+            format!("and {} more", picker.suggestions.len() - MAX_SUGGESTIONS),
+            theme.placeholder,
+        )));
+    }
+
+    let popup_area = centered_rect(area, 70, MAX_SUGGESTIONS as u16 + 6);
+    let widget = Paragraph::new(Text::from(lines)).block(
+        Block::default()
+            .padding(PADDING)
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .title(Span::styled("Attach File", theme.header))
+            .title_bottom(Span::styled("[Enter] Attach | [Tab] Complete | [Esc] Cancel", theme.hint)),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
 }
 
 #[allow(clippy::ptr_arg)] // TODO fix
@@ -614,26 +2032,88 @@ fn is_typing(is_typing: &Vec<String>) -> String {
     }
 }
 
-fn user_status(status: &UserStatus) -> (String, Style) {
+fn user_status(theme: &Theme, status: &UserStatus) -> (&'static str, Style) {
     match status {
-        UserStatus::Offline => ("●".to_owned(), Style::default().fg(Color::Gray).add_modifier(Modifier::DIM)),
-        UserStatus::Online => ("●".to_owned(), Style::default().fg(Color::Green)),
-        UserStatus::Idle => ("●".to_owned(), Style::default().fg(Color::Yellow)),
-        UserStatus::DoNotDisturb => ("●".to_owned(), Style::default().fg(Color::Red)),
+        UserStatus::Offline => ("●", theme.offline),
+        UserStatus::Online => ("●", theme.online),
+        UserStatus::Idle => ("●", theme.idle),
+        UserStatus::DoNotDisturb => ("●", theme.do_not_disturb),
+    }
+}
+
+/// Message text wrapped to the width of the history, with the `#channel` links in it highlighted.
+/// Messages longer than `max_lines` are cut off, the number of lines left out is returned with the lines
+fn message_body<'a>(
+    theme: &Theme,
+    indent: &str,
+    text: &'a str,
+    channels: &[DisplayChannel],
+    style: Style,
+    width: usize,
+    max_lines: usize,
+) -> (Vec<Line<'a>>, usize) {
+    let links: Vec<Range<usize>> = find_channel_links(text, channels).into_iter().map(|(range, _)| range).collect();
+    let ranges = wrap_ranges(text, width.saturating_sub(display_width(indent)));
+    let hidden = ranges.len().saturating_sub(max_lines);
+    let lines = ranges[..ranges.len() - hidden]
+        .iter()
+        .map(|range| message_line(theme, indent, text, range.clone(), &links, style, width))
+        .collect();
+    (lines, hidden)
+}
+
+/// One wrapped line of a message, `links` are the byte ranges of the channel links in the whole text
+fn message_line<'a>(theme: &Theme, indent: &str, text: &'a str, line: Range<usize>, links: &[Range<usize>], style: Style, width: usize) -> Line<'a> {
+    let mut spans = vec![Span::styled(indent.to_owned(), style)];
+    let mut written = line.start;
+    // A link can be split over two lines when it is longer than a line
+    for link in links.iter().filter(|link| link.start < line.end && link.end > line.start) {
+        let (start, end) = (link.start.max(line.start), link.end.min(line.end));
+        spans.push(Span::styled(&text[written..start], style));
+        spans.push(Span::styled(&text[start..end], style.patch(theme.link)));
+        written = end;
     }
+    spans.push(Span::styled(&text[written..line.end], style));
+    let used: usize = spans.iter().map(|span| display_width(&span.content)).sum();
+    spans.push(Span::styled(" ".repeat(width.saturating_sub(used)), style));
+    Line::from(spans)
+}
+
+/// Columns a string takes up in the terminal. Counted per grapheme the way the buffer draws it,
+/// so emoji joined by zero width joiners or followed by a skin tone modifier count as the one glyph they show as
+fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(UnicodeWidthStr::width).sum()
 }
 
 fn pad_to_width(line: &str, width: usize) -> String {
-    let current_len = line.len();
-    let pad_len = width.saturating_sub(current_len);
+    let pad_len = width.saturating_sub(display_width(line));
     format!("{line}{}", " ".repeat(pad_len))
 }
 
-fn padtruncate(string: &str, max_len: usize) -> String {
-    let string_len = string.chars().count();
-    if string_len < max_len {
-        format!("{string}{}", " ".repeat(max_len.saturating_sub(string_len)))
-    } else {
-        format!("{}...", string.chars().take(max_len).collect::<String>()) // TODO the case where the string is the exact width
+/// Pads or cuts a string to exactly `width` columns, a cut string ends in an ellipsis.
+fn padtruncate(string: &str, width: usize) -> String {
+    // A wide glyph that did not fit leaves a column to pad
+    pad_to_width(&truncate_to_width(string, width), width)
+}
+
+/// Cuts a string to at most `width` columns, ending in an ellipsis when it was cut.
+/// Cuts fall between graphemes, so an emoji sequence is never split into its parts and a double width character
+/// is never drawn half
+fn truncate_to_width(string: &str, width: usize) -> String {
+    if display_width(string) <= width {
+        return string.to_owned();
+    }
+    let ellipsis = &"..."[..width.min(3)];
+    let room = width - ellipsis.len();
+    let mut used = 0;
+    let mut cut = String::new();
+    for grapheme in string.graphemes(true) {
+        let grapheme_width = grapheme.width();
+        if used + grapheme_width > room {
+            break;
+        }
+        used += grapheme_width;
+        cut.push_str(grapheme);
     }
+    format!("{cut}{ellipsis}")
 }
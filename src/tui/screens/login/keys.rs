@@ -1,6 +1,7 @@
-use ratatui::crossterm::event::{Event, KeyCode, KeyModifiers};
+use ratatui::crossterm::event::{Event, KeyCode};
 
 use crate::tui::events::TuiEvent;
+use crate::tui::input::input_key_event;
 use crate::tui::screens::login::LoginFocus;
 
 pub fn handle_login_key_event(event: Event, focus: LoginFocus) -> Option<TuiEvent> {
@@ -8,54 +9,65 @@ pub fn handle_login_key_event(event: Event, focus: LoginFocus) -> Option<TuiEven
     use LoginFocus::*;
 
     match event {
+        // A known server is picked with its number from anywhere but the inputs
+        Event::Key(key_event)
+            if matches!(focus, LoginButton | KnownServers(_) | Nothing)
+                && let Char(digit @ '1'..='9') = key_event.code =>
+        {
+            Some(TuiEvent::PickKnownServer(digit as usize - '1' as usize))
+        }
         Event::Key(key_event) => match focus {
             UsernameInput(idx) => match key_event.code {
-                Left if key_event.modifiers == KeyModifiers::CONTROL => Some(TuiEvent::InputLeftTab),
-                Left => Some(TuiEvent::InputLeft),
-                Right if key_event.modifiers == KeyModifiers::CONTROL => Some(TuiEvent::InputRightTab),
-                Right => Some(TuiEvent::InputRight),
                 Down | Tab | Enter => Some(TuiEvent::LoginFocusChange(LoginFocus::PasswordInput(idx))),
-                Backspace => Some(TuiEvent::InputDelete),
                 Esc => Some(TuiEvent::LoginFocusChange(LoginFocus::Nothing)),
-                Char(chr) => Some(TuiEvent::InputChar(chr)),
-
-                _ => None,
+                _ => input_key_event(&key_event),
             },
             PasswordInput(idx) => match key_event.code {
                 Up | BackTab => Some(TuiEvent::LoginFocusChange(LoginFocus::UsernameInput(idx))),
                 Down | Tab | Enter => Some(TuiEvent::LoginFocusChange(LoginFocus::ServerAddressInput(idx))),
-                Left if key_event.modifiers == KeyModifiers::CONTROL => Some(TuiEvent::InputLeftTab),
-                Left => Some(TuiEvent::InputLeft),
-                Right if key_event.modifiers == KeyModifiers::CONTROL => Some(TuiEvent::InputRightTab),
-                Right => Some(TuiEvent::InputRight),
-                Backspace => Some(TuiEvent::InputDelete),
                 Esc => Some(TuiEvent::LoginFocusChange(LoginFocus::Nothing)),
-                Char(chr) => Some(TuiEvent::InputChar(chr)),
-                _ => None,
+                _ => input_key_event(&key_event),
             },
             ServerAddressInput(idx) => match key_event.code {
                 Up | BackTab => Some(TuiEvent::LoginFocusChange(LoginFocus::PasswordInput(idx))),
                 Down | Tab | Enter => Some(TuiEvent::LoginFocusChange(LoginFocus::LoginButton)),
-                Left if key_event.modifiers == KeyModifiers::CONTROL => Some(TuiEvent::InputLeftTab),
-                Left => Some(TuiEvent::InputLeft),
-                Right if key_event.modifiers == KeyModifiers::CONTROL => Some(TuiEvent::InputRightTab),
-                Right => Some(TuiEvent::InputRight),
-                Backspace => Some(TuiEvent::InputDelete),
                 Esc => Some(TuiEvent::LoginFocusChange(LoginFocus::Nothing)),
-                Char(chr) => Some(TuiEvent::InputChar(chr)),
-                _ => None,
+                _ => input_key_event(&key_event),
+            },
+            LoginCodeInput(_) => match key_event.code {
+                Enter => Some(TuiEvent::SubmitLoginCode),
+                Esc => Some(TuiEvent::CancelLoginCode),
+                _ => input_key_event(&key_event),
             },
             LoginButton => match key_event.code {
                 Char('q') | Char('Q') => Some(TuiEvent::Exit),
                 Char('l') | Char('L') => Some(TuiEvent::ToggleLogs),
+                Char('t') | Char('T') => Some(TuiEvent::TestConnection),
                 Up | BackTab => Some(TuiEvent::LoginFocusChange(LoginFocus::ServerAddressInput(0))),
+                Down | Tab => Some(TuiEvent::LoginFocusChange(LoginFocus::KnownServers(0))),
                 Esc => Some(TuiEvent::LoginFocusChange(LoginFocus::Nothing)),
                 Enter => Some(TuiEvent::Login),
                 _ => None,
             },
+            KnownServers(idx) => match key_event.code {
+                Up | BackTab if idx == 0 => Some(TuiEvent::LoginFocusChange(LoginFocus::LoginButton)),
+                Up | BackTab => Some(TuiEvent::LoginFocusChange(LoginFocus::KnownServers(idx - 1))),
+                Down | Tab => Some(TuiEvent::LoginFocusChange(LoginFocus::KnownServers(idx + 1))),
+                Enter => Some(TuiEvent::PickKnownServer(idx)),
+                Delete | Backspace | Char('d') | Char('D') => Some(TuiEvent::ForgetKnownServer(idx)),
+                Esc => Some(TuiEvent::LoginFocusChange(LoginFocus::Nothing)),
+                Char('q') | Char('Q') => Some(TuiEvent::Exit),
+                _ => None,
+            },
+            ConnectionTest => match key_event.code {
+                Char('t') | Char('T') => Some(TuiEvent::TestConnection),
+                Esc | Enter => Some(TuiEvent::LoginFocusChange(LoginFocus::LoginButton)),
+                _ => None,
+            },
             Nothing => match key_event.code {
                 Char('q') | Char('Q') => Some(TuiEvent::Exit),
                 Char('l') | Char('L') => Some(TuiEvent::ToggleLogs),
+                Char('t') | Char('T') => Some(TuiEvent::TestConnection),
                 Char(_) | Tab | Up | Down | Left | Right | Enter => Some(TuiEvent::LoginFocusChange(LoginFocus::UsernameInput(0))),
                 _ => None,
             },
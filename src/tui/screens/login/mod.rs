@@ -1,33 +1,91 @@
 pub mod keys;
 pub mod ui;
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{self, ErrorKind};
 use std::net::SocketAddr;
 
 use anyhow::{Result, anyhow};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use tokio::net::lookup_host;
 use tokio::sync::mpsc::Sender;
 use tokio::time::Instant;
 
-use crate::cli::{DEFAULT_ADDRESS, DEFAULT_PORT};
+use crate::cli::{AppConfig, DEFAULT_ADDRESS, DEFAULT_PORT};
+use crate::media::cache::MediaCache;
+use crate::media::fetch::MediaFetcher;
 use crate::network::client::{Client, ConnectionType, ServerAddrInfo, ServerConnectionStatus};
-use crate::network::protocol::UserStatus;
-use crate::tui::events::TuiEvent;
+use crate::network::probe::{ProbeOutcome, ProbeStage, probe_connection};
+use crate::network::protocol::server::DisconnectReason;
+use crate::network::protocol::{Capabilities, UserStatus};
+use crate::storage::channel_groups::ChannelGroups;
+use crate::storage::checkpoint::Checkpoint;
+use crate::storage::history_cache::HistoryCache;
+use crate::storage::known_servers::KnownServers;
+use crate::storage::preferences::ServerPreferences;
+use crate::storage::secrets::{Secret, is_login_code, save_totp_secret, totp_code, totp_secret};
+use crate::tui::events::{TuiEvent, UserId};
+use crate::tui::input::{apply_edit, insert_char};
 use crate::tui::screens::Screen;
-use crate::tui::screens::chat::{ChatFocus, ChatState, UserProfile};
+use crate::tui::screens::chat::changelog::whats_new;
+use crate::tui::screens::chat::clock_skew::ClockSkew;
+use crate::tui::screens::chat::pagination::HistoryPagination;
+use crate::tui::screens::chat::startup::StartupSequence;
+use crate::tui::screens::chat::{ChatFocus, ChatState, Popup, UserProfile, handle_chat_event};
+use crate::tui::theme::Theme;
 use crate::tui::{AppState, State};
 
+/// Long enough for the base32 secret of an authenticator
+const MAX_LOGIN_CODE_INPUT: usize = 128;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum LoginFocus {
     UsernameInput(usize),
     PasswordInput(usize),
     ServerAddressInput(usize),
+    LoginCodeInput(usize),
     LoginButton,
+    /// Shows the results of the connection test
+    ConnectionTest,
+    /// A server of the known servers list under the form is selected
+    KnownServers(usize),
     Nothing,
 }
 
+impl LoginFocus {
+    /// Byte offset of the cursor when an input is focused
+    pub fn cursor(self) -> Option<usize> {
+        match self {
+            LoginFocus::UsernameInput(cursor)
+            | LoginFocus::PasswordInput(cursor)
+            | LoginFocus::ServerAddressInput(cursor)
+            | LoginFocus::LoginCodeInput(cursor) => Some(cursor),
+            LoginFocus::LoginButton | LoginFocus::ConnectionTest | LoginFocus::KnownServers(_) | LoginFocus::Nothing => None,
+        }
+    }
+
+    /// The same input with the cursor moved to `cursor`
+    pub fn with_cursor(self, cursor: usize) -> Self {
+        match self {
+            LoginFocus::UsernameInput(_) => LoginFocus::UsernameInput(cursor),
+            LoginFocus::PasswordInput(_) => LoginFocus::PasswordInput(cursor),
+            LoginFocus::ServerAddressInput(_) => LoginFocus::ServerAddressInput(cursor),
+            LoginFocus::LoginCodeInput(_) => LoginFocus::LoginCodeInput(cursor),
+            focus => focus,
+        }
+    }
+
+    /// Longest text in bytes the focused input accepts
+    fn max_input_len(self) -> usize {
+        match self {
+            LoginFocus::UsernameInput(_) => 129,
+            LoginFocus::PasswordInput(_) => 1025,
+            LoginFocus::ServerAddressInput(_) => 64,
+            _ => MAX_LOGIN_CODE_INPUT,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum InputStatus {
     AllFine,
@@ -38,17 +96,83 @@ pub enum InputStatus {
     ServerNotFound,
     AddressNotParsable,
     UnknownError,
+    /// The password was changed from the chat, the new one has to be typed
+    PasswordChanged,
+}
+
+impl InputStatus {
+    pub fn from_login_failure(message: &str) -> Self {
+        match message {
+            "Incorrect username or password." => InputStatus::IncorrectUsernameOrPassword,
+            _ => InputStatus::FailedToLogin,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct LoginState {
     pub username_input: String,
-    pub password_input: String,
+    pub password_input: Secret,
     pub server_address_input: String,
     pub server_address: Option<ServerAddrInfo>,
     pub focus: LoginFocus,
     pub input_status: InputStatus,
     pub enable_tls: bool,
+    /// Shown once the password is accepted by a server that also wants a login code
+    pub login_code: Option<LoginCodePrompt>,
+    /// Set when the account switcher filled in an account, it is logged in to on the next tick
+    pub login_pending: bool,
+    /// Why the server ended the last session, shown until the next login attempt. Boxed as it is rarely set
+    pub goodbye: Option<Box<(DisconnectReason, String)>>,
+    /// Stages of the last connection test that have been reached
+    pub connection_probe: Vec<(ProbeStage, ProbeOutcome)>,
+    /// Servers logged in to before, listed under the form
+    pub known_servers: KnownServers,
+}
+
+impl LoginState {
+    /// Fills the form with the last successful login, unless the CLI or the config file named a server or username.
+    /// Passwords are not remembered, it is taken from a matching account or kept when the username did not change
+    pub fn prefill_last_login(&mut self, config: &AppConfig) {
+        if !config.remember_login || !config.default_login {
+            return;
+        }
+        let Some(last) = self.known_servers.servers.first() else {
+            return;
+        };
+        if let Some(password) = config.account_password(&last.address, &last.username) {
+            self.password_input = password;
+        } else if last.username != self.username_input {
+            self.password_input = Secret::default();
+            self.focus = LoginFocus::PasswordInput(0);
+        }
+        self.server_address_input = last.address.clone();
+        self.enable_tls = last.enable_tls;
+        self.username_input = last.username.clone();
+    }
+
+    /// Text of the input that has focus
+    fn focused_input(&mut self) -> Option<&mut String> {
+        match self.focus {
+            LoginFocus::UsernameInput(_) => Some(&mut self.username_input),
+            LoginFocus::PasswordInput(_) => Some(self.password_input.expose_mut()),
+            LoginFocus::ServerAddressInput(_) => Some(&mut self.server_address_input),
+            LoginFocus::LoginCodeInput(_) => self.login_code.as_mut().map(|prompt| &mut prompt.input),
+            LoginFocus::LoginButton | LoginFocus::ConnectionTest | LoginFocus::KnownServers(_) | LoginFocus::Nothing => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LoginCodePrompt {
+    /// A login code, or the secret of the authenticator to remember in the keyring
+    pub input: String,
+    /// Why the last code was rejected
+    pub message: Option<String>,
+    /// Waiting for the server to accept the code
+    pub submitted: bool,
+    /// Secret the submitted code was generated from, remembered in the keyring once the server accepts the code
+    pub pending_secret: Option<Secret>,
 }
 
 pub async fn handle_login_event(tui: &mut State, event: TuiEvent, client: &mut Client) -> Result<()> {
@@ -59,128 +183,102 @@ pub async fn handle_login_event(tui: &mut State, event: TuiEvent, client: &mut C
 
     use TuiEvent::*;
     match event {
-        LoginFocusChange(focus) => login_state.focus = focus,
-        InputChar(chr) => match login_state.focus {
-            LoginFocus::UsernameInput(i) if i < 129 => {
-                login_state.username_input.insert(i, chr);
-                login_state.focus = LoginFocus::UsernameInput(i + 1);
-                login_state.input_status = InputStatus::AllFine;
-            }
-            LoginFocus::PasswordInput(i) if i < 1025 => {
-                login_state.password_input.insert(i, chr);
-                login_state.focus = LoginFocus::PasswordInput(i + 1);
-                login_state.input_status = InputStatus::AllFine;
-            }
-            LoginFocus::ServerAddressInput(i) if i < 64 => {
-                login_state.server_address_input.insert(i, chr);
-                login_state.focus = LoginFocus::ServerAddressInput(i + 1);
-                login_state.input_status = InputStatus::AllFine;
+        LoginFocusChange(LoginFocus::KnownServers(idx)) => {
+            if let Some(last) = login_state.known_servers.servers.len().checked_sub(1) {
+                login_state.focus = LoginFocus::KnownServers(idx.min(last));
             }
-            _ => {}
-        },
-        InputDelete => match login_state.focus {
-            LoginFocus::UsernameInput(i) if i > 0 => {
-                login_state.username_input.remove(i - 1);
-                login_state.focus = LoginFocus::UsernameInput(i - 1);
-                login_state.input_status = InputStatus::AllFine;
-            }
-            LoginFocus::PasswordInput(i) if i > 0 => {
-                login_state.password_input.remove(i - 1);
-                login_state.focus = LoginFocus::PasswordInput(i - 1);
-                login_state.input_status = InputStatus::AllFine;
+        }
+        LoginFocusChange(focus) => login_state.focus = focus,
+        PickKnownServer(idx) => {
+            let Some(server) = login_state.known_servers.servers.get(idx).cloned() else {
+                return Ok(());
+            };
+            let same_user = login_state.username_input == server.username;
+            login_state.server_address_input = server.address.clone();
+            login_state.enable_tls = server.enable_tls;
+            login_state.username_input = server.username.clone();
+            login_state.input_status = InputStatus::AllFine;
+            login_state.goodbye = None;
+            let account_password = tui.global_state.config.account_password(&server.address, &server.username);
+            // Passwords are not remembered with the servers, only accounts of the config file can log in without typing it
+            login_state.focus = match account_password {
+                Some(password) => {
+                    login_state.password_input = password;
+                    login_state.login_pending = true;
+                    LoginFocus::Nothing
+                }
+                None if same_user && !login_state.password_input.expose().is_empty() => LoginFocus::LoginButton,
+                None => {
+                    login_state.password_input = Secret::default();
+                    LoginFocus::PasswordInput(0)
+                }
+            };
+        }
+        ForgetKnownServer(idx) => {
+            login_state.known_servers.forget(idx);
+            if let Err(e) = login_state.known_servers.save() {
+                warn!("Failed to save the known servers: {e}");
             }
-            LoginFocus::ServerAddressInput(i) if i > 0 => {
-                login_state.server_address_input.remove(i - 1);
-                login_state.focus = LoginFocus::ServerAddressInput(i - 1);
-                login_state.input_status = InputStatus::AllFine;
+            login_state.focus = match login_state.known_servers.servers.len().checked_sub(1) {
+                Some(last) => LoginFocus::KnownServers(idx.min(last)),
+                None => LoginFocus::LoginButton,
+            };
+        }
+        InputChar(chr) => {
+            let focus = login_state.focus;
+            if let Some(cursor) = focus.cursor()
+                && let Some(input) = login_state.focused_input()
+                && input.len() < focus.max_input_len()
+            {
+                let cursor = insert_char(input, cursor, chr);
+                login_state.focus = login_state.focus.with_cursor(cursor);
                 login_state.input_status = InputStatus::AllFine;
             }
-            _ => {}
-        },
-        InputLeft => match login_state.focus {
-            LoginFocus::UsernameInput(i) if i > 0 => login_state.focus = LoginFocus::UsernameInput(i - 1),
-            LoginFocus::PasswordInput(i) if i > 0 => login_state.focus = LoginFocus::PasswordInput(i - 1),
-            LoginFocus::ServerAddressInput(i) if i > 0 => login_state.focus = LoginFocus::ServerAddressInput(i - 1),
-            _ => {}
-        },
-        InputRight => match login_state.focus {
-            LoginFocus::UsernameInput(i) if i < login_state.username_input.len() => login_state.focus = LoginFocus::UsernameInput(i + 1),
-            LoginFocus::PasswordInput(i) if i < login_state.password_input.len() => login_state.focus = LoginFocus::PasswordInput(i + 1),
-            LoginFocus::ServerAddressInput(i) if i < login_state.server_address_input.len() => {
-                login_state.focus = LoginFocus::ServerAddressInput(i + 1)
+        }
+        InputEdit(edit) => {
+            if let Some(cursor) = login_state.focus.cursor()
+                && let Some(input) = login_state.focused_input()
+            {
+                let cursor = apply_edit(input, cursor, edit);
+                login_state.focus = login_state.focus.with_cursor(cursor);
+                if edit.changes_text() {
+                    login_state.input_status = InputStatus::AllFine;
+                }
             }
-            _ => {}
-        },
-        InputLeftTab => match login_state.focus {
-            LoginFocus::UsernameInput(_) => login_state.focus = LoginFocus::UsernameInput(0),
-            LoginFocus::PasswordInput(_) => login_state.focus = LoginFocus::PasswordInput(0),
-            LoginFocus::ServerAddressInput(_) => login_state.focus = LoginFocus::ServerAddressInput(0),
-            _ => {}
-        },
-        InputRightTab => match login_state.focus {
-            LoginFocus::UsernameInput(_) => login_state.focus = LoginFocus::UsernameInput(login_state.username_input.len()),
-            LoginFocus::PasswordInput(_) => login_state.focus = LoginFocus::PasswordInput(login_state.password_input.len()),
-            LoginFocus::ServerAddressInput(_) => login_state.focus = LoginFocus::ServerAddressInput(login_state.server_address_input.len()),
-            _ => {}
-        },
-        Login => {
-            let server_address_raw = login_state.server_address_input.trim();
-
-            let server_address = match server_address_raw.parse::<SocketAddr>() {
-                Ok(addr) => {
-                    if login_state.enable_tls {
-                        return Err(anyhow!("Unable to make TLS connection without a domain"));
-                    }
-                    ServerAddrInfo {
-                        ip: addr.ip(),
-                        port: addr.port(),
-                        domain: None,
-                        connection_type: ConnectionType::Raw,
-                    }
+        }
+        TestConnection => {
+            login_state.focus = LoginFocus::ConnectionTest;
+            login_state.connection_probe = vec![(ProbeStage::Lookup, ProbeOutcome::Running)];
+            let input_status = login_state.input_status.clone();
+            let lookup = resolve_server_address(login_state).await;
+            // A failed test is reported in its own popup, the form is left as it was
+            login_state.input_status = input_status;
+            match lookup {
+                Ok(server_address) => {
+                    let resolved = match &server_address.domain {
+                        Some(domain) => format!("{domain} is {}", server_address.ip),
+                        None => format!("{} needs no lookup", server_address.ip),
+                    };
+                    login_state.connection_probe = vec![(ProbeStage::Lookup, ProbeOutcome::Passed(resolved))];
+                    tokio::spawn(probe_connection(server_address, client.event_sender()));
                 }
                 Err(e) => {
-                    debug!("Looking up {server_address_raw} using DNS");
-                    let mut chunks = server_address_raw.split(':');
-                    let domain = if let Some(domain) = chunks.next() {
-                        domain
-                    } else {
-                        return Err(anyhow!("Unable to parse address {server_address_raw}"));
-                    };
-                    let port: &str = if let Some(port) = chunks.next() {
-                        port
-                    } else {
-                        &DEFAULT_PORT.to_string()
-                    };
-
-                    let mut possible_server_addrs: Vec<SocketAddr> = match lookup_host(format!("{domain}:{port}")).await {
-                        Ok(addr_list) => addr_list,
-                        Err(e) => {
-                            login_state.input_status = InputStatus::AddressNotParsable;
-                            return Err(anyhow!("Could not parse address {server_address_raw}"));
-                        }
-                    }
-                    .collect();
-
-                    if possible_server_addrs.is_empty() {
-                        login_state.input_status = InputStatus::ServerNotFound;
-                        return Err(anyhow!("Could not resolve address: {server_address_raw}"));
-                    }
-
-                    let addr = possible_server_addrs.remove(0);
-                    debug!("Resolved {addr} from DNS");
-
-                    ServerAddrInfo {
-                        ip: addr.ip(),
-                        port: addr.port(),
-                        domain: Some(domain.to_owned()),
-                        connection_type: if login_state.enable_tls {
-                            ConnectionType::TLS
-                        } else {
-                            ConnectionType::Raw
-                        },
+                    login_state.connection_probe = vec![(ProbeStage::Lookup, ProbeOutcome::Failed(e.to_string()))];
+                    for stage in [ProbeStage::Tcp, ProbeStage::Tls, ProbeStage::Handshake] {
+                        login_state
+                            .connection_probe
+                            .push((stage, ProbeOutcome::Skipped("an earlier stage failed".to_owned())));
                     }
                 }
-            };
+            }
+        }
+        ConnectionProbeUpdate(stage, outcome) => match login_state.connection_probe.iter_mut().find(|(reached, _)| *reached == stage) {
+            Some((_, previous)) => *previous = outcome,
+            None => login_state.connection_probe.push((stage, outcome)),
+        },
+        Login => {
+            login_state.goodbye = None;
+            let server_address = resolve_server_address(login_state).await?;
 
             match client.connect(&server_address).await {
                 Ok(_) => {
@@ -202,69 +300,162 @@ pub async fn handle_login_event(tui: &mut State, event: TuiEvent, client: &mut C
                             }
                         }
                     }
+
+                    // Read what was cached during earlier sessions while the server is unreachable, it keeps reconnecting in the background
+                    let history_cache = HistoryCache::load(&server_address.to_string());
+                    if !history_cache.is_empty() {
+                        warn!("Unable to reach {server_address}, showing the cached history until it can be reached");
+                        login_state.server_address = Some(server_address.clone());
+                        let username = login_state.username_input.clone();
+                        let password = login_state.password_input.clone();
+                        tui.state_map.insert(Screen::Login, AppState::Login(login_state.clone()));
+
+                        let chat_state = new_chat_state(
+                            tui,
+                            &username,
+                            &password,
+                            &server_address,
+                            0,
+                            ServerConnectionStatus::Disconnected,
+                            history_cache,
+                        );
+                        tui.current_state = AppState::Chat(Box::new(chat_state));
+                    }
                 }
             }
         }
         LoginSuccess(user_id) => {
-            if let Some(server_address) = &login_state.server_address {
+            if let Some(server_address) = login_state.server_address.clone() {
                 // Save login state
                 login_state.input_status = InputStatus::AllFine;
+                // A wrong secret would generate wrong codes on every login after this one
+                if let Some(secret) = login_state.login_code.take().and_then(|prompt| prompt.pending_secret) {
+                    match save_totp_secret(&server_address.to_string(), &login_state.username_input, secret.expose()) {
+                        Ok(()) => info!("Remembered the login code secret in the keyring"),
+                        Err(e) => warn!("Failed to remember the login code secret: {e}"),
+                    }
+                }
+                if let LoginFocus::LoginCodeInput(_) = login_state.focus {
+                    login_state.focus = LoginFocus::LoginButton;
+                }
+                if tui.global_state.config.remember_login {
+                    login_state
+                        .known_servers
+                        .remember(&login_state.server_address_input, login_state.enable_tls, &login_state.username_input);
+                    if let Err(e) = login_state.known_servers.save() {
+                        warn!("Failed to save the known servers: {e}");
+                    }
+                }
                 tui.state_map.insert(Screen::Login, AppState::Login(login_state.clone()));
 
                 let username = login_state.username_input.clone();
                 let password = login_state.password_input.clone();
 
-                debug!(
-                    "{:?} {} {} {}:{}",
-                    tui.state_map.keys(),
-                    username,
-                    password,
-                    server_address.ip,
-                    server_address.port
-                );
-                if let Some(chat_state) = tui.state_map.get(&Screen::Chat(username, password, server_address.clone())) {
+                debug!("{:?} {} {}:{}", tui.state_map.keys(), username, server_address.ip, server_address.port);
+                if tui.global_state.config.safe_mode {
+                    warn!("Running in safe mode, the config file is ignored and no state is restored or saved");
+                }
+                if !tui.global_state.config.safe_mode
+                    && let Some(chat_state) = tui
+                        .state_map
+                        .get(&Screen::Chat(username.clone(), password.clone(), server_address.clone()))
+                {
                     tui.current_state = chat_state.clone();
                     info!("Restored a saved session");
+                    // Catch up on what happened while the session was parked
+                    handle_chat_event(tui, LoginSuccess(user_id), client).await?;
                 } else {
-                    client.request_channel_ids().await?;
-                    client.request_user_statuses().await?;
-                    tui.current_state = AppState::Chat(Box::new(ChatState {
-                        focus: ChatFocus::Channels,
-                        channels: vec![],
-                        users: vec![],
-                        chat_history: HashMap::new(),
-                        chat_inputs: HashMap::new(),
-                        active_channel_idx: 0,
-                        current_user: UserProfile {
-                            user_id,
-                            status: UserStatus::Online,
-                            username: login_state.username_input.clone(),
-                            password: login_state.password_input.clone(),
-                        },
-                        chat_scroll_offset: 0,
-                        replying_to: None,
-                        server_connection_status: ServerConnectionStatus::Connected,
-                        server_address: server_address.clone(),
-                        waiting_message_acks_id: VecDeque::new(),
-                        incrementing_ack_id: 100000, // TODO better value
-                        users_typing: HashMap::new(),
-                        is_typing: false,
-                        time_since_last_typing: Instant::now(),
-                        time_since_last_focused: None,
-                    }));
+                    let mut chat_state = new_chat_state(
+                        tui,
+                        &username,
+                        &password,
+                        &server_address,
+                        user_id,
+                        ServerConnectionStatus::Connected,
+                        HistoryCache::load(&server_address.to_string()),
+                    );
+                    chat_state.startup.request_current_step(client).await?;
+                    client.request_capabilities().await?;
+                    if tui.global_state.config.check_version {
+                        client.request_server_info().await?;
+                    }
+                    tui.current_state = AppState::Chat(Box::new(chat_state));
                 };
             } else {
                 panic!("Should be unreachable");
             }
         }
+        LoginCodeRequired(message) => {
+            let server_key = login_state.server_address.as_ref().map(ServerAddrInfo::to_string).unwrap_or_default();
+            // A remembered secret fills in the code, unless the code it generated was just rejected
+            if message.is_none()
+                && !tui.global_state.config.safe_mode
+                && let Some(secret) = totp_secret(&server_key, &login_state.username_input)
+            {
+                match totp_code(&secret) {
+                    Ok(code) => {
+                        info!("Sending the login code generated from the keyring");
+                        client.send_login_code(code).await?;
+                        login_state.login_code = Some(LoginCodePrompt {
+                            submitted: true,
+                            ..Default::default()
+                        });
+                        return Ok(());
+                    }
+                    Err(e) => warn!("Failed to generate a login code: {e}"),
+                }
+            }
+            login_state.login_code = Some(LoginCodePrompt {
+                message,
+                ..Default::default()
+            });
+            login_state.focus = LoginFocus::LoginCodeInput(0);
+        }
+        SubmitLoginCode => {
+            let Some(prompt) = &mut login_state.login_code else {
+                return Ok(());
+            };
+            let input = prompt.input.trim().to_owned();
+            let code = if is_login_code(&input) {
+                prompt.pending_secret = None;
+                input
+            } else {
+                match totp_code(&input) {
+                    Ok(code) => {
+                        prompt.pending_secret = Some(Secret::new(input));
+                        code
+                    }
+                    Err(e) => {
+                        prompt.message = Some(e.to_string());
+                        return Ok(());
+                    }
+                }
+            };
+            prompt.input.clear();
+            prompt.message = None;
+            prompt.submitted = true;
+            login_state.focus = LoginFocus::LoginCodeInput(0);
+            client.send_login_code(code).await?;
+        }
+        CancelLoginCode => {
+            login_state.login_code = None;
+            login_state.focus = LoginFocus::LoginButton;
+            client.disconnect()?;
+        }
         LoginFail(message) => {
-            match message.as_str() {
-                "Incorrect username or password." => login_state.input_status = InputStatus::IncorrectUsernameOrPassword,
-                _ => login_state.input_status = InputStatus::FailedToLogin,
+            if login_state.login_code.take().is_some() {
+                login_state.focus = LoginFocus::LoginButton;
             }
+            login_state.input_status = InputStatus::from_login_failure(&message);
 
             client.disconnect()?; // TODO make it work properly
         }
+        ServerGoodbye(reason, message) => {
+            warn!("The server refused the session: {reason:?} {message}");
+            login_state.login_code = None;
+            login_state.goodbye = Some(Box::new((reason, message)));
+            client.disconnect()?;
+        }
         ToggleLogs => {
             tui.global_state.show_logs = !tui.global_state.show_logs;
         }
@@ -274,3 +465,176 @@ pub async fn handle_login_event(tui: &mut State, event: TuiEvent, client: &mut C
     }
     Ok(())
 }
+
+/// Chat state of a new session, filled with the history cached during earlier sessions until the server sends it
+fn new_chat_state(
+    tui: &mut State,
+    username: &str,
+    password: &Secret,
+    server_address: &ServerAddrInfo,
+    user_id: UserId,
+    server_connection_status: ServerConnectionStatus,
+    history_cache: HistoryCache,
+) -> ChatState {
+    let startup = StartupSequence::new(tui.global_state.config.initial_history);
+    let preferences = ServerPreferences::load(&server_address.to_string());
+    if let Some(show_logs) = preferences.show_logs {
+        tui.global_state.show_logs = show_logs;
+    }
+    tui.global_state.theme = match preferences.theme.as_deref().map(Theme::load) {
+        Some(Ok(theme)) => theme,
+        Some(Err(e)) => {
+            warn!("Could not load the theme of this server, using the one of the config file: {e:#}");
+            tui.global_state.config.theme.clone()
+        }
+        None => tui.global_state.config.theme.clone(),
+    };
+    // Only connected sessions got through the login, the cache is opened when the server can not be reached
+    let login_verified = server_connection_status == ServerConnectionStatus::Connected;
+    let mut chat_state = ChatState {
+        focus: ChatFocus::Channels,
+        channels: vec![],
+        users: vec![],
+        user_order: vec![],
+        users_changed: false,
+        users_sorted: Instant::now(),
+        pending_statuses: HashMap::new(),
+        requested_users: HashSet::new(),
+        chat_history: HashMap::new(),
+        chat_inputs: HashMap::new(),
+        active_channel_idx: 0,
+        current_user: UserProfile {
+            user_id,
+            status: UserStatus::Online,
+            username: username.to_owned(),
+            password: password.clone(),
+        },
+        chat_scroll_offset: 0,
+        chat_line_offset: 0,
+        reply_targets: HashMap::new(),
+        edits: HashMap::new(),
+        pending_edits: HashMap::new(),
+        server_connection_status,
+        server_address: server_address.clone(),
+        waiting_message_acks_id: VecDeque::new(),
+        incrementing_ack_id: 100000, // TODO better value
+        users_typing: HashMap::new(),
+        is_typing: false,
+        time_since_last_typing: Instant::now(),
+        time_since_last_focused: None,
+        manual_status: false,
+        terminal_focused: true,
+        user_statuses_requested: Instant::now(),
+        vim_g_pressed: None,
+        unseen_messages: 0,
+        written_title: None,
+        channel_groups: ChannelGroups::load(&server_address.to_string()),
+        selected_channel_group: None,
+        startup,
+        preferences,
+        // Safe mode does not remember the version, so the release notes would show on every login
+        popup: (!tui.global_state.config.safe_mode).then(whats_new).flatten().map(Popup::Changelog),
+        toasts: VecDeque::new(),
+        upload: None,
+        profile_update: None,
+        password_change_pending: false,
+        staged_attachments: vec![],
+        voice_note: None,
+        media_fetcher: MediaFetcher::default(),
+        media_cache: MediaCache::default(),
+        previews: HashMap::new(),
+        avatars: HashMap::new(),
+        translations: HashMap::new(),
+        lock: None,
+        written_status: None,
+        unread_counts: HashMap::new(),
+        version_warning: None,
+        capabilities: Capabilities::ASSUMED,
+        pending_downloads: HashSet::new(),
+        last_seen: HashMap::new(),
+        history_cache_dirty: false,
+        history_cache_saved: Instant::now(),
+        retention: tui.global_state.config.retention.clone(),
+        audit_log: tui.global_state.config.audit_log,
+        pending_direct_channels: HashMap::new(),
+        opening_direct_message: None,
+        history_pagination: HistoryPagination::default(),
+        clock_skew: ClockSkew::new(tui.global_state.config.correct_clock_skew),
+        saved_checkpoint: Checkpoint::default(),
+        checkpoint_saved: Instant::now(),
+        pending_view: None,
+        last_server_channel: None,
+        syncing: HashMap::new(),
+        collapsed_users: HashSet::new(),
+        message_jump: None,
+        session_token: None,
+        token_login: false,
+        login_verified,
+    };
+    chat_state.restore_history_cache(history_cache);
+    chat_state.restore_notes(tui.global_state.config.notes_keep_days);
+    chat_state.restore_checkpoint(Checkpoint::load(&server_address.to_string()));
+    chat_state
+}
+
+/// Turns the typed server address into the address to connect to, looking the domain up when it is not an IP address
+async fn resolve_server_address(login_state: &mut LoginState) -> Result<ServerAddrInfo> {
+    let server_address_raw = login_state.server_address_input.trim();
+
+    let server_address = match server_address_raw.parse::<SocketAddr>() {
+        Ok(addr) => {
+            if login_state.enable_tls {
+                return Err(anyhow!("Unable to make TLS connection without a domain"));
+            }
+            ServerAddrInfo {
+                ip: addr.ip(),
+                port: addr.port(),
+                domain: None,
+                connection_type: ConnectionType::Raw,
+            }
+        }
+        Err(e) => {
+            debug!("Looking up {server_address_raw} using DNS");
+            let mut chunks = server_address_raw.split(':');
+            let domain = if let Some(domain) = chunks.next() {
+                domain
+            } else {
+                return Err(anyhow!("Unable to parse address {server_address_raw}"));
+            };
+            let port: &str = if let Some(port) = chunks.next() {
+                port
+            } else {
+                &DEFAULT_PORT.to_string()
+            };
+
+            let mut possible_server_addrs: Vec<SocketAddr> = match lookup_host(format!("{domain}:{port}")).await {
+                Ok(addr_list) => addr_list,
+                Err(e) => {
+                    login_state.input_status = InputStatus::AddressNotParsable;
+                    return Err(anyhow!("Could not parse address {server_address_raw}"));
+                }
+            }
+            .collect();
+
+            if possible_server_addrs.is_empty() {
+                login_state.input_status = InputStatus::ServerNotFound;
+                return Err(anyhow!("Could not resolve address: {server_address_raw}"));
+            }
+
+            let addr = possible_server_addrs.remove(0);
+            debug!("Resolved {addr} from DNS");
+
+            ServerAddrInfo {
+                ip: addr.ip(),
+                port: addr.port(),
+                domain: Some(domain.to_owned()),
+                connection_type: if login_state.enable_tls {
+                    ConnectionType::TLS
+                } else {
+                    ConnectionType::Raw
+                },
+            }
+        }
+    };
+    Ok(server_address)
+}
@@ -5,12 +5,18 @@ use ratatui::layout::{Alignment, Constraint, Direction, Flex, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::symbols::{border, line};
 use ratatui::text::{Line, Span, Text};
-use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
+use unicode_segmentation::UnicodeSegmentation;
 
+use crate::network::probe::{ProbeOutcome, ProbeStage};
+use crate::network::protocol::server::DisconnectReason;
 use crate::tui::LoginState;
+use crate::tui::input::snap_to_grapheme;
+use crate::tui::logs::visible_log_lines;
 use crate::tui::screens::GlobalState;
 use crate::tui::screens::chat::ui::split_app_info_areas;
 use crate::tui::screens::login::{InputStatus, LoginFocus};
+use crate::tui::theme::Theme;
 
 pub fn draw_login(global_state: &GlobalState, login_state: &LoginState, frame: &mut Frame) {
     let main_area = frame.area();
@@ -26,8 +32,12 @@ pub fn draw_login(global_state: &GlobalState, login_state: &LoginState, frame: &
     };
     render_background(global_state, login_state, frame, background_area);
 
+    let [login_area, known_servers_area] = Layout::vertical([Constraint::Length(LOGIN_HEIGHT), Constraint::Fill(1)]).areas(login_area);
     render_login(global_state, login_state, frame, login_area);
-    render_info(frame, info_area);
+    if !login_state.known_servers.servers.is_empty() {
+        render_known_servers(global_state, login_state, frame, known_servers_area);
+    }
+    render_info(global_state, login_state, frame, info_area);
 }
 
 fn split_background_log_areas(_global_state: &GlobalState, area: Rect) -> (Rect, Rect) {
@@ -39,9 +49,18 @@ fn split_background_log_areas(_global_state: &GlobalState, area: Rect) -> (Rect,
     (chunks[0], chunks[1])
 }
 
-fn split_login_area_background(_global_state: &GlobalState, _login_state: &LoginState, area: Rect) -> (Rect, Rect) {
+/// Rows of the login box, the known servers are listed under it
+const LOGIN_HEIGHT: u16 = 16;
+
+fn split_login_area_background(_global_state: &GlobalState, login_state: &LoginState, area: Rect) -> (Rect, Rect) {
+    let known_servers_height = match login_state.known_servers.servers.len() {
+        0 => 0,
+        count => count as u16 + 2,
+    };
     let [horizontally_centered] = Layout::horizontal([Constraint::Percentage(15)]).flex(Flex::Center).areas(area);
-    let [centered] = Layout::vertical([Constraint::Length(16)]).flex(Flex::Center).areas(horizontally_centered);
+    let [centered] = Layout::vertical([Constraint::Length(LOGIN_HEIGHT + known_servers_height)])
+        .flex(Flex::Center)
+        .areas(horizontally_centered);
     (centered, area)
 }
 
@@ -49,10 +68,11 @@ pub enum LineSelected {
     Username,
     Password,
     ServerAddress,
+    LoginCode,
 }
 
-fn input_line(login_state: &'_ LoginState, line_selected: LineSelected, input_length: usize) -> Vec<Span<'_>> {
-    let (input, focus_index) = match line_selected {
+fn input_line<'a>(theme: &Theme, login_state: &'a LoginState, line_selected: LineSelected, input_length: usize) -> Vec<Span<'a>> {
+    let (input, focus_index): (&str, usize) = match line_selected {
         LineSelected::Username => (
             &login_state.username_input,
             if let LoginFocus::UsernameInput(idx) = login_state.focus {
@@ -62,7 +82,7 @@ fn input_line(login_state: &'_ LoginState, line_selected: LineSelected, input_le
             },
         ),
         LineSelected::Password => (
-            &login_state.password_input,
+            login_state.password_input.expose(),
             if let LoginFocus::PasswordInput(idx) = login_state.focus {
                 idx
             } else {
@@ -77,36 +97,49 @@ fn input_line(login_state: &'_ LoginState, line_selected: LineSelected, input_le
                 usize::MAX
             },
         ),
+        LineSelected::LoginCode => (
+            login_state.login_code.as_ref().map_or("", |prompt| prompt.input.as_str()),
+            if let LoginFocus::LoginCodeInput(idx) = login_state.focus {
+                idx
+            } else {
+                usize::MAX
+            },
+        ),
     };
 
     let input_status = login_state.input_status.clone();
 
     let mut selected_style = match (&line_selected, &login_state.focus) {
-        (LineSelected::Username, LoginFocus::UsernameInput(_)) => Style::default().fg(Color::Cyan),
-        (LineSelected::Password, LoginFocus::PasswordInput(_)) => Style::default().fg(Color::Cyan),
-        (LineSelected::Username, _) if input_status == InputStatus::IncorrectUsernameOrPassword => Style::default().fg(Color::Red),
-        (LineSelected::Password, _) if input_status == InputStatus::IncorrectUsernameOrPassword => Style::default().fg(Color::Red),
+        (LineSelected::Username, LoginFocus::UsernameInput(_)) => theme.focus,
+        (LineSelected::Password, LoginFocus::PasswordInput(_)) => theme.focus,
+        (LineSelected::Username, _) if input_status == InputStatus::IncorrectUsernameOrPassword => theme.error,
+        (LineSelected::Password, _) if input_status == InputStatus::IncorrectUsernameOrPassword => theme.error,
         (LineSelected::ServerAddress, _) if input_status == InputStatus::AddressNotParsable || input_status == InputStatus::ServerNotFound => {
-            Style::default().fg(Color::Red)
+            theme.error
         }
-        (LineSelected::ServerAddress, LoginFocus::ServerAddressInput(_)) => Style::default().fg(Color::Cyan),
+        (LineSelected::ServerAddress, LoginFocus::ServerAddressInput(_)) => theme.focus,
+        (LineSelected::LoginCode, LoginFocus::LoginCodeInput(_)) => theme.focus,
         _ => Style::default(),
     };
-    selected_style = selected_style.add_modifier(Modifier::UNDERLINED);
+    selected_style = selected_style.patch(theme.cursor);
 
+    let focus_index = if focus_index == usize::MAX {
+        focus_index
+    } else {
+        snap_to_grapheme(input, focus_index)
+    };
     let mut spans: Vec<Span> = format!("{input} ")
-        .chars()
-        .enumerate()
-        .map(|(idx, c)| {
+        .grapheme_indices(true)
+        .map(|(idx, grapheme)| {
             if idx == focus_index {
-                Span::styled(c.to_string(), selected_style.add_modifier(Modifier::DIM))
+                Span::styled(grapheme.to_owned(), selected_style.patch(theme.hint))
             } else {
-                Span::styled(c.to_string(), selected_style)
+                Span::styled(grapheme.to_owned(), selected_style)
             }
         })
         .collect();
 
-    let current_len = spans.len();
+    let current_len: usize = spans.iter().map(Span::width).sum();
     if current_len < input_length {
         let padding = " ".repeat(input_length - current_len);
         spans.push(Span::styled(padding, selected_style));
@@ -115,7 +148,8 @@ fn input_line(login_state: &'_ LoginState, line_selected: LineSelected, input_le
     spans
 }
 
-fn render_login(_global_state: &GlobalState, login_state: &LoginState, frame: &mut Frame, area: Rect) {
+fn render_login(global_state: &GlobalState, login_state: &LoginState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Length(2), Constraint::Min(11), Constraint::Length(2)])
@@ -126,9 +160,9 @@ fn render_login(_global_state: &GlobalState, login_state: &LoginState, frame: &m
 
     let input_length = login_form_area.width.saturating_sub(3 * side_padding_len) as usize;
 
-    let username_input = input_line(login_state, LineSelected::Username, input_length);
-    let password_input = input_line(login_state, LineSelected::Password, input_length);
-    let server_input = input_line(login_state, LineSelected::ServerAddress, input_length);
+    let username_input = input_line(theme, login_state, LineSelected::Username, input_length);
+    let password_input = input_line(theme, login_state, LineSelected::Password, input_length);
+    let server_input = input_line(theme, login_state, LineSelected::ServerAddress, input_length);
 
     let side_padding = " ".repeat(side_padding_len as usize);
 
@@ -142,15 +176,29 @@ fn render_login(_global_state: &GlobalState, login_state: &LoginState, frame: &m
             InputStatus::ServerNotFound => "Server not found",
             InputStatus::AddressNotParsable => "Cant parse address",
             InputStatus::UnknownError => "Unknown error while logging in",
+            InputStatus::PasswordChanged => "Password changed",
         },
-        Modifier::ITALIC | Modifier::DIM,
+        theme.placeholder,
     );
+    // A session the server ended on purpose takes the place of the error, with the explanation of the server under it
+    let (error_message, goodbye_message) = match login_state.goodbye.as_deref() {
+        Some((reason, message)) => (
+            Span::styled(
+                match reason {
+                    DisconnectReason::Shutdown => "The server shut down",
+                    DisconnectReason::Kicked => "You were kicked from the server",
+                    DisconnectReason::Banned => "You are banned from the server",
+                    DisconnectReason::IdleTimeout => "Disconnected for being idle too long",
+                },
+                theme.error.patch(theme.header),
+            ),
+            Span::styled(message.as_str(), theme.error.patch(theme.annotation)),
+        ),
+        None => (error_message, Span::raw("")),
+    };
 
     let lines = Text::from(vec![
-        Line::from(vec![Span::styled(
-            " Username",
-            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
-        )]),
+        Line::from(vec![Span::styled(" Username", theme.attention.patch(theme.header))]),
         Line::from({
             let mut spans = Vec::new();
             spans.push(Span::raw(&side_padding));
@@ -159,10 +207,7 @@ fn render_login(_global_state: &GlobalState, login_state: &LoginState, frame: &m
             spans
         }),
         Line::from(""),
-        Line::from(vec![Span::styled(
-            " Password",
-            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
-        )]),
+        Line::from(vec![Span::styled(" Password", theme.attention.patch(theme.header))]),
         Line::from({
             let mut spans = Vec::new();
             spans.push(Span::raw(&side_padding));
@@ -171,10 +216,7 @@ fn render_login(_global_state: &GlobalState, login_state: &LoginState, frame: &m
             spans
         }),
         Line::from(""),
-        Line::from(vec![Span::styled(
-            " Server Address",
-            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
-        )]),
+        Line::from(vec![Span::styled(" Server Address", theme.attention.patch(theme.header))]),
         Line::from({
             let mut spans = Vec::new();
             spans.push(Span::raw(&side_padding));
@@ -183,41 +225,39 @@ fn render_login(_global_state: &GlobalState, login_state: &LoginState, frame: &m
             spans
         }),
         Line::from(error_message).alignment(Alignment::Center),
-        Line::from(""),
+        Line::from(goodbye_message).alignment(Alignment::Center),
     ]);
 
     let login_button_style = if LoginFocus::LoginButton == login_state.focus {
-        if InputStatus::AllFine == login_state.input_status {
-            Style::default().bg(Color::Cyan).fg(Color::Black).add_modifier(Modifier::BOLD)
+        if matches!(login_state.input_status, InputStatus::AllFine | InputStatus::PasswordChanged) {
+            theme.button
         } else {
-            Style::default().bg(Color::Red).fg(Color::Black).add_modifier(Modifier::BOLD)
+            theme.button_error
         }
     } else {
-        Style::default().add_modifier(Modifier::BOLD)
+        theme.header
     };
 
-    let title_block = Paragraph::new(Text::from(Span::styled(
-        "Welcome to Chatger!",
-        Style::default().add_modifier(Modifier::BOLD),
-    )))
-    .alignment(Alignment::Center)
-    .block(
-        Block::default()
-            .borders(Borders::LEFT | Borders::RIGHT | Borders::TOP)
-            .border_style(Style::default())
-            .style(Style::default()),
-    );
+    let title_block = Paragraph::new(Text::from(Span::styled("Welcome to Chatger!", theme.header)))
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .borders(Borders::LEFT | Borders::RIGHT | Borders::TOP)
+                .border_type(theme.borders)
+                .border_style(Style::default())
+                .style(Style::default()),
+        );
 
     let form_block = Paragraph::new(lines).block(
         Block::default()
             .style(Style::default())
-            .border_set(border::Set {
+            .border_set(theme.border_set(border::Set {
                 bottom_right: line::NORMAL.vertical_left,
                 bottom_left: line::NORMAL.vertical_right,
                 top_right: line::NORMAL.vertical_left,
                 top_left: line::NORMAL.vertical_right,
                 ..border::PLAIN
-            })
+            }))
             .borders(Borders::ALL)
             .border_style(Style::default()),
     );
@@ -226,6 +266,7 @@ fn render_login(_global_state: &GlobalState, login_state: &LoginState, frame: &m
         .block(
             Block::default()
                 .borders(Borders::LEFT | Borders::RIGHT | Borders::BOTTOM)
+                .border_type(theme.borders)
                 .border_style(Style::default()),
         )
         .alignment(Alignment::Center);
@@ -233,6 +274,129 @@ fn render_login(_global_state: &GlobalState, login_state: &LoginState, frame: &m
     frame.render_widget(title_block, login_title_area);
     frame.render_widget(form_block, login_form_area);
     frame.render_widget(login_block, login_button_area);
+
+    if login_state.login_code.is_some() {
+        render_login_code(theme, login_state, frame, login_form_area);
+    }
+    if login_state.focus == LoginFocus::ConnectionTest {
+        render_connection_test(theme, login_state, frame, login_form_area);
+    }
+}
+
+/// Servers logged in to before, numbered for picking them with one key
+fn render_known_servers(global_state: &GlobalState, login_state: &LoginState, frame: &mut Frame, area: Rect) {
+    let theme = &global_state.theme;
+    let lines: Vec<Line> = login_state
+        .known_servers
+        .servers
+        .iter()
+        .enumerate()
+        .map(|(idx, server)| {
+            let style = if login_state.focus == LoginFocus::KnownServers(idx) {
+                theme.focus
+            } else {
+                Style::default()
+            };
+            let mut spans = vec![
+                Span::styled(format!(" {} ", idx + 1), theme.hint),
+                Span::styled(format!("{}@{}", server.username, server.address), style),
+            ];
+            if server.enable_tls {
+                spans.push(Span::styled(" tls", theme.placeholder));
+            }
+            Line::from(spans)
+        })
+        .collect();
+
+    let title_bottom = if matches!(login_state.focus, LoginFocus::KnownServers(_)) {
+        Line::from(Span::styled("[Enter] Pick | [D]elete", theme.hint)).centered()
+    } else {
+        Line::default()
+    };
+    let widget = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .title(Span::styled(" Servers ", theme.attention.patch(theme.header)))
+            .title_bottom(title_bottom),
+    );
+    frame.render_widget(widget, area);
+}
+
+/// Drawn over the login form while the results of a connection test are shown, one line per stage
+fn render_connection_test(theme: &Theme, login_state: &LoginState, frame: &mut Frame, area: Rect) {
+    let [popup_area] = Layout::vertical([Constraint::Length(ProbeStage::ALL.len() as u16 * 2 + 1)])
+        .flex(Flex::Center)
+        .areas(area);
+
+    let mut lines = vec![];
+    for stage in ProbeStage::ALL {
+        let outcome = login_state
+            .connection_probe
+            .iter()
+            .find(|(reached, _)| *reached == stage)
+            .map(|(_, outcome)| outcome);
+        let (symbol, style, details) = match outcome {
+            Some(ProbeOutcome::Passed(details)) => ("✓", theme.success, details.as_str()),
+            Some(ProbeOutcome::Failed(reason)) => ("✗", theme.error, reason.as_str()),
+            Some(ProbeOutcome::Running) => ("…", theme.attention, "testing"),
+            Some(ProbeOutcome::Skipped(reason)) => ("-", theme.hint, reason.as_str()),
+            None => (" ", theme.hint, "waiting"),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!(" {symbol} "), style.patch(theme.header)),
+            Span::styled(stage.label(), style.patch(theme.header)),
+        ]));
+        lines.push(Line::from(Span::styled(format!("   {details}"), theme.placeholder)));
+    }
+
+    let widget = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .title(Span::styled(" Connection test ", theme.attention.patch(theme.header)))
+            .title_bottom(Line::from(Span::styled("[T] Test again | [Esc] Close", theme.hint)).centered()),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
+}
+
+/// Drawn over the login form once the server asks for the code of the second factor
+fn render_login_code(theme: &Theme, login_state: &LoginState, frame: &mut Frame, area: Rect) {
+    let Some(prompt) = &login_state.login_code else {
+        return;
+    };
+    let [popup_area] = Layout::vertical([Constraint::Length(7)]).flex(Flex::Center).areas(area);
+    let input_length = popup_area.width.saturating_sub(6) as usize;
+
+    let status = match (&prompt.message, prompt.submitted) {
+        (Some(message), _) => Span::styled(message.clone(), theme.error.patch(theme.annotation)),
+        (None, true) => Span::styled("Checking the code...", theme.placeholder),
+        (None, false) => Span::styled("Code from your authenticator app", theme.placeholder),
+    };
+    let lines = Text::from(vec![
+        Line::from(status).alignment(Alignment::Center),
+        Line::from(""),
+        Line::from(
+            [
+                vec![Span::raw("  ")],
+                input_line(theme, login_state, LineSelected::LoginCode, input_length),
+            ]
+            .concat(),
+        ),
+        Line::from(""),
+        Line::from(Span::styled("Or type the secret key to remember it", theme.placeholder)).alignment(Alignment::Center),
+    ]);
+
+    let widget = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(theme.borders)
+            .title(Span::styled(" Login code ", theme.attention.patch(theme.header)))
+            .title_bottom(Line::from(Span::styled("[Enter] Submit | [Esc] Cancel", theme.hint)).centered()),
+    );
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(widget, popup_area);
 }
 
 const PENGERS: &str = include_str!("../../../../assets/penger.txt");
@@ -261,10 +425,13 @@ fn render_background(_global_state: &GlobalState, _login_state: &LoginState, fra
     frame.render_widget(widget, area);
 }
 
-fn render_info(frame: &mut Frame, area: Rect) {
-    let info_text =
-        "[Enter] Login | [Backspace] Delete | [←→] Move Cursor | [Ctrl + ←→] Tab move Cursor | [↑↓] Move Field | [ESC]ape | [L]ogs | [Q]uit"
+fn render_info(_global_state: &GlobalState, login_state: &LoginState, frame: &mut Frame, area: Rect) {
+    let mut info_text =
+        "[Enter] Login | [Backspace] Delete | [←→] Move Cursor | [Ctrl + ←→] Tab move Cursor | [↑↓] Move Field | [ESC]ape | [T]est connection | [L]ogs | [Q]uit"
             .to_owned();
+    if !login_state.known_servers.servers.is_empty() {
+        info_text.push_str(" | [1-9] Server");
+    }
 
     let widget = Paragraph::new(Text::from(info_text)).alignment(Alignment::Center);
 
@@ -272,13 +439,14 @@ fn render_info(frame: &mut Frame, area: Rect) {
 }
 
 fn render_logs(global_state: &GlobalState, frame: &mut Frame, area: Rect) {
-    let current_log_count = global_state.logs.len();
-    let start_index = current_log_count
-        .saturating_sub(area.height.saturating_sub(2) as usize)
-        .saturating_sub(global_state.log_scroll_offset);
-
-    let logs: Vec<Line> = global_state.logs.iter().skip(start_index).map(|entry| entry.format()).collect();
+    let (logs, scroll) = visible_log_lines(
+        &global_state.logs,
+        global_state.log_scroll_offset,
+        area.width,
+        area.height,
+        !global_state.expand_log_repeats,
+    );
 
-    let widget = Paragraph::new(Text::from(logs)).wrap(Wrap { trim: true });
+    let widget = Paragraph::new(Text::from(logs)).wrap(Wrap { trim: true }).scroll((scroll, 0));
     frame.render_widget(widget, area);
 }
@@ -1,39 +1,62 @@
 pub mod chat;
 pub mod login;
+pub mod render_stats;
+pub mod time_travel;
 use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::warn;
 use ratatui::Frame;
-use ratatui::crossterm::event::Event;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{Event, KeyCode};
 use tokio::sync::mpsc::{self, Sender};
 use tokio::time::Instant;
 
-use crate::cli::AppConfig;
+use crate::cli::{AppConfig, HistoryLayout, Workspace};
+use crate::media::preview::{GraphicsProtocol, GraphicsRenderer};
 use crate::network::client::{Client, ServerAddrInfo, ServerConnectionStatus};
+use crate::storage::secrets::Secret;
+use crate::storage::status::write_status;
 use crate::tui::events::TuiEvent;
 use crate::tui::framework::{Tui, TuiRunner};
 use crate::tui::logs::LogEntry;
+use crate::tui::screens::chat::alerts::set_title;
 use crate::tui::screens::chat::keys::handle_chat_key_event;
+use crate::tui::screens::chat::plugins::Plugins;
+use crate::tui::screens::chat::profile_editor::ProfileUpdate;
+use crate::tui::screens::chat::tutorial::Tutorial;
 use crate::tui::screens::chat::ui::draw_main;
-use crate::tui::screens::chat::{ChatState, handle_chat_event};
+use crate::tui::screens::chat::{
+    ChatState, HiddenPanes, Popup, TOAST_DURATION, backfill_search, fetch_visible_media, flush_status_updates, handle_chat_event,
+};
 use crate::tui::screens::login::keys::handle_login_key_event;
 use crate::tui::screens::login::ui::draw_login;
 use crate::tui::screens::login::{InputStatus, LoginFocus, LoginState, handle_login_event};
+use crate::tui::screens::render_stats::RenderStats;
+use crate::tui::screens::time_travel::TimeTravel;
+use crate::tui::theme::Theme;
 
 const USER_TIME_UNTIL_IDLE: u64 = 60;
+/// History is written to the cache at most this often, and when leaving the chat or losing the connection
+const HISTORY_CACHE_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Drafts and the view are written this often, at most this much is lost when the client is killed
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(3);
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum Screen {
-    Chat(String, String, ServerAddrInfo),
+    Chat(String, Secret, ServerAddrInfo),
     Login,
 }
 
 #[derive(Clone, Debug)]
 pub enum AppState {
     Chat(Box<ChatState>),
-    Login(LoginState),
+    Login(Box<LoginState>),
 }
 
 #[derive(Clone)]
@@ -41,10 +64,51 @@ pub struct GlobalState {
     logs: Vec<LogEntry>,
     log_scroll_offset: usize,
     show_logs: bool,
+    hidden_panes: HiddenPanes,
+    /// The theme of the config file, or the one picked for the current server with `/theme`
+    theme: Theme,
+    /// Hint bubbles for new users, `None` when there was a config file or every hint was dismissed
+    tutorial: Option<Tutorial>,
+    expand_log_repeats: bool,
+    /// Message times are shown as how long ago they were, starts out as set in `[timestamps]`
+    relative_timestamps: bool,
     should_quit: bool,
     fps: u32,
     frame_counter: u32,
     last_fps_check: Instant,
+    config: AppConfig,
+    graphics: GraphicsRenderer,
+    time_travel: TimeTravel,
+    render_stats: RenderStats,
+    /// Opened with `/workspace`, its pinned channels are applied once the chat of its account has channels
+    workspace: Option<Workspace>,
+    workspace_pins_pending: bool,
+    /// Loaded once at startup and shared by the chats of every server
+    plugins: Arc<Plugins>,
+}
+
+impl GlobalState {
+    /// The layout of the open workspace, or the one of the config file
+    pub fn layout(&self) -> &HistoryLayout {
+        self.workspace
+            .as_ref()
+            .and_then(|workspace| workspace.layout.as_ref())
+            .unwrap_or(&self.config.layout)
+    }
+
+    /// Zen mode hides the logs pane of the chat as well, without turning the logs off
+    pub fn logs_shown(&self) -> bool {
+        self.show_logs && !self.hidden_panes.zen
+    }
+
+    /// Time of a message as shown in the chat history and the reply bar, relative ones are redrawn every tick so they stay current
+    pub fn message_time(&self, timestamp: DateTime<Utc>) -> String {
+        if self.relative_timestamps {
+            self.config.timestamps.relative(timestamp, Utc::now())
+        } else {
+            self.config.timestamps.time(timestamp)
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -55,16 +119,28 @@ pub struct State {
 }
 
 impl State {
-    pub fn new(initial_state: AppState) -> Self {
+    pub fn new(initial_state: AppState, config: AppConfig) -> Self {
         State {
             global_state: GlobalState {
                 should_quit: false,
                 show_logs: false,
+                hidden_panes: HiddenPanes::default(),
+                theme: config.theme.clone(),
+                tutorial: Tutorial::new(config.first_run),
+                expand_log_repeats: false,
+                relative_timestamps: config.timestamps.relative,
                 log_scroll_offset: 0,
                 logs: vec![],
                 fps: 0,
                 frame_counter: 0,
                 last_fps_check: Instant::now(),
+                graphics: GraphicsRenderer::new(GraphicsProtocol::from_config(config.graphics)),
+                plugins: Arc::new(Plugins::load(&config)),
+                config,
+                time_travel: TimeTravel::default(),
+                render_stats: RenderStats::default(),
+                workspace: None,
+                workspace_pins_pending: false,
             },
             current_state: initial_state.clone(),
             state_map: HashMap::new(),
@@ -84,28 +160,110 @@ impl Tui<TuiEvent> for State {
             self.global_state.frame_counter = 0;
             self.global_state.last_fps_check = now;
         }
-        match &mut self.current_state {
-            AppState::Chat(chat_state) => draw_main(&self.global_state, chat_state, frame),
-            AppState::Login(login_state) => draw_login(&self.global_state, login_state, frame),
+        // Taken out so the panels can be timed while the rest of the global state is borrowed
+        let mut stats = mem::take(&mut self.global_state.render_stats);
+        stats.start_frame();
+
+        if let Some(recorded) = self.global_state.time_travel.viewed() {
+            match &recorded.state {
+                AppState::Chat(chat_state) => {
+                    draw_main(&self.global_state, chat_state, frame, &mut stats);
+                }
+                AppState::Login(login_state) => stats.time("login", || draw_login(&self.global_state, login_state, frame)),
+            }
+            self.global_state.time_travel.render_overlay(frame);
+            // Previews drawn through a graphics protocol belong to the live state
+            self.global_state.graphics.placements.clear();
+        } else {
+            match &mut self.current_state {
+                AppState::Chat(chat_state) => self.global_state.graphics.placements = draw_main(&self.global_state, chat_state, frame, &mut stats),
+                AppState::Login(login_state) => {
+                    self.global_state.graphics.placements.clear();
+                    stats.time("login", || draw_login(&self.global_state, login_state, frame))
+                }
+            }
+        }
+
+        stats.frame_time = now.elapsed();
+        stats.render_overlay(self.global_state.fps, frame);
+        self.global_state.render_stats = stats;
+    }
+
+    fn draw_graphics(&mut self, buffer: &Buffer) -> Result<()> {
+        match &self.current_state {
+            AppState::Chat(chat_state) => self.global_state.graphics.draw(&chat_state.previews, &chat_state.avatars, buffer),
+            AppState::Login(_) => self.global_state.graphics.draw(&HashMap::new(), &HashMap::new(), buffer),
         }
     }
 
     fn process_event(&mut self, event: Event) -> Option<TuiEvent> {
+        if let Event::Key(key_event) = &event
+            && key_event.code == KeyCode::F(12)
+        {
+            return Some(TuiEvent::ToggleRenderStats);
+        }
+        if self.global_state.config.time_travel {
+            let viewing = self.global_state.time_travel.viewing.is_some();
+            match &event {
+                Event::Key(key_event) if key_event.code == KeyCode::F(9) => return Some(TuiEvent::ToggleTimeTravel),
+                Event::Key(key_event) if viewing => {
+                    return match key_event.code {
+                        KeyCode::Left => Some(TuiEvent::TimeTravelBack),
+                        KeyCode::Right => Some(TuiEvent::TimeTravelForward),
+                        KeyCode::Esc => Some(TuiEvent::ToggleTimeTravel),
+                        _ => None,
+                    };
+                }
+                _ if viewing => return None,
+                _ => {}
+            }
+        }
         match &mut self.current_state {
             AppState::Login(login_state) => handle_login_key_event(event, login_state.focus),
-            AppState::Chat(chat_state) => handle_chat_key_event(event, chat_state.focus, &self.global_state),
+            AppState::Chat(chat_state) => handle_chat_key_event(event, chat_state, &self.global_state),
         }
     }
 
     async fn handle_event(&mut self, event: TuiEvent, client: &mut Client) -> Result<()> {
-        match &mut self.current_state {
+        let time_travel = &mut self.global_state.time_travel;
+        match event {
+            TuiEvent::ToggleTimeTravel => {
+                time_travel.toggle();
+                return Ok(());
+            }
+            TuiEvent::TimeTravelBack => {
+                time_travel.step_back();
+                return Ok(());
+            }
+            TuiEvent::TimeTravelForward => {
+                time_travel.step_forward();
+                return Ok(());
+            }
+            TuiEvent::ToggleRenderStats => {
+                self.global_state.render_stats.visible = !self.global_state.render_stats.visible;
+                return Ok(());
+            }
+            _ => {}
+        }
+        // Logs do not touch the screen state and would push everything else out of the recording
+        let description =
+            (self.global_state.config.time_travel && !matches!(event, TuiEvent::Log(_))).then(|| TimeTravel::describe(&event, &self.current_state));
+
+        let result = match &mut self.current_state {
             AppState::Chat(_) => handle_chat_event(self, event, client).await,
             AppState::Login(_) => handle_login_event(self, event, client).await,
+        };
+        if let Some(description) = description {
+            self.global_state.time_travel.record(description, &self.current_state);
         }
+        result
     }
 
     async fn on_tick(&mut self, event_send: &Sender<TuiEvent>, client: &mut Client) -> Result<()> {
         if let AppState::Chat(state) = &mut self.current_state {
+            if matches!(state.popup, Some(Popup::QuitGuard { waiting: true })) && state.pending_operations().is_empty() {
+                event_send.send(TuiEvent::Exit).await?;
+            }
             if state.is_typing && state.time_since_last_typing.elapsed() > Duration::from_secs(2) {
                 event_send.send(TuiEvent::TypingExpired).await?;
             }
@@ -122,12 +280,73 @@ impl Tui<TuiEvent> for State {
                 event_send.send(TuiEvent::Reconnect).await?;
             }
 
+            if let Some(upload) = &mut state.upload
+                && !upload.progress.is_done()
+                && client.connection_status == ServerConnectionStatus::Connected
+                && let Some(progress) = client.pump_upload().await?
+            {
+                upload.progress = progress;
+            }
+            if let Some(ProfileUpdate {
+                picture_upload: Some(upload_progress),
+                ..
+            }) = &mut state.profile_update
+                && !upload_progress.is_done()
+                && client.connection_status == ServerConnectionStatus::Connected
+                && let Some(progress) = client.pump_upload().await?
+            {
+                *upload_progress = progress;
+            }
+
+            if state.startup.is_done() && client.connection_status == ServerConnectionStatus::Connected {
+                fetch_visible_media(state, client, self.global_state.config.author_avatars).await?;
+                backfill_search(state, client, self.global_state.config.history_page_size).await?;
+            }
+
+            flush_status_updates(state, client).await?;
+            state.sort_users_debounced();
+
+            if state.history_cache_dirty && state.history_cache_saved.elapsed() > HISTORY_CACHE_SAVE_INTERVAL {
+                state.save_history_cache_in_background();
+            }
+
+            if self.global_state.config.title_unread && state.written_title != Some(state.unseen_messages) {
+                if let Err(e) = set_title(state.unseen_messages) {
+                    warn!("Failed to set the terminal title: {e}");
+                }
+                state.written_title = Some(state.unseen_messages);
+            }
+
+            if state.checkpoint_saved.elapsed() > CHECKPOINT_INTERVAL {
+                state.save_checkpoint();
+            }
+
+            state.toasts.retain(|toast| toast.created.elapsed() < TOAST_DURATION);
+
+            let summary = state.unread_summary();
+            if state.startup.is_done() && state.written_status != Some(summary) {
+                if let Err(e) = write_status(&summary) {
+                    warn!("Failed to write the status file: {e}");
+                }
+                state.written_status = Some(summary);
+            }
+
+            if state.startup.timed_out() && client.connection_status == ServerConnectionStatus::Connected {
+                state.startup.reset_timeout();
+                event_send.send(TuiEvent::StartupStepTimeout).await?;
+            }
+
             if let Some(time) = state.time_since_last_focused
                 && time.elapsed() > Duration::from_secs(USER_TIME_UNTIL_IDLE)
             {
                 event_send.send(TuiEvent::IdleUser).await?;
                 state.time_since_last_focused = None;
             }
+        } else if let AppState::Login(login_state) = &mut self.current_state
+            && login_state.login_pending
+        {
+            login_state.login_pending = false;
+            event_send.send(TuiEvent::Login).await?;
         }
 
         Ok(())
@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+use ratatui::Frame;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+
+/// Frames slower than this are highlighted, a frame is drawn every 10ms at most
+const SLOW_FRAME: Duration = Duration::from_millis(10);
+const OVERLAY_WIDTH: u16 = 34;
+
+/// How long the last frame took to lay out, shown with `F12`
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    pub visible: bool,
+    pub frame_time: Duration,
+    pub panels: Vec<(&'static str, Duration)>,
+    /// Messages and lines laid out by the chat history, including the ones cut off at the bottom
+    pub history_messages: usize,
+    pub history_lines: usize,
+    /// Lines of the newest message in view and the height of the history they were drawn in, kept between frames for scrolling
+    pub newest_in_view_lines: usize,
+    pub history_height: usize,
+}
+
+impl RenderStats {
+    /// Forgets the previous frame
+    pub fn start_frame(&mut self) {
+        self.panels.clear();
+        self.history_messages = 0;
+        self.history_lines = 0;
+    }
+
+    /// Runs the render function of a panel and records how long it took
+    pub fn time<T>(&mut self, panel: &'static str, render: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = render();
+        self.record(panel, start);
+        result
+    }
+
+    /// For panels that need the stats themselves while rendering
+    pub fn record(&mut self, panel: &'static str, start: Instant) {
+        self.panels.push((panel, start.elapsed()));
+    }
+
+    pub fn render_overlay(&self, fps: u32, frame: &mut Frame) {
+        if !self.visible {
+            return;
+        }
+        let duration_style = |duration: Duration| {
+            if duration >= SLOW_FRAME {
+                Style::default().fg(Color::LightRed)
+            } else {
+                Style::default().fg(Color::Green)
+            }
+        };
+        let row = |label: &str, value: Span<'static>| Line::from(vec![Span::from(format!("{label:<16}")), value]);
+
+        let mut lines = vec![
+            row("frame", Span::styled(format_duration(self.frame_time), duration_style(self.frame_time))),
+            row("fps", Span::from(fps.to_string())),
+        ];
+        lines.extend(self.panels.iter().map(|(panel, duration)| {
+            row(
+                panel,
+                Span::styled(format_duration(*duration), duration_style(*duration).add_modifier(Modifier::DIM)),
+            )
+        }));
+        if self.history_messages > 0 {
+            lines.push(row(
+                "history lines",
+                Span::from(format!("{} ({} messages)", self.history_lines, self.history_messages)),
+            ));
+        }
+
+        let area = frame.area();
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let width = OVERLAY_WIDTH.min(area.width);
+        let overlay_area = Rect::new(area.x + area.width - width, area.y + area.height - height, width, height);
+        let widget = Paragraph::new(Text::from(lines)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Magenta))
+                .title(Span::styled(
+                    "Render time",
+                    Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+                )),
+        );
+        frame.render_widget(Clear, overlay_area);
+        frame.render_widget(widget, overlay_area);
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
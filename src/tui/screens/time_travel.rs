@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::mem::discriminant;
+
+use chrono::{DateTime, Local};
+use ratatui::Frame;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
+
+use crate::storage::secrets::Secret;
+use crate::tui::events::TuiEvent;
+use crate::tui::screens::AppState;
+use crate::tui::screens::chat::Popup;
+use crate::tui::screens::chat::password_editor::PasswordEditor;
+use crate::tui::screens::login::LoginFocus;
+
+/// States kept around to step through, older ones are dropped
+pub const MAX_RECORDED_STATES: usize = 64;
+const MAX_EVENT_DESCRIPTION: usize = 120;
+const OVERLAY_HEIGHT: u16 = 5;
+
+/// The state right after an event was handled
+#[derive(Clone, Debug)]
+pub struct RecordedState {
+    pub event: String,
+    /// Hash of the parts of the state that are shown, equal hashes in a row mean the event did not change anything
+    pub hash: u64,
+    pub recorded_at: DateTime<Local>,
+    pub state: AppState,
+}
+
+/// Copy of a state with the passwords and the session token left out. Recordings only replay the UI, the secrets would outlive their session in them
+fn without_secrets(state: &AppState) -> AppState {
+    let mut state = state.clone();
+    match &mut state {
+        AppState::Chat(chat_state) => {
+            chat_state.current_user.password = Secret::default();
+            chat_state.session_token = None;
+            if let Some(lock) = &mut chat_state.lock {
+                lock.input.clear();
+            }
+            if let Some(Popup::ChangePassword(editor)) = &mut chat_state.popup {
+                *editor = PasswordEditor {
+                    field: editor.field,
+                    ..PasswordEditor::new()
+                };
+            }
+        }
+        AppState::Login(login_state) => {
+            login_state.password_input = Secret::default();
+            if let Some(prompt) = &mut login_state.login_code {
+                prompt.input.clear();
+                prompt.pending_secret = None;
+            }
+        }
+    }
+    state
+}
+
+/// Whether typed characters end up in a password or a login code
+fn secret_input_focused(state: &AppState) -> bool {
+    match state {
+        AppState::Chat(chat_state) => chat_state.lock.is_some() || matches!(chat_state.popup, Some(Popup::ChangePassword(_))),
+        AppState::Login(login_state) => matches!(login_state.focus, LoginFocus::PasswordInput(_) | LoginFocus::LoginCodeInput(_)),
+    }
+}
+
+/// Bounded log of recent states that can be stepped through and rendered again, to reproduce intermittent UI glitches
+#[derive(Clone, Debug, Default)]
+pub struct TimeTravel {
+    recorded: VecDeque<RecordedState>,
+    /// Index of the recorded state that is shown instead of the live one
+    pub viewing: Option<usize>,
+}
+
+impl TimeTravel {
+    /// Taken before the event is handled, since handling consumes it. Typed characters are left out while a secret is typed
+    pub fn describe(event: &TuiEvent, state: &AppState) -> String {
+        let mut description = match event {
+            TuiEvent::InputChar(_) if secret_input_focused(state) => "InputChar(***)".to_owned(),
+            TuiEvent::LockInputChar(_) => "LockInputChar(***)".to_owned(),
+            TuiEvent::PasswordEditorChar(_) => "PasswordEditorChar(***)".to_owned(),
+            event => format!("{event:?}"),
+        };
+        if let Some((end, _)) = description.char_indices().nth(MAX_EVENT_DESCRIPTION) {
+            description.truncate(end);
+            description.push('…');
+        }
+        description
+    }
+
+    pub fn record(&mut self, event: String, state: &AppState) {
+        if self.recorded.len() == MAX_RECORDED_STATES {
+            self.recorded.pop_front();
+            // Keep showing the same state while new ones come in
+            self.viewing = self.viewing.map(|idx| idx.saturating_sub(1));
+        }
+        self.recorded.push_back(RecordedState {
+            event,
+            hash: state_hash(state),
+            recorded_at: Local::now(),
+            state: without_secrets(state),
+        });
+    }
+
+    /// Opens the overlay at the newest state, or closes it
+    pub fn toggle(&mut self) {
+        self.viewing = match self.viewing {
+            Some(_) => None,
+            None => self.recorded.len().checked_sub(1),
+        };
+    }
+
+    pub fn step_back(&mut self) {
+        if let Some(idx) = &mut self.viewing {
+            *idx = idx.saturating_sub(1);
+        }
+    }
+
+    pub fn step_forward(&mut self) {
+        if let Some(idx) = &mut self.viewing {
+            *idx = (*idx + 1).min(self.recorded.len().saturating_sub(1));
+        }
+    }
+
+    pub fn viewed(&self) -> Option<&RecordedState> {
+        self.viewing.and_then(|idx| self.recorded.get(idx))
+    }
+
+    pub fn render_overlay(&self, frame: &mut Frame) {
+        let (Some(idx), Some(recorded)) = (self.viewing, self.viewed()) else {
+            return;
+        };
+        let changed = idx
+            .checked_sub(1)
+            .and_then(|previous| self.recorded.get(previous))
+            .is_none_or(|previous| previous.hash != recorded.hash);
+
+        let lines = vec![
+            Line::from(vec![
+                Span::styled(format!("{}/{} ", idx + 1, self.recorded.len()), Style::default().fg(Color::Yellow)),
+                Span::raw(format!("{} ", recorded.recorded_at.format("%H:%M:%S%.3f"))),
+                Span::styled(
+                    format!("state {:016x}", recorded.hash),
+                    Style::default().fg(if changed { Color::Green } else { Color::DarkGray }),
+                ),
+                Span::styled(if changed { "" } else { " (unchanged)" }, Style::default().add_modifier(Modifier::DIM)),
+            ]),
+            Line::from(recorded.event.as_str()),
+        ];
+
+        let area = frame.area();
+        let overlay_area = Rect::new(area.x, area.y, area.width, OVERLAY_HEIGHT.min(area.height));
+        let widget = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: false }).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Magenta))
+                .title(Span::styled(
+                    "Time travel",
+                    Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+                ))
+                .title_bottom(Span::styled(
+                    "[←/→] Step  [F9] Back to the present",
+                    Style::default().add_modifier(Modifier::DIM),
+                )),
+        );
+        frame.render_widget(Clear, overlay_area);
+        frame.render_widget(widget, overlay_area);
+    }
+}
+
+/// Hashes what an event usually changes on screen instead of the whole state, which holds the full history and is hashed after every event
+fn state_hash(state: &AppState) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    discriminant(state).hash(&mut hasher);
+    match state {
+        AppState::Chat(chat_state) => {
+            let channel_id = chat_state.channels.get(chat_state.active_channel_idx).map(|channel| channel.id);
+            channel_id.hash(&mut hasher);
+            channel_id.and_then(|id| chat_state.chat_history.get(&id)).map(Vec::len).hash(&mut hasher);
+            channel_id.and_then(|id| chat_state.chat_inputs.get(&id)).hash(&mut hasher);
+            chat_state.chat_scroll_offset.hash(&mut hasher);
+            chat_state.chat_line_offset.hash(&mut hasher);
+            chat_state.popup.as_ref().map(discriminant).hash(&mut hasher);
+            chat_state.lock.as_ref().map(|lock| lock.input.len()).hash(&mut hasher);
+            discriminant(&chat_state.server_connection_status).hash(&mut hasher);
+            chat_state.users.len().hash(&mut hasher);
+            chat_state.toasts.len().hash(&mut hasher);
+        }
+        AppState::Login(login_state) => {
+            login_state.username_input.hash(&mut hasher);
+            login_state.password_input.expose().len().hash(&mut hasher);
+            login_state.server_address_input.hash(&mut hasher);
+            discriminant(&login_state.focus).hash(&mut hasher);
+            login_state.focus.cursor().hash(&mut hasher);
+            discriminant(&login_state.input_status).hash(&mut hasher);
+            login_state.enable_tls.hash(&mut hasher);
+            login_state.login_code.as_ref().map(|prompt| prompt.input.len()).hash(&mut hasher);
+            login_state.connection_probe.len().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{Context, Result, anyhow};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols::{border, line};
+use ratatui::widgets::BorderType;
+use serde::Deserialize;
+
+use crate::storage::config_file;
+
+pub const DEFAULT_THEME: &str = "dark";
+/// Themes that come with the client, others are read from `~/.config/chatger/themes/<name>.toml`
+pub const BUILT_IN_THEMES: [&str; 3] = ["dark", "light", "mono"];
+const THEMES_DIR: &str = "themes";
+
+/// Styles the UI is drawn with, picked with `theme` in the config file or `--theme`.
+///
+/// A theme file replaces the styles it lists and takes the others from a built-in theme:
+/// ```toml
+/// base = "light"
+/// focus = { fg = "magenta" }
+/// selection = { bg = "#3a3a3a" }
+/// header = { fg = "blue", modifiers = ["bold", "underlined"] }
+/// borders = "rounded"
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    /// Titles of panes and popups
+    pub header: Style,
+    /// Border of the focused pane and of popups
+    pub focus: Style,
+    /// Confirmations, warnings of the server and counts of unread channels
+    pub attention: Style,
+    /// Selected message, channel or list entry
+    pub selection: Style,
+    /// Timestamp of the selected message, which would otherwise not stand out from the selection
+    pub selected_timestamp: Style,
+    /// Key hints and secondary details
+    pub hint: Style,
+    /// Placeholders, empty lists and progress that is still going on
+    pub placeholder: Style,
+    pub author: Style,
+    /// Message bodies
+    pub text: Style,
+    pub timestamp: Style,
+    /// Added on top of messages that are still being sent
+    pub pending: Style,
+    pub error: Style,
+    pub warning: Style,
+    pub success: Style,
+    /// Joins, leaves and other messages of the server itself
+    pub event: Style,
+    /// Added on top of the message a reply quotes
+    pub quote: Style,
+    /// Added on top of edited tags, translations and attachment names
+    pub annotation: Style,
+    /// `#channel` links in messages
+    pub link: Style,
+    /// Channel names in lists, headings and charts
+    pub accent: Style,
+    pub unread: Style,
+    pub muted: Style,
+    /// Who is typing
+    pub typing: Style,
+    pub online: Style,
+    pub idle: Style,
+    pub do_not_disturb: Style,
+    pub offline: Style,
+    /// Added on top of the headings of sections in the users panel
+    pub section: Style,
+    /// Voice notes that are being recorded
+    pub recording: Style,
+    /// Cursor of text inputs
+    pub cursor: Style,
+    /// Focused button of the login screen
+    pub button: Style,
+    /// Focused button of the login screen after the login failed
+    pub button_error: Style,
+    /// Lines the panes and popups are drawn with, plain, rounded, double or thick
+    pub borders: BorderType,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    /// The colors the client always had, for terminals with a dark background
+    fn dark() -> Self {
+        Theme {
+            name: "dark".to_owned(),
+            header: Style::new().add_modifier(Modifier::BOLD),
+            focus: Style::new().fg(Color::Cyan),
+            attention: Style::new().fg(Color::Yellow),
+            selection: Style::new().bg(Color::DarkGray),
+            selected_timestamp: Style::new().fg(Color::Gray).bg(Color::DarkGray),
+            hint: Style::new().add_modifier(Modifier::DIM),
+            placeholder: Style::new().add_modifier(Modifier::DIM.union(Modifier::ITALIC)),
+            author: Style::new().fg(Color::Yellow),
+            text: Style::new().fg(Color::Gray),
+            timestamp: Style::new().fg(Color::DarkGray),
+            pending: Style::new().add_modifier(Modifier::DIM.union(Modifier::ITALIC)),
+            error: Style::new().fg(Color::LightRed),
+            warning: Style::new().fg(Color::LightYellow),
+            success: Style::new().fg(Color::Green),
+            event: Style::new().fg(Color::Gray).add_modifier(Modifier::DIM.union(Modifier::ITALIC)),
+            quote: Style::new().add_modifier(Modifier::DIM),
+            annotation: Style::new().add_modifier(Modifier::ITALIC),
+            link: Style::new().fg(Color::Cyan).add_modifier(Modifier::UNDERLINED),
+            accent: Style::new().fg(Color::Cyan),
+            unread: Style::new().add_modifier(Modifier::BOLD),
+            muted: Style::new().add_modifier(Modifier::DIM),
+            typing: Style::new().fg(Color::Gray).add_modifier(Modifier::DIM),
+            online: Style::new().fg(Color::Green),
+            idle: Style::new().fg(Color::Yellow),
+            do_not_disturb: Style::new().fg(Color::Red),
+            offline: Style::new().fg(Color::Gray).add_modifier(Modifier::DIM),
+            section: Style::new().add_modifier(Modifier::UNDERLINED),
+            recording: Style::new().fg(Color::Red),
+            cursor: Style::new().add_modifier(Modifier::UNDERLINED),
+            button: Style::new().fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD),
+            button_error: Style::new().fg(Color::Black).bg(Color::Red).add_modifier(Modifier::BOLD),
+            borders: BorderType::Plain,
+        }
+    }
+
+    /// Darker colors that stay readable on a light background
+    fn light() -> Self {
+        Theme {
+            name: "light".to_owned(),
+            focus: Style::new().fg(Color::Blue),
+            attention: Style::new().fg(Color::Magenta),
+            selection: Style::new().bg(Color::Indexed(253)),
+            selected_timestamp: Style::new().fg(Color::Black).bg(Color::Indexed(253)),
+            author: Style::new().fg(Color::Indexed(130)),
+            text: Style::new().fg(Color::Black),
+            timestamp: Style::new().fg(Color::Indexed(244)),
+            error: Style::new().fg(Color::Red),
+            warning: Style::new().fg(Color::Indexed(130)),
+            success: Style::new().fg(Color::Indexed(28)),
+            event: Style::new().fg(Color::Indexed(244)).add_modifier(Modifier::ITALIC),
+            link: Style::new().fg(Color::Blue).add_modifier(Modifier::UNDERLINED),
+            accent: Style::new().fg(Color::Blue),
+            typing: Style::new().fg(Color::Indexed(244)),
+            online: Style::new().fg(Color::Indexed(28)),
+            idle: Style::new().fg(Color::Indexed(130)),
+            offline: Style::new().fg(Color::Indexed(244)),
+            button: Style::new().fg(Color::White).bg(Color::Blue).add_modifier(Modifier::BOLD),
+            button_error: Style::new().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD),
+            ..Theme::dark()
+        }
+    }
+
+    /// No colors at all, only bold, dim and reversed text, for monochrome terminals and screenshots
+    fn mono() -> Self {
+        let plain = Style::new();
+        Theme {
+            name: "mono".to_owned(),
+            focus: Style::new().add_modifier(Modifier::BOLD),
+            attention: Style::new().add_modifier(Modifier::BOLD),
+            selection: Style::new().add_modifier(Modifier::REVERSED),
+            selected_timestamp: Style::new().add_modifier(Modifier::REVERSED),
+            author: Style::new().add_modifier(Modifier::BOLD),
+            text: plain,
+            timestamp: Style::new().add_modifier(Modifier::DIM),
+            error: Style::new().add_modifier(Modifier::BOLD.union(Modifier::UNDERLINED)),
+            warning: Style::new().add_modifier(Modifier::BOLD),
+            success: plain,
+            event: Style::new().add_modifier(Modifier::DIM.union(Modifier::ITALIC)),
+            link: Style::new().add_modifier(Modifier::UNDERLINED),
+            accent: plain,
+            typing: Style::new().add_modifier(Modifier::DIM),
+            online: plain,
+            idle: Style::new().add_modifier(Modifier::DIM),
+            do_not_disturb: Style::new().add_modifier(Modifier::BOLD),
+            offline: Style::new().add_modifier(Modifier::DIM),
+            recording: Style::new().add_modifier(Modifier::BOLD),
+            button: Style::new().add_modifier(Modifier::BOLD.union(Modifier::REVERSED)),
+            button_error: Style::new().add_modifier(Modifier::BOLD.union(Modifier::REVERSED).union(Modifier::UNDERLINED)),
+            ..Theme::dark()
+        }
+    }
+
+    fn built_in(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            "mono" => Some(Theme::mono()),
+            _ => None,
+        }
+    }
+
+    /// Loads a built-in theme by name, or else a theme file.
+    /// A name with a `/` or ending in `.toml` is a path, other names are looked up in `~/.config/chatger/themes/`
+    pub fn load(name: &str) -> Result<Self> {
+        if let Some(theme) = Theme::built_in(name) {
+            return Ok(theme);
+        }
+        let path = if name.contains('/') || name.ends_with(".toml") {
+            PathBuf::from(name)
+        } else {
+            config_file(THEMES_DIR)?.join(format!("{name}.toml"))
+        };
+        let contents = fs::read_to_string(&path).with_context(|| {
+            format!(
+                "Unknown theme {name}, pick one of {} or add {}",
+                BUILT_IN_THEMES.join(", "),
+                path.display()
+            )
+        })?;
+        let file: ThemeFile = toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        let base = file.base.as_deref().unwrap_or(DEFAULT_THEME);
+        let mut theme = Theme::built_in(base).ok_or_else(|| {
+            anyhow!(
+                "Unknown base theme {base} in {}, pick one of {}",
+                path.display(),
+                BUILT_IN_THEMES.join(", ")
+            )
+        })?;
+        theme.name = name.to_owned();
+        if let Some(borders) = &file.borders {
+            theme.borders = border_type(borders).with_context(|| format!("Invalid borders in {}", path.display()))?;
+        }
+        for (role, spec) in file.styles {
+            let style = spec.style().with_context(|| format!("Invalid style for {role} in {}", path.display()))?;
+            *theme
+                .style_mut(&role)
+                .ok_or_else(|| anyhow!("Unknown style {role} in {}", path.display()))? = style;
+        }
+        Ok(theme)
+    }
+
+    /// Draws a set made of the plain line glyphs, with the junctions the panes share, in the lines of the theme
+    pub fn border_set(&self, set: border::Set) -> border::Set {
+        let lines = match self.borders {
+            BorderType::Rounded => line::ROUNDED,
+            BorderType::Double => line::DOUBLE,
+            BorderType::Thick => line::THICK,
+            _ => return set,
+        };
+        let glyph = |glyph: &'static str| match glyph {
+            line::VERTICAL => lines.vertical,
+            line::HORIZONTAL => lines.horizontal,
+            line::TOP_LEFT => lines.top_left,
+            line::TOP_RIGHT => lines.top_right,
+            line::BOTTOM_LEFT => lines.bottom_left,
+            line::BOTTOM_RIGHT => lines.bottom_right,
+            line::VERTICAL_LEFT => lines.vertical_left,
+            line::VERTICAL_RIGHT => lines.vertical_right,
+            line::HORIZONTAL_DOWN => lines.horizontal_down,
+            line::HORIZONTAL_UP => lines.horizontal_up,
+            line::CROSS => lines.cross,
+            glyph => glyph,
+        };
+        border::Set {
+            top_left: glyph(set.top_left),
+            top_right: glyph(set.top_right),
+            bottom_left: glyph(set.bottom_left),
+            bottom_right: glyph(set.bottom_right),
+            vertical_left: glyph(set.vertical_left),
+            vertical_right: glyph(set.vertical_right),
+            horizontal_top: glyph(set.horizontal_top),
+            horizontal_bottom: glyph(set.horizontal_bottom),
+        }
+    }
+
+    fn style_mut(&mut self, role: &str) -> Option<&mut Style> {
+        let style = match role {
+            "header" => &mut self.header,
+            "focus" => &mut self.focus,
+            "attention" => &mut self.attention,
+            "selection" => &mut self.selection,
+            "selected_timestamp" => &mut self.selected_timestamp,
+            "hint" => &mut self.hint,
+            "placeholder" => &mut self.placeholder,
+            "author" => &mut self.author,
+            "text" => &mut self.text,
+            "timestamp" => &mut self.timestamp,
+            "pending" => &mut self.pending,
+            "error" => &mut self.error,
+            "warning" => &mut self.warning,
+            "success" => &mut self.success,
+            "event" => &mut self.event,
+            "quote" => &mut self.quote,
+            "annotation" => &mut self.annotation,
+            "link" => &mut self.link,
+            "accent" => &mut self.accent,
+            "unread" => &mut self.unread,
+            "muted" => &mut self.muted,
+            "typing" => &mut self.typing,
+            "online" => &mut self.online,
+            "idle" => &mut self.idle,
+            "do_not_disturb" => &mut self.do_not_disturb,
+            "offline" => &mut self.offline,
+            "section" => &mut self.section,
+            "recording" => &mut self.recording,
+            "cursor" => &mut self.cursor,
+            "button" => &mut self.button,
+            "button_error" => &mut self.button_error,
+            _ => return None,
+        };
+        Some(style)
+    }
+}
+
+/// Contents of a theme file, the built-in theme it starts from and the styles it replaces
+#[derive(Deserialize, Debug)]
+struct ThemeFile {
+    base: Option<String>,
+    borders: Option<String>,
+    #[serde(flatten)]
+    styles: BTreeMap<String, StyleSpec>,
+}
+
+fn border_type(name: &str) -> Result<BorderType> {
+    match name {
+        "plain" => Ok(BorderType::Plain),
+        "rounded" => Ok(BorderType::Rounded),
+        "double" => Ok(BorderType::Double),
+        "thick" => Ok(BorderType::Thick),
+        _ => Err(anyhow!("Unknown borders {name}, pick one of plain, rounded, double or thick")),
+    }
+}
+
+/// A style as written in a theme file. Colors are names like `light-red`, `#rrggbb` or a number of the 256 color palette
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct StyleSpec {
+    fg: Option<String>,
+    bg: Option<String>,
+    #[serde(default)]
+    modifiers: Vec<String>,
+}
+
+impl StyleSpec {
+    fn style(&self) -> Result<Style> {
+        let color = |color: &str| Color::from_str(color).map_err(|_| anyhow!("Unknown color {color}"));
+        let mut style = Style::new();
+        if let Some(fg) = &self.fg {
+            style = style.fg(color(fg)?);
+        }
+        if let Some(bg) = &self.bg {
+            style = style.bg(color(bg)?);
+        }
+        for modifier in &self.modifiers {
+            style = style.add_modifier(match modifier.as_str() {
+                "bold" => Modifier::BOLD,
+                "dim" => Modifier::DIM,
+                "italic" => Modifier::ITALIC,
+                "underlined" => Modifier::UNDERLINED,
+                "reversed" => Modifier::REVERSED,
+                "crossed_out" => Modifier::CROSSED_OUT,
+                "slow_blink" => Modifier::SLOW_BLINK,
+                "rapid_blink" => Modifier::RAPID_BLINK,
+                _ => return Err(anyhow!("Unknown modifier {modifier}")),
+            });
+        }
+        Ok(style)
+    }
+}